@@ -0,0 +1,43 @@
+#![allow(internal_features,incomplete_features)]
+#![feature(lang_items,adt_const_params,core_intrinsics,start)]
+#![no_std]
+use core::future::IntoFuture;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use mycorrhiza::system::threading::tasks::Task;
+use mycorrhiza::system::{Exception, MString};
+use mycorrhiza::task::{block_on, delay, into_task, try_into_task, TaskError};
+use mycorrhiza::{panic_handler, start};
+panic_handler! {}
+start! {}
+#[lang = "eh_personality"]
+fn rust_eh_personality() {}
+static POLLS: AtomicUsize = AtomicUsize::new(0);
+fn check(cond: bool) {
+    if !cond {
+        core::intrinsics::abort();
+    }
+}
+fn main() {
+    // Awaiting a .NET task, woken by its continuation.
+    check(block_on(delay(20)) == Ok(()));
+    // A Rust future driven by the thread pool, awaited both from Rust and as a .NET task.
+    let task = into_task(async {
+        POLLS.fetch_add(1, Ordering::Relaxed);
+        delay(20).await.unwrap();
+        POLLS.fetch_add(1, Ordering::Relaxed);
+    });
+    check(block_on(task.into_future()) == Ok(()));
+    check(POLLS.load(Ordering::Relaxed) == 2);
+    // Errors fault the task.
+    let task = try_into_task(
+        async {
+            Task::delay(10).await.unwrap();
+            Err::<(), _>("failed")
+        },
+        |err| Exception::new(MString::new(err)),
+    );
+    check(block_on(task.into_future()) == Err(TaskError::Faulted));
+    // The .NET side sees the same task.
+    let task = into_task(async {});
+    check(block_on(task.into_future()).is_ok() && task.is_completed() && !task.is_faulted());
+}
@@ -0,0 +1,59 @@
+use std::any::Any;
+use std::hint::black_box;
+use std::panic;
+
+fn check(condition: bool) {
+    if !condition {
+        core::intrinsics::abort();
+    }
+}
+fn payload_str(payload: &Box<dyn Any + Send>) -> Option<&str> {
+    payload
+        .downcast_ref::<&'static str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+}
+pub fn catch_unwind_test() {
+    // No panic: the closure's result is returned.
+    let ok = panic::catch_unwind(|| black_box(7));
+    check(matches!(ok, Ok(7)));
+    // A panic with a string literal message.
+    let err = panic::catch_unwind(|| {
+        if black_box(true) {
+            panic!("static message");
+        }
+    })
+    .unwrap_err();
+    check(payload_str(&err) == Some("static message"));
+    // A panic with a formatted message.
+    let err = panic::catch_unwind(|| {
+        panic!("formatted {}", black_box(42));
+    })
+    .unwrap_err();
+    check(payload_str(&err) == Some("formatted 42"));
+    // A custom payload is returned unchanged.
+    let err = panic::catch_unwind(|| panic::panic_any(black_box(0xDEAD_u32))).unwrap_err();
+    check(err.downcast_ref::<u32>() == Some(&0xDEAD));
+    // `resume_unwind` round-trips the exact payload.
+    let payload: Box<dyn Any + Send> = Box::new(String::from("resumed"));
+    let payload_ptr = &*payload as *const (dyn Any + Send) as *const u8;
+    let err = panic::catch_unwind(panic::AssertUnwindSafe(move || {
+        panic::resume_unwind(payload)
+    }))
+    .unwrap_err();
+    check(&*err as *const (dyn Any + Send) as *const u8 == payload_ptr);
+    check(payload_str(&err) == Some("resumed"));
+    // Unwinding runs destructors of the values it passes.
+    struct SetOnDrop<'a>(&'a std::cell::Cell<bool>);
+    impl Drop for SetOnDrop<'_> {
+        fn drop(&mut self) {
+            self.0.set(true);
+        }
+    }
+    let dropped = std::cell::Cell::new(false);
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let _guard = SetOnDrop(&dropped);
+        panic!("drop");
+    }));
+    check(result.is_err() && dropped.get());
+}
@@ -5,6 +5,7 @@
 use std::ffi::{c_char, c_int};
 use std::hint::black_box;
 use std::io::Write;
+mod catch_unwind;
 mod cstr;
 mod exchange_malloc;
 use crate::exchange_malloc::exchange_malloc_test;
@@ -194,6 +195,7 @@ fn main() {
    
     test_vec();
     test_string();
+    catch_unwind::catch_unwind_test();
     let int = std::hint::black_box(8);
     let boxed_int = std::hint::black_box(Box::new(8));
     //let mut file = std::fs::File::create("foo.txt").unwrap();
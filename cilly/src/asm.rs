@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use serde::{Deserialize, Serialize};
 
@@ -6,9 +6,9 @@ use crate::{
     access_modifier::AccessModifer,
     basic_block::BasicBlock,
     call_site::CallSite,
-    cil_node::CILNode,
     cil_root::CILRoot,
     method::{Method, MethodType},
+    reachability::{Item, Reachability},
     static_field_desc::StaticFieldDescriptor,
     type_def::TypeDef,
     FnSig, IString, Type,
@@ -45,6 +45,8 @@ pub struct Assembly {
     extern_fns: HashMap<ExternFnDef, IString>,
     /// List of all static fields within the assembly
     static_fields: HashMap<IString, Type>,
+    /// Names of the static fields which have a separate value for each thread.
+    thread_statics: HashSet<IString>,
 }
 impl Assembly {
     pub fn call_graph(&self) -> String {
@@ -109,6 +111,7 @@ edge [fontname=\"Helvetica,Arial,sans-serif\"]\nnode [shape=box];\n");
             entrypoint: None,
             extern_refs: HashMap::new(),
             static_fields: HashMap::new(),
+            thread_statics: HashSet::new(),
             extern_fns: HashMap::new(),
         };
         let dotnet_ver = AssemblyExternRef {
@@ -138,6 +141,8 @@ edge [fontname=\"Helvetica,Arial,sans-serif\"]\nnode [shape=box];\n");
         let mut static_fields = self.static_fields;
         let mut extern_fns = self.extern_fns;
         static_fields.extend(other.static_fields);
+        let mut thread_statics = self.thread_statics;
+        thread_statics.extend(other.thread_statics);
         extern_refs.extend(other.extern_refs);
         extern_fns.extend(other.extern_fns);
         Self {
@@ -147,6 +152,7 @@ edge [fontname=\"Helvetica,Arial,sans-serif\"]\nnode [shape=box];\n");
             extern_refs,
             extern_fns,
             static_fields,
+            thread_statics,
         }
     }
     /// Gets the typdefef at path `path`.
@@ -180,6 +186,16 @@ edge [fontname=\"Helvetica,Arial,sans-serif\"]\nnode [shape=box];\n");
     pub fn add_static(&mut self, tpe: Type, name: &str) {
         self.static_fields.insert(name.into(), tpe);
     }
+    /// Adds a global static field named *name* of type *tpe*, which has a separate value for each thread.
+    pub fn add_thread_static(&mut self, tpe: Type, name: &str) {
+        self.add_static(tpe, name);
+        self.thread_statics.insert(name.into());
+    }
+    /// Checks if the static field named *name* has a separate value for each thread.
+    #[must_use]
+    pub fn is_thread_static(&self, name: &str) -> bool {
+        self.thread_statics.contains(name)
+    }
     pub fn add_cctor(&mut self) -> &mut Method {
         self.functions
             .entry(CallSite::new(
@@ -228,17 +244,18 @@ edge [fontname=\"Helvetica,Arial,sans-serif\"]\nnode [shape=box];\n");
     pub fn call_sites(&self) -> Vec<&CallSite> {
         self.methods().flat_map(Method::calls).collect()
     }
+    /// Removes the static fields which can't be reached, along with their initializers.
     pub fn remove_dead_statics(&mut self) {
-        // Get the set of "alive" fields(fields referenced outside of the static initializer).
-        let alive_fields: std::collections::HashSet<_> = self
-            .methods()
-            .filter(|method| method.name() != ".cctor")
-            .flat_map(Method::sflds)
-            .cloned()
-            .collect();
-        // Remove the definitions of all non-alive fields
+        let reachability = self.reachability(false);
+        self.retain_statics(&reachability);
+    }
+    fn retain_statics(&mut self, reachability: &Reachability) {
         self.static_fields.retain(|name, tpe| {
-            alive_fields.contains(&StaticFieldDescriptor::new(None, tpe.clone(), name.clone()))
+            reachability.is_kept(&Item::Static(StaticFieldDescriptor::new(
+                None,
+                tpe.clone(),
+                name.clone(),
+            )))
         });
         // Remove their initializers from the cctor
         let Some(cctor) = self.cctor_mut() else {
@@ -249,11 +266,9 @@ edge [fontname=\"Helvetica,Arial,sans-serif\"]\nnode [shape=box];\n");
             .iter_mut()
             .flat_map(BasicBlock::trees_mut)
         {
-            if let CILRoot::SetStaticField { descr, value } = tree.root_mut() {
+            if let CILRoot::SetStaticField { descr, value: _ } = tree.root_mut() {
                 // Assigement to a dead static, remove.
-                if !alive_fields.contains(descr) {
-                    debug_assert!(descr.name().contains('a'));
-                    debug_assert!(matches!(value, CILNode::Call { site: _, args: _ }));
+                if descr.owner().is_none() && !reachability.is_kept(&Item::Static(descr.clone())) {
                     *tree = CILRoot::Nop.into();
                 }
             }
@@ -273,12 +288,13 @@ edge [fontname=\"Helvetica,Arial,sans-serif\"]\nnode [shape=box];\n");
     }
     /// Optimizes all the methods witin the assembly.
     pub fn opt(&mut self) {
-        self.functions.iter_mut().for_each(|method| {
-            let (site, method) = method;
-            let mut method = method.clone();
-            method.opt();
-            //crate::opt::opt_method(&mut method, self);
-        });
+        let sites: Vec<CallSite> = self.functions.keys().cloned().collect();
+        for site in sites {
+            // The method is taken out of the assembly, so that it can't be inlined into itself.
+            let mut method = self.functions.remove(&site).unwrap();
+            crate::opt::opt_method(&mut method, self);
+            self.functions.insert(site, method);
+        }
     }
     /// Adds a definition of a type to the assembly.
     pub fn add_typedef(&mut self, type_def: TypeDef) {
@@ -301,114 +317,52 @@ edge [fontname=\"Helvetica,Arial,sans-serif\"]\nnode [shape=box];\n");
     pub fn add_extern_fn(&mut self, name: IString, sig: FnSig, lib: IString, preserve_errno: bool) {
         self.extern_fns.insert((name, sig, preserve_errno), lib);
     }
-    fn get_exported_fn(&self) -> HashMap<CallSite, Method> {
-        let mut externs = HashMap::new();
-        if let Some(entrypoint) = &self.entrypoint {
-            let method = self.functions.get(entrypoint).cloned().unwrap();
-            externs.insert(entrypoint.clone(), method);
-        }
-        if let Some(cctor) = self.cctor() {
-            externs.insert(
-                CallSite::new(None, ".cctor".into(), FnSig::new(&[], Type::Void), true),
-                cctor.clone(),
-            );
-        }
-        for call in self
-            .types()
-            .flat_map(|(_, type_def)| type_def.methods())
-            .flat_map(|method| (method.calls()))
-        {
-            if let Some(method) = self.functions.get(&call).cloned() {
-                externs.insert(call.clone(), method);
-            };
-        }
-        externs
+    /// Finds everything reachable from the entrypoint, the static initializer and the types of this assembly. If
+    /// `keep_public` is set, public methods are treated as exported. Types are roots unless `dead_type_elimination` is
+    /// enabled.
+    #[must_use]
+    pub fn reachability(&self, keep_public: bool) -> Reachability {
+        Reachability::new(
+            self,
+            keep_public,
+            !crate::config::config().dead_type_elimination,
+        )
+    }
+    /// Explains why the methods, static fields and types whose name contains `name` are kept, by listing the chain of
+    /// items which leads to them from a root.
+    #[must_use]
+    pub fn why_kept(&self, name: &str, keep_public: bool) -> Option<String> {
+        self.reachability(keep_public).why_kept(name)
     }
+    /// Removes the methods which can't be reached.
     pub fn eliminate_dead_fn(&mut self) {
-        let mut alive: HashMap<CallSite, Method> = HashMap::new();
-        let mut resurecting: HashMap<CallSite, Method> = HashMap::new();
-        let mut to_resurect: HashMap<CallSite, Method> = self.get_exported_fn();
-        while !to_resurect.is_empty() {
-            alive.extend(resurecting.clone());
-            resurecting.clear();
-            resurecting.extend(to_resurect.clone());
-            to_resurect.clear();
-            for call in resurecting.iter().flat_map(|fnc| fnc.1.calls()) {
-                if let Some(_class) = call.class() {
-                    // TODO: if dead code elimination too agressive check this
-                    // Methods reference by methods inside types are NOT tracked.
-                    continue;
-                }
-                if alive.contains_key(&call) || resurecting.contains_key(&call) {
-                    // Already alive, ignore!
-                    continue;
-                }
-                if let Some(method) = self.functions.get(&call).cloned() {
-                    to_resurect.insert(call.clone(), method);
-                };
-            }
-        }
-        alive.extend(resurecting);
-        self.functions = alive;
+        let reachability = self.reachability(false);
+        self.functions
+            .retain(|site, _| reachability.is_kept(&Item::Method(site.clone())));
     }
+    /// Removes the methods, static fields and types which can't be reached. Types are only removed if
+    /// `dead_type_elimination` is enabled.
     pub fn eliminate_dead_code(&mut self) {
-        if *DEAD_CODE_ELIMINATION {
-            self.eliminate_dead_fn();
-            self.remove_dead_statics();
-            // Call eliminate_dead_fn again, to remove now-dead static initializers.
-            self.eliminate_dead_fn();
+        let config = crate::config::config();
+        if !config.dead_code_elimination {
+            return;
+        }
+        let reachability = self.reachability(false);
+        self.functions
+            .retain(|site, _| reachability.is_kept(&Item::Method(site.clone())));
+        self.retain_statics(&reachability);
+        if config.dead_type_elimination {
+            self.retain_types(&reachability);
         }
-
-        //self.eliminate_dead_types();
     }
+    /// Removes the types which can't be reached.
     pub fn eliminate_dead_types(&mut self) {
-        let mut alive = HashMap::new();
-        let mut resurected: HashMap<IString, _> = self
-            .functions
-            .values()
-            .flat_map(Method::dotnet_types)
-            .filter_map(|tpe| match tpe.asm() {
-                Some(_) => None,
-                None => Some(IString::from(tpe.name_path())),
-            })
-            .map(|name| (name.clone(), self.types.get(&name).unwrap().clone()))
-            .collect();
-        resurected.insert(
-            "RustVoid".into(),
-            self.types.get("RustVoid").cloned().unwrap(),
-        );
-        let mut to_resurect: HashMap<IString, _> = HashMap::new();
-        while !resurected.is_empty() {
-            for tpe in &resurected {
-                alive.insert(tpe.0.clone(), tpe.1.clone());
-                for (name, type_def) in tpe
-                    .1
-                    .all_types()
-                    .filter_map(Type::dotnet_refs)
-                    .filter_map(|tpe| match tpe.asm() {
-                        Some(_) => None,
-                        None => Some(IString::from(tpe.name_path())),
-                    })
-                    .filter_map(|name| name.split_once('\\').map(|(a, _)| a.into()))
-                    //.map(|(a,b)|a.into())
-                    .map(|name: IString| {
-                        (
-                            name.clone(),
-                            self.types
-                                .get(&name)
-                                .unwrap_or_else(|| panic!("Can't find type {name:?}"))
-                                .clone(),
-                        )
-                    })
-                {
-                    let name: IString = IString::from(name);
-                    to_resurect.insert(name, type_def);
-                }
-            }
-            resurected = to_resurect;
-            to_resurect = HashMap::new();
-        }
-        self.types = alive;
+        let reachability = Reachability::new(self, false, false);
+        self.retain_types(&reachability);
+    }
+    fn retain_types(&mut self, reachability: &Reachability) {
+        self.types
+            .retain(|name, _| reachability.is_kept(&Item::Type(name.clone())));
     }
 
     pub fn cctor_mut(&mut self) -> Option<&mut Method> {
@@ -424,22 +378,20 @@ edge [fontname=\"Helvetica,Arial,sans-serif\"]\nnode [shape=box];\n");
         &mut self.static_fields
     }
 
+    #[must_use]
+    pub fn static_fields(&self) -> &HashMap<IString, Type> {
+        &self.static_fields
+    }
+
+    #[must_use]
+    pub fn entrypoint(&self) -> Option<&CallSite> {
+        self.entrypoint.as_ref()
+    }
+
     pub fn functions(&self) -> &HashMap<CallSite, Method> {
         &self.functions
     }
 }
-use lazy_static::*;
-lazy_static! {
-    #[doc = "Tells the codegen to remove dead code before export."]pub static ref DEAD_CODE_ELIMINATION:bool = {
-        std::env::vars().into_iter().find_map(|(key,value)|if key == stringify!(DEAD_CODE_ELIMINATION){
-            Some(value)
-        }else {
-            None
-        }).map(|value|match value.as_ref(){
-            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(DEAD_CODE_ELIMINATION),value),
-        }).unwrap_or(true)
-    };
-}
 fn link_static_initializers(a: Option<&Method>, b: Option<&Method>) -> Option<Method> {
     match (a, b) {
         (None, None) => None,
@@ -24,6 +24,8 @@ pub trait AssemblyExporter: Sized {
     fn add_extern_ref(&mut self, asm_name: &str, info: &AssemblyExternRef);
     /// Adds a global field
     fn add_global(&mut self, tpe: &Type, name: &str);
+    /// Adds a global field, which has a separate value for each thread.
+    fn add_thread_local(&mut self, tpe: &Type, name: &str);
     /// Handles the whole assembly export process all at once.
     fn export_assembly(
         mut self,
@@ -54,13 +56,15 @@ pub trait AssemblyExporter: Sized {
         for ((name, sig, preserve_errno), lib) in asm.extern_fns() {
             self.add_extern_method(lib, name, sig, *preserve_errno);
         }
-        for global in asm.globals() {
-            self.add_global(global.1, global.0);
+        for (name, tpe) in asm.globals() {
+            if asm.is_thread_static(name) {
+                self.add_thread_local(tpe, name);
+            } else {
+                self.add_global(tpe, name);
+            }
         }
 
         self.finalize(final_path, is_dll)
-            .expect("Could not export assembly");
-        Ok(())
     }
 }
 #[derive(Debug)]
@@ -77,6 +81,19 @@ pub enum AssemblyExportError {
     /// A generic formatter error happended when exporting the assembly.
     FmtError(std::fmt::Error),
 }
+impl std::fmt::Display for AssemblyExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidIL => write!(f, "The assembly IL is invalid."),
+            Self::CouldNotCanonalizePath(err, path) => {
+                write!(f, "Could not canonicalize path {}: {err}", path.display())
+            }
+            Self::IoError(err) => write!(f, "IO error: {err}"),
+            Self::ExporterError(msg) => write!(f, "{msg}"),
+            Self::FmtError(err) => write!(f, "Formatting error: {err}"),
+        }
+    }
+}
 impl From<std::io::Error> for AssemblyExportError {
     fn from(error: std::io::Error) -> Self {
         Self::IoError(error)
@@ -37,6 +37,17 @@ impl Handler {
         }
     }
 }
+/// Returns the local the exception caught by `handler` should be stored in, as requested by a [`CILRoot::StoreCaught`].
+#[must_use]
+pub fn caught_local(handler: &[BasicBlock]) -> Option<u32> {
+    handler
+        .iter()
+        .flat_map(BasicBlock::trees)
+        .find_map(|tree| match tree.root() {
+            CILRoot::StoreCaught { local } => Some(*local),
+            _ => None,
+        })
+}
 fn find_bb(id: u32, bbs: &[BasicBlock]) -> &BasicBlock {
     bbs.iter().find(|bb| bb.id() == id).unwrap()
 }
@@ -238,7 +249,11 @@ pub fn export(
     }
     if let Some(handler) = block.handler() {
         let handler = handler.as_blocks().unwrap();
-        write!(out, "}}catch [System.Runtime]System.Object{{\npop").unwrap();
+        writeln!(out, "}}catch [System.Runtime]System.Object{{").unwrap();
+        match caught_local(handler) {
+            Some(local) => write!(out, "stloc {local}").unwrap(),
+            None => write!(out, "pop").unwrap(),
+        }
         DepthSetting::with_pading().pad(out).unwrap();
         for handler_block in handler {
             writeln!(
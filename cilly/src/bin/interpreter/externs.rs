@@ -0,0 +1,477 @@
+//! Emulation of methods which are not defined in the interpreted assembly: the parts of the .NET standard library used
+//! by the codegen, and the native functions it calls via PInvoke.
+use std::io::Write;
+
+use cilly::{call_site::CallSite, Type};
+
+use crate::{layout::int128_kind, managed_exception, value::Value, Exception, InterpreterState};
+
+/// Calls an emulated method.
+pub fn call_extern(
+    state: &mut InterpreterState,
+    site: &CallSite,
+    args: Vec<Value>,
+) -> Result<Value, Exception> {
+    let class = site.class().map_or("", |class| class.name_path());
+    match class {
+        "" => call_native(state, site, &args),
+        "System.Int128" | "System.UInt128" => call_int128(site, &args),
+        _ => call_dotnet(state, class, site, &args),
+    }
+}
+/// Constructs an object of an extern type.
+pub fn new_obj(
+    _state: &mut InterpreterState,
+    site: &CallSite,
+    args: Vec<Value>,
+) -> Result<Value, Exception> {
+    let class = site
+        .class()
+        .ok_or_else(|| Exception::UnsupportedExtern(site.clone()))?;
+    if let Some(signed) = int128_kind(class) {
+        // `Int128(ulong upper, ulong lower)`
+        let (upper, lower) = match args.as_slice() {
+            [upper, lower] => (upper.as_u64()?, lower.as_u64()?),
+            _ => return Err(Exception::UnsupportedExtern(site.clone())),
+        };
+        let val = (u128::from(upper) << 64) | u128::from(lower);
+        return Ok(if signed {
+            Value::I128(val as i128)
+        } else {
+            Value::U128(val)
+        });
+    }
+    if class.name_path().ends_with("Exception") {
+        let message = match args.first() {
+            Some(Value::String(message)) => message.clone(),
+            _ => "".into(),
+        };
+        return Ok(Value::Exception {
+            class: class.name_path().into(),
+            message,
+        });
+    }
+    Err(Exception::UnsupportedExtern(site.clone()))
+}
+/// Reads a nul-terminated string.
+fn read_c_str(state: &InterpreterState, ptr: usize) -> Result<Vec<u8>, Exception> {
+    let bytes = state.bytes_from(ptr)?;
+    let len = bytes
+        .iter()
+        .position(|byte| *byte == 0)
+        .ok_or(Exception::AllocOffsetOutOfRange)?;
+    Ok(bytes[..len].to_vec())
+}
+/// Returns the type pointed to by `tpe`, if it is a pointer or a managed reference.
+fn pointee(tpe: &Type) -> Option<&Type> {
+    match tpe {
+        Type::Ptr(inner) | Type::ManagedReference(inner) => Some(inner),
+        _ => None,
+    }
+}
+/// Emulates the native functions called via PInvoke.
+fn call_native(
+    state: &mut InterpreterState,
+    site: &CallSite,
+    args: &[Value],
+) -> Result<Value, Exception> {
+    let arg = |idx: usize| {
+        args.get(idx)
+            .ok_or_else(|| Exception::UnsupportedExtern(site.clone()))
+    };
+    let ret = site.signature().output();
+    let ok = |val: i64| Value::I64(val).convert_to(ret);
+    match site.name() {
+        "malloc" => Ok(Value::USize(state.alloc(arg(0)?.as_usize()?))),
+        "calloc" => {
+            let size = arg(0)?.as_usize()? * arg(1)?.as_usize()?;
+            Ok(Value::USize(state.alloc(size)))
+        }
+        "realloc" => Ok(Value::USize(
+            state.realloc(arg(0)?.as_usize()?, arg(1)?.as_usize()?)?,
+        )),
+        "free" => {
+            state.free(arg(0)?.as_usize()?);
+            Ok(Value::Undef)
+        }
+        "memcpy" | "memmove" => {
+            let (dst, src, len) = (
+                arg(0)?.as_usize()?,
+                arg(1)?.as_usize()?,
+                arg(2)?.as_usize()?,
+            );
+            let bytes = state.bytes(src, len)?.to_vec();
+            state.bytes_mut(dst, len)?.copy_from_slice(&bytes);
+            Ok(Value::USize(dst))
+        }
+        "memset" => {
+            let (dst, val, len) = (arg(0)?.as_usize()?, arg(1)?.as_i64()?, arg(2)?.as_usize()?);
+            state.bytes_mut(dst, len)?.fill(val as u8);
+            Ok(Value::USize(dst))
+        }
+        "memcmp" | "bcmp" => {
+            let (a, b, len) = (
+                arg(0)?.as_usize()?,
+                arg(1)?.as_usize()?,
+                arg(2)?.as_usize()?,
+            );
+            let res = match state.bytes(a, len)?.cmp(state.bytes(b, len)?) {
+                std::cmp::Ordering::Less => -1,
+                std::cmp::Ordering::Equal => 0,
+                std::cmp::Ordering::Greater => 1,
+            };
+            ok(res)
+        }
+        "strlen" => Ok(Value::USize(read_c_str(state, arg(0)?.as_usize()?)?.len())),
+        "write" => {
+            let (fd, buf, len) = (arg(0)?.as_i64()?, arg(1)?.as_usize()?, arg(2)?.as_usize()?);
+            let bytes = state.bytes(buf, len)?.to_vec();
+            let written = match fd {
+                1 => std::io::stdout().write(&bytes),
+                2 => std::io::stderr().write(&bytes),
+                _ => return ok(-1),
+            };
+            ok(written.map_or(-1, |len| len as i64))
+        }
+        "puts" => {
+            let string = read_c_str(state, arg(0)?.as_usize()?)?;
+            let mut stdout = std::io::stdout();
+            let res = stdout
+                .write_all(&string)
+                .and_then(|()| stdout.write_all(b"\n"));
+            ok(if res.is_ok() { 0 } else { -1 })
+        }
+        "getrandom" => {
+            let (buf, len) = (arg(0)?.as_usize()?, arg(1)?.as_usize()?);
+            // Interpreted programs should be reproducible, so the randomness is deterministic.
+            let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+            for byte in state.bytes_mut(buf, len)? {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                *byte = seed as u8;
+            }
+            ok(len as i64)
+        }
+        "getenv" => Ok(Value::USize(0)),
+        "abort" => Err(Exception::Exit(134)),
+        "exit" | "_exit" => Err(Exception::Exit(arg(0)?.as_i32()?)),
+        "pthread_attr_init"
+        | "pthread_attr_destroy"
+        | "pthread_detach"
+        | "pthread_atfork"
+        | "pthread_attr_setstacksize"
+        | "sched_yield" => ok(0),
+        "pthread_self" => Ok(Value::USize(1)),
+        "mcheck" | "mcheck_check_all" => ok(0),
+        _ => Err(Exception::UnsupportedExtern(site.clone())),
+    }
+}
+/// Emulates the operators of `System.Int128` and `System.UInt128`.
+fn call_int128(site: &CallSite, args: &[Value]) -> Result<Value, Exception> {
+    let class = site.class().expect("Int128 methods always have a class");
+    let signed = int128_kind(class).ok_or_else(|| Exception::UnsupportedExtern(site.clone()))?;
+    let inputs = site.signature().inputs();
+    let arg = |idx: usize| -> Result<u128, Exception> {
+        args.get(idx)
+            .ok_or_else(|| Exception::UnsupportedExtern(site.clone()))?
+            .as_u128()
+    };
+    let wrap = |val: u128| {
+        if signed {
+            Value::I128(val as i128)
+        } else {
+            Value::U128(val)
+        }
+    };
+    let cmp = |a: u128, b: u128| {
+        if signed {
+            (a as i128).cmp(&(b as i128))
+        } else {
+            a.cmp(&b)
+        }
+    };
+    Ok(match site.name() {
+        "op_Implicit" | "op_Explicit" => {
+            let val = args
+                .first()
+                .ok_or_else(|| Exception::UnsupportedExtern(site.clone()))?;
+            // Unsigned integers are zero extended, and not sign extended.
+            let val = match inputs.first() {
+                Some(
+                    Type::U8 | Type::U16 | Type::U32 | Type::U64 | Type::USize | Type::DotnetChar,
+                ) => Value::U128(u128::from(val.as_u64()?)),
+                _ => val.clone(),
+            };
+            val.convert_to(site.signature().output())?
+        }
+        "op_Addition" => wrap(arg(0)?.wrapping_add(arg(1)?)),
+        "op_Subtraction" => wrap(arg(0)?.wrapping_sub(arg(1)?)),
+        "op_Multiply" => wrap(arg(0)?.wrapping_mul(arg(1)?)),
+        "op_Division" | "op_Modulus" => {
+            let (a, b) = (arg(0)?, arg(1)?);
+            if b == 0 {
+                return Err(managed_exception(
+                    "System.DivideByZeroException",
+                    "Attempted to divide by zero.",
+                ));
+            }
+            let div = site.name() == "op_Division";
+            match (signed, div) {
+                (true, true) => wrap((a as i128).wrapping_div(b as i128) as u128),
+                (true, false) => wrap((a as i128).wrapping_rem(b as i128) as u128),
+                (false, true) => wrap(a / b),
+                (false, false) => wrap(a % b),
+            }
+        }
+        "op_BitwiseAnd" => wrap(arg(0)? & arg(1)?),
+        "op_BitwiseOr" => wrap(arg(0)? | arg(1)?),
+        "op_ExclusiveOr" => wrap(arg(0)? ^ arg(1)?),
+        "op_OnesComplement" => wrap(!arg(0)?),
+        "op_UnaryNegation" => wrap(arg(0)?.wrapping_neg()),
+        "op_LeftShift" => wrap(arg(0)?.wrapping_shl(arg(1)? as u32)),
+        "op_RightShift" if signed => wrap((arg(0)? as i128).wrapping_shr(arg(1)? as u32) as u128),
+        "op_RightShift" | "op_UnsignedRightShift" => wrap(arg(0)?.wrapping_shr(arg(1)? as u32)),
+        "op_Equality" => Value::bool(arg(0)? == arg(1)?),
+        "op_Inequality" => Value::bool(arg(0)? != arg(1)?),
+        "op_LessThan" => Value::bool(cmp(arg(0)?, arg(1)?).is_lt()),
+        "op_LessThanOrEqual" => Value::bool(cmp(arg(0)?, arg(1)?).is_le()),
+        "op_GreaterThan" => Value::bool(cmp(arg(0)?, arg(1)?).is_gt()),
+        "op_GreaterThanOrEqual" => Value::bool(cmp(arg(0)?, arg(1)?).is_ge()),
+        "PopCount" => wrap(u128::from(arg(0)?.count_ones())),
+        "LeadingZeroCount" => wrap(u128::from(arg(0)?.leading_zeros())),
+        "TrailingZeroCount" => wrap(u128::from(arg(0)?.trailing_zeros())),
+        "RotateLeft" => wrap(arg(0)?.rotate_left(arg(1)? as u32)),
+        "RotateRight" => wrap(arg(0)?.rotate_right(arg(1)? as u32)),
+        _ => return Err(Exception::UnsupportedExtern(site.clone())),
+    })
+}
+/// Returns the bit width of an integer type.
+fn int_bits(tpe: &Type) -> Option<u32> {
+    match tpe {
+        Type::I8 | Type::U8 | Type::Bool => Some(8),
+        Type::I16 | Type::U16 | Type::DotnetChar => Some(16),
+        Type::I32 | Type::U32 => Some(32),
+        Type::I64 | Type::U64 | Type::ISize | Type::USize | Type::Ptr(_) => Some(64),
+        Type::I128 | Type::U128 => Some(128),
+        Type::DotnetType(tpe) if int128_kind(tpe).is_some() => Some(128),
+        _ => None,
+    }
+}
+/// Calls a unary float function, computing it in the precision of the return type.
+fn float_fn(
+    args: &[Value],
+    ret: &Type,
+    f32_fn: impl Fn(f32) -> f32,
+    f64_fn: impl Fn(f64) -> f64,
+) -> Result<Value, Exception> {
+    let val = args
+        .first()
+        .ok_or_else(|| Exception::InvalidOperand("Missing float argument".into()))?
+        .as_f64()?;
+    Ok(match ret {
+        Type::F32 => Value::F32(f32_fn(val as f32)),
+        _ => Value::F64(f64_fn(val)),
+    })
+}
+/// Calls a binary float function, computing it in the precision of the return type.
+fn float_fn2(
+    args: &[Value],
+    ret: &Type,
+    f32_fn: impl Fn(f32, f32) -> f32,
+    f64_fn: impl Fn(f64, f64) -> f64,
+) -> Result<Value, Exception> {
+    let (a, b) = match args {
+        [a, b] => (a.as_f64()?, b.as_f64()?),
+        _ => {
+            return Err(Exception::InvalidOperand(
+                "Expected 2 float arguments".into(),
+            ))
+        }
+    };
+    Ok(match ret {
+        Type::F32 => Value::F32(f32_fn(a as f32, b as f32)),
+        _ => Value::F64(f64_fn(a, b)),
+    })
+}
+/// Formats a value the way `Console.Write` would.
+fn display(val: &Value) -> String {
+    match val {
+        Value::String(string) => string.to_string(),
+        Value::I32(val) => val.to_string(),
+        Value::I64(val) => val.to_string(),
+        Value::USize(val) => val.to_string(),
+        Value::F32(val) => val.to_string(),
+        Value::F64(val) => val.to_string(),
+        Value::I128(val) => val.to_string(),
+        Value::U128(val) => val.to_string(),
+        Value::Null | Value::Undef => String::new(),
+        _ => format!("{val:?}"),
+    }
+}
+/// Emulates the methods of the .NET standard library.
+fn call_dotnet(
+    state: &mut InterpreterState,
+    class: &str,
+    site: &CallSite,
+    args: &[Value],
+) -> Result<Value, Exception> {
+    let arg = |idx: usize| {
+        args.get(idx)
+            .ok_or_else(|| Exception::UnsupportedExtern(site.clone()))
+    };
+    let inputs = site.signature().inputs();
+    let ret = site.signature().output();
+    match (class, site.name()) {
+        ("System.Console", "Write" | "WriteLine") => {
+            let mut out = args.first().map(display).unwrap_or_default();
+            if site.name() == "WriteLine" {
+                out.push('\n');
+            }
+            print!("{out}");
+            Ok(Value::Undef)
+        }
+        ("System.Environment", "GetCommandLineArgs") => Ok(Value::StringArray(
+            std::env::args().skip(1).map(Into::into).collect(),
+        )),
+        ("System.Environment", "Exit") => Err(Exception::Exit(arg(0)?.as_i32()?)),
+        ("System.Runtime.InteropServices.Marshal", "AllocHGlobal")
+        | ("System.Runtime.InteropServices.NativeMemory", "Alloc" | "AlignedAlloc") => {
+            Ok(Value::USize(state.alloc(arg(0)?.as_usize()?)))
+        }
+        ("System.Runtime.InteropServices.Marshal", "ReAllocHGlobal")
+        | ("System.Runtime.InteropServices.NativeMemory", "Realloc" | "AlignedRealloc") => Ok(
+            Value::USize(state.realloc(arg(0)?.as_usize()?, arg(1)?.as_usize()?)?),
+        ),
+        ("System.Runtime.InteropServices.Marshal", "FreeHGlobal")
+        | ("System.Runtime.InteropServices.NativeMemory", "Free" | "AlignedFree") => {
+            state.free(arg(0)?.as_usize()?);
+            Ok(Value::Undef)
+        }
+        ("System.Runtime.InteropServices.Marshal", "StringToCoTaskMemUTF8") => {
+            let string = arg(0)?.as_string()?.to_owned();
+            let ptr = state.alloc(string.len() + 1);
+            state
+                .bytes_mut(ptr, string.len())?
+                .copy_from_slice(string.as_bytes());
+            Ok(Value::USize(ptr))
+        }
+        ("System.Double" | "System.Single", "IsNaN") => Ok(Value::bool(arg(0)?.as_f64()?.is_nan())),
+        ("System.Double" | "System.Single", "IsInfinity") => {
+            Ok(Value::bool(arg(0)?.as_f64()?.is_infinite()))
+        }
+        ("System.Math" | "System.MathF", name) => match name {
+            "Sqrt" => float_fn(args, ret, f32::sqrt, f64::sqrt),
+            "Floor" => float_fn(args, ret, f32::floor, f64::floor),
+            "Ceiling" => float_fn(args, ret, f32::ceil, f64::ceil),
+            "Truncate" => float_fn(args, ret, f32::trunc, f64::trunc),
+            "Round" => float_fn(args, ret, f32::round_ties_even, f64::round_ties_even),
+            "Abs" if matches!(ret, Type::F32 | Type::F64) => {
+                float_fn(args, ret, f32::abs, f64::abs)
+            }
+            "Exp" => float_fn(args, ret, f32::exp, f64::exp),
+            "Log" => float_fn(args, ret, f32::ln, f64::ln),
+            "Log2" => float_fn(args, ret, f32::log2, f64::log2),
+            "Log10" => float_fn(args, ret, f32::log10, f64::log10),
+            "Sin" => float_fn(args, ret, f32::sin, f64::sin),
+            "Cos" => float_fn(args, ret, f32::cos, f64::cos),
+            "Tan" => float_fn(args, ret, f32::tan, f64::tan),
+            "Pow" => float_fn2(args, ret, f32::powf, f64::powf),
+            "Min" if matches!(ret, Type::F32 | Type::F64) => {
+                float_fn2(args, ret, f32::min, f64::min)
+            }
+            "Max" if matches!(ret, Type::F32 | Type::F64) => {
+                float_fn2(args, ret, f32::max, f64::max)
+            }
+            "CopySign" => float_fn2(args, ret, f32::copysign, f64::copysign),
+            "FusedMultiplyAdd" => match args {
+                [a, b, c] => {
+                    let (a, b, c) = (a.as_f64()?, b.as_f64()?, c.as_f64()?);
+                    Ok(match ret {
+                        Type::F32 => Value::F32((a as f32).mul_add(b as f32, c as f32)),
+                        _ => Value::F64(a.mul_add(b, c)),
+                    })
+                }
+                _ => Err(Exception::UnsupportedExtern(site.clone())),
+            },
+            _ => Err(Exception::UnsupportedExtern(site.clone())),
+        },
+        (
+            "System.Numerics.BitOperations",
+            "PopCount" | "LeadingZeroCount" | "TrailingZeroCount" | "RotateLeft" | "RotateRight",
+        ) => {
+            let bits = inputs
+                .first()
+                .and_then(int_bits)
+                .ok_or_else(|| Exception::UnsupportedExtern(site.clone()))?;
+            let mask = if bits == 64 {
+                u64::MAX
+            } else {
+                (1 << bits) - 1
+            };
+            let val = arg(0)?.as_u64()? & mask;
+            let res = match site.name() {
+                "PopCount" => u64::from(val.count_ones()),
+                "LeadingZeroCount" => u64::from(val.leading_zeros() - (64 - bits)),
+                "TrailingZeroCount" => u64::from(val.trailing_zeros().min(bits)),
+                "RotateLeft" if bits == 32 => {
+                    u64::from((val as u32).rotate_left(arg(1)?.as_i32()? as u32))
+                }
+                "RotateRight" if bits == 32 => {
+                    u64::from((val as u32).rotate_right(arg(1)?.as_i32()? as u32))
+                }
+                "RotateLeft" => val.rotate_left(arg(1)?.as_i32()? as u32),
+                _ => val.rotate_right(arg(1)?.as_i32()? as u32),
+            };
+            Value::U128(u128::from(res)).convert_to(ret)
+        }
+        ("System.Buffers.Binary.BinaryPrimitives", "ReverseEndianness") => {
+            let bits = inputs
+                .first()
+                .and_then(int_bits)
+                .ok_or_else(|| Exception::UnsupportedExtern(site.clone()))?;
+            let val = arg(0)?.as_u128()?;
+            let swapped = val.swap_bytes() >> (128 - bits);
+            Value::U128(swapped).convert_to(ret)
+        }
+        ("System.Threading.Interlocked", "MemoryBarrier") => Ok(Value::Undef),
+        ("System.Threading.Interlocked", name) => {
+            let tpe = inputs
+                .first()
+                .and_then(pointee)
+                .ok_or_else(|| Exception::UnsupportedExtern(site.clone()))?;
+            let addr = arg(0)?.as_usize()?;
+            let old = state.load(addr, tpe)?;
+            match name {
+                "Exchange" => state.store(addr, tpe, arg(1)?)?,
+                "CompareExchange" => {
+                    if crate::compare(&old, arg(2)?, true)? == Some(std::cmp::Ordering::Equal) {
+                        state.store(addr, tpe, arg(1)?)?;
+                    }
+                }
+                "Add" | "Increment" | "Decrement" => {
+                    let delta = match name {
+                        "Add" => arg(1)?.clone(),
+                        "Increment" => Value::I32(1),
+                        _ => Value::I32(-1),
+                    };
+                    let new = crate::arith(&old, &delta, crate::ArithOp::Add)?;
+                    state.store(addr, tpe, &new)?;
+                    // `Interlocked.Add` returns the new value.
+                    return Ok(new);
+                }
+                _ => return Err(Exception::UnsupportedExtern(site.clone())),
+            }
+            Ok(old)
+        }
+        ("System.Type", "GetTypeFromHandle") => Ok(arg(0)?.clone()),
+        ("System.Object", "GetHashCode") => {
+            use std::hash::{Hash, Hasher};
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            format!("{:?}", arg(0)?).hash(&mut hasher);
+            Ok(Value::I32(hasher.finish() as i32))
+        }
+        ("System.Threading.Thread", "Yield") => Ok(Value::bool(true)),
+        _ => Err(Exception::UnsupportedExtern(site.clone())),
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use cilly::{asm::Assembly, DotnetTypeRef, IString, Type};
+
+use crate::Exception;
+
+/// The memory layout of a type defined in the assembly.
+#[derive(Debug)]
+pub struct TypeLayout {
+    pub size: u64,
+    pub align: u64,
+    /// Name, offset and type of each field.
+    pub fields: Vec<(IString, u64, Type)>,
+}
+/// Computes and caches the layouts of types. Layouts are computed just like the .NET runtime would: fields with
+/// explicit offsets are placed there, while sequential types place each field at the next suitably aligned offset.
+pub struct Layouts<'asm> {
+    asm: &'asm Assembly,
+    cache: HashMap<IString, std::rc::Rc<TypeLayout>>,
+}
+/// Returns true if a value of this type is an object reference.
+pub fn is_object_ref(tpe: &Type) -> bool {
+    match tpe {
+        Type::DotnetType(tpe) => !tpe.is_valuetype(),
+        Type::ManagedArray { .. } => true,
+        _ => false,
+    }
+}
+/// Returns true if this is the `System.Int128` or `System.UInt128` type.
+pub fn int128_kind(tpe: &DotnetTypeRef) -> Option<bool> {
+    if tpe.asm() != Some("System.Runtime") {
+        return None;
+    }
+    match tpe.name_path() {
+        "System.Int128" => Some(true),
+        "System.UInt128" => Some(false),
+        _ => None,
+    }
+}
+impl<'asm> Layouts<'asm> {
+    pub fn new(asm: &'asm Assembly) -> Self {
+        Self {
+            asm,
+            cache: HashMap::new(),
+        }
+    }
+    /// Returns the size and aligement of `tpe`.
+    pub fn size_align(&mut self, tpe: &Type) -> Result<(u64, u64), Exception> {
+        Ok(match tpe {
+            Type::Void => (0, 1),
+            Type::Bool | Type::I8 | Type::U8 => (1, 1),
+            Type::I16 | Type::U16 | Type::DotnetChar | Type::F16 => (2, 2),
+            Type::I32 | Type::U32 | Type::F32 => (4, 4),
+            Type::I64 | Type::U64 | Type::F64 => (8, 8),
+            Type::ISize
+            | Type::USize
+            | Type::Ptr(_)
+            | Type::ManagedReference(_)
+            | Type::DelegatePtr(_)
+            | Type::ManagedArray { .. } => (8, 8),
+            Type::I128 | Type::U128 | Type::F128 => (16, 16),
+            // Zero-sized types still take up a byte in .NET.
+            Type::Unresolved | Type::Foreign => (1, 1),
+            Type::FnDef(name) => {
+                let tref = DotnetTypeRef::new::<&str, _>(None, &format!("fn_{name}") as &str);
+                if self.asm.get_typedef_by_path(tref.name_path()).is_some() {
+                    let layout = self.layout(&tref)?;
+                    (layout.size, layout.align)
+                } else {
+                    (1, 1)
+                }
+            }
+            Type::DotnetType(tpe) => {
+                if !tpe.is_valuetype() {
+                    (8, 8)
+                } else if int128_kind(tpe).is_some() {
+                    (16, 16)
+                } else {
+                    let layout = self.layout(tpe)?;
+                    (layout.size, layout.align)
+                }
+            }
+            Type::GenericArg(_) | Type::CallGenericArg(_) | Type::MethodGenericArg(_) => {
+                return Err(Exception::UnsupportedType(tpe.clone()))
+            }
+        })
+    }
+    pub fn size_of(&mut self, tpe: &Type) -> Result<u64, Exception> {
+        self.size_align(tpe).map(|(size, _)| size)
+    }
+    /// Returns the layout of a type defined in this assembly.
+    pub fn layout(&mut self, tpe: &DotnetTypeRef) -> Result<std::rc::Rc<TypeLayout>, Exception> {
+        if let Some(layout) = self.cache.get(tpe.name_path()) {
+            return Ok(layout.clone());
+        }
+        if tpe.asm().is_some() {
+            return Err(Exception::UnsupportedType(Type::DotnetType(Box::new(
+                tpe.clone(),
+            ))));
+        }
+        let Some(type_def) = self.asm.get_typedef_by_path(tpe.name_path()) else {
+            return Err(Exception::TypeNotFound(tpe.name_path().into()));
+        };
+        let mut fields = Vec::with_capacity(type_def.fields().len());
+        let mut align = 1;
+        let mut end: u64 = 0;
+        for (idx, (name, field_tpe)) in type_def.fields().iter().enumerate() {
+            let (field_size, field_align) = self.size_align(field_tpe)?;
+            let offset = match type_def.explicit_offsets() {
+                Some(offsets) => u64::from(offsets[idx]),
+                None => end.next_multiple_of(field_align),
+            };
+            align = align.max(field_align);
+            end = end.max(offset + field_size);
+            fields.push((name.clone(), offset, field_tpe.clone()));
+        }
+        let size = match type_def.explict_size() {
+            Some(size) => size.get(),
+            None => end.next_multiple_of(align).max(1),
+        };
+        let layout = std::rc::Rc::new(TypeLayout {
+            size,
+            align,
+            fields,
+        });
+        self.cache.insert(tpe.name_path().into(), layout.clone());
+        Ok(layout)
+    }
+    /// Returns the offset of field `name` in type `owner`.
+    pub fn field_offset(&mut self, owner: &DotnetTypeRef, name: &str) -> Result<u64, Exception> {
+        let layout = self.layout(owner)?;
+        layout
+            .fields
+            .iter()
+            .find(|(field_name, _, _)| field_name.as_ref() == name)
+            .map(|(_, offset, _)| *offset)
+            .ok_or_else(|| Exception::FieldNotFound(owner.name_path().into(), name.into()))
+    }
+}
@@ -1,613 +1,1273 @@
-#![feature(iterator_try_collect)]
 use std::{collections::HashMap, io::Write};
 
 use cilly::{
     asm::Assembly,
+    basic_block::{BasicBlock, Handler},
     call_site::CallSite,
     cil_node::CILNode,
     cil_root::{CILRoot, SFI},
-    field_desc,
     method::Method,
     static_field_desc::StaticFieldDescriptor,
-    FnSig, IString, Type,
+    IString, Type,
 };
+mod externs;
+mod layout;
 mod value;
+use layout::{int128_kind, is_object_ref, Layouts};
 use value::Value;
+// The fields are only read trough `Debug`, when reporting errors.
+#[allow(dead_code)]
 #[derive(Debug)]
 enum Exception {
     MethodNotFound(CallSite),
-    LocalOutOfRange { loc: usize, lcount: usize },
-    ArgOutOfRange { arg: usize, lcount: usize },
+    LocalOutOfRange {
+        loc: usize,
+        lcount: usize,
+    },
+    ArgOutOfRange {
+        arg: usize,
+        lcount: usize,
+    },
     AllocOffsetOutOfRange,
+    /// A pointer which does not point into any live allocation was dereferenced.
+    InvalidPointer(usize),
+    /// An instruction got operands it can't operate on.
+    InvalidOperand(String),
+    UnsupportedType(Type),
+    TypeNotFound(IString),
+    FieldNotFound(IString, IString),
+    /// A call to a method which is neither defined in the assembly, nor emulated by the interpreter.
+    UnsupportedExtern(CallSite),
+    /// A branch to a block which does not exist.
+    InvalidTarget {
+        target: u32,
+        sub_target: u32,
+    },
+    /// A managed exception, thrown by the interpreted program.
+    Thrown(Value),
+    /// The interpreted program called `exit`.
+    Exit(i32),
+}
+/// Creates a managed exception of class `class`.
+fn managed_exception(class: &str, message: &str) -> Exception {
+    Exception::Thrown(Value::Exception {
+        class: class.into(),
+        message: message.into(),
+    })
 }
 type AllocID = u32;
+/// Allocation IDs at or above this value represent function pointers.
+const FN_PTR_BASE: AllocID = 1 << 31;
+/// Pointers are encoded as native ints: the upper 32 bits are the allocation ID, the lower 32 bits are the offset.
+fn encode_ptr(alloc: AllocID, offset: u32) -> usize {
+    ((alloc as usize) << 32) | offset as usize
+}
+fn decode_ptr(ptr: usize) -> (AllocID, u32) {
+    ((ptr >> 32) as AllocID, ptr as u32)
+}
+/// What should happen after a root is executed.
+enum Flow {
+    Next,
+    Jump { target: u32, sub_target: u32 },
+    Return(Value),
+}
+/// A single method invocation.
+struct Frame<'asm> {
+    method: &'asm Method,
+    /// Index of the current block, within the method.
+    block: usize,
+    /// Index of the current handler block, if the exception handler of `block` is running.
+    handler: Option<usize>,
+    tree: usize,
+    sfi: Option<&'asm SFI>,
+    /// Addresses and types of the arguments.
+    args: Vec<(usize, &'asm Type)>,
+    /// Addresses and types of the locals.
+    locals: Vec<(usize, &'asm Type)>,
+    /// Allocations freed once this frame returns(locals, arguments and `localloc`ed buffers).
+    allocs: Vec<AllocID>,
+    /// Addresses and types of the temporary locals currently in scope.
+    tmps: Vec<(usize, &'asm Type)>,
+    /// Values currently inspected by `InspectValue`.
+    inspected: Vec<Value>,
+    /// The exception being handled, used by `rethrow`.
+    caught: Option<Value>,
+}
+impl<'asm> Frame<'asm> {
+    fn block(&self) -> &'asm BasicBlock {
+        &self.method.blocks()[self.block]
+    }
+    /// Returns the trees of the block which is currently executed.
+    fn trees(&self) -> Result<&'asm [cilly::cil_tree::CILTree], Exception> {
+        match self.handler {
+            None => Ok(self.block().trees()),
+            Some(handler) => Ok(handler_blocks(self.block())?[handler].trees()),
+        }
+    }
+}
+fn handler_blocks(block: &BasicBlock) -> Result<&[BasicBlock], Exception> {
+    match block.handler() {
+        Some(Handler::Blocks(blocks)) => Ok(blocks),
+        Some(Handler::RawID(_)) => Err(Exception::InvalidOperand(format!(
+            "Unresolved exception handler in block {}",
+            block.id()
+        ))),
+        None => Ok(&[]),
+    }
+}
 struct InterpreterState<'asm> {
     asm: &'asm Assembly,
-    call_stack: Vec<(&'asm CallSite, usize, usize, cilly::cil_root::SFI)>,
-    locals: Vec<Box<[Value]>>,
+    call_stack: Vec<Frame<'asm>>,
     mem: HashMap<AllocID, Box<[u8]>>,
     last_alloc: AllocID,
+    /// Objects referenced from memory. A handle stored in memory is an index into this vector, plus one(0 is null).
+    objects: Vec<Value>,
+    layouts: Layouts<'asm>,
     fields: HashMap<StaticFieldDescriptor, Value>,
     methods: HashMap<AllocID, CallSite>,
     inv_methods: HashMap<CallSite, AllocID>,
     last_alloc_method: AllocID,
+    const_values: HashMap<u128, usize>,
+    /// Backtrace of the last error, recorded as the call stack unwinds.
+    trace: Vec<String>,
+}
+/// Integer operands, promoted to the same evaluation stack type.
+enum IntPair {
+    I32(i32, i32),
+    I64(i64, i64),
+    USize(usize, usize),
+}
+fn int_pair(a: &Value, b: &Value) -> Result<IntPair, Exception> {
+    Ok(match (a, b) {
+        (Value::I32(a), Value::I32(b)) => IntPair::I32(*a, *b),
+        (Value::I64(_), Value::I64(_) | Value::I32(_)) | (Value::I32(_), Value::I64(_)) => {
+            IntPair::I64(a.as_i64()?, b.as_i64()?)
+        }
+        (
+            Value::USize(_) | Value::I32(_) | Value::I64(_),
+            Value::USize(_) | Value::I32(_) | Value::I64(_),
+        ) => IntPair::USize(a.as_usize()?, b.as_usize()?),
+        _ => {
+            return Err(Exception::InvalidOperand(format!(
+                "Can't operate on {a:?} and {b:?}"
+            )))
+        }
+    })
+}
+#[derive(Clone, Copy)]
+enum ArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    DivUn,
+    Rem,
+    RemUn,
+    And,
+    Or,
+    XOr,
+}
+macro_rules! int_arith {
+    ($op:ident, $a:ident, $b:ident, $signed:ty, $unsigned:ty) => {{
+        let (a, b) = ($a as $signed, $b as $signed);
+        let needs_nonzero = matches!(
+            $op,
+            ArithOp::Div | ArithOp::DivUn | ArithOp::Rem | ArithOp::RemUn
+        );
+        if needs_nonzero && b == 0 {
+            return Err(managed_exception(
+                "System.DivideByZeroException",
+                "Attempted to divide by zero.",
+            ));
+        }
+        match $op {
+            ArithOp::Add => a.wrapping_add(b),
+            ArithOp::Sub => a.wrapping_sub(b),
+            ArithOp::Mul => a.wrapping_mul(b),
+            ArithOp::Div => a.wrapping_div(b),
+            ArithOp::DivUn => ((a as $unsigned) / (b as $unsigned)) as $signed,
+            ArithOp::Rem => a.wrapping_rem(b),
+            ArithOp::RemUn => ((a as $unsigned) % (b as $unsigned)) as $signed,
+            ArithOp::And => a & b,
+            ArithOp::Or => a | b,
+            ArithOp::XOr => a ^ b,
+        }
+    }};
+}
+fn arith(a: &Value, b: &Value, op: ArithOp) -> Result<Value, Exception> {
+    if a.is_float() || b.is_float() {
+        let float_op = |a: f64, b: f64| match op {
+            ArithOp::Add => Ok(a + b),
+            ArithOp::Sub => Ok(a - b),
+            ArithOp::Mul => Ok(a * b),
+            ArithOp::Div => Ok(a / b),
+            ArithOp::Rem => Ok(a % b),
+            _ => Err(Exception::InvalidOperand(format!(
+                "Can't use a bitwise or unsigned op on floats {a:?} and {b:?}"
+            ))),
+        };
+        return match (a, b) {
+            // f32 ops are computed in single precision, to get the same rounding.
+            (Value::F32(a), Value::F32(b)) => Ok(Value::F32(match op {
+                ArithOp::Add => a + b,
+                ArithOp::Sub => a - b,
+                ArithOp::Mul => a * b,
+                ArithOp::Div => a / b,
+                ArithOp::Rem => a % b,
+                _ => float_op(f64::from(*a), f64::from(*b))? as f32,
+            })),
+            _ => Ok(Value::F64(float_op(a.as_f64()?, b.as_f64()?)?)),
+        };
+    }
+    Ok(match int_pair(a, b)? {
+        IntPair::I32(a, b) => Value::I32(int_arith!(op, a, b, i32, u32)),
+        IntPair::I64(a, b) => Value::I64(int_arith!(op, a, b, i64, u64)),
+        IntPair::USize(a, b) => Value::USize(int_arith!(op, a, b, isize, usize) as usize),
+    })
+}
+#[derive(Clone, Copy)]
+enum ShiftOp {
+    Shl,
+    Shr,
+    ShrUn,
+}
+fn shift(val: &Value, amount: &Value, op: ShiftOp) -> Result<Value, Exception> {
+    let amount = amount.as_i64()? as u32;
+    Ok(match val {
+        Value::I32(val) => Value::I32(match op {
+            ShiftOp::Shl => val.wrapping_shl(amount),
+            ShiftOp::Shr => val.wrapping_shr(amount),
+            ShiftOp::ShrUn => (*val as u32).wrapping_shr(amount) as i32,
+        }),
+        Value::I64(val) => Value::I64(match op {
+            ShiftOp::Shl => val.wrapping_shl(amount),
+            ShiftOp::Shr => val.wrapping_shr(amount),
+            ShiftOp::ShrUn => (*val as u64).wrapping_shr(amount) as i64,
+        }),
+        Value::USize(val) => Value::USize(match op {
+            ShiftOp::Shl => val.wrapping_shl(amount),
+            ShiftOp::Shr => (*val as isize).wrapping_shr(amount) as usize,
+            ShiftOp::ShrUn => val.wrapping_shr(amount),
+        }),
+        _ => return Err(Exception::InvalidOperand(format!("Can't shift {val:?}"))),
+    })
+}
+/// Compares two values. Returns `None` if they are unordered(at least one of them is NaN).
+fn compare(a: &Value, b: &Value, signed: bool) -> Result<Option<std::cmp::Ordering>, Exception> {
+    if a.is_float() || b.is_float() {
+        return Ok(a.as_f64()?.partial_cmp(&b.as_f64()?));
+    }
+    if !matches!(a, Value::I32(_) | Value::I64(_) | Value::USize(_))
+        || !matches!(b, Value::I32(_) | Value::I64(_) | Value::USize(_))
+    {
+        // Object references can only be checked for equality.
+        return Ok(if a == b {
+            Some(std::cmp::Ordering::Equal)
+        } else {
+            None
+        });
+    }
+    Ok(Some(match (int_pair(a, b)?, signed) {
+        (IntPair::I32(a, b), true) => a.cmp(&b),
+        (IntPair::I32(a, b), false) => (a as u32).cmp(&(b as u32)),
+        (IntPair::I64(a, b), true) => a.cmp(&b),
+        (IntPair::I64(a, b), false) => (a as u64).cmp(&(b as u64)),
+        (IntPair::USize(a, b), true) => (a as isize).cmp(&(b as isize)),
+        (IntPair::USize(a, b), false) => a.cmp(&b),
+    }))
 }
-
 fn eval_node<'asm>(
     node: &'asm CILNode,
     state: &mut InterpreterState<'asm>,
-    args: &mut Box<[Value]>,
 ) -> Result<Value, Exception> {
-    assert_eq!(state.locals.len(), state.call_stack.len());
     match node {
-        CILNode::LdcU64(val) => Ok(Value::U64(*val)),
+        CILNode::LdcU64(val) => Ok(Value::I64(*val as i64)),
         CILNode::LdcI64(val) => Ok(Value::I64(*val)),
-        CILNode::LdcU32(val) => Ok(Value::U32(*val)),
+        CILNode::LdcU32(val) => Ok(Value::I32(*val as i32)),
         CILNode::LdcI32(val) => Ok(Value::I32(*val)),
         CILNode::LdcF64(val) => Ok(Value::F64(*val)),
         CILNode::LdcF32(val) => Ok(Value::F32(*val)),
-        CILNode::LdFalse => Ok(Value::Bool(false)),
-        CILNode::LdTrue => Ok(Value::Bool(true)),
-        CILNode::SizeOf(tpe) => match tpe.as_ref() {
-            Type::USize | Type::ISize => Ok(Value::I32(8)),
-            _ => todo!("Can't yet calc sizeof {tpe:?}"),
-        },
-        CILNode::TransmutePtr { val, new_ptr: _ } => match eval_node(val, state, args)? {
-            Value::Ptr(alloc_id, offset) => Ok(Value::Ptr(alloc_id, offset)),
-            _ => todo!("Can't transmute {val:?} to pointer."),
-        },
-        CILNode::Call {
-            args: call_args,
-            site,
+        CILNode::LdFalse => Ok(Value::bool(false)),
+        CILNode::LdTrue => Ok(Value::bool(true)),
+        CILNode::LdStr(string) => Ok(Value::String(string.clone())),
+        CILNode::SizeOf(tpe) => Ok(Value::I32(state.layouts.size_of(tpe)? as i32)),
+        CILNode::TransmutePtr { val, new_ptr: _ } => eval_node(val, state),
+        CILNode::BlackBox(val) | CILNode::MRefToRawPtr(val) => eval_node(val, state),
+        CILNode::Call { args, site } | CILNode::CallVirt { args, site } => {
+            let args = eval_args(args, state)?;
+            state.call(site, args)
         }
-        | CILNode::CallVirt {
-            args: call_args,
-            site,
+        CILNode::NewObj { args, site } => {
+            let args = eval_args(args, state)?;
+            state.new_obj(site, args)
         }
-        | CILNode::NewObj {
-            args: call_args,
-            site,
-        } => {
-            let mut call_args: Box<[_]> = call_args
-                .iter()
-                .map(|arg| eval_node(arg, state, args).map(|arg| arg.pass_as_arg(state)))
-                .try_collect()?;
-            assert_eq!(state.locals.len(), state.call_stack.len());
-            match state.run(site, &mut call_args) {
-                Ok(val) => Ok(val),
-                Err(Exception::MethodNotFound(_)) => state.try_call_extern(site, &mut call_args),
-                Err(err) => Err(err),
-            }
+        CILNode::CallI(sig_ptr_args) => {
+            let (_sig, fn_ptr, args) = sig_ptr_args.as_ref();
+            let args = eval_args(args, state)?;
+            let fn_ptr = eval_node(fn_ptr, state)?.as_usize()?;
+            state.call_ptr(fn_ptr, args)
         }
+        CILNode::LDFtn(site) => Ok(Value::USize(encode_ptr(state.get_fn_ptr_alloc(site), 0))),
+        CILNode::LDTypeToken(tpe) => Ok(Value::TypeHandle(tpe.clone())),
+        CILNode::Add(a, b) => bin_op(a, b, state, ArithOp::Add),
+        CILNode::Sub(a, b) => bin_op(a, b, state, ArithOp::Sub),
+        CILNode::Mul(a, b) => bin_op(a, b, state, ArithOp::Mul),
+        CILNode::Div(a, b) => bin_op(a, b, state, ArithOp::Div),
+        CILNode::DivUn(a, b) => bin_op(a, b, state, ArithOp::DivUn),
+        CILNode::Rem(a, b) => bin_op(a, b, state, ArithOp::Rem),
+        CILNode::RemUn(a, b) => bin_op(a, b, state, ArithOp::RemUn),
+        CILNode::And(a, b) => bin_op(a, b, state, ArithOp::And),
+        CILNode::Or(a, b) => bin_op(a, b, state, ArithOp::Or),
+        CILNode::XOr(a, b) => bin_op(a, b, state, ArithOp::XOr),
+        CILNode::Shl(a, b) => shift_op(a, b, state, ShiftOp::Shl),
+        CILNode::Shr(a, b) => shift_op(a, b, state, ShiftOp::Shr),
+        CILNode::ShrUn(a, b) => shift_op(a, b, state, ShiftOp::ShrUn),
         CILNode::Eq(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-            match (&a, &b) {
-                (Value::I8(b), Value::I8(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::U8(b), Value::U8(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::I16(b), Value::I16(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::U16(b), Value::U16(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::I32(b), Value::I32(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::U32(b), Value::U32(val)) => Ok(Value::Bool(*b == *val)),
-                (Value::Bool(a), Value::Bool(b)) => Ok(Value::Bool(*a == *b)),
-
-                (Value::Bool(b), Value::I32(val)) => Ok(Value::Bool(*b as u8 as i32 == *val)),
-                (Value::Bool(b), Value::U32(val)) => Ok(Value::Bool(*b as u8 as u32 == *val)),
-                (Value::U16(b), Value::U32(val)) => Ok(Value::Bool(*b as u32 == *val)),
-
-                _ => todo!("Can't yet cmp {a:?} and {b:?}"),
-            }
+            let (a, b) = (eval_node(a, state)?, eval_node(b, state)?);
+            Ok(Value::bool(
+                compare(&a, &b, true)? == Some(std::cmp::Ordering::Equal),
+            ))
         }
-        CILNode::Lt(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-            let res = match (&a, &b) {
-                (Value::I32(a), Value::I32(b)) => Ok(Value::Bool(*a < *b)),
-                (Value::F32(a), Value::F32(b)) => Ok(Value::Bool(*a < *b)),
-                _ => todo!("Can't yet lt {a:?} and {b:?}"),
-            };
-            res
-        }
-        CILNode::Shr(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-            match (&a, &b) {
-                (Value::I16(a), Value::U32(b)) => Ok(Value::I32((*a as i32) << b)),
-                _ => todo!("Can't yet shift right {a:?} by {b:?}"),
-            }
+        CILNode::Lt(a, b) => cmp_op(a, b, state, true, |ord| {
+            ord == Some(std::cmp::Ordering::Less)
+        }),
+        CILNode::Gt(a, b) => cmp_op(a, b, state, true, |ord| {
+            ord == Some(std::cmp::Ordering::Greater)
+        }),
+        CILNode::LtUn(a, b) => cmp_op(a, b, state, false, |ord| {
+            ord.map_or(true, |ord| ord == std::cmp::Ordering::Less)
+        }),
+        CILNode::GtUn(a, b) => cmp_op(a, b, state, false, |ord| {
+            ord.map_or(true, |ord| ord == std::cmp::Ordering::Greater)
+        }),
+        CILNode::Neg(val) => match eval_node(val, state)? {
+            Value::I32(val) => Ok(Value::I32(val.wrapping_neg())),
+            Value::I64(val) => Ok(Value::I64(val.wrapping_neg())),
+            Value::USize(val) => Ok(Value::USize(val.wrapping_neg())),
+            Value::F32(val) => Ok(Value::F32(-val)),
+            Value::F64(val) => Ok(Value::F64(-val)),
+            val => Err(Exception::InvalidOperand(format!("Can't neg {val:?}"))),
+        },
+        CILNode::Not(val) => match eval_node(val, state)? {
+            Value::I32(val) => Ok(Value::I32(!val)),
+            Value::I64(val) => Ok(Value::I64(!val)),
+            Value::USize(val) => Ok(Value::USize(!val)),
+            val => Err(Exception::InvalidOperand(format!("Can't not {val:?}"))),
+        },
+        CILNode::ConvI8(val) => eval_node(val, state)?.convert_to(&Type::I8),
+        CILNode::ConvU8(val) => eval_node(val, state)?.convert_to(&Type::U8),
+        CILNode::ConvI16(val) => eval_node(val, state)?.convert_to(&Type::I16),
+        CILNode::ConvU16(val) => eval_node(val, state)?.convert_to(&Type::U16),
+        CILNode::ConvI32(val) => eval_node(val, state)?.convert_to(&Type::I32),
+        CILNode::ConvU32(val) => eval_node(val, state)?.convert_to(&Type::U32),
+        CILNode::ConvI64(val) => eval_node(val, state)?.convert_to(&Type::I64),
+        CILNode::ConvU64(val) => eval_node(val, state)?.convert_to(&Type::U64),
+        CILNode::ConvISize(val) => eval_node(val, state)?.convert_to(&Type::ISize),
+        CILNode::ZeroExtendToUSize(val) | CILNode::ZeroExtendToISize(val) => {
+            eval_node(val, state)?.convert_to(&Type::USize)
         }
-        CILNode::RemUn(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-            match (&a, &b) {
-                (Value::U32(a), Value::U32(b)) => Ok(Value::U32(a % b)),
-                _ => todo!("Can't yet rem unsigned {a:?} by {b:?}"),
-            }
+        CILNode::ConvF32(val) => eval_node(val, state)?.convert_to(&Type::F32),
+        CILNode::ConvF64(val) => eval_node(val, state)?.convert_to(&Type::F64),
+        CILNode::ConvF64Un(val) => match eval_node(val, state)? {
+            val @ (Value::F32(_) | Value::F64(_)) => val.convert_to(&Type::F64),
+            val => Ok(Value::F64(val.as_u64()? as f64)),
+        },
+        CILNode::LDIndI8 { ptr } => load_ind(ptr, state, &Type::I8),
+        CILNode::LDIndU8 { ptr } => load_ind(ptr, state, &Type::U8),
+        CILNode::LDIndBool { ptr } => load_ind(ptr, state, &Type::Bool),
+        CILNode::LDIndI16 { ptr } => load_ind(ptr, state, &Type::I16),
+        CILNode::LDIndU16 { ptr } => load_ind(ptr, state, &Type::U16),
+        CILNode::LDIndI32 { ptr } => load_ind(ptr, state, &Type::I32),
+        CILNode::LDIndU32 { ptr } => load_ind(ptr, state, &Type::U32),
+        CILNode::LDIndI64 { ptr } => load_ind(ptr, state, &Type::I64),
+        CILNode::LDIndU64 { ptr } => load_ind(ptr, state, &Type::U64),
+        CILNode::LDIndISize { ptr } => load_ind(ptr, state, &Type::ISize),
+        CILNode::LDIndUSize { ptr } | CILNode::LDIndPtr { ptr, .. } => {
+            load_ind(ptr, state, &Type::USize)
         }
-        CILNode::Add(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-            match (&a, &b) {
-                (Value::I8(a), Value::I8(b)) => Ok(Value::I8(a.wrapping_add(*b))),
-                (Value::U8(a), Value::U8(b)) => Ok(Value::U8(a.wrapping_add(*b))),
-                (Value::U16(a), Value::U16(b)) => Ok(Value::U16(a.wrapping_add(*b))),
-                (Value::I16(a), Value::I16(b)) => Ok(Value::I16(a.wrapping_add(*b))),
-                (Value::U32(a), Value::U32(b)) => Ok(Value::U32(a.wrapping_add(*b))),
-                (Value::I32(a), Value::I32(b)) => Ok(Value::I32(a.wrapping_add(*b))),
-                (Value::U64(a), Value::U64(b)) => Ok(Value::U64(a.wrapping_add(*b))),
-                (Value::I64(a), Value::I64(b)) => Ok(Value::I64(a.wrapping_add(*b))),
-                (Value::I32(a), Value::USize(b)) => {
-                    Ok(Value::USize((*a as usize).wrapping_add(*b)))
+        CILNode::LDIndF32 { ptr } => load_ind(ptr, state, &Type::F32),
+        CILNode::LDIndF64 { ptr } => load_ind(ptr, state, &Type::F64),
+        CILNode::LdObj { ptr, obj } => load_ind(ptr, state, obj),
+        CILNode::LDField { addr, field } => {
+            let offset = state.layouts.field_offset(field.owner(), field.name())?;
+            match eval_node(addr, state)? {
+                // Fields of valuetypes can be loaded directly from the value.
+                Value::Struct(bytes) => {
+                    let size = state.layouts.size_of(field.tpe())?;
+                    let bytes = bytes
+                        .get(offset as usize..(offset + size) as usize)
+                        .ok_or(Exception::AllocOffsetOutOfRange)?;
+                    state.decode(bytes, field.tpe())
                 }
-                (Value::Ptr(alloc, offset), Value::USize(b)) => Ok(Value::Ptr(
-                    *alloc,
-                    offset
-                        .checked_add(*b as u32)
-                        .ok_or(Exception::AllocOffsetOutOfRange)?,
-                )),
-                (Value::Ptr(alloc, offset), Value::U32(b)) => Ok(Value::Ptr(
-                    *alloc,
-                    offset
-                        .checked_add(*b)
-                        .ok_or(Exception::AllocOffsetOutOfRange)?,
-                )),
-                // Value::USize(val) => Ok(Value::I32(val as i32)),
-                _ => todo!("Can't yet add {a:?} and {b:?}"),
+                addr => state.load(addr.as_usize()? + offset as usize, field.tpe()),
             }
         }
+        CILNode::LDFieldAdress { addr, field } => {
+            let offset = state.layouts.field_offset(field.owner(), field.name())?;
+            let addr = eval_node(addr, state)?.as_usize()?;
+            Ok(Value::USize(addr + offset as usize))
+        }
+        CILNode::IsInst { obj, class } => match eval_node(obj, state)? {
+            Value::Exception { class: actual, .. } if actual.as_ref() != class.name_path() => {
+                Ok(Value::Null)
+            }
+            // Objects of classes defined by the assembly are plain allocations, which carry no type to check.
+            obj => Ok(obj),
+        },
+        CILNode::LDLen { arr } => match eval_node(arr, state)? {
+            Value::StringArray(arr) => Ok(Value::USize(arr.len())),
+            arr => Err(Exception::InvalidOperand(format!(
+                "Can't get the length of {arr:?}"
+            ))),
+        },
         CILNode::LDElelemRef { arr, idx } => {
-            let a = eval_node(arr, state, args)?;
-            let b = eval_node(idx, state, args)?;
-            match (&a, &b) {
-                (Value::StringArray(arr), Value::I32(idx)) => {
-                    Ok(Value::String(arr[*idx as usize].clone()))
-                }
-                // Value::USize(val) => Ok(Value::I32(val as i32)),
-                _ => todo!("Can't yet index into {a:?} wtih {b:?}"),
+            let arr = eval_node(arr, state)?;
+            let idx = eval_node(idx, state)?.as_usize()?;
+            match arr {
+                Value::StringArray(arr) => arr
+                    .get(idx)
+                    .map(|string| Value::String(string.clone()))
+                    .ok_or_else(|| {
+                        managed_exception(
+                            "System.IndexOutOfRangeException",
+                            "Index was outside the bounds of the array.",
+                        )
+                    }),
+                _ => Err(Exception::InvalidOperand(format!(
+                    "Can't index into {arr:?}"
+                ))),
             }
         }
-        CILNode::Mul(a, b) => {
-            let a = eval_node(a, state, args)?;
-            let b = eval_node(b, state, args)?;
-            match (&a, &b) {
-                (Value::I32(a), Value::I32(b)) => Ok(Value::I32(a.wrapping_mul(*b))),
-                (Value::I64(a), Value::I64(b)) => Ok(Value::I64(a.wrapping_mul(*b))),
-                (Value::USize(a), Value::USize(b)) => Ok(Value::USize(a.wrapping_mul(*b))),
-                // Value::USize(val) => Ok(Value::I32(val as i32)),
-                _ => todo!("Can't yet mul {a:?} and {b:?}"),
-            }
+        CILNode::LDArg(arg) => {
+            let frame = state.frame();
+            let (addr, tpe) = *frame
+                .args
+                .get(*arg as usize)
+                .ok_or(Exception::ArgOutOfRange {
+                    arg: *arg as usize,
+                    lcount: frame.args.len(),
+                })?;
+            state.load(addr, tpe)
         }
-
-        CILNode::ConvI8(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::I8(val as i8)),
-                Value::U8(val) => Ok(Value::I8(val as i8)),
-                Value::I16(val) => Ok(Value::I8(val as i8)),
-                Value::U16(val) => Ok(Value::I8(val as i8)),
-                Value::I32(val) => Ok(Value::I8(val as i8)),
-                Value::U32(val) => Ok(Value::I8(val as i8)),
-                _ => todo!("Can't convert a value of type {value:?} to i8"),
-            }
+        CILNode::LDArgA(arg) => {
+            let frame = state.frame();
+            frame
+                .args
+                .get(*arg as usize)
+                .map(|(addr, _)| Value::USize(*addr))
+                .ok_or(Exception::ArgOutOfRange {
+                    arg: *arg as usize,
+                    lcount: frame.args.len(),
+                })
         }
-        CILNode::MRefToRawPtr(ptr) => eval_node(ptr, state, args),
-        CILNode::ConvU8(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::U8(val as u8)),
-                Value::U8(val) => Ok(Value::U8(val as u8)),
-                Value::I16(val) => Ok(Value::U8(val as u8)),
-                Value::U16(val) => Ok(Value::U8(val as u8)),
-                Value::I32(val) => Ok(Value::U8(val as u8)),
-                Value::U32(val) => Ok(Value::U8(val as u8)),
-                Value::I64(val) => Ok(Value::U8(val as u8)),
-                Value::U64(val) => Ok(Value::U8(val as u8)),
-                _ => todo!("Can't convert a value of type {value:?} to u8"),
-            }
+        CILNode::LDLoc(loc) => {
+            let frame = state.frame();
+            let (addr, tpe) =
+                *frame
+                    .locals
+                    .get(*loc as usize)
+                    .ok_or(Exception::LocalOutOfRange {
+                        loc: *loc as usize,
+                        lcount: frame.locals.len(),
+                    })?;
+            state.load(addr, tpe)
         }
-        CILNode::ConvI16(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::I16(val as i16)),
-                Value::U8(val) => Ok(Value::I16(val as i16)),
-                Value::I16(val) => Ok(Value::I16(val as i16)),
-                Value::U16(val) => Ok(Value::I16(val as i16)),
-                Value::I32(val) => Ok(Value::I16(val as i16)),
-                Value::U32(val) => Ok(Value::I16(val as i16)),
-                _ => todo!("Can't convert a value of type {value:?} to i16"),
-            }
+        CILNode::LDLocA(loc) => {
+            let frame = state.frame();
+            frame
+                .locals
+                .get(*loc as usize)
+                .map(|(addr, _)| Value::USize(*addr))
+                .ok_or(Exception::LocalOutOfRange {
+                    loc: *loc as usize,
+                    lcount: frame.locals.len(),
+                })
         }
-        CILNode::Neg(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I64(val) => Ok(Value::I64(-val)),
-                _ => todo!("Can't neg {value:?}"),
+        CILNode::LDStaticField(field_desc) => match state.fields.get(field_desc) {
+            Some(val) => Ok(val.clone()),
+            None => state.zeroed(field_desc.tpe()),
+        },
+        CILNode::PointerToConstValue(value) => {
+            if let Some(addr) = state.const_values.get(value) {
+                return Ok(Value::USize(*addr));
             }
+            let addr = state.alloc(16);
+            state
+                .bytes_mut(addr, 16)?
+                .copy_from_slice(&value.to_le_bytes());
+            state.const_values.insert(*value, addr);
+            Ok(Value::USize(addr))
         }
-        CILNode::Not(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I64(val) => Ok(Value::I64(!val)),
-                Value::I8(val) => Ok(Value::I8(!val)),
-                _ => todo!("Can't not {value:?}"),
-            }
+        CILNode::LoadGlobalAllocPtr { alloc_id } => Err(Exception::InvalidOperand(format!(
+            "Global allocation {alloc_id} should have been lowered before export."
+        ))),
+        CILNode::LocAlloc { size } => {
+            let size = eval_node(size, state)?.as_usize()?;
+            let addr = state.alloc(size);
+            state.frame_mut().allocs.push(decode_ptr(addr).0);
+            Ok(Value::USize(addr))
         }
-        CILNode::ConvU16(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::U16(val as u16)),
-                Value::U8(val) => Ok(Value::U16(val as u16)),
-                Value::I16(val) => Ok(Value::U16(val as u16)),
-                Value::U16(val) => Ok(Value::U16(val as u16)),
-                Value::I32(val) => Ok(Value::U16(val as u16)),
-                Value::U32(val) => Ok(Value::U16(val as u16)),
-                _ => todo!("Can't convert a value of type {value:?} to u16"),
-            }
+        CILNode::LocAllocAligned { tpe, align: _ } => {
+            // Allocations always start at an offset of 0, so they are always aligned.
+            let size = state.layouts.size_of(tpe)?;
+            let addr = state.alloc(size as usize);
+            state.frame_mut().allocs.push(decode_ptr(addr).0);
+            Ok(Value::USize(addr))
         }
-        CILNode::ConvI32(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::I32(val as i32)),
-                Value::U8(val) => Ok(Value::I32(val as i32)),
-                Value::I16(val) => Ok(Value::I32(val as i32)),
-                Value::U16(val) => Ok(Value::I32(val as i32)),
-                Value::I32(val) => Ok(Value::I32(val as i32)),
-                Value::U32(val) => Ok(Value::I32(val as i32)),
-                Value::USize(val) => Ok(Value::I32(val as i32)),
-                Value::I64(val) => Ok(Value::I32(val as i32)),
-                Value::U64(val) => Ok(Value::I32(val as i32)),
-                _ => todo!("Can't convert a value of type {value:?} to i32"),
-            }
+        CILNode::TemporaryLocal(tmp) => {
+            let (tpe, roots, main) = tmp.as_ref();
+            let size = state.layouts.size_of(tpe)?;
+            let addr = state.alloc(size as usize);
+            state.frame_mut().tmps.push((addr, tpe));
+            let res = exec_inline(roots, state).and_then(|()| eval_node(main, state));
+            state.frame_mut().tmps.pop();
+            state.free(addr);
+            res
         }
-        CILNode::ConvU32(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I8(val) => Ok(Value::U32(val as u32)),
-                Value::U8(val) => Ok(Value::U32(val as u32)),
-                Value::I16(val) => Ok(Value::U32(val as u32)),
-                Value::U16(val) => Ok(Value::U32(val as u32)),
-                Value::I32(val) => Ok(Value::U32(val as u32)),
-                Value::U32(val) => Ok(Value::U32(val as u32)),
-                _ => todo!("Can't convert a value of type {value:?} to u32"),
-            }
+        CILNode::SubTrees(roots, main) => {
+            exec_inline(roots, state)?;
+            eval_node(main, state)
         }
-
-        CILNode::ConvF32(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I32(val) => Ok(Value::F32(val as f32)),
-                _ => todo!("Can't convert a value of type {value:?} to f32"),
-            }
+        CILNode::LoadTMPLocal => {
+            let (addr, tpe) = state.tmp()?;
+            state.load(addr, tpe)
         }
-        CILNode::ConvU64(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::U64(val) => Ok(Value::U64(val)),
-                _ => todo!("Can't convert a value of type {value:?} to u64"),
-            }
+        CILNode::LoadAddresOfTMPLocal => Ok(Value::USize(state.tmp()?.0)),
+        CILNode::InspectValue { val, inspect } => {
+            let val = eval_node(val, state)?;
+            state.frame_mut().inspected.push(val.clone());
+            let res = exec_inline(inspect, state);
+            state.frame_mut().inspected.pop();
+            res.map(|()| val)
         }
-        CILNode::ConvI64(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I64(val) => Ok(Value::I64(val)),
-                _ => todo!("Can't convert a value of type {value:?} to u64"),
+        CILNode::GetStackTop => state
+            .frame()
+            .inspected
+            .last()
+            .cloned()
+            .ok_or_else(|| Exception::InvalidOperand("GetStackTop outside InspectValue".into())),
+    }
+}
+fn eval_args<'asm>(
+    args: &'asm [CILNode],
+    state: &mut InterpreterState<'asm>,
+) -> Result<Vec<Value>, Exception> {
+    args.iter().map(|arg| eval_node(arg, state)).collect()
+}
+fn bin_op<'asm>(
+    a: &'asm CILNode,
+    b: &'asm CILNode,
+    state: &mut InterpreterState<'asm>,
+    op: ArithOp,
+) -> Result<Value, Exception> {
+    let a = eval_node(a, state)?;
+    let b = eval_node(b, state)?;
+    arith(&a, &b, op)
+}
+fn shift_op<'asm>(
+    a: &'asm CILNode,
+    b: &'asm CILNode,
+    state: &mut InterpreterState<'asm>,
+    op: ShiftOp,
+) -> Result<Value, Exception> {
+    let a = eval_node(a, state)?;
+    let b = eval_node(b, state)?;
+    shift(&a, &b, op)
+}
+fn cmp_op<'asm>(
+    a: &'asm CILNode,
+    b: &'asm CILNode,
+    state: &mut InterpreterState<'asm>,
+    signed: bool,
+    pred: impl Fn(Option<std::cmp::Ordering>) -> bool,
+) -> Result<Value, Exception> {
+    let a = eval_node(a, state)?;
+    let b = eval_node(b, state)?;
+    Ok(Value::bool(pred(compare(&a, &b, signed)?)))
+}
+fn load_ind<'asm>(
+    ptr: &'asm CILNode,
+    state: &mut InterpreterState<'asm>,
+    tpe: &Type,
+) -> Result<Value, Exception> {
+    let ptr = eval_node(ptr, state)?.as_usize()?;
+    state.load(ptr, tpe)
+}
+fn store_ind<'asm>(
+    addr: &'asm CILNode,
+    val: &'asm CILNode,
+    state: &mut InterpreterState<'asm>,
+    tpe: &Type,
+) -> Result<Flow, Exception> {
+    let addr = eval_node(addr, state)?.as_usize()?;
+    let val = eval_node(val, state)?;
+    state.store(addr, tpe, &val)?;
+    Ok(Flow::Next)
+}
+/// Executes roots nested within a node. They can't change the control flow.
+fn exec_inline<'asm>(
+    roots: &'asm [CILRoot],
+    state: &mut InterpreterState<'asm>,
+) -> Result<(), Exception> {
+    for root in roots {
+        match exec_root(root, state)? {
+            Flow::Next => (),
+            _ => {
+                return Err(Exception::InvalidOperand(format!(
+                    "Root {root:?} changes control flow, but is nested within a node."
+                )))
             }
         }
-        CILNode::ConvISize(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::U64(val) => Ok(Value::ISize(val as i64 as isize)),
-                Value::I64(val) => Ok(Value::ISize(val as isize)),
-                _ => todo!("Can't convert a value of type {value:?} to isize"),
+    }
+    Ok(())
+}
+fn branch_if<'asm>(
+    a: &'asm CILNode,
+    b: &'asm CILNode,
+    state: &mut InterpreterState<'asm>,
+    (target, sub_target): (u32, u32),
+    signed: bool,
+    pred: impl Fn(Option<std::cmp::Ordering>) -> bool,
+) -> Result<Flow, Exception> {
+    let a = eval_node(a, state)?;
+    let b = eval_node(b, state)?;
+    if pred(compare(&a, &b, signed)?) {
+        Ok(Flow::Jump { target, sub_target })
+    } else {
+        Ok(Flow::Next)
+    }
+}
+fn exec_root<'asm>(
+    root: &'asm CILRoot,
+    state: &mut InterpreterState<'asm>,
+) -> Result<Flow, Exception> {
+    use std::cmp::Ordering;
+    match root {
+        CILRoot::GoTo { target, sub_target } => Ok(Flow::Jump {
+            target: *target,
+            sub_target: *sub_target,
+        }),
+        CILRoot::JumpingPad { source: _, target } => Ok(Flow::Jump {
+            target: *target,
+            sub_target: 0,
+        }),
+        CILRoot::BTrue {
+            target,
+            sub_target,
+            cond,
+        } => {
+            if eval_node(cond, state)?.as_bool()? {
+                Ok(Flow::Jump {
+                    target: *target,
+                    sub_target: *sub_target,
+                })
+            } else {
+                Ok(Flow::Next)
             }
         }
-        CILNode::ZeroExtendToUSize(value) => {
-            let value = eval_node(value, state, args)?;
-            match value {
-                Value::I32(val) => Ok(Value::USize(val as u32 as usize)),
-                Value::U32(val) => Ok(Value::USize(val as usize)),
-                _ => todo!("Can't convert a value of type {value:?} to usize"),
+        CILRoot::BFalse {
+            target,
+            sub_target,
+            cond,
+        } => {
+            if eval_node(cond, state)?.as_bool()? {
+                Ok(Flow::Next)
+            } else {
+                Ok(Flow::Jump {
+                    target: *target,
+                    sub_target: *sub_target,
+                })
             }
         }
-        CILNode::LDLen { arr } => {
-            let arr = eval_node(arr, state, args)?;
-            match arr {
-                Value::StringArray(arr) => Ok(Value::USize(arr.len())),
-                _ => todo!("Can't get the length of a {arr:?}"),
+        CILRoot::BEq {
+            target,
+            sub_target,
+            a,
+            b,
+        } => branch_if(a, b, state, (*target, *sub_target), true, |ord| {
+            ord == Some(Ordering::Equal)
+        }),
+        CILRoot::BNe {
+            target,
+            sub_target,
+            a,
+            b,
+        } => branch_if(a, b, state, (*target, *sub_target), true, |ord| {
+            ord != Some(Ordering::Equal)
+        }),
+        CILRoot::BLt {
+            target,
+            sub_target,
+            a,
+            b,
+        } => branch_if(a, b, state, (*target, *sub_target), true, |ord| {
+            ord == Some(Ordering::Less)
+        }),
+        CILRoot::BLtUn {
+            target,
+            sub_target,
+            a,
+            b,
+        } => branch_if(a, b, state, (*target, *sub_target), false, |ord| {
+            ord.map_or(true, |ord| ord == Ordering::Less)
+        }),
+        CILRoot::BGt {
+            target,
+            sub_target,
+            a,
+            b,
+        } => branch_if(a, b, state, (*target, *sub_target), true, |ord| {
+            ord == Some(Ordering::Greater)
+        }),
+        CILRoot::BGtUn {
+            target,
+            sub_target,
+            a,
+            b,
+        } => branch_if(a, b, state, (*target, *sub_target), false, |ord| {
+            ord.map_or(true, |ord| ord == Ordering::Greater)
+        }),
+        CILRoot::BLe {
+            target,
+            sub_target,
+            a,
+            b,
+        } => branch_if(a, b, state, (*target, *sub_target), true, |ord| {
+            matches!(ord, Some(Ordering::Less | Ordering::Equal))
+        }),
+        CILRoot::BGe {
+            target,
+            sub_target,
+            a,
+            b,
+        } => branch_if(a, b, state, (*target, *sub_target), true, |ord| {
+            matches!(ord, Some(Ordering::Greater | Ordering::Equal))
+        }),
+        CILRoot::Nop | CILRoot::Break => Ok(Flow::Next),
+        CILRoot::SourceFileInfo(sfi) => {
+            state.frame_mut().sfi = Some(sfi);
+            Ok(Flow::Next)
+        }
+        CILRoot::STLoc { local, tree } => {
+            let val = eval_node(tree, state)?;
+            let frame = state.frame();
+            let (addr, tpe) =
+                *frame
+                    .locals
+                    .get(*local as usize)
+                    .ok_or(Exception::LocalOutOfRange {
+                        loc: *local as usize,
+                        lcount: frame.locals.len(),
+                    })?;
+            state.store(addr, tpe, &val)?;
+            Ok(Flow::Next)
+        }
+        CILRoot::STArg { arg, tree } => {
+            let val = eval_node(tree, state)?;
+            let frame = state.frame();
+            let (addr, tpe) = *frame
+                .args
+                .get(*arg as usize)
+                .ok_or(Exception::ArgOutOfRange {
+                    arg: *arg as usize,
+                    lcount: frame.args.len(),
+                })?;
+            state.store(addr, tpe, &val)?;
+            Ok(Flow::Next)
+        }
+        CILRoot::SetTMPLocal { value } => {
+            let val = eval_node(value, state)?;
+            let (addr, tpe) = state.tmp()?;
+            state.store(addr, tpe, &val)?;
+            Ok(Flow::Next)
+        }
+        CILRoot::SetField { addr, value, desc } => {
+            let addr = eval_node(addr, state)?.as_usize()?;
+            let val = eval_node(value, state)?;
+            let offset = state.layouts.field_offset(desc.owner(), desc.name())?;
+            state.store(addr + offset as usize, desc.tpe(), &val)?;
+            Ok(Flow::Next)
+        }
+        CILRoot::SetStaticField { descr, value } => {
+            let val = eval_node(value, state)?;
+            state.fields.insert(descr.clone(), val);
+            Ok(Flow::Next)
+        }
+        CILRoot::STIndI8(addr, val) => store_ind(addr, val, state, &Type::I8),
+        CILRoot::STIndI16(addr, val) => store_ind(addr, val, state, &Type::I16),
+        CILRoot::STIndI32(addr, val) => store_ind(addr, val, state, &Type::I32),
+        CILRoot::STIndI64(addr, val) => store_ind(addr, val, state, &Type::I64),
+        CILRoot::STIndISize(addr, val) => store_ind(addr, val, state, &Type::ISize),
+        CILRoot::STIndF32(addr, val) => store_ind(addr, val, state, &Type::F32),
+        CILRoot::STIndF64(addr, val) => store_ind(addr, val, state, &Type::F64),
+        CILRoot::STObj {
+            tpe,
+            addr_calc,
+            value_calc,
+        } => store_ind(addr_calc, value_calc, state, tpe),
+        CILRoot::CpBlk { dst, src, len } => {
+            let dst = eval_node(dst, state)?.as_usize()?;
+            let src = eval_node(src, state)?.as_usize()?;
+            let len = eval_node(len, state)?.as_usize()?;
+            let bytes = state.bytes(src, len)?.to_vec();
+            state.bytes_mut(dst, len)?.copy_from_slice(&bytes);
+            Ok(Flow::Next)
+        }
+        CILRoot::InitBlk { dst, val, count } => {
+            let dst = eval_node(dst, state)?.as_usize()?;
+            let val = eval_node(val, state)?.as_i64()? as u8;
+            let count = eval_node(count, state)?.as_usize()?;
+            state.bytes_mut(dst, count)?.fill(val);
+            Ok(Flow::Next)
+        }
+        CILRoot::Call { args, site } | CILRoot::CallVirt { args, site } => {
+            let args = eval_args(args, state)?;
+            state.call(site, args)?;
+            Ok(Flow::Next)
+        }
+        CILRoot::CallI {
+            sig: _,
+            fn_ptr,
+            args,
+        } => {
+            let args = eval_args(args, state)?;
+            let fn_ptr = eval_node(fn_ptr, state)?.as_usize()?;
+            if fn_ptr == 0 {
+                return Err(managed_exception(
+                    "System.NullReferenceException",
+                    "Object reference not set to an instance of an object.",
+                ));
             }
+            state.call_ptr(fn_ptr, args)?;
+            Ok(Flow::Next)
+        }
+        CILRoot::Pop { tree } => {
+            eval_node(tree, state)?;
+            Ok(Flow::Next)
+        }
+        CILRoot::VoidRet => Ok(Flow::Return(Value::Undef)),
+        CILRoot::Ret { tree } => Ok(Flow::Return(eval_node(tree, state)?)),
+        CILRoot::Throw(tree) => Err(Exception::Thrown(eval_node(tree, state)?)),
+        CILRoot::ReThrow => match state.frame().caught.clone() {
+            Some(caught) => Err(Exception::Thrown(caught)),
+            None => Err(Exception::InvalidOperand(
+                "rethrow outside of an exception handler".into(),
+            )),
+        },
+        CILRoot::StoreCaught { local } => {
+            let Some(caught) = state.frame().caught.clone() else {
+                return Err(Exception::InvalidOperand(
+                    "Caught exception stored outside of an exception handler".into(),
+                ));
+            };
+            let frame = state.frame();
+            let (addr, tpe) =
+                *frame
+                    .locals
+                    .get(*local as usize)
+                    .ok_or(Exception::LocalOutOfRange {
+                        loc: *local as usize,
+                        lcount: frame.locals.len(),
+                    })?;
+            state.store(addr, tpe, &caught)?;
+            Ok(Flow::Next)
         }
-        CILNode::LDFtn(site) => Ok(Value::Ptr(state.get_fn_ptr_alloc(site), 0)),
-        CILNode::LDArg(arg) => args
-            .get(*arg as usize)
-            .cloned()
-            .ok_or(Exception::ArgOutOfRange {
-                arg: *arg as usize,
-                lcount: args.len(),
-            }),
-        CILNode::LDStaticField(field_desc) => Ok(state
-            .fields
-            .get(&field_desc)
-            .cloned()
-            .unwrap_or(Value::Undef)),
-        CILNode::LDLoc(loc) => state
-            .locals
-            .last()
-            .unwrap()
-            .get(*loc as usize)
-            .cloned()
-            .ok_or(Exception::LocalOutOfRange {
-                loc: *loc as usize,
-                lcount: state.locals.len(),
-            }),
-        _ => todo!("Can't handle node:{node:?}"),
     }
 }
 impl<'asm> InterpreterState<'asm> {
     pub fn get_fn_ptr_alloc(&mut self, site: &CallSite) -> AllocID {
-        *self.inv_methods.entry(site.clone()).or_insert_with(|| {
-            let new_method = self.last_alloc_method;
-            self.methods.insert(new_method, site.clone());
-            new_method
-        })
+        if let Some(alloc) = self.inv_methods.get(site) {
+            return *alloc;
+        }
+        let new_method = self.last_alloc_method;
+        self.last_alloc_method += 1;
+        self.methods.insert(new_method, site.clone());
+        self.inv_methods.insert(site.clone(), new_method);
+        new_method
     }
-    pub fn alloc(&mut self, size: usize) -> AllocID {
+    /// Allocates a zero-initialized buffer of size `size`, returning a pointer to it.
+    pub fn alloc(&mut self, size: usize) -> usize {
         let new_alloc = self.last_alloc;
         self.last_alloc += 1;
-        self.mem
-            .insert(new_alloc, (0..size).map(|_| 0_u8).collect());
-        new_alloc
-    }
-    pub fn try_call_extern(
-        &mut self,
-        call: &'asm CallSite,
-        args: &mut Box<[Value]>,
-    ) -> Result<Value, Exception> {
-        assert_eq!(self.locals.len(), self.call_stack.len());
-        let res = match (call.class(), call.name(), call.signature()) {
-            (Some(_), "IsNaN", _) => match args[0] {
-                Value::F32(f) => Ok(Value::Bool(f.is_nan())),
-                Value::F64(f) => Ok(Value::Bool(f.is_nan())),
-                _ => panic!("Can't check if {:?} is nan.", args[0]),
+        assert!(
+            self.last_alloc < FN_PTR_BASE,
+            "Exhausted all the allocation IDs"
+        );
+        self.mem.insert(new_alloc, vec![0; size].into());
+        encode_ptr(new_alloc, 0)
+    }
+    pub fn free(&mut self, ptr: usize) {
+        self.mem.remove(&decode_ptr(ptr).0);
+    }
+    /// Resizes the allocation `ptr` points to, preserving its contents.
+    pub fn realloc(&mut self, ptr: usize, size: usize) -> Result<usize, Exception> {
+        if ptr == 0 {
+            return Ok(self.alloc(size));
+        }
+        let (id, _) = decode_ptr(ptr);
+        let old = self.mem.remove(&id).ok_or(Exception::InvalidPointer(ptr))?;
+        let mut new = old.into_vec();
+        new.resize(size, 0);
+        self.mem.insert(id, new.into());
+        Ok(ptr)
+    }
+    pub fn bytes(&self, ptr: usize, len: usize) -> Result<&[u8], Exception> {
+        let (id, offset) = decode_ptr(ptr);
+        let alloc = self.mem.get(&id).ok_or(Exception::InvalidPointer(ptr))?;
+        alloc
+            .get(offset as usize..offset as usize + len)
+            .ok_or(Exception::AllocOffsetOutOfRange)
+    }
+    pub fn bytes_mut(&mut self, ptr: usize, len: usize) -> Result<&mut [u8], Exception> {
+        let (id, offset) = decode_ptr(ptr);
+        let alloc = self
+            .mem
+            .get_mut(&id)
+            .ok_or(Exception::InvalidPointer(ptr))?;
+        alloc
+            .get_mut(offset as usize..offset as usize + len)
+            .ok_or(Exception::AllocOffsetOutOfRange)
+    }
+    /// Returns all the bytes from `ptr` to the end of its allocation.
+    pub fn bytes_from(&self, ptr: usize) -> Result<&[u8], Exception> {
+        let (id, offset) = decode_ptr(ptr);
+        let alloc = self.mem.get(&id).ok_or(Exception::InvalidPointer(ptr))?;
+        alloc
+            .get(offset as usize..)
+            .ok_or(Exception::AllocOffsetOutOfRange)
+    }
+    /// Decodes a value of type `tpe` stored in `bytes`.
+    fn decode(&mut self, bytes: &[u8], tpe: &Type) -> Result<Value, Exception> {
+        let int = |len: usize| {
+            let mut buff = [0; 16];
+            buff[..len].copy_from_slice(&bytes[..len]);
+            u128::from_le_bytes(buff)
+        };
+        Ok(match tpe {
+            Type::Void => Value::Undef,
+            Type::Bool | Type::U8 => Value::I32(i32::from(bytes[0])),
+            Type::I8 => Value::I32(i32::from(bytes[0] as i8)),
+            Type::U16 | Type::DotnetChar => Value::I32(i32::from(int(2) as u16)),
+            Type::I16 => Value::I32(i32::from(int(2) as u16 as i16)),
+            Type::I32 | Type::U32 => Value::I32(int(4) as u32 as i32),
+            Type::I64 | Type::U64 => Value::I64(int(8) as u64 as i64),
+            Type::ISize
+            | Type::USize
+            | Type::Ptr(_)
+            | Type::ManagedReference(_)
+            | Type::DelegatePtr(_) => Value::USize(int(8) as usize),
+            Type::F32 => Value::F32(f32::from_bits(int(4) as u32)),
+            Type::F64 => Value::F64(f64::from_bits(int(8) as u64)),
+            Type::I128 => Value::I128(int(16) as i128),
+            Type::U128 => Value::U128(int(16)),
+            Type::DotnetType(tref) if tref.is_valuetype() => match int128_kind(tref) {
+                Some(true) => Value::I128(int(16) as i128),
+                Some(false) => Value::U128(int(16)),
+                None => {
+                    let size = self.layouts.size_of(tpe)? as usize;
+                    Value::Struct(bytes[..size].into())
+                }
+            },
+            _ if is_object_ref(tpe) => match int(8) as usize {
+                0 => Value::Null,
+                handle => self
+                    .objects
+                    .get(handle - 1)
+                    .cloned()
+                    .ok_or(Exception::InvalidPointer(handle))?,
             },
-            (Some(_), "AlignedAlloc" | "AllocHGlobal", _) => {
-                Ok(Value::Ptr(self.alloc(args[0].as_usize().unwrap()), 0))
+            Type::FnDef(_) | Type::Unresolved | Type::Foreign => {
+                let size = self.layouts.size_of(tpe)? as usize;
+                Value::Struct(bytes[..size].into())
             }
-            (Some(_), "GetCommandLineArgs", _) => Ok(Value::StringArray(
-                std::env::args().map(|arg| arg.into()).collect(),
-            )),
-
-            (Some(_), "get_Length", _) => {
-                let arg = &args[0];
-                match arg {
-                    Value::StringArray(arr) => Ok(Value::I32(arr.len() as u32 as i32)),
-                    _ => todo!("Can't get length of {arg:?}"),
-                }
+            _ => return Err(Exception::UnsupportedType(tpe.clone())),
+        })
+    }
+    /// Encodes `val` as a value of type `tpe`, writing it into `out`.
+    fn encode(&mut self, val: &Value, tpe: &Type, out: &mut [u8]) -> Result<(), Exception> {
+        let size = out.len();
+        match (tpe, val) {
+            (_, Value::Undef) => out.fill(0),
+            (_, Value::Struct(bytes)) => {
+                let len = bytes.len().min(size);
+                out[..len].copy_from_slice(&bytes[..len]);
             }
-            (Some(tpe), ".ctor", _) => match tpe.name_path() {
-                "System.UInt128" => {
-                    let (lower, upper) = (
-                        *args[0].as_u64().unwrap() as u128,
-                        *args[1].as_u64().unwrap() as u128,
-                    );
-                    //Ok(Value::U128(lower<<64))
-
-                    Ok(Value::U128(lower << 64 | upper))
-                }
-                _ => todo!("Can't newobj {tpe:?}"),
-            },
-            (Some(_), "StringToCoTaskMemUTF8", _) => {
-                let string = args[0].as_string().unwrap().clone();
-                let alloc = self.alloc(string.len() + 1);
-                let alloc = Value::Ptr(alloc, 0);
-                self.get_memory_at_mut(alloc.clone())[..string.len()]
-                    .copy_from_slice(string.as_bytes());
-                Ok(alloc)
+            (Type::F32, _) => {
+                let val = match val {
+                    Value::F32(val) => *val,
+                    _ => val.as_f64()? as f32,
+                };
+                out.copy_from_slice(&val.to_le_bytes());
             }
-
-            (Some(class), name, sig) => todo!("Can't yet call extern {call:?}"),
-            _ => Err(Exception::MethodNotFound(call.clone())),
+            (Type::F64, _) => out.copy_from_slice(&val.as_f64()?.to_le_bytes()),
+            _ if is_object_ref(tpe) => {
+                let handle = match val {
+                    Value::Null => 0,
+                    _ => {
+                        self.objects.push(val.clone());
+                        self.objects.len()
+                    }
+                };
+                out.copy_from_slice(&(handle as u64).to_le_bytes());
+            }
+            _ => {
+                let bytes = val.as_u128()?.to_le_bytes();
+                out.copy_from_slice(&bytes[..size]);
+            }
+        }
+        Ok(())
+    }
+    pub fn load(&mut self, ptr: usize, tpe: &Type) -> Result<Value, Exception> {
+        let size = self.layouts.size_of(tpe)? as usize;
+        let bytes = self.bytes(ptr, size)?.to_vec();
+        self.decode(&bytes, tpe)
+    }
+    pub fn store(&mut self, ptr: usize, tpe: &Type, val: &Value) -> Result<(), Exception> {
+        let size = self.layouts.size_of(tpe)? as usize;
+        let mut bytes = vec![0; size];
+        self.encode(val, tpe, &mut bytes)?;
+        self.bytes_mut(ptr, size)?.copy_from_slice(&bytes);
+        Ok(())
+    }
+    /// Returns the default(all zeroes) value of `tpe`.
+    fn zeroed(&mut self, tpe: &Type) -> Result<Value, Exception> {
+        let size = self.layouts.size_of(tpe)? as usize;
+        self.decode(&vec![0; size], tpe)
+    }
+    fn frame(&self) -> &Frame<'asm> {
+        self.call_stack.last().expect("No method is running")
+    }
+    fn frame_mut(&mut self) -> &mut Frame<'asm> {
+        self.call_stack.last_mut().expect("No method is running")
+    }
+    fn tmp(&self) -> Result<(usize, &'asm Type), Exception> {
+        self.frame()
+            .tmps
+            .last()
+            .copied()
+            .ok_or_else(|| Exception::InvalidOperand("No temporary local in scope".into()))
+    }
+    /// Finds the method a call site refers to.
+    pub fn method(&self, site: &CallSite) -> Result<&'asm Method, Exception> {
+        if let Some(method) = self.asm.functions().get(site) {
+            return Ok(method);
+        }
+        // Methods of types defined in this assembly.
+        site.class()
+            .filter(|class| class.asm().is_none())
+            .and_then(|class| self.asm.get_typedef_by_path(class.name_path()))
+            .and_then(|tpe| {
+                tpe.methods()
+                    .find(|method| method.name() == site.name() && method.sig() == site.signature())
+            })
+            .ok_or_else(|| Exception::MethodNotFound(site.clone()))
+    }
+    /// Calls the method `site` refers to, either interpreting it or emulating it if it is not a part of the assembly.
+    pub fn call(&mut self, site: &CallSite, args: Vec<Value>) -> Result<Value, Exception> {
+        if site.is_nop() {
+            return Ok(args.into_iter().next().unwrap_or(Value::Undef));
+        }
+        match self.method(site) {
+            Ok(method) => self.run(method, args),
+            Err(Exception::MethodNotFound(_)) => externs::call_extern(self, site, args),
+            Err(err) => Err(err),
+        }
+    }
+    fn call_ptr(&mut self, fn_ptr: usize, args: Vec<Value>) -> Result<Value, Exception> {
+        let (id, _) = decode_ptr(fn_ptr);
+        let site = self
+            .methods
+            .get(&id)
+            .cloned()
+            .ok_or(Exception::InvalidPointer(fn_ptr))?;
+        self.call(&site, args)
+    }
+    fn new_obj(&mut self, site: &CallSite, args: Vec<Value>) -> Result<Value, Exception> {
+        let Some(class) = site.class() else {
+            return Err(Exception::MethodNotFound(site.clone()));
         };
-
+        if class.asm().is_some() {
+            return externs::new_obj(self, site, args);
+        }
+        // Valuetypes defined in this assembly are constructed in place, and then loaded.
+        let tpe = Type::DotnetType(Box::new(class.clone()));
+        let size = self.layouts.size_of(&tpe)?;
+        let this = self.alloc(size as usize);
+        let mut ctor_args = vec![Value::USize(this)];
+        ctor_args.extend(args);
+        let res = self
+            .call(site, ctor_args)
+            .and_then(|_| self.load(this, &tpe));
+        self.free(this);
         res
     }
-    pub fn run_cctor(&mut self) -> Result<Value, Exception> {
-        match self.asm.cctor() {
-            Some(_) => self.run(
-                Box::<CallSite>::leak(Box::new(CallSite::builtin(
-                    ".cctor".into(),
-                    FnSig::new(&[], Type::Void),
-                    true,
-                ))),
-                &mut vec![].into(),
-            ),
-            None => Ok(Value::Undef),
+    /// Runs `method`, with arguments `args`.
+    pub fn run(&mut self, method: &'asm Method, args: Vec<Value>) -> Result<Value, Exception> {
+        let inputs = method.sig().inputs();
+        if args.len() != inputs.len() {
+            return Err(Exception::InvalidOperand(format!(
+                "Method {name} expects {expected} arguments, but got {got}.",
+                name = method.name(),
+                expected = inputs.len(),
+                got = args.len()
+            )));
         }
-    }
-    pub fn run_entypoint(&mut self) -> Result<Value, Exception> {
-        let entry = self.asm.methods().find(|method| method.is_entrypoint());
-        match entry {
-            Some(entry) => self.run(
-                Box::<CallSite>::leak(Box::new(entry.call_site())),
-                &mut vec![Value::StringArray(
-                    std::env::args().map(|arg| arg.into()).collect(),
-                )]
-                .into(),
-            ),
-            None => Ok(Value::Undef),
+        let mut frame = Frame {
+            method,
+            block: 0,
+            handler: None,
+            tree: 0,
+            sfi: None,
+            args: Vec::with_capacity(inputs.len()),
+            locals: Vec::with_capacity(method.locals().len()),
+            allocs: vec![],
+            tmps: vec![],
+            inspected: vec![],
+            caught: None,
+        };
+        for (arg, tpe) in args.iter().zip(inputs) {
+            let size = self.layouts.size_of(tpe)?;
+            let addr = self.alloc(size as usize);
+            frame.allocs.push(decode_ptr(addr).0);
+            frame.args.push((addr, tpe));
+            self.store(addr, tpe, arg)?;
+        }
+        for (_, tpe) in method.locals() {
+            let size = self.layouts.size_of(tpe)?;
+            let addr = self.alloc(size as usize);
+            frame.allocs.push(decode_ptr(addr).0);
+            frame.locals.push((addr, tpe));
+        }
+        self.call_stack.push(frame);
+        let res = self.run_frame();
+        if let Err(err) = &res {
+            if !matches!(err, Exception::Thrown(_) | Exception::Exit(_)) {
+                let frame = self.frame();
+                let location = frame.sfi.map_or(String::new(), |sfi| {
+                    format!(" at {}:{}:{}", sfi.2, sfi.0.start, sfi.1.start)
+                });
+                self.trace.push(format!(
+                    "{name} block {block} tree {tree}{location}",
+                    name = method.name(),
+                    block = frame.block().id(),
+                    tree = frame.tree
+                ));
+            }
         }
+        let frame = self.call_stack.pop().unwrap();
+        for alloc in frame.allocs {
+            self.mem.remove(&alloc);
+        }
+        res
     }
-    pub fn method(&self, site: &'asm CallSite) -> Result<&'asm Method, Exception> {
-        self.asm
-            .functions()
-            .get(site)
-            .ok_or(Exception::MethodNotFound(site.clone()))
-    }
-    pub fn run(
-        &mut self,
-        call: &'asm CallSite,
-        args: &mut Box<[Value]>,
-    ) -> Result<Value, Exception> {
-        assert_eq!(self.locals.len(), self.call_stack.len());
-        assert!(args.len() >= self.method(call)?.sig().inputs().len());
-        let locals = self.method(call)?.locals().clone();
-        let locals = locals
-            .iter()
-            .map(|(_name, tpe)| Value::default_for_type(tpe, self))
-            .collect();
-        self.locals.push(locals);
-        let sfi: SFI = Box::new((0..1, 0..1, "".into()));
-        self.call_stack.push((call, 0, 0, sfi));
+    fn run_frame(&mut self) -> Result<Value, Exception> {
         loop {
-            let (method, block, tree, curr_sfi) = if let Some(method) = self.call_stack.last_mut() {
-                method
-            } else {
-                break;
-            };
-            let method = self
-                .asm
-                .functions()
-                .get(method)
-                .ok_or::<Exception>(Exception::MethodNotFound(method.clone()))?;
-            let block_ref = &method.blocks()[*block];
-            let tree_ref = &block_ref.trees()[*tree];
-            match tree_ref.root() {
-                CILRoot::GoTo { target, sub_target } => {
-                    assert_eq!(*sub_target, 0);
-                    let (method, block, tree, _) = self.call_stack.last_mut().unwrap();
-                    *block = *target as usize;
-                    *tree = 0;
-                    continue;
+            let frame = self.frame();
+            let trees = frame.trees()?;
+            let Some(tree) = trees.get(frame.tree) else {
+                // Fall trough to the next block.
+                let frame = self.frame_mut();
+                frame.tree = 0;
+                match &mut frame.handler {
+                    Some(handler) => *handler += 1,
+                    None => frame.block += 1,
                 }
-                CILRoot::BTrue {
-                    target,
-                    sub_target,
-                    cond,
-                } => {
-                    assert_eq!(*sub_target, 0);
-                    let cond = eval_node(cond, self, args)?.as_bool().unwrap();
-                    if cond {
-                        let (method, block, tree, _) = self.call_stack.last_mut().unwrap();
-                        *block = *target as usize;
-                        *tree = 0;
-                        continue;
-                    }
+                if frame.block >= frame.method.blocks().len() {
+                    return Err(Exception::InvalidOperand(format!(
+                        "Control flow fell off the end of method {}",
+                        frame.method.name()
+                    )));
                 }
-                CILRoot::BFalse {
-                    target,
-                    sub_target,
-                    cond,
-                } => {
-                    assert_eq!(*sub_target, 0);
-                    let cond = eval_node(cond, self, args)?.as_bool().unwrap();
-                    if !cond {
-                        let (method, block, tree, _) = self.call_stack.last_mut().unwrap();
-                        *block = *target as usize;
-                        *tree = 0;
-                        continue;
+                continue;
+            };
+            match exec_root(tree.root(), self) {
+                Ok(Flow::Next) => self.frame_mut().tree += 1,
+                Ok(Flow::Jump { target, sub_target }) => self.jump(target, sub_target)?,
+                Ok(Flow::Return(val)) => return Ok(val),
+                Err(Exception::Thrown(val)) => {
+                    let frame = self.frame_mut();
+                    let has_handler = matches!(frame.block().handler(), Some(Handler::Blocks(_)));
+                    if frame.handler.is_some() || !has_handler {
+                        return Err(Exception::Thrown(val));
                     }
+                    frame.caught = Some(val);
+                    frame.handler = Some(0);
+                    frame.tree = 0;
                 }
-                CILRoot::Nop => (),
-                CILRoot::STLoc { local, tree } => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let val = eval_node(tree, self, args)?;
-                    let mut cloned = self.locals.last().unwrap()[*local as usize].clone();
-                    cloned.set(val, self);
-                    self.locals.last_mut().unwrap()[*local as usize] = cloned;
-                }
-                CILRoot::STArg { arg, tree } => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let val = eval_node(tree, self, args)?;
-                    args[*arg as usize].set(val, self);
-                }
-                CILRoot::SetField { addr, value, desc } => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let val = eval_node(value, self, args)?;
-                    let addr = eval_node(addr, self, args)?;
-                    todo!()
-                }
-                CILRoot::SetStaticField { descr, value } => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let val = eval_node(value, self, args)?;
-                    self.fields.insert(descr.clone(), val);
-                }
-                CILRoot::Pop { tree } => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    eval_node(tree, self, args)?;
-                }
-                CILRoot::STIndISize(addr, val) => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let addr = eval_node(addr, self, args)?;
-                    let val = eval_node(val, self, args)?;
-                    self.get_memory_at_mut(addr)[..8]
-                        .copy_from_slice(&val.as_usize().unwrap().to_le_bytes()[..])
-                }
-                CILRoot::STIndI8(addr, val) => {
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    let addr = eval_node(addr, self, args)?;
-                    let val = eval_node(val, self, args)?;
-                    self.get_memory_at_mut(addr)[0] = val.as_i8().unwrap() as u8;
-                }
-                CILRoot::VoidRet => {
-                    self.locals.pop();
-                    self.call_stack.pop();
-                    return Ok(Value::Undef);
-                }
-                CILRoot::Ret { tree } => {
-                    let tree = eval_node(tree, self, args)?;
-                    self.locals.pop();
-                    self.call_stack.pop();
-                    return Ok(tree);
-                }
-                CILRoot::SourceFileInfo(sfi) => {
-                    println!("{}:{:?}:{:?}", sfi.2, sfi.0, sfi.1);
-                    *curr_sfi = sfi.clone();
-                }
-                CILRoot::Call {
-                    args: call_args,
-                    site,
-                }
-                | CILRoot::CallVirt {
-                    args: call_args,
-                    site,
-                } => {
-                    let mut call_args: Box<[_]> = call_args
-                        .iter()
-                        .map(|arg| eval_node(arg, self, args).map(|arg| arg.pass_as_arg(self)))
-                        .try_collect()?;
-                    assert_eq!(self.locals.len(), self.call_stack.len());
-                    match self.run(site, &mut call_args) {
-                        Ok(val) => Ok(val),
-                        Err(Exception::MethodNotFound(_)) => {
-                            self.try_call_extern(site, &mut call_args)
-                        }
-                        Err(err) => Err(err),
-                    }?;
-                }
-                _ => todo!("unhandled root {root:?}", root = tree_ref.root()),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+    /// Jumps to the label `bb_{target}_{sub_target}`. A non-zero `sub_target` refers either to a block of the exception handler of `target`, or
+    /// to a jumping pad within `target`.
+    fn jump(&mut self, target: u32, sub_target: u32) -> Result<(), Exception> {
+        let frame = self.frame_mut();
+        let block = frame
+            .method
+            .blocks()
+            .iter()
+            .position(|block| block.id() == target)
+            .ok_or(Exception::InvalidTarget { target, sub_target })?;
+        let block_ref = &frame.method.blocks()[block];
+        frame.block = block;
+        if sub_target == 0 {
+            frame.handler = None;
+            frame.tree = 0;
+            return Ok(());
+        }
+        if let Some(pad) = block_ref.trees().iter().position(|tree| {
+            matches!(tree.root(), CILRoot::JumpingPad { source, target: pad_target } if *source == target && *pad_target == sub_target)
+        }) {
+            frame.handler = None;
+            frame.tree = pad;
+            return Ok(());
+        }
+        let handler = handler_blocks(block_ref)?
+            .iter()
+            .position(|block| block.id() == sub_target)
+            .ok_or(Exception::InvalidTarget { target, sub_target })?;
+        frame.handler = Some(handler);
+        frame.tree = 0;
+        Ok(())
+    }
+    pub fn run_cctor(&mut self) -> Result<Value, Exception> {
+        match self.asm.cctor() {
+            Some(cctor) => self.run(cctor, vec![]),
+            None => Ok(Value::Undef),
+        }
+    }
+    pub fn run_entypoint(&mut self) -> Result<Value, Exception> {
+        let entry = self.asm.methods().find(|method| method.is_entrypoint());
+        match entry {
+            Some(entry) => {
+                let args = if entry.sig().inputs().is_empty() {
+                    vec![]
+                } else {
+                    vec![Value::StringArray(
+                        std::env::args().skip(1).map(Into::into).collect(),
+                    )]
+                };
+                self.run(entry, args)
             }
-            let (_, _, tree, _) = self.call_stack.last_mut().unwrap();
-            *tree += 1;
+            None => Ok(Value::Undef),
         }
-        todo!()
     }
     fn new(asm: &'asm Assembly) -> Self {
         Self {
             asm,
             call_stack: vec![],
-            locals: vec![],
             mem: HashMap::new(),
             last_alloc: 1,
+            objects: vec![],
+            layouts: Layouts::new(asm),
             fields: HashMap::new(),
             methods: HashMap::new(),
             inv_methods: HashMap::new(),
-            last_alloc_method: 1 << 31,
+            last_alloc_method: FN_PTR_BASE,
+            const_values: HashMap::new(),
+            trace: vec![],
         }
     }
-
-    fn get_memory_at_mut(&mut self, addr: Value) -> &mut [u8] {
-        let (id, offset) = addr.as_ptr().unwrap();
-        &mut self.mem.get_mut(&id).unwrap()[offset as usize..]
-    }
 }
 fn load_asm(mut file: impl std::io::Read) -> Assembly {
     let mut asm_bytes = Vec::with_capacity(0x100);
@@ -626,6 +1286,24 @@ fn main() {
         .write_all(asm.call_graph().as_bytes())
         .unwrap();
     let mut interpreter = InterpreterState::new(&asm);
-    interpreter.run_cctor().unwrap();
-    interpreter.run_entypoint().unwrap();
+    let res = interpreter
+        .run_cctor()
+        .and_then(|_| interpreter.run_entypoint());
+    std::io::stdout().flush().unwrap();
+    match res {
+        Ok(Value::I32(code)) => std::process::exit(code),
+        Ok(_) => (),
+        Err(Exception::Exit(code)) => std::process::exit(code),
+        Err(Exception::Thrown(exception)) => {
+            eprintln!("Unhandled exception: {exception:?}");
+            std::process::exit(134);
+        }
+        Err(err) => {
+            eprintln!("Interpreter error: {err:?}");
+            for frame in &interpreter.trace {
+                eprintln!("\tin {frame}");
+            }
+            std::process::exit(101);
+        }
+    }
 }
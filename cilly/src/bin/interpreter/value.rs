@@ -0,0 +1,162 @@
+use cilly::{IString, Type};
+
+use crate::Exception;
+
+/// A value on the CIL evaluation stack.
+///
+/// Just like in the CLR, all the integers smaller than 32 bits are widened to `I32`, and signedness is decided by the
+/// instruction which operates on the value. Pointers, managed references and function pointers are all `USize`s.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Undef,
+    I32(i32),
+    I64(i64),
+    USize(usize),
+    F32(f32),
+    F64(f64),
+    /// A `System.Int128`
+    I128(i128),
+    /// A `System.UInt128`
+    U128(u128),
+    /// The raw bytes of a valuetype.
+    Struct(Box<[u8]>),
+    /// A null object reference.
+    Null,
+    String(IString),
+    StringArray(Box<[IString]>),
+    /// A managed exception object, of type `class`.
+    Exception {
+        class: IString,
+        message: IString,
+    },
+    /// A runtime type handle, loaded by `ldtoken`.
+    TypeHandle(Box<Type>),
+}
+impl Value {
+    /// Returns the value of an integer as an `i64`. Native ints are reinterpreted, 32 bit ints are sign extended.
+    pub fn as_i64(&self) -> Result<i64, Exception> {
+        match self {
+            Self::I32(val) => Ok(i64::from(*val)),
+            Self::I64(val) => Ok(*val),
+            Self::USize(val) => Ok(*val as i64),
+            _ => Err(Exception::InvalidOperand(format!(
+                "Expected an integer, got {self:?}"
+            ))),
+        }
+    }
+    /// Returns the value of an integer as an `u64`. 32 bit ints are zero extended.
+    pub fn as_u64(&self) -> Result<u64, Exception> {
+        match self {
+            Self::I32(val) => Ok(u64::from(*val as u32)),
+            _ => self.as_i64().map(|val| val as u64),
+        }
+    }
+    pub fn as_i32(&self) -> Result<i32, Exception> {
+        self.as_i64().map(|val| val as i32)
+    }
+    pub fn as_usize(&self) -> Result<usize, Exception> {
+        match self {
+            Self::I32(val) => Ok(*val as isize as usize),
+            _ => self.as_i64().map(|val| val as usize),
+        }
+    }
+    pub fn as_bool(&self) -> Result<bool, Exception> {
+        match self {
+            Self::Null => Ok(false),
+            Self::String(_) | Self::StringArray(_) | Self::Exception { .. } => Ok(true),
+            _ => self.as_i64().map(|val| val != 0),
+        }
+    }
+    pub fn as_f64(&self) -> Result<f64, Exception> {
+        match self {
+            Self::F32(val) => Ok(f64::from(*val)),
+            Self::F64(val) => Ok(*val),
+            _ => Err(Exception::InvalidOperand(format!(
+                "Expected a float, got {self:?}"
+            ))),
+        }
+    }
+    pub fn as_string(&self) -> Result<&str, Exception> {
+        match self {
+            Self::String(string) => Ok(string),
+            _ => Err(Exception::InvalidOperand(format!(
+                "Expected a string, got {self:?}"
+            ))),
+        }
+    }
+    /// Returns the value of a 128 bit integer, or an integer widened to 128 bits.
+    pub fn as_u128(&self) -> Result<u128, Exception> {
+        match self {
+            Self::I128(val) => Ok(*val as u128),
+            Self::U128(val) => Ok(*val),
+            _ => self.as_i64().map(|val| val as i128 as u128),
+        }
+    }
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::F32(_) | Self::F64(_))
+    }
+    /// Creates a boolean value, which is an `I32` on the evaluation stack.
+    pub fn bool(val: bool) -> Self {
+        Self::I32(i32::from(val))
+    }
+    /// Converts an integer or float to the evaluation stack representation of `tpe`. Used by `op_Implicit` / `op_Explicit`.
+    pub fn convert_to(&self, tpe: &Type) -> Result<Self, Exception> {
+        let int = || -> Result<i128, Exception> {
+            match self {
+                Self::F32(val) => Ok(*val as i128),
+                Self::F64(val) => Ok(*val as i128),
+                Self::I128(val) => Ok(*val),
+                Self::U128(val) => Ok(*val as i128),
+                _ => self.as_i64().map(i128::from),
+            }
+        };
+        let unsigned = || -> Result<u128, Exception> {
+            match self {
+                Self::F32(val) => Ok(*val as u128),
+                Self::F64(val) => Ok(*val as u128),
+                Self::I128(val) => Ok(*val as u128),
+                Self::U128(val) => Ok(*val),
+                _ => self.as_u64().map(u128::from),
+            }
+        };
+        let float = || -> Result<f64, Exception> {
+            match self {
+                Self::I128(val) => Ok(*val as f64),
+                Self::U128(val) => Ok(*val as f64),
+                Self::F32(_) | Self::F64(_) => self.as_f64(),
+                _ => self.as_i64().map(|val| val as f64),
+            }
+        };
+        Ok(match tpe {
+            Type::Bool => Self::bool(int()? != 0),
+            Type::I8 => Self::I32(i32::from(int()? as i8)),
+            Type::U8 => Self::I32(i32::from(unsigned()? as u8)),
+            Type::I16 => Self::I32(i32::from(int()? as i16)),
+            Type::U16 | Type::DotnetChar => Self::I32(i32::from(unsigned()? as u16)),
+            Type::I32 => Self::I32(int()? as i32),
+            Type::U32 => Self::I32(unsigned()? as u32 as i32),
+            Type::I64 => Self::I64(int()? as i64),
+            Type::U64 => Self::I64(unsigned()? as u64 as i64),
+            Type::ISize => Self::USize(int()? as isize as usize),
+            Type::USize | Type::Ptr(_) => Self::USize(unsigned()? as usize),
+            Type::F32 => Self::F32(float()? as f32),
+            Type::F64 => Self::F64(float()?),
+            Type::I128 => Self::I128(int()?),
+            Type::U128 => Self::U128(unsigned()?),
+            Type::DotnetType(tpe) if tpe.asm() == Some("System.Runtime") => match tpe.name_path() {
+                "System.Int128" => Self::I128(int()?),
+                "System.UInt128" => Self::U128(unsigned()?),
+                _ => {
+                    return Err(Exception::InvalidOperand(format!(
+                        "Can't convert {self:?} to {tpe:?}"
+                    )))
+                }
+            },
+            _ => {
+                return Err(Exception::InvalidOperand(format!(
+                    "Can't convert {self:?} to {tpe:?}"
+                )))
+            }
+        })
+    }
+}
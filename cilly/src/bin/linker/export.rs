@@ -2,8 +2,8 @@ use cilly::{
     asm::Assembly,
     asm_exporter::{AssemblyExportError, AssemblyExporter},
     ilasm_exporter::ILASMExporter,
+    pe_exporter::PEExporter,
 };
-use lazy_static::*;
 use std::path::Path;
 
 pub fn export_assembly(
@@ -11,22 +11,21 @@ pub fn export_assembly(
     path: impl AsRef<Path>,
     is_lib: bool,
 ) -> Result<(), AssemblyExportError> {
+    let config = cilly::config::config();
+    if config.direct_pe {
+        return PEExporter::export_assembly(
+            PEExporter::default(),
+            asm,
+            path.as_ref(),
+            is_lib,
+            config.escape_names,
+        );
+    }
     ILASMExporter::export_assembly(
         ILASMExporter::default(),
         asm,
         path.as_ref(),
         is_lib,
-        *ESCAPE_NAMES,
+        config.escape_names,
     )
 }
-lazy_static! {
-    #[doc = "Tells the codegen to escape class and method names."]pub static ref ESCAPE_NAMES:bool = {
-        std::env::vars().into_iter().find_map(|(key,value)|if key == stringify!(ESCAPE_NAMES){
-            Some(value)
-        }else {
-            None
-        }).map(|value|match value.as_ref(){
-            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(ESCAPE_NAMES),value),
-        }).unwrap_or(false)
-    };
-}
@@ -440,6 +440,13 @@ fn autopatch(asm: &mut Assembly, native_pastrough: &NativePastroughInfo) {
             override_pthread_atfork(&mut patched, call);
             continue;
         }
+        if name == "_Unwind_RaiseException" {
+            patched.insert(
+                (*call).clone(),
+                cilly::unwind::raise_exception(call, cilly::config::config().c_mode),
+            );
+            continue;
+        }
         //#[cfg(not(target_os = "linux"))]
         if libc_fns::LIBC_FNS.iter().any(|libc_fn| *libc_fn == name) {
             externs.push((
@@ -680,6 +687,7 @@ fn main() {
         .position(|arg| arg == "-o")
         .expect("No output file!")];
     // Configs
+    let config = cilly::config::config();
     let aot_compile_mode = aot_compile_mode(args);
     let cargo_support = args.iter().any(|arg| arg.contains("--cargo-support"));
 
@@ -693,25 +701,57 @@ fn main() {
     let mut native_pastrough = NativePastroughInfo::new();
     #[cfg(target_os = "linux")]
     {
-        if *NATIVE_PASSTROUGH {
+        if config.native_passtrough {
             add_shared(get_libc(), &mut native_pastrough);
         }
     }
-    if *NATIVE_PASSTROUGH {
+    if config.native_passtrough {
         handle_native_passtrough(args, &linkables, output_file_path, &mut native_pastrough);
     }
 
-    if !*ABORT_ON_ERROR {
+    // Stubs of native functions call them, so they must be added before those calls get resolved.
+    cilly::native_callbacks::add_unmanaged_stubs(&mut final_assembly);
+    if !config.abort_on_error {
         autopatch(&mut final_assembly, &native_pastrough);
     }
     let is_lib = output_file_path.contains(".dll")
         || output_file_path.contains(".so")
         || output_file_path.contains(".o");
     add_mandatory_statics(&mut final_assembly);
+    if is_lib {
+        let class = if config.exports_class.is_empty() {
+            let path = std::path::Path::new(output_file_path);
+            let stem = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("rust");
+            let stem = match path.extension().and_then(|ext| ext.to_str()) {
+                Some("so") => stem.strip_prefix("lib").unwrap_or(stem),
+                _ => stem,
+            };
+            cilly::exports::pascal_case(stem)
+        } else {
+            config.exports_class.clone()
+        };
+        cilly::exports::add_export_wrappers(&mut final_assembly, &class);
+    }
     if !is_lib {
         final_assembly.eliminate_dead_code();
     }
-    if *C_MODE {
+    if !config.why_kept.is_empty() {
+        match final_assembly.why_kept(&config.why_kept, is_lib) {
+            Some(explanation) => print!("{explanation}"),
+            None => println!("Nothing named like {:?} is kept.", config.why_kept),
+        }
+    }
+    if !config.size_report.is_empty() {
+        std::fs::write(
+            &config.size_report,
+            cilly::reachability::size_report(&final_assembly),
+        )
+        .expect("Could not write the size report");
+    }
+    if config.c_mode {
         type Exporter = cilly::c_exporter::CExporter;
         use cilly::asm_exporter::AssemblyExporter;
         println!(
@@ -725,7 +765,7 @@ fn main() {
             is_lib,
             true,
         )
-        .unwrap();
+        .unwrap_or_else(|err| panic!("Could not build the C source: {err}"));
         return;
     }
 
@@ -736,6 +776,13 @@ fn main() {
     final_assembly
         .save_tmp(&mut std::fs::File::create(path.with_extension("cilly")).unwrap())
         .unwrap();
+    // Lets stack traces of the final assembly be mapped back to Rust functions.
+    std::fs::write(
+        path.with_extension("symbols"),
+        cilly::symbol_map::SymbolMap::from_assembly(&final_assembly, config.escape_names)
+            .to_string(),
+    )
+    .expect("Could not write the symbol map");
     // Run AOT compiler
     aot_compile_mode.compile(output_file_path);
 
@@ -745,7 +792,7 @@ fn main() {
         let bootstrap = format!(
             include_str!("dotnet_jumpstart.rs"),
             exec_file = path.file_name().unwrap().to_string_lossy(),
-            has_native_companion = *NATIVE_PASSTROUGH,
+            has_native_companion = config.native_passtrough,
             has_pdb = match *ILASM_FLAVOUR {
                 IlasmFlavour::Clasic => false,
                 IlasmFlavour::Modern => true,
@@ -757,7 +804,7 @@ fn main() {
                     output_file_path = path.file_name().unwrap().to_string_lossy()
                 ),
             },
-            native_companion_file = if *NATIVE_PASSTROUGH {
+            native_companion_file = if config.native_passtrough {
                 format!(
                     "rust_native_{output_file_path}.so",
                     output_file_path = file_stem(output_file_path)
@@ -787,39 +834,6 @@ fn main() {
     }
     //todo!();
 }
-lazy_static! {
-    #[doc = "Tells the codegen compile linked static libraries into a shared library, which will be bundled with the .NET executable."]pub static ref NATIVE_PASSTROUGH:bool = {
-        std::env::vars().into_iter().find_map(|(key,value)|if key == stringify!(NATIVE_PASSTROUGH){
-            Some(value)
-        }else {
-            None
-        }).map(|value|match value.as_ref(){
-            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(NATIVE_PASSTROUGH),value),
-        }).unwrap_or(false)
-    };
-}
-lazy_static! {
-    #[doc = "Should the codegen stop working when ecountering an error, or try to press on, replacing unusuported code with exceptions throws?"]pub static ref ABORT_ON_ERROR:bool = {
-        std::env::vars().into_iter().find_map(|(key,value)|if key == stringify!(ABORT_ON_ERROR){
-            Some(value)
-        }else {
-            None
-        }).map(|value|match value.as_ref(){
-            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(ABORT_ON_ERROR),value),
-        }).unwrap_or(false)
-    };
-}
-lazy_static! {
-    #[doc = "Tells the codegen to emmit C source files."]pub static ref C_MODE:bool = {
-        std::env::vars().into_iter().find_map(|(key,value)|if key == stringify!(C_MODE){
-            Some(value)
-        }else {
-            None
-        }).map(|value|match value.as_ref(){
-            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(C_MODE),value),
-        }).unwrap_or(false)
-    };
-}
 fn override_errno(asm: &mut Assembly) {
     for method in asm.methods_mut() {
         if method.name().contains("errno")
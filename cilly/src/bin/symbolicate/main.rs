@@ -0,0 +1,30 @@
+//! Rewrites a .NET stack trace, replacing the names of methods with the Rust functions they were compiled from.
+//!
+//! Usage: `symbolicate <symbol map> [stack trace]`. The symbol map is written by the linker next to the assembly, with
+//! the `symbols` extension. If no stack trace file is given, the trace is read from the standard input.
+use std::io::Read;
+
+use cilly::symbol_map::SymbolMap;
+
+fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(map_path) = args.get(1) else {
+        eprintln!("Usage: symbolicate <symbol map> [stack trace]");
+        std::process::exit(2);
+    };
+    let map = std::fs::read_to_string(map_path)
+        .unwrap_or_else(|err| panic!("Could not read the symbol map {map_path:?}: {err}"));
+    let map = SymbolMap::parse(&map);
+    let trace = match args.get(2) {
+        Some(trace_path) => std::fs::read_to_string(trace_path)
+            .unwrap_or_else(|err| panic!("Could not read the stack trace {trace_path:?}: {err}")),
+        None => {
+            let mut trace = String::new();
+            std::io::stdin()
+                .read_to_string(&mut trace)
+                .expect("Could not read the stack trace from stdin");
+            trace
+        }
+    };
+    print!("{}", map.symbolicate(&trace));
+}
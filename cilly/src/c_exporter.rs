@@ -1,26 +1,279 @@
+use crate::access_modifier::AccessModifer;
 use crate::asm::AssemblyExternRef;
+use crate::basic_block::{BasicBlock, Handler};
+use crate::call_site::CallSite;
 use crate::cil_node::CILNode;
 use crate::cil_root::CILRoot;
-use crate::cil_tree::CILTree;
+use crate::custom_attribute::{AGGRESSIVE_INLINING, NO_INLINING};
 use crate::method::Method;
 use crate::type_def::TypeDef;
 
 use crate::asm_exporter::{AssemblyExportError, AssemblyExporter, AssemblyInfo};
 
-use crate::{r#type::Type, IString};
+use crate::{r#type::Type, FnSig, IString};
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use std::collections::HashMap;
 use std::hash::Hasher;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::{borrow::Cow, collections::HashSet, io::Write};
 pub struct CExporter {
     types: Vec<u8>,
     type_defs: Vec<u8>,
     method_defs: Vec<u8>,
+    static_decls: Vec<u8>,
     static_defs: Vec<u8>,
     encoded_asm: Vec<u8>,
-    headers: Vec<u8>,
+    /// The definitions of methods. They are split between C source files when building in parallel.
+    method_bodies: Vec<Vec<u8>>,
     defined: HashSet<IString>,
     delayed_typedefs: HashMap<IString, TypeDef>,
+    toolchain: CToolchain,
+    /// All the types of this assembly, used to describe the types the exported functions use.
+    type_map: HashMap<IString, TypeDef>,
+    /// Functions exported from Rust(`#[no_mangle] pub extern fn`), declared in the header of a library.
+    exported: Vec<(IString, FnSig)>,
+}
+/// What [`CExporter`] builds from the C source.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum COutputKind {
+    /// An executable, or a shared library if the assembly is a library.
+    #[default]
+    Executable,
+    /// An object file.
+    Object,
+    /// A static library.
+    StaticLib,
+    /// Only the C source, to be built by a separate build system.
+    Source,
+}
+impl COutputKind {
+    /// The name of this output kind, as used in the configuration.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Executable => "executable",
+            Self::Object => "object",
+            Self::StaticLib => "static_lib",
+            Self::Source => "source",
+        }
+    }
+    /// Returns the output kind named `name`.
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Self::Executable,
+            Self::Object,
+            Self::StaticLib,
+            Self::Source,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == name)
+    }
+}
+/// The C compiler used to build the exported source, and the way it is invoked.
+#[derive(Clone, Debug)]
+pub struct CToolchain {
+    compiler: IString,
+    opt_level: IString,
+    flags: Vec<IString>,
+    sanitizers: Vec<IString>,
+    output: COutputKind,
+    units: usize,
+    freestanding: bool,
+}
+impl Default for CToolchain {
+    fn default() -> Self {
+        Self {
+            compiler: "gcc".into(),
+            opt_level: "1".into(),
+            flags: vec![],
+            sanitizers: vec![],
+            output: COutputKind::Executable,
+            units: 1,
+            freestanding: false,
+        }
+    }
+}
+impl CToolchain {
+    /// The toolchain set up by the `c_*` options of `config`.
+    #[must_use]
+    pub fn from_config(config: &crate::config::Config) -> Self {
+        let sanitizers: Vec<&str> = match config.c_sanitize.as_str() {
+            // `C_SANITIZE` used to be a boolean flag, so its values are still accepted.
+            "1" | "true" => vec!["undefined"],
+            "0" | "false" => vec![],
+            sanitizers => sanitizers
+                .split(',')
+                .map(str::trim)
+                .filter(|sanitizer| !sanitizer.is_empty())
+                .collect(),
+        };
+        Self::default()
+            .with_compiler(&config.c_compiler)
+            .with_opt_level(&config.c_opt_level)
+            .with_flags(config.c_flags.split_whitespace())
+            .with_sanitizers(sanitizers)
+            .with_output(config.c_output)
+            .with_units(config.c_units)
+            .with_freestanding(config.c_freestanding)
+    }
+    /// Sets the C compiler to `compiler`: `gcc`, `clang`, `tcc` or a path to one of them.
+    #[must_use]
+    pub fn with_compiler(mut self, compiler: &str) -> Self {
+        self.compiler = compiler.into();
+        self
+    }
+    /// Sets the optimization level, passed to the compiler as `-O{opt_level}`.
+    #[must_use]
+    pub fn with_opt_level(mut self, opt_level: &str) -> Self {
+        self.opt_level = opt_level.into();
+        self
+    }
+    /// Adds `flags` to the flags passed to the compiler.
+    #[must_use]
+    pub fn with_flags<'a>(mut self, flags: impl IntoIterator<Item = &'a str>) -> Self {
+        self.flags.extend(flags.into_iter().map(Into::into));
+        self
+    }
+    /// Adds `sanitizers`(like `undefined` or `address`) to the sanitizers the source is built with.
+    #[must_use]
+    pub fn with_sanitizers<'a>(mut self, sanitizers: impl IntoIterator<Item = &'a str>) -> Self {
+        self.sanitizers
+            .extend(sanitizers.into_iter().map(Into::into));
+        self
+    }
+    /// Sets what is built from the C source.
+    #[must_use]
+    pub const fn with_output(mut self, output: COutputKind) -> Self {
+        self.output = output;
+        self
+    }
+    /// Sets the number of C source files the methods are split into. Those files are compiled in parallel.
+    #[must_use]
+    pub const fn with_units(mut self, units: usize) -> Self {
+        self.units = units;
+        self
+    }
+    /// Targets a freestanding environment, without libc, if `freestanding` is true. The embedder provides the
+    /// allocator and I/O, through the `__rcl_*` functions declared in `c_freestanding.h`, and links the result with
+    /// libgcc, which implements operations like 128 bit division.
+    ///
+    /// Without libc, there is no startup code to call `main`, so freestanding executables are built as object files.
+    /// An executable can still be requested afterwards, if the embedder passes its own entry point in the flags.
+    #[must_use]
+    pub const fn with_freestanding(mut self, freestanding: bool) -> Self {
+        self.freestanding = freestanding;
+        if freestanding && matches!(self.output, COutputKind::Executable) {
+            self.output = COutputKind::Object;
+        }
+        self
+    }
+    /// Returns what is built from the C source.
+    #[must_use]
+    pub const fn output(&self) -> COutputKind {
+        self.output
+    }
+    /// Returns the number of C source files the methods are split into.
+    #[must_use]
+    pub const fn units(&self) -> usize {
+        self.units
+    }
+    /// Checks if the toolchain targets a freestanding environment.
+    #[must_use]
+    pub const fn freestanding(&self) -> bool {
+        self.freestanding
+    }
+    /// Compiles the C source files `sources` in parallel, and then links them into `final_path`.
+    fn build(
+        &self,
+        sources: &[PathBuf],
+        final_path: &Path,
+        is_dll: bool,
+    ) -> Result<(), AssemblyExportError> {
+        if self.output == COutputKind::Source {
+            return Ok(());
+        }
+        let objects: Vec<_> = if self.output == COutputKind::Object && sources.len() == 1 {
+            vec![final_path.to_owned()]
+        } else {
+            sources.iter().map(|src| src.with_extension("o")).collect()
+        };
+        sources
+            .par_iter()
+            .zip(objects.par_iter())
+            .try_for_each(|(src, obj)| {
+                let mut cc = self.command(is_dll);
+                cc.arg("-c").arg("-o").arg(obj).arg(src);
+                run_tool(cc)
+            })?;
+        match self.output {
+            COutputKind::Executable => {
+                let mut cc = self.command(is_dll);
+                if is_dll {
+                    cc.arg("-shared");
+                }
+                cc.arg("-o").arg(final_path).args(&objects);
+                if self.freestanding {
+                    // `-nostdlib` drops libgcc too, but the code still calls its helpers.
+                    cc.args(["-nostdlib", "-lgcc"]);
+                } else {
+                    cc.arg("-lm");
+                }
+                run_tool(cc)
+            }
+            COutputKind::Object if objects.len() > 1 => {
+                let mut cc = self.command(is_dll);
+                cc.arg("-r").arg("-o").arg(final_path).args(&objects);
+                run_tool(cc)
+            }
+            COutputKind::StaticLib => {
+                let mut ar = Command::new("ar");
+                ar.arg("rcs").arg(final_path).args(&objects);
+                run_tool(ar)
+            }
+            COutputKind::Object | COutputKind::Source => Ok(()),
+        }
+    }
+    /// The compiler invocation, with the flags used both when compiling and linking.
+    fn command(&self, is_dll: bool) -> Command {
+        let mut cc = Command::new(self.compiler.as_ref());
+        cc.args([
+            "-g",
+            &format!("-O{}", self.opt_level),
+            "-fno-strict-aliasing",
+        ]);
+        if !self.sanitizers.is_empty() {
+            cc.arg(format!("-fsanitize={}", self.sanitizers.join(",")));
+        }
+        cc.args(self.flags.iter().map(AsRef::<str>::as_ref));
+        if is_dll {
+            cc.arg("-fPIC");
+        }
+        if self.freestanding {
+            cc.arg("-ffreestanding");
+        }
+        cc
+    }
+}
+/// Runs the build tool `cmd`, turning its failures into errors.
+fn run_tool(mut cmd: Command) -> Result<(), AssemblyExportError> {
+    let program = cmd.get_program().to_string_lossy().into_owned();
+    let out = cmd.output().map_err(|err| {
+        AssemblyExportError::ExporterError(format!("Could not run {program}: {err}").into())
+    })?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(AssemblyExportError::ExporterError(
+            format!(
+                "{program} failed({status}):\n{stderr}",
+                status = out.status,
+                stderr = String::from_utf8_lossy(&out.stderr)
+            )
+            .into(),
+        ))
+    }
 }
 impl CExporter {
     pub fn init(_asm_info: &AssemblyInfo) -> Self {
@@ -28,17 +281,13 @@ impl CExporter {
         let types = Vec::with_capacity(0x1_00);
         let type_defs = Vec::with_capacity(0x1_00);
         let method_defs = Vec::with_capacity(0x1_00);
-        let static_defs = Vec::with_capacity(0x1_00);
-        let mut headers = Vec::with_capacity(0x1_00);
-        write!(headers, "/*  This file was autogenerated by `rustc_codegen_clr` by FractalFir\n It contains C code made from Rust.*/\n").expect("Write error!");
-
-        write!(
-            headers,
-            "#include  <stdint.h>\n#include <stdbool.h>\n#include <stddef.h>\n#include <stdio.h>\n#include <stdlib.h>\n#include <mm_malloc.h>\n#include <sys/syscall.h>\n"
+        let static_decls = Vec::with_capacity(0x1_00);
+        let mut static_defs = Vec::with_capacity(0x1_00);
+        writeln!(
+            static_defs,
+            "char* exec_fname;\n_Thread_local unwind_frame* unwind_top;"
         )
-        .expect("Write error!");
-        headers.write_all(include_bytes!("c_header.h")).unwrap();
-        writeln!(headers).expect("Write error!");
+        .unwrap();
         writeln!(
             encoded_asm,
             "#pragma GCC diagnostic ignored \"-Wmaybe-uninitialized\""
@@ -69,12 +318,22 @@ impl CExporter {
             type_defs,
             encoded_asm,
             method_defs,
+            static_decls,
             static_defs,
-            headers,
+            method_bodies: Vec::new(),
             defined: HashSet::new(),
             delayed_typedefs: HashMap::new(),
+            toolchain: CToolchain::from_config(crate::config::config()),
+            type_map: HashMap::new(),
+            exported: Vec::new(),
         }
     }
+    /// Sets the toolchain used to build the exported C source. By default, it is set up by the configuration.
+    #[must_use]
+    pub fn with_toolchain(mut self, toolchain: CToolchain) -> Self {
+        self.toolchain = toolchain;
+        self
+    }
 }
 impl std::io::Write for CExporter {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
@@ -84,6 +343,15 @@ impl std::io::Write for CExporter {
         self.encoded_asm.flush()
     }
 }
+/// Functions declared by the libc headers included in hosted C code.
+const HOSTED_PROVIDED: &[&str] = &[
+    "puts", "malloc", "printf", "free", "realloc", "syscall", "getenv", "rename",
+];
+/// Functions defined on top of the runtime of the embedder in freestanding C code.
+const FREESTANDING_PROVIDED: &[&str] = &["puts", "malloc", "free", "realloc"];
+/// Runs the static constructor, and then the entrypoint of an executable.
+const C_MAIN: &str =
+    "int main(int argc,char** argv){_cctor();exec_fname = argv[0];entrypoint(argv + 1);}\n";
 fn escape_type_name(name: &str) -> String {
     name.replace(['.', ' '], "_")
         .replace('<', "lt")
@@ -96,72 +364,222 @@ fn escape_type_name(name: &str) -> String {
 }
 impl CExporter {
     fn as_source(&self, is_dll: bool) -> Vec<u8> {
-        let mut res = self.headers.clone();
+        let mut res = self.as_decls();
+        res.extend(&self.static_defs);
+        for body in &self.method_bodies {
+            res.extend(body);
+        }
+        if !is_dll {
+            res.extend(C_MAIN.as_bytes());
+        }
+        res
+    }
+    /// The includes and the runtime support code of the C environment the toolchain targets.
+    fn prelude(&self) -> Vec<u8> {
+        let mut res = Vec::with_capacity(0x1_00);
+        write!(res, "/*  This file was autogenerated by `rustc_codegen_clr` by FractalFir\n It contains C code made from Rust.*/\n").expect("Write error!");
+        if self.toolchain.freestanding() {
+            write!(
+                res,
+                "#include  <stdint.h>\n#include <stdbool.h>\n#include <stddef.h>\n"
+            )
+            .expect("Write error!");
+        } else {
+            write!(
+                res,
+                "#include  <stdint.h>\n#include <stdbool.h>\n#include <stddef.h>\n#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n#include <alloca.h>\n#include <setjmp.h>\n#include <mm_malloc.h>\n#include <sys/syscall.h>\n"
+            )
+            .expect("Write error!");
+        }
+        res.extend(include_bytes!("c_header.h"));
+        if self.toolchain.freestanding() {
+            res.extend(include_bytes!("c_freestanding.h"));
+        } else {
+            res.extend(include_bytes!("c_hosted.h"));
+        }
+        writeln!(res).expect("Write error!");
+        res
+    }
+    /// Checks if the function `name` is already declared or defined by the C environment.
+    fn is_provided(&self, name: &str) -> bool {
+        if self.toolchain.freestanding() {
+            FREESTANDING_PROVIDED.contains(&name)
+        } else {
+            HOSTED_PROVIDED.contains(&name)
+        }
+    }
+    /// The declarations of types, methods and statics, shared by all the C source files.
+    fn as_decls(&self) -> Vec<u8> {
+        let mut res = self.prelude();
         res.extend(&self.types);
         res.extend(&self.type_defs);
         res.extend(&self.method_defs);
-        res.extend(&self.static_defs);
+        res.extend(&self.static_decls);
         res.extend(&self.encoded_asm);
+        res
+    }
+    /// Splits the methods between `units` C source files, which include the shared declarations from `decls`.
+    fn as_units(&self, decls: &str, units: usize, is_dll: bool) -> Vec<Vec<u8>> {
+        let mut res: Vec<Vec<u8>> = (0..units.max(1))
+            .map(|_| format!("#include \"{decls}\"\n").into_bytes())
+            .collect();
+        res[0].extend(&self.static_defs);
         if !is_dll {
-            writeln!(res, "int main(int argc,char** argv){{_cctor();exec_fname = argv[0];entrypoint(argv + 1);}}").unwrap();
+            res[0].extend(C_MAIN.as_bytes());
+        }
+        for body in &self.method_bodies {
+            // Files of similar size take a similar time to compile.
+            let unit = res.iter_mut().min_by_key(|unit| unit.len()).unwrap();
+            unit.extend(body);
+        }
+        res
+    }
+    /// Creates a header declaring the functions exported from Rust, and the types they use, so C code can link with
+    /// the library built from this assembly.
+    fn as_header(&self, guard: &str) -> Vec<u8> {
+        let mut forward = Vec::new();
+        let mut defs = Vec::new();
+        let mut described = HashSet::new();
+        for (_, sig) in &self.exported {
+            for tpe in sig.inputs().iter().chain(std::iter::once(sig.output())) {
+                self.describe_type(tpe, &mut described, &mut forward, &mut defs);
+            }
         }
+        let mut res = Vec::with_capacity(0x1_00);
+        write!(res, "/*  This file was autogenerated by `rustc_codegen_clr` by FractalFir\n It declares the functions exported from Rust.*/\n").unwrap();
+        write!(res, "#ifndef {guard}\n#define {guard}\n#include <stdint.h>\n#include <stdbool.h>\n#include <stddef.h>\n#ifdef __cplusplus\nextern \"C\" {{\n#endif\n").unwrap();
+        res.extend(forward);
+        res.extend(defs);
+        for (name, sig) in &self.exported {
+            writeln!(
+                res,
+                "{output} {name}{inputs};",
+                output = c_tpe(sig.output()),
+                inputs = c_inputs(sig)
+            )
+            .unwrap();
+        }
+        write!(res, "#ifdef __cplusplus\n}}\n#endif\n#endif\n").unwrap();
         res
     }
+    /// Writes the definition of `tpe`, and of all the types it depends on, if they are defined in this assembly.
+    fn describe_type(
+        &self,
+        tpe: &Type,
+        described: &mut HashSet<IString>,
+        forward: &mut Vec<u8>,
+        defs: &mut Vec<u8>,
+    ) {
+        match tpe {
+            Type::Ptr(inner) | Type::ManagedReference(inner) => {
+                self.describe_type(inner, described, forward, defs);
+            }
+            Type::ManagedArray { element, .. } => {
+                self.describe_type(element, described, forward, defs);
+            }
+            Type::DotnetType(tref) if tref.asm().is_none() => {
+                let name: IString = escape_type_name(tref.name_path()).into();
+                if !described.insert(name.clone()) {
+                    return;
+                }
+                let Some(tpe) = self.type_map.get(&name) else {
+                    return;
+                };
+                for (_, field) in tpe.fields() {
+                    self.describe_type(field, described, forward, defs);
+                }
+                let kind = if tpe.explicit_offsets().is_some() {
+                    "union"
+                } else {
+                    "struct"
+                };
+                writeln!(forward, "typedef {kind} {name} {name};").unwrap();
+                writeln!(defs, "{kind} {name}{{\n{}}};", type_fields(tpe)).unwrap();
+            }
+            _ => (),
+        }
+    }
     fn add_method_inner(&mut self, method: &Method, class: Option<&str>) {
         //eprintln!("C source:\n{}",String::from_utf8_lossy(&self.as_source()));
         let sig = method.sig();
 
         let name = method.name().replace('.', "_");
-        // Puts is already defined in C.
-        if name == "puts"
-            || name == "malloc"
-            || name == "printf"
-            || name == "free"
-            || name == "realloc"
-            || name == "syscall"
-        {
+        // Functions provided by the C environment can't be redefined.
+        if self.is_provided(&name) {
             return;
         }
         let output = c_tpe(sig.output());
-        let mut inputs: String = "(".into();
-        let mut input_iter = sig
-            .inputs()
-            .iter()
-            .enumerate()
-            .filter(|(_, tpe)| **tpe != Type::Void);
-        if let Some((idx, input)) = input_iter.next() {
-            inputs.push_str(&format!("{input} A{idx}", input = c_tpe(input)));
-        }
-        for (idx, input) in input_iter {
-            inputs.push_str(&format!(",{input} A{idx} ", input = c_tpe(input)));
+        let inputs = c_inputs(sig);
+        if class.is_none()
+            && method.access() == AccessModifer::Public
+            && method.export_info().is_some()
+        {
+            self.exported.push((name.clone().into(), sig.clone()));
         }
-        inputs.push(')');
         let mut code = String::new();
+        // Exceptions are implemented using `setjmp` and `longjmp`, so locals changed in a protected block must be
+        // `volatile`, to have a known value in its handler.
+        let protected: Vec<_> = method
+            .blocks()
+            .iter()
+            .filter(|bb| bb.handler().is_some())
+            .map(BasicBlock::id)
+            .collect();
+        let volatile = if protected.is_empty() {
+            ""
+        } else {
+            "volatile "
+        };
         for (id, (_, local)) in method.locals().iter().enumerate() {
             if *local == Type::Void {
                 continue;
             }
-            code.push_str(&format!("\t{local} L{id};\n", local = c_tpe(local)));
+            code.push_str(&format!(
+                "\t{volatile}{local} L{id};\n",
+                local = c_tpe(local)
+            ));
+        }
+        for id in &protected {
+            code.push_str(&format!("\tunwind_frame F{id};\n"));
         }
         for bb in method.blocks() {
             code.push_str(&format!("\tBB_{}:\n", bb.id()));
+            if let Some(Handler::Blocks(handler)) = bb.handler() {
+                // The first block of the handler is the one jumping to its entry.
+                code.push_str(&format!(
+                    "\tunwind_push(&F{id});\n\tif(setjmp(F{id}.buf))goto BB_{id}_{first};\n",
+                    id = bb.id(),
+                    first = handler[0].id()
+                ));
+            }
             for tree in bb.trees() {
-                code.push_str(&format!("{}\n", tree_string(tree, method)));
-                //code.push_str(&format!("/*{tree:?}*/\n"));
+                code.push_str(&format!("{}\n", root_string(tree.root(), method)));
+            }
+            if let Some(Handler::Blocks(handler)) = bb.handler() {
+                for handler_bb in handler {
+                    code.push_str(&format!("\tBB_{}_{}:\n", bb.id(), handler_bb.id()));
+                    for tree in handler_bb.trees() {
+                        code.push_str(&format!("{}\n", root_string(tree.root(), method)));
+                    }
+                }
             }
         }
-        if let Some(class) = class {
-            let class = escape_type_name(class);
-            writeln!(self.method_defs, "{output} {class}{name} {inputs};").unwrap();
-            write!(
-                self.encoded_asm,
-                "{output} {class}{name} {inputs}{{\n{code}}}\n"
-            )
-            .unwrap();
+        let impl_options = method.method_impl_options();
+        // Methods may be called from other C source files, so only their definitions can be inline.
+        let (decl_attrs, attrs) = if impl_options & NO_INLINING != 0 {
+            ("__attribute__((noinline)) ", "")
+        } else if impl_options & AGGRESSIVE_INLINING != 0 {
+            ("", "__attribute__((always_inline)) inline ")
         } else {
-            writeln!(self.method_defs, "{output} {name} {inputs};").unwrap();
-            write!(self.encoded_asm, "{output} {name} {inputs}{{\n{code}}}\n").unwrap();
-        }
+            ("", "")
+        };
+        let name = match class {
+            Some(class) => format!("{}{name}", escape_type_name(class)),
+            None => name,
+        };
+        writeln!(self.method_defs, "{decl_attrs}{output} {name} {inputs};").unwrap();
+        self.method_bodies
+            .push(format!("{attrs}{output} {name} {inputs}{{\n{code}}}\n").into_bytes());
     }
 }
 impl AssemblyExporter for CExporter {
@@ -170,6 +588,7 @@ impl AssemblyExporter for CExporter {
         if self.defined.contains(&name) {
             return;
         }
+        self.type_map.insert(name.clone(), tpe.clone());
         for tpe_name in tpe
             .fields()
             .iter()
@@ -188,31 +607,12 @@ impl AssemblyExporter for CExporter {
                 return;
             }
         }
-        let mut fields = String::new();
-        if let Some(offsets) = tpe.explicit_offsets() {
-            for ((field_name, field_type), offset) in tpe.fields().iter().zip(offsets) {
-                if *field_type == Type::Void {
-                    continue;
-                }
-
-                fields.push_str(&format!(
-                    "\tstruct {{char pad[{offset}];{field_type} f;}} {field_name};\n\n",
-                    field_type = c_tpe(field_type)
-                ));
-            }
-        } else {
-            for (field_name, field_type) in tpe.fields() {
-                if *field_type == Type::Void {
-                    continue;
-                }
-                fields.push_str(&format!(
-                    "\tstruct {{{field_type} f;}} {field_name};\n",
-                    field_type = c_tpe(field_type)
-                ));
-            }
-        }
+        let fields = type_fields(tpe);
         for method in tpe.methods() {
-            self.add_method_inner(method, Some(&name));
+            let mut method = method.clone();
+            method.sheed_trees();
+            method.allocate_temporaries();
+            self.add_method_inner(&method, Some(&name));
         }
         if tpe.explicit_offsets().is_some() {
             writeln!(self.types, "typedef union {name} {name};").unwrap();
@@ -240,30 +640,11 @@ impl AssemblyExporter for CExporter {
         sig: &crate::FnSig,
         preserve_errno: bool,
     ) {
-        if name == "puts"
-            || name == "malloc"
-            || name == "printf"
-            || name == "free"
-            || name == "syscall"
-            || name == "getenv"
-            || name == "rename"
-        {
+        if self.is_provided(name) {
             return;
         }
         let output = c_tpe(sig.output());
-        let mut inputs: String = "(".into();
-        let mut input_iter = sig
-            .inputs()
-            .iter()
-            .enumerate()
-            .filter(|(_, tpe)| **tpe != Type::Void);
-        if let Some((idx, input)) = input_iter.next() {
-            inputs.push_str(&format!("{input} A{idx}", input = c_tpe(input)));
-        }
-        for (idx, input) in input_iter {
-            inputs.push_str(&format!(",{input} A{idx} ", input = c_tpe(input)));
-        }
-        inputs.push(')');
+        let inputs = c_inputs(sig);
         writeln!(self.method_defs, "extern {output} {name} {inputs};").unwrap();
     }
 
@@ -272,33 +653,34 @@ impl AssemblyExporter for CExporter {
         final_path: &std::path::Path,
         is_dll: bool,
     ) -> Result<(), AssemblyExportError> {
-        let cc = "gcc";
-        let src_path = final_path.with_extension("c");
-        std::fs::File::create(&src_path)
-            .unwrap()
-            .write_all(&self.as_source(is_dll))
-            .unwrap();
-        let san_undef = false; //*crate::config::C_SANITIZE
-        let sanitize = if san_undef {
-            "-fsanitize=undefined"
+        let stem = final_path.file_stem().unwrap_or_default().to_string_lossy();
+        let sources = if self.toolchain.units() <= 1 {
+            let src_path = final_path.with_extension("c");
+            std::fs::File::create(&src_path)?.write_all(&self.as_source(is_dll))?;
+            vec![src_path]
         } else {
-            "-O"
+            let decls = format!("{stem}_decls.h");
+            std::fs::File::create(final_path.with_file_name(&decls))?
+                .write_all(&self.as_decls())?;
+            self.as_units(&decls, self.toolchain.units(), is_dll)
+                .into_iter()
+                .enumerate()
+                .map(|(idx, unit)| {
+                    let src_path = final_path.with_file_name(format!("{stem}_{idx}.c"));
+                    std::fs::File::create(&src_path)?.write_all(&unit)?;
+                    Ok(src_path)
+                })
+                .collect::<Result<_, AssemblyExportError>>()?
         };
-        let out = Command::new(cc)
-            .args([
-                "-g",
-                sanitize,
-                "-o",
-                final_path.to_string_lossy().as_ref(),
-                src_path.to_string_lossy().as_ref(),
-                "-lm",
-                "-fno-strict-aliasing",
-            ])
-            .output()
-            .unwrap();
-        let stderr = String::from_utf8_lossy(&out.stderr);
-        assert!(!stderr.contains("error"), "C compiler error:{stderr:?}!");
-        Ok(())
+        if is_dll {
+            let guard = format!(
+                "{}_H",
+                escape_type_name(&stem).replace('-', "_").to_uppercase()
+            );
+            std::fs::File::create(final_path.with_extension("h"))?
+                .write_all(&self.as_header(&guard))?;
+        }
+        self.toolchain.build(&sources, final_path, is_dll)
     }
 
     fn add_extern_ref(&mut self, _asm_name: &str, _info: &AssemblyExternRef) {
@@ -306,13 +688,20 @@ impl AssemblyExporter for CExporter {
     }
 
     fn add_global(&mut self, tpe: &crate::r#type::Type, name: &str) {
-        writeln!(self.static_defs, "static {tpe} {name};", tpe = c_tpe(tpe)).unwrap();
+        let tpe = c_tpe(tpe);
+        writeln!(self.static_decls, "extern {tpe} {name};").unwrap();
+        writeln!(self.static_defs, "{tpe} {name};").unwrap();
+    }
+    fn add_thread_local(&mut self, tpe: &crate::r#type::Type, name: &str) {
+        let tpe = c_tpe(tpe);
+        writeln!(self.static_decls, "extern _Thread_local {tpe} {name};").unwrap();
+        writeln!(self.static_defs, "_Thread_local {tpe} {name};").unwrap();
     }
 }
 fn node_string(tree: &CILNode, method: &Method) -> String {
     match tree {
         CILNode::LocAllocAligned { tpe, align } => format!(
-            "(((uintptr_t)alloca(sizeof({tpe})) + {align})) + ({align} - 1)) & (-{align}))",
+            "((((uintptr_t)alloca(sizeof({tpe}) + {align} - 1)) + {align} - 1) & ~((uintptr_t){align} - 1))",
             tpe = c_tpe(tpe)
         ),
         CILNode::PointerToConstValue(_value) => {
@@ -382,7 +771,7 @@ fn node_string(tree: &CILNode, method: &Method) -> String {
                     return format!(
                         "(&(({owner}*)&{ptr})->{name}.f)",
                         ptr = node_string(addr, method),
-                        owner = c_tpe(&field.owner().clone().into()),
+                        owner = escape_type_name(field.owner().name_path()),
                         name = field.name()
                     );
                 }
@@ -395,7 +784,7 @@ fn node_string(tree: &CILNode, method: &Method) -> String {
                     return format!(
                         "(&(({owner}*)&{ptr})->{name}.f)",
                         ptr = node_string(addr, method),
-                        owner = c_tpe(&field.owner().clone().into()),
+                        owner = escape_type_name(field.owner().name_path()),
                         name = field.name()
                     );
                 }
@@ -403,7 +792,7 @@ fn node_string(tree: &CILNode, method: &Method) -> String {
             format!(
                 "(&(({owner}*){ptr})->{name}.f)",
                 ptr = node_string(addr, method),
-                owner = c_tpe(&field.owner().clone().into()),
+                owner = escape_type_name(field.owner().name_path()),
                 name = field.name()
             )
         }
@@ -416,7 +805,7 @@ fn node_string(tree: &CILNode, method: &Method) -> String {
                     return format!(
                         "//{addr:?}\n(({owner}*)&{ptr})->{name}.f",
                         ptr = node_string(addr, method),
-                        owner = c_tpe(&field.owner().clone().into()),
+                        owner = escape_type_name(field.owner().name_path()),
                         name = field.name()
                     );
                 }
@@ -429,7 +818,7 @@ fn node_string(tree: &CILNode, method: &Method) -> String {
                     return format!(
                         "//{addr:?}\n(({owner}*)&{ptr})->{name}.f",
                         ptr = node_string(addr, method),
-                        owner = c_tpe(&field.owner().clone().into()),
+                        owner = escape_type_name(field.owner().name_path()),
                         name = field.name()
                     );
                 }
@@ -437,7 +826,7 @@ fn node_string(tree: &CILNode, method: &Method) -> String {
             format!(
                 "//{addr:?}\n(({owner}*){ptr})->{name}.f",
                 ptr = node_string(addr, method),
-                owner = c_tpe(&field.owner().clone().into()),
+                owner = escape_type_name(field.owner().name_path()),
                 name = field.name()
             )
         }
@@ -466,13 +855,11 @@ fn node_string(tree: &CILNode, method: &Method) -> String {
             a = node_string(a, method),
             b = node_string(b, method)
         ),
-        CILNode::Rem(a, b) | CILNode::RemUn(a, b) => {
-            format!(
-                "{a} % {b}",
-                a = node_string(a, method),
-                b = node_string(b, method)
-            )
-        }
+        CILNode::Rem(a, b) | CILNode::RemUn(a, b) => format!(
+            "({a}) % ({b})",
+            a = node_string(a, method),
+            b = node_string(b, method)
+        ),
         CILNode::Or(a, b) => format!(
             "({a}) | ({b})",
             a = node_string(a, method),
@@ -483,46 +870,51 @@ fn node_string(tree: &CILNode, method: &Method) -> String {
             a = node_string(a, method),
             b = node_string(b, method)
         ),
-        CILNode::Shr(a, b) => format!(
-            "{a} >> {b}",
+        CILNode::Shr(a, b) | CILNode::ShrUn(a, b) => format!(
+            "({a}) >> ({b})",
+            a = node_string(a, method),
+            b = node_string(b, method)
+        ),
+        CILNode::Shl(a, b) => format!(
+            "({a}) << ({b})",
             a = node_string(a, method),
             b = node_string(b, method)
         ),
-        CILNode::Shl(a, b) | CILNode::ShrUn(a, b) => {
-            format!(
-                "{a} << {b}",
-                a = node_string(a, method),
-                b = node_string(b, method)
-            )
-        }
 
         CILNode::Call { args, site } | CILNode::CallVirt { args, site } => {
             let name = site.name();
-            let mut input_iter = args
-                .iter()
-                .zip(site.signature().inputs())
-                .filter_map(|(code, tpe)| if *tpe == Type::Void { Some(code) } else { None });
-            let mut inputs: String = "(".into();
-            if let Some(input) = input_iter.next() {
-                inputs.push_str(&node_string(input, method).to_string());
-            }
-            for input in input_iter {
-                inputs.push_str(&format!(",{input} ", input = node_string(input, method)));
+            // Operations on .NET vectors are implemented by macros, which need to know the type of their result.
+            if site
+                .class()
+                .is_some_and(crate::DotnetTypeRef::is_vector_helpers)
+            {
+                let output = instantiate(site.signature().output(), site.generics());
+                let args: Vec<_> = args.iter().map(|arg| node_string(arg, method)).collect();
+                return format!("SIMD_{name}({},{})", c_tpe(&output), args.join(","));
             }
-            inputs.push(')');
-            let tpe_name = site
+            // `f16` is a `_Float16` in C, so the operators of `System.Half` are native C operators.
+            if site
                 .class()
-                .map_or(String::new(), |tpe| escape_type_name(tpe.name_path()));
-            format!("{tpe_name}{name}{inputs}")
+                .is_some_and(|class| *class == crate::DotnetTypeRef::half())
+            {
+                let args: Vec<_> = args.iter().map(|arg| node_string(arg, method)).collect();
+                return half_op(name, site.signature().output(), &args);
+            }
+            format!(
+                "{name}{args}",
+                name = site_name(site),
+                args = call_args(args, site.signature().inputs(), site.generics(), method)
+            )
         }
-        //CILNode::CallVirt { .. } => panic!("Virtual calls not supported in C."),
         CILNode::LdcI64(value) => format!("{value}l"),
         CILNode::LdcU64(value) => format!("{value}ul"),
         CILNode::LdcI32(value) => format!("{value}"),
         CILNode::LdcU32(value) => format!("{value}u"),
         CILNode::LdcF64(value) => format!("{value}"),
         CILNode::LdcF32(value) => format!("{value}"),
-        CILNode::LoadGlobalAllocPtr { .. } => todo!(),
+        CILNode::LoadGlobalAllocPtr { .. } => {
+            panic!("ERROR: global allocations must be resolved before CIL export phase.")
+        }
         CILNode::ConvU8(inner) => format!("((uint8_t){inner})", inner = node_string(inner, method)),
         CILNode::ConvU16(inner) => {
             format!("((uint16_t){inner})", inner = node_string(inner, method))
@@ -563,38 +955,28 @@ fn node_string(tree: &CILNode, method: &Method) -> String {
             a = node_string(a, method),
             b = node_string(b, method)
         ),
-        CILNode::Lt(a, b) | CILNode::LtUn(a, b) => {
-            format!(
-                "{a} < {b}",
-                a = node_string(a, method),
-                b = node_string(b, method)
-            )
-        }
-        CILNode::Gt(a, b) | CILNode::GtUn(a, b) => {
-            format!(
-                "{a} > {b}",
-                a = node_string(a, method),
-                b = node_string(b, method)
-            )
+        CILNode::Lt(a, b) | CILNode::LtUn(a, b) => format!(
+            "(({a}) < ({b}))",
+            a = node_string(a, method),
+            b = node_string(b, method)
+        ),
+        CILNode::Gt(a, b) | CILNode::GtUn(a, b) => format!(
+            "(({a}) > ({b}))",
+            a = node_string(a, method),
+            b = node_string(b, method)
+        ),
+        CILNode::TemporaryLocal(_)
+        | CILNode::LoadAddresOfTMPLocal
+        | CILNode::LoadTMPLocal => {
+            panic!("Temporary locals must be resolved before the export stage! tree:{tree:?}")
         }
-        CILNode::TemporaryLocal(_) => todo!(),
+        // Sub-trees left after shedding are evaluated before the main tree, in a statement expression.
         CILNode::SubTrees(sub, main) => {
-            assert!(sub.is_empty(), "A sub-tree still remains!");
-            println!(
-                "WARNING: Sub-trees impropely resolved: an empty sub-tree list still remains!"
-            );
-            node_string(main, method)
+            let sub: String = sub.iter().map(|root| root_string(root, method)).collect();
+            format!("({{{sub}{main};}})", main = node_string(main, method))
         }
-        CILNode::LoadAddresOfTMPLocal => todo!(),
-        CILNode::LoadTMPLocal => todo!(),
 
-        CILNode::LDFtn(fn_sig) => {
-            let name = fn_sig.name();
-            let tpe_name = fn_sig
-                .class()
-                .map_or(String::new(), |tpe| escape_type_name(tpe.name_path()));
-            format!("(uintptr_t)(&{tpe_name}{name})")
-        }
+        CILNode::LDFtn(site) => format!("(uintptr_t)(&{name})", name = site_name(site)),
         CILNode::LDTypeToken(tpe) => {
             use std::hash::Hash;
             let mut hasher = std::hash::DefaultHasher::new();
@@ -603,27 +985,37 @@ fn node_string(tree: &CILNode, method: &Method) -> String {
             format!("{hsh}")
         }
         CILNode::NewObj { site, args } => {
-            let mut input_iter = args
-                .iter()
-                .zip(site.signature().inputs())
-                .filter_map(|(code, tpe)| if *tpe == Type::Void { None } else { Some(code) });
-            let mut inputs: String = "(".into();
-            if let Some(input) = input_iter.next() {
-                inputs.push_str(&node_string(input, method).to_string());
-            }
-            for input in input_iter {
-                inputs.push_str(&format!(",{input} ", input = node_string(input, method)));
-            }
-            inputs.push(')');
+            // The arguments don't include the object being constructed.
+            let inputs = &site.signature().inputs()[1..];
             let tpe_name = escape_type_name(site.class().unwrap().name_path());
-            format!("ctor_{tpe_name}{inputs}")
+            format!(
+                "ctor_{tpe_name}{args}",
+                args = call_args(args, inputs, site.generics(), method)
+            )
         }
         CILNode::LdStr(string) => format!("{string:?}"),
-        CILNode::CallI(_sig_ptr_args) => todo!(),
+        CILNode::CallI(sig_ptr_args) => {
+            let (sig, fn_ptr, args) = sig_ptr_args.as_ref();
+            format!(
+                "(({fn_ptr_tpe}){fn_ptr}){args}",
+                fn_ptr_tpe = fn_ptr_tpe(sig),
+                fn_ptr = node_string(fn_ptr, method),
+                args = call_args(args, sig.inputs(), &[], method)
+            )
+        }
         CILNode::LDLen { arr } => todo!("arr:{arr:?}"),
         CILNode::LDElelemRef { arr, idx } => todo!("arr:{arr:?} idx:{idx:?}"),
-        CILNode::GetStackTop => todo!(),
-        CILNode::InspectValue { val, inspect: _ } => node_string(val, method),
+        CILNode::IsInst { obj, class } => {
+            panic!("C mode has no managed objects, so {obj:?} can't be checked to be a {class:?}")
+        }
+        CILNode::GetStackTop => "stack_top".into(),
+        CILNode::InspectValue { val, inspect } => {
+            let inspect: String = inspect.iter().map(|root| root_string(root, method)).collect();
+            format!(
+                "({{__auto_type stack_top = {val};{inspect}stack_top;}})",
+                val = node_string(val, method)
+            )
+        }
         CILNode::TransmutePtr { val, new_ptr } => format!(
             "({new_ptr}){val}",
             new_ptr = c_tpe(new_ptr),
@@ -631,11 +1023,14 @@ fn node_string(tree: &CILNode, method: &Method) -> String {
         ),
         CILNode::LdFalse => "false".into(),
         CILNode::LdTrue => "true".into(),
-        CILNode::LocAlloc { size } => todo!(),
+        CILNode::LocAlloc { size } => format!(
+            "((uintptr_t)alloca({size}))",
+            size = node_string(size, method)
+        ),
     }
 }
-fn tree_string(tree: &CILTree, method: &Method) -> String {
-    match tree.root() {
+fn root_string(root: &CILRoot, method: &Method) -> String {
+    match root {
         CILRoot::SourceFileInfo(sfi) => format!(
             "//{fname}:{line}:{col}",
             line = sfi.0.start,
@@ -658,76 +1053,42 @@ fn tree_string(tree: &CILTree, method: &Method) -> String {
             target,
             sub_target,
             cond: ops,
-        } => {
-            if *sub_target != 0 {
-                format!(
-                    "\tif(({ops}) != 0)goto BB_{sub_target};\n",
-                    ops = node_string(ops, method)
-                )
-            } else {
-                format!(
-                    "\tif(({ops}) != 0)goto BB_{target};\n",
-                    ops = node_string(ops, method)
-                )
-            }
-        }
+        } => format!(
+            "\tif(({ops}) != 0)goto {label};\n",
+            ops = node_string(ops, method),
+            label = label(*target, *sub_target)
+        ),
         CILRoot::BFalse {
             target,
             sub_target,
             cond: ops,
-        } => {
-            if *sub_target != 0 {
-                format!(
-                    "\tif(({ops}) == 0)goto BB_{sub_target};\n",
-                    ops = node_string(ops, method)
-                )
-            } else {
-                format!(
-                    "\tif(({ops}) == 0)goto BB_{target};\n",
-                    ops = node_string(ops, method)
-                )
-            }
-        }
+        } => format!(
+            "\tif(({ops}) == 0)goto {label};\n",
+            ops = node_string(ops, method),
+            label = label(*target, *sub_target)
+        ),
         CILRoot::BEq {
             target,
             sub_target,
             a,
             b,
-        } => {
-            if *sub_target != 0 {
-                format!(
-                    "\tif(({a}) == ({b}))goto BB_{sub_target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            } else {
-                format!(
-                    "\tif(({a}) == ({b}))goto BB_{target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            }
-        }
+        } => format!(
+            "\tif(({a}) == ({b}))goto {label};\n",
+            a = node_string(a, method),
+            b = node_string(b, method),
+            label = label(*target, *sub_target)
+        ),
         CILRoot::BNe {
             target,
             sub_target,
             a,
             b,
-        } => {
-            if *sub_target != 0 {
-                format!(
-                    "\tif(({a}) != ({b}))goto BB_{sub_target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            } else {
-                format!(
-                    "\tif(({a}) != ({b}))goto BB_{target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            }
-        }
+        } => format!(
+            "\tif(({a}) != ({b}))goto {label};\n",
+            a = node_string(a, method),
+            b = node_string(b, method),
+            label = label(*target, *sub_target)
+        ),
         CILRoot::BLt {
             target,
             sub_target,
@@ -739,21 +1100,12 @@ fn tree_string(tree: &CILTree, method: &Method) -> String {
             sub_target,
             a,
             b,
-        } => {
-            if *sub_target != 0 {
-                format!(
-                    "\tif(({a}) < ({b}))goto BB_{sub_target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            } else {
-                format!(
-                    "\tif(({a}) < ({b}))goto BB_{target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            }
-        }
+        } => format!(
+            "\tif(({a}) < ({b}))goto {label};\n",
+            a = node_string(a, method),
+            b = node_string(b, method),
+            label = label(*target, *sub_target)
+        ),
         CILRoot::BGt {
             target,
             sub_target,
@@ -765,111 +1117,49 @@ fn tree_string(tree: &CILTree, method: &Method) -> String {
             sub_target,
             a,
             b,
-        } => {
-            if *sub_target != 0 {
-                format!(
-                    "\tif(({a}) > ({b}))goto BB_{sub_target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            } else {
-                format!(
-                    "\tif(({a}) > ({b}))goto BB_{target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            }
-        }
+        } => format!(
+            "\tif(({a}) > ({b}))goto {label};\n",
+            a = node_string(a, method),
+            b = node_string(b, method),
+            label = label(*target, *sub_target)
+        ),
         CILRoot::BLe {
             target,
             sub_target,
             a,
             b,
-        } => {
-            if *sub_target != 0 {
-                format!(
-                    "\tif(({a}) <= ({b}))goto BB_{sub_target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            } else {
-                format!(
-                    "\tif(({a}) <= ({b}))goto BB_{target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            }
-        }
+        } => format!(
+            "\tif(({a}) <= ({b}))goto {label};\n",
+            a = node_string(a, method),
+            b = node_string(b, method),
+            label = label(*target, *sub_target)
+        ),
         CILRoot::BGe {
             target,
             sub_target,
             a,
             b,
-        } => {
-            if *sub_target != 0 {
-                format!(
-                    "\tif(({a}) >= ({b}))goto BB_{sub_target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            } else {
-                format!(
-                    "\tif(({a}) >= ({b}))goto BB_{target};\n",
-                    a = node_string(a, method),
-                    b = node_string(b, method)
-                )
-            }
-        }
+        } => format!(
+            "\tif(({a}) >= ({b}))goto {label};\n",
+            a = node_string(a, method),
+            b = node_string(b, method),
+            label = label(*target, *sub_target)
+        ),
         CILRoot::GoTo { target, sub_target } => {
-            if *sub_target != 0 {
-                format!("goto BB_{sub_target};")
-            } else {
-                format!("goto BB_{target};")
-            }
-        }
-        CILRoot::Call { site, args } => {
-            let name = site.name();
-            let mut input_iter = args
-                .iter()
-                .zip(site.signature().inputs())
-                .filter(|(_, tpe)| **tpe != Type::Void);
-            let mut inputs: String = "(".into();
-            if let Some((input, arg)) = input_iter.next() {
-                if arg.as_dotnet().is_some() {
-                    inputs.push_str(&node_string(input, method).to_string());
-                } else {
-                    inputs.push_str(&format!(
-                        "({arg})({ops})",
-                        ops = node_string(input, method),
-                        arg = c_tpe(arg)
-                    ));
-                }
-                //                inputs.push_str(&format!("{input}", input = node_string(input)));
-            }
-            for (input, arg) in input_iter {
-                if arg.as_dotnet().is_some() {
-                    // Can't cast to a struct in C.
-                    inputs.push_str(&format!(",{ops}", ops = node_string(input, method)));
-                } else {
-                    inputs.push_str(&format!(
-                        ",({arg})({ops})",
-                        ops = node_string(input, method),
-                        arg = c_tpe(arg)
-                    ));
-                }
-            }
-            inputs.push(')');
-            let tpe_name = site
-                .class()
-                .map_or(String::new(), |tpe| escape_type_name(tpe.name_path()));
-            format!("{tpe_name}{name}{inputs};")
+            format!("goto {label};", label = label(*target, *sub_target))
         }
+        // There are no virtual methods in C, so virtual calls are direct calls.
+        CILRoot::Call { site, args } | CILRoot::CallVirt { site, args } => format!(
+            "{name}{args};",
+            name = site_name(site),
+            args = call_args(args, site.signature().inputs(), site.generics(), method)
+        ),
         CILRoot::SetField { addr, value, desc } => {
             if desc.tpe().as_dotnet().is_some() {
                 format!(
                     "(({owner}*){ptr})->{name}.f = {value};",
                     ptr = node_string(addr, method),
-                    owner = c_tpe(&desc.owner().clone().into()),
+                    owner = escape_type_name(desc.owner().name_path()),
                     name = desc.name(),
                     value = node_string(value, method)
                 )
@@ -877,7 +1167,7 @@ fn tree_string(tree: &CILTree, method: &Method) -> String {
                 format!(
                     "(({owner}*){ptr})->{name}.f = ({tpe}){value};",
                     ptr = node_string(addr, method),
-                    owner = c_tpe(&desc.owner().clone().into()),
+                    owner = escape_type_name(desc.owner().name_path()),
                     name = desc.name(),
                     value = node_string(value, method),
                     tpe = c_tpe(desc.tpe()),
@@ -889,7 +1179,7 @@ fn tree_string(tree: &CILTree, method: &Method) -> String {
             panic!("Temporary locals must be resolved before the export stage! value:{value:?}")
         }
         CILRoot::CpBlk { src, dst, len } => format!(
-            "memcpy({src},{dst},{len});",
+            "memcpy((void*)({dst}),(void*)({src}),(size_t)({len}));",
             src = node_string(src, method),
             dst = node_string(dst, method),
             len = node_string(len, method)
@@ -919,8 +1209,16 @@ fn tree_string(tree: &CILTree, method: &Method) -> String {
             addr_calc = node_string(addr_calc, method),
             value_calc = node_string(value_calc, method)
         ),
-        CILRoot::STIndF64(_, _) => todo!(),
-        CILRoot::STIndF32(_, _) => todo!(),
+        CILRoot::STIndF64(addr_calc, value_calc) => format!(
+            "*((double*)({addr_calc})) = (double){value_calc};",
+            addr_calc = node_string(addr_calc, method),
+            value_calc = node_string(value_calc, method)
+        ),
+        CILRoot::STIndF32(addr_calc, value_calc) => format!(
+            "*((float*)({addr_calc})) = (float){value_calc};",
+            addr_calc = node_string(addr_calc, method),
+            value_calc = node_string(value_calc, method)
+        ),
         CILRoot::STObj {
             tpe,
             addr_calc,
@@ -965,7 +1263,6 @@ fn tree_string(tree: &CILTree, method: &Method) -> String {
                 count = node_string(count, method)
             )
         }
-        CILRoot::CallVirt { .. } => panic!("Virtual calls not supported in C."),
         CILRoot::Ret { tree } => {
             if method.sig().output().as_dotnet().is_some() {
                 format!("\treturn {ops};", ops = node_string(tree, method))
@@ -981,15 +1278,22 @@ fn tree_string(tree: &CILTree, method: &Method) -> String {
             format!("\t{ops};", ops = node_string(tree, method))
         }
         CILRoot::VoidRet => "return;".into(),
-        CILRoot::Throw(_) => "abort();".to_string(),
-        CILRoot::ReThrow => todo!(),
-        CILRoot::CallI { sig, fn_ptr, args } => todo!(
-            "Can't yet call function pointers in C. fn_ptr:{fn_ptr:?} sig:{sig:?} args:{args:?}"
+        // The exception objects are .NET classes, so only the fact that an exception was thrown is propagated.
+        CILRoot::Throw(_) | CILRoot::ReThrow => "rust_throw();".into(),
+        CILRoot::StoreCaught { local } => {
+            panic!("C mode has no managed exception objects, so none can be stored in L{local}")
+        }
+        CILRoot::CallI { sig, fn_ptr, args } => format!(
+            "(({fn_ptr_tpe}){fn_ptr}){args};",
+            fn_ptr_tpe = fn_ptr_tpe(sig),
+            fn_ptr = node_string(fn_ptr, method),
+            args = call_args(args, sig.inputs(), &[], method)
+        ),
+        // Leaves the protected block `source`. Restoring the previous frame also works from within its handler,
+        // whose frame was already popped by the throw.
+        CILRoot::JumpingPad { source, target } => format!(
+            "\tBB_{source}_{target}:\n\tunwind_top = F{source}.prev;\n\tgoto BB_{target};\n"
         ),
-        CILRoot::JumpingPad { .. } => {
-            println!("WARNING: There should be no jumping pads in C, jet a jumping pad remains!");
-            "/*Invalid jump pad was here*/abort();\n".into()
-        }
         CILRoot::SetStaticField { descr, value } => {
             let local_ty = descr.tpe();
             if local_ty.as_dotnet().is_some() {
@@ -1009,6 +1313,148 @@ fn tree_string(tree: &CILTree, method: &Method) -> String {
         }
     }
 }
+/// The label of the block `target`, or of the block `sub_target` of the protected block `target` if it is not 0.
+/// The fields of `tpe`. Each field is wrapped in a struct, padded to its explicit offset, if `tpe` has one.
+fn type_fields(tpe: &TypeDef) -> String {
+    let mut fields = String::new();
+    if let Some(offsets) = tpe.explicit_offsets() {
+        for ((field_name, field_type), offset) in tpe.fields().iter().zip(offsets) {
+            if *field_type == Type::Void {
+                continue;
+            }
+
+            fields.push_str(&format!(
+                "\tstruct {{char pad[{offset}];{field_type} f;}} {field_name};\n\n",
+                field_type = c_tpe(field_type)
+            ));
+        }
+    } else {
+        for (field_name, field_type) in tpe.fields() {
+            if *field_type == Type::Void {
+                continue;
+            }
+            fields.push_str(&format!(
+                "\tstruct {{{field_type} f;}} {field_name};\n",
+                field_type = c_tpe(field_type)
+            ));
+        }
+    }
+    fields
+}
+/// The parameter list of a function with the signature `sig`.
+fn c_inputs(sig: &FnSig) -> String {
+    let mut inputs: String = "(".into();
+    let mut input_iter = sig
+        .inputs()
+        .iter()
+        .enumerate()
+        .filter(|(_, tpe)| **tpe != Type::Void);
+    if let Some((idx, input)) = input_iter.next() {
+        inputs.push_str(&format!("{input} A{idx}", input = c_tpe(input)));
+    }
+    for (idx, input) in input_iter {
+        inputs.push_str(&format!(",{input} A{idx} ", input = c_tpe(input)));
+    }
+    inputs.push(')');
+    inputs
+}
+fn label(target: u32, sub_target: u32) -> String {
+    if sub_target == 0 {
+        format!("BB_{target}")
+    } else {
+        format!("BB_{target}_{sub_target}")
+    }
+}
+/// The name of the C function or macro `site` refers to.
+fn site_name(site: &CallSite) -> String {
+    let tpe_name = site
+        .class()
+        .map_or(String::new(), |tpe| escape_type_name(tpe.name_path()));
+    format!("{tpe_name}{name}", name = site.name().replace('.', "_"))
+}
+/// Lowers the operator `name` of `System.Half`, applied to `args`, to C operators on `_Float16`.
+fn half_op(name: &str, output: &Type, args: &[String]) -> String {
+    let binop = match name {
+        "op_Addition" => "+",
+        "op_Subtraction" => "-",
+        "op_Multiply" => "*",
+        "op_Division" => "/",
+        "op_Equality" => "==",
+        "op_Inequality" => "!=",
+        "op_LessThan" => "<",
+        "op_LessThanOrEqual" => "<=",
+        "op_GreaterThan" => ">",
+        "op_GreaterThanOrEqual" => ">=",
+        // `_Float16` is promoted to `float` by `fmodf`, which is exact, and so is the remainder.
+        "op_Modulus" => {
+            return format!(
+                "((_Float16)__builtin_fmodf(({a}),({b})))",
+                a = args[0],
+                b = args[1]
+            )
+        }
+        "op_UnaryNegation" => return format!("(-({a}))", a = args[0]),
+        "op_Explicit" | "op_Implicit" => {
+            return format!("(({tpe})({a}))", tpe = c_tpe(output), a = args[0])
+        }
+        _ => panic!("System.Half::{name} has no C equivalent!"),
+    };
+    format!("(({a}) {binop} ({b}))", a = args[0], b = args[1])
+}
+/// The argument list of a call to a function taking `inputs`, with each argument cast to its input type.
+fn call_args(args: &[CILNode], inputs: &[Type], generics: &[Type], method: &Method) -> String {
+    let args: Vec<_> = args
+        .iter()
+        .zip(inputs)
+        .filter(|(_, tpe)| **tpe != Type::Void)
+        .map(|(arg, tpe)| {
+            let tpe = instantiate(tpe, generics);
+            // Can't cast to a struct in C.
+            if tpe.as_dotnet().is_some() {
+                node_string(arg, method)
+            } else {
+                format!(
+                    "({tpe})({arg})",
+                    tpe = c_tpe(&tpe),
+                    arg = node_string(arg, method)
+                )
+            }
+        })
+        .collect();
+    format!("({})", args.join(","))
+}
+/// The C type of a pointer to a function with the signature `sig`.
+fn fn_ptr_tpe(sig: &FnSig) -> String {
+    let inputs: Vec<_> = sig
+        .inputs()
+        .iter()
+        .filter(|tpe| **tpe != Type::Void)
+        .map(|tpe| c_tpe(tpe).into_owned())
+        .collect();
+    let inputs = if inputs.is_empty() {
+        "void".into()
+    } else {
+        inputs.join(",")
+    };
+    format!("{output}(*)({inputs})", output = c_tpe(sig.output()))
+}
+/// Replaces the generic arguments of a method, used in `tpe`, with `generics`.
+fn instantiate(tpe: &Type, generics: &[Type]) -> Type {
+    match tpe {
+        Type::CallGenericArg(idx) => generics[*idx as usize].clone(),
+        Type::DotnetType(tref) if !tref.generics().is_empty() => {
+            let mut tref = tref.as_ref().clone();
+            let instantiated: Vec<_> = tref
+                .generics()
+                .iter()
+                .map(|generic| instantiate(generic, generics))
+                .collect();
+            tref.set_generics(instantiated);
+            tref.into()
+        }
+        _ => tpe.clone(),
+    }
+}
 fn c_tpe(tpe: &Type) -> Cow<'static, str> {
     match tpe {
         Type::Bool => "bool".into(),
@@ -1022,14 +1468,19 @@ fn c_tpe(tpe: &Type) -> Cow<'static, str> {
         Type::U64 => "uint64_t".into(),
         Type::I32 => "int32_t".into(),
         Type::U32 => "uint32_t".into(),
-        Type::F64 => "float".into(),
-        Type::F32 => "double".into(),
+        Type::F64 => "double".into(),
+        Type::F32 => "float".into(),
+        Type::F16 => "_Float16".into(),
+        Type::F128 => "__float128".into(),
         Type::I16 => "int16_t".into(),
         Type::U16 => "uint16_t".into(),
         Type::I8 => "int8_t".into(),
         Type::U8 => "uint8_t".into(),
         Type::Ptr(inner) => format!("{inner}*", inner = c_tpe(inner)).into(),
         Type::DotnetType(tref) => {
+            if let Some((bits, elem)) = tref.as_vector() {
+                return format!("SIMD_VECTOR({},{})", c_tpe(elem), bits / 8).into();
+            }
             if let Some(asm) = tref.asm() {
                 match (asm, tref.name_path()) {
                     ("System.Runtime", "System.UInt128") => return c_tpe(&Type::U128),
@@ -1044,13 +1495,350 @@ fn c_tpe(tpe: &Type) -> Cow<'static, str> {
             {
                 return c_tpe(&Type::Void);
             }
-            escape_type_name(tref.name_path()).into()
+            // Classes defined in this assembly are reference types.
+            if tref.is_valuetype() || tref.asm().is_some() {
+                escape_type_name(tref.name_path()).into()
+            } else {
+                format!("{}*", escape_type_name(tref.name_path())).into()
+            }
         }
         Type::DelegatePtr(_sig) => "void*".into(),
         Type::ManagedArray { element, dims } => {
             let ptrs: String = (0..(dims.get())).map(|_| '*').collect();
             format!("{element}{ptrs}", element = c_tpe(element)).into()
         }
-        _ => todo!("Unsuported type {tpe:?}"),
+        // Nothing can move in C, so managed references are just pointers.
+        Type::ManagedReference(inner) => format!("{inner}*", inner = c_tpe(inner)).into(),
+        Type::Foreign => "void".into(),
+        Type::FnDef(name) => escape_type_name(&format!("fn_{name}")).into(),
+        Type::Unresolved
+        | Type::GenericArg(_)
+        | Type::CallGenericArg(_)
+        | Type::MethodGenericArg(_) => {
+            panic!("{tpe:?} must be resolved or instantiated before the C export stage!")
+        }
+    }
+}
+#[test]
+fn protected_blocks() {
+    let mut asm = crate::asm::Assembly::empty();
+    crate::unwind::add_unwind_support(&mut asm, true);
+    let mut exporter = CExporter::init("protected_blocks");
+    for method in asm.methods() {
+        exporter.add_method(method);
     }
+    let source = String::from_utf8(exporter.as_source(true)).unwrap();
+    // `catch_unwind` protects its first block, and leaves it trough jumping pads.
+    assert!(source.contains("\tunwind_push(&F0);\n\tif(setjmp(F0.buf))goto BB_0_"));
+    assert!(source.contains("\tunwind_top = F0.prev;\n\tgoto BB_1;"));
+    assert!(source.contains("rust_throw();"));
+}
+#[test]
+fn exported_header() {
+    use crate::exports::{ExportInfo, ExportedType};
+    use crate::method::{Attribute, MethodType};
+    use crate::DotnetTypeRef;
+    let mut exporter = CExporter::init("exported_header");
+    let inner = DotnetTypeRef::new::<&str, _>(None, "Inner");
+    let outer = DotnetTypeRef::new::<&str, _>(None, "Outer");
+    exporter.add_type(&TypeDef::new(
+        AccessModifer::Public,
+        "Inner".into(),
+        vec![],
+        vec![("a".into(), Type::U8), ("b".into(), Type::U32)],
+        vec![],
+        Some(vec![0, 4]),
+        0,
+        None,
+        None,
+    ));
+    exporter.add_type(&TypeDef::new(
+        AccessModifer::Public,
+        "Outer".into(),
+        vec![],
+        vec![("inner".into(), Type::DotnetType(Box::new(inner)))],
+        vec![],
+        None,
+        0,
+        None,
+        None,
+    ));
+    let mut exported = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(
+            &[Type::Ptr(Box::new(Type::DotnetType(Box::new(outer))))],
+            Type::I32,
+        ),
+        "read_outer",
+        vec![],
+        vec![BasicBlock::new(
+            vec![CILRoot::Ret {
+                tree: CILNode::LdcI32(0),
+            }
+            .into()],
+            0,
+            None,
+        )],
+        vec![Some("outer".into())],
+    );
+    exported.add_attribute(Attribute::Export(ExportInfo::new(
+        vec![ExportedType::Direct],
+        ExportedType::Direct,
+    )));
+    exporter.add_method(&exported);
+    let header = String::from_utf8(exporter.as_header("EXPORTED_HEADER_H")).unwrap();
+    // Types used by value are defined before the types containing them.
+    let inner = header.find("union Inner{").unwrap();
+    assert!(inner < header.find("struct Outer{").unwrap());
+    assert!(header.contains("struct {char pad[4];uint32_t f;} b;"));
+    assert!(header.contains("int32_t read_outer(Outer* A0);"));
+}
+#[test]
+fn unwind_round_trip() {
+    use crate::unwind::{add_unwind_support, raise_exception};
+    let mut asm = crate::asm::Assembly::empty();
+    add_unwind_support(&mut asm, true);
+    asm.add_method(raise_exception(
+        &CallSite::builtin(
+            "_Unwind_RaiseException".into(),
+            FnSig::new(&[Type::Ptr(Box::new(Type::U8))], Type::Void),
+            true,
+        ),
+        true,
+    ));
+    let dir = std::env::temp_dir().join("unwind_round_trip_test");
+    std::fs::create_dir_all(&dir).unwrap();
+    CExporter::init("unwind_round_trip")
+        .with_toolchain(
+            CToolchain::default()
+                .with_compiler("cc")
+                .with_output(COutputKind::Object),
+        )
+        .export_assembly(&asm, &dir.join("unwind.o"), true, false)
+        .unwrap();
+    // `try_fn` either returns, or panics with `data` as the exception object, which `catch_fn` has to receive.
+    std::fs::write(
+        dir.join("main.c"),
+        "#include <stdint.h>
+int32_t catch_unwind(void* try_fn,uint8_t* data,void* catch_fn);
+void _Unwind_RaiseException(uint8_t* exception);
+static uint8_t* caught;
+static void returns(uint8_t* data){}
+static void throws(uint8_t* data){_Unwind_RaiseException(data);}
+static void catches(uint8_t* data,uint8_t* exception){caught = exception;}
+int main(){
+\tstatic uint8_t exception;
+\tif(catch_unwind(returns,&exception,catches) != 0 || caught)return 1;
+\tif(catch_unwind(throws,&exception,catches) != 1 || caught != &exception)return 2;
+\tcaught = 0;
+\treturn catch_unwind(returns,&exception,catches) != 0 || caught ? 3 : 0;
+}
+",
+    )
+    .unwrap();
+    let status = Command::new("cc")
+        .current_dir(&dir)
+        .args(["unwind.o", "main.c", "-o", "unwind_round_trip"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(Command::new(dir.join("unwind_round_trip"))
+        .status()
+        .unwrap()
+        .success());
+}
+/// A method returning the sum of its two arguments.
+#[cfg(test)]
+fn add_method(name: &str) -> Method {
+    Method::new(
+        AccessModifer::Public,
+        crate::method::MethodType::Static,
+        FnSig::new(&[Type::U32, Type::U32], Type::U32),
+        name,
+        vec![],
+        vec![BasicBlock::new(
+            vec![CILRoot::Ret {
+                tree: CILNode::Add(CILNode::LDArg(0).into(), CILNode::LDArg(1).into()),
+            }
+            .into()],
+            0,
+            None,
+        )],
+        vec![Some("a".into()), Some("b".into())],
+    )
+}
+#[test]
+fn split_units() {
+    let mut exporter = CExporter::init("split_units");
+    for name in ["add_a", "add_b", "add_c"] {
+        exporter.add_method(&add_method(name));
+    }
+    exporter.add_global(&Type::U32, "counter");
+    let units = exporter.as_units("split_units_decls.h", 2, true);
+    assert_eq!(units.len(), 2);
+    let units: Vec<_> = units
+        .into_iter()
+        .map(|unit| String::from_utf8(unit).unwrap())
+        .collect();
+    assert!(units
+        .iter()
+        .all(|unit| unit.starts_with("#include \"split_units_decls.h\"\n")));
+    // Statics are defined once, and declared in the shared header.
+    assert!(units[0].contains("\nuint32_t counter;"));
+    assert!(!units[1].contains("counter"));
+    let decls = String::from_utf8(exporter.as_decls()).unwrap();
+    assert!(decls.contains("extern uint32_t counter;"));
+    // Both files get some of the methods.
+    assert!(units.iter().all(|unit| unit.contains("uint32_t add_")));
+}
+#[test]
+fn freestanding_prelude() {
+    let mut exporter = CExporter::init("freestanding_prelude")
+        .with_toolchain(CToolchain::default().with_freestanding(true));
+    let sig = FnSig::new(&[Type::Ptr(Box::new(Type::U8))], Type::I32);
+    exporter.add_extern_method("libc", "puts", &sig, false);
+    exporter.add_extern_method("libc", "printf", &sig, false);
+    let source = String::from_utf8(exporter.as_source(true)).unwrap();
+    assert!(!source.contains("<stdio.h>") && !source.contains("<setjmp.h>"));
+    assert!(source.contains("void* __rcl_alloc(size_t size,size_t align);"));
+    // `puts` is implemented on top of the runtime, but `printf` has to be provided by the embedder.
+    assert!(!source.contains("extern int32_t puts"));
+    assert!(source.contains("extern int32_t printf (uint8_t* A0);"));
+    let dir = std::env::temp_dir().join("freestanding_prelude_test");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("freestanding_prelude.c"), source).unwrap();
+    let status = Command::new("gcc")
+        .current_dir(&dir)
+        .args(["-ffreestanding", "-c", "freestanding_prelude.c"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+#[test]
+fn sanitizers_from_config() {
+    let sanitizers = |value: &str| {
+        let mut config = crate::config::Config::default();
+        config.c_sanitize = value.into();
+        CToolchain::from_config(&config).sanitizers
+    };
+    for off in ["", "0", "false"] {
+        assert_eq!(sanitizers(off), Vec::<IString>::new());
+    }
+    for on in ["1", "true"] {
+        assert_eq!(sanitizers(on), vec![IString::from("undefined")]);
+    }
+    assert_eq!(
+        sanitizers("address, undefined"),
+        vec![IString::from("address"), IString::from("undefined")]
+    );
+}
+#[test]
+fn freestanding_output() {
+    // There is no libc to start an executable, so an object file is built instead.
+    let toolchain = CToolchain::default().with_freestanding(true);
+    assert_eq!(toolchain.output(), COutputKind::Object);
+    let toolchain = CToolchain::default()
+        .with_output(COutputKind::StaticLib)
+        .with_freestanding(true);
+    assert_eq!(toolchain.output(), COutputKind::StaticLib);
+    let toolchain = CToolchain::default()
+        .with_freestanding(true)
+        .with_output(COutputKind::Executable);
+    assert_eq!(toolchain.output(), COutputKind::Executable);
+}
+#[test]
+fn half_ops() {
+    use crate::call;
+    use crate::method::MethodType;
+    use crate::DotnetTypeRef;
+    let half = |name: &str, inputs: &[Type], output: Type, args: Box<[CILNode]>| {
+        call!(
+            CallSite::new_extern(
+                DotnetTypeRef::half(),
+                name.into(),
+                FnSig::new(inputs, output),
+                true
+            ),
+            args
+        )
+    };
+    let binop = |name: &str, output: Type, a: CILNode, b: CILNode| {
+        half(name, &[Type::F16, Type::F16], output, [a, b].into())
+    };
+    // Returns `-((a + b) % b)` as an `f32`, or `0.0` if `a >= b`.
+    let rem = binop(
+        "op_Modulus",
+        Type::F16,
+        binop(
+            "op_Addition",
+            Type::F16,
+            CILNode::LDArg(0),
+            CILNode::LDArg(1),
+        ),
+        CILNode::LDArg(1),
+    );
+    let neg = half("op_UnaryNegation", &[Type::F16], Type::F16, [rem].into());
+    let method = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[Type::F16, Type::F16], Type::F32),
+        "half_ops",
+        vec![],
+        vec![
+            BasicBlock::new(
+                vec![
+                    CILRoot::BFalse {
+                        target: 1,
+                        sub_target: 0,
+                        cond: binop(
+                            "op_LessThan",
+                            Type::Bool,
+                            CILNode::LDArg(0),
+                            CILNode::LDArg(1),
+                        ),
+                    }
+                    .into(),
+                    CILRoot::Ret {
+                        tree: half("op_Explicit", &[Type::F16], Type::F32, [neg].into()),
+                    }
+                    .into(),
+                ],
+                0,
+                None,
+            ),
+            BasicBlock::new(
+                vec![CILRoot::Ret {
+                    tree: CILNode::LdcF32(0.0),
+                }
+                .into()],
+                1,
+                None,
+            ),
+        ],
+        vec![Some("a".into()), Some("b".into())],
+    );
+    let mut exporter = CExporter::init("half_ops");
+    exporter.add_method(&method);
+    let source = String::from_utf8(exporter.as_source(true)).unwrap();
+    assert!(!source.contains("System_Half"));
+    assert!(source.contains("__builtin_fmodf"));
+    let dir = std::env::temp_dir().join("half_ops_test");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("half_ops.c"), source).unwrap();
+    std::fs::write(
+        dir.join("main.c"),
+        "float half_ops(_Float16 a,_Float16 b);\nint main(){return !(half_ops(1.5,2.0) == -1.5 && half_ops(3.0,2.0) == 0.0);}\n",
+    )
+    .unwrap();
+    let status = Command::new("cc")
+        .current_dir(&dir)
+        .args(["half_ops.c", "main.c", "-lm", "-o", "half_ops"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(Command::new(dir.join("half_ops"))
+        .status()
+        .unwrap()
+        .success());
 }
@@ -0,0 +1,64 @@
+// Freestanding environment: there is no libc, so the generated code only uses the runtime below, which is provided by
+// the embedder.
+
+// Allocates `size` bytes aligned to `align`. Returns NULL on failure.
+void* __rcl_alloc(size_t size,size_t align);
+// Resizes the allocation `ptr`, made by `__rcl_alloc` with the alignment `align`.
+void* __rcl_realloc(void* ptr,size_t new_size,size_t align);
+// Frees the allocation `ptr`, made by `__rcl_alloc` or `__rcl_realloc`.
+void __rcl_free(void* ptr);
+// Writes `len` bytes from `buf` to the stream `fd`: 1 is the standard output, and 2 is the standard error.
+void __rcl_write(int fd,const void* buf,size_t len);
+// Stops the program. Called on unhandled panics.
+__attribute__((noreturn)) void __rcl_abort(void);
+// C compilers emit calls to those even in freestanding code, so the runtime has to provide them too.
+void* memcpy(void* dst,const void* src,size_t len);
+void* memmove(void* dst,const void* src,size_t len);
+void* memset(void* dst,int val,size_t len);
+int memcmp(const void* a,const void* b,size_t len);
+
+#define alloca __builtin_alloca
+// libc functions used by Rust
+#define malloc(size) __rcl_alloc((size),_Alignof(max_align_t))
+#define realloc(ptr,size) __rcl_realloc((ptr),(size),_Alignof(max_align_t))
+#define free(ptr) __rcl_free(ptr)
+static inline int32_t rcl_puts(const char* str){
+	size_t len = 0;
+	while(str[len])len++;
+	__rcl_write(1,str,len);
+	__rcl_write(1,"\n",1);
+	return 0;
+}
+#define puts(str) rcl_puts((const char*)(str))
+// String
+static inline size_t rcl_strlen(const char* str){
+	size_t len = 0;
+	while(str[len])len++;
+	return len;
+}
+static inline char* rcl_strndup(const char* str,size_t len){
+	size_t copied = 0;
+	while(copied < len && str[copied])copied++;
+	char* res = __rcl_alloc(copied + 1,1);
+	if(res == NULL)return NULL;
+	for(size_t idx = 0; idx < copied; idx++)res[idx] = str[idx];
+	res[copied] = 0;
+	return res;
+}
+#define System_Stringget_Length(arg) (rcl_strlen(arg) - 1)
+#define System_Runtime_InteropServices_MarshalPtrToStringUTF8(ptr,len) rcl_strndup((const char*)(ptr),(size_t)(len))
+// IO
+#define System_ConsoleWrite(chr) ({char chr_ = (chr); __rcl_write(1,&chr_,1);})
+// Allocation
+#define System_Runtime_InteropServices_NativeMemoryAlignedAlloc(size,align) __rcl_alloc((size),(align))
+#define System_Runtime_InteropServices_NativeMemoryAlignedFree(ptr) __rcl_free(ptr)
+#define System_Runtime_InteropServices_NativeMemoryAlignedRealloc(ptr,new_size,align) __rcl_realloc((ptr),(new_size),(align))
+// Exceptions: `setjmp` is not available without libc, so panics can't be caught, and abort instead.
+typedef struct unwind_frame{struct unwind_frame* prev;} unwind_frame;
+extern _Thread_local unwind_frame* unwind_top;
+#define setjmp(buf) 0
+__attribute__((noreturn)) static void rust_throw(void){
+	static const char msg[] = "Unhandled exception\n";
+	__rcl_write(2,msg,sizeof(msg) - 1);
+	__rcl_abort();
+}
@@ -1,6 +1,7 @@
 // Statics
 
-static char* exec_fname;
+// Defined in the first C source file.
+extern char* exec_fname;
 
 // Functions
 
@@ -23,6 +24,15 @@ static char* exec_fname;
 #define System_UInt128op_ExclusiveOr(a,b) ((a) ^ (b))
 #define System_Int128op_ExclusiveOr(a,b) ((a) ^ (b))
 
+#define System_UInt128op_BitwiseAnd(a,b) ((a) & (b))
+#define System_Int128op_BitwiseAnd(a,b) ((a) & (b))
+
+#define System_UInt128op_BitwiseOr(a,b) ((a) | (b))
+#define System_Int128op_BitwiseOr(a,b) ((a) | (b))
+
+#define System_UInt128op_LeftShift(a,b) ((a) << (b))
+#define System_Int128op_LeftShift(a,b) ((a) << (b))
+
 #define System_UInt128op_RightShift(a,b) ((a) >> (b))
 #define System_Int128op_RightShift(a,b) ((a) >> (b))
 
@@ -42,19 +52,14 @@ static char* exec_fname;
 #define System_UIntPtrget_MinValue() ((size_t)0)
 #define System_UIntPtrget_MaxValue() (~((size_t)0))
 // Bswap
-#define System_Buffers_Binary_BinaryPrimitivesReverseEndianness(val) __bswap_32(val)
+#define System_Buffers_Binary_BinaryPrimitivesReverseEndianness(val) __builtin_bswap32(val)
 // Assembly utilis needed for statup
 #define System_Reflection_AssemblyGetEntryAssembly() exec_fname
 #define System_Reflection_Assemblyget_Location(arg) (arg)
 // String 
-#define System_Stringget_Length(arg) (strlen(arg) - 1)
 #define System_Runtime_InteropServices_MarshalStringToCoTaskMemUTF8(arg) arg
 // IO
-#define System_ConsoleWrite(chr) putc(chr,stdout)
 #define System_ConsoleWriteLine(arg)
-// Allocation
-#define System_Runtime_InteropServices_NativeMemoryAlignedAlloc _mm_malloc
-#define System_Runtime_InteropServices_NativeMemoryAlignedFree(ptr) _mm_free(ptr)
 //Atomics
 #define System_Threading_InterlockedCompareExchange(addr,value,comparand) ({typeof(comparand) expected = comparand;typeof(value) val = value;  __atomic_compare_exchange((addr),&(expected),&(val),0,__ATOMIC_SEQ_CST,0); expected;})
 #define System_Threading_InterlockedExchange(addr,val) ({typeof(val) value = val;typeof(val) ret; __atomic_exchange((addr),&value,&ret,__ATOMIC_SEQ_CST);ret;})
@@ -64,17 +69,53 @@ static char* exec_fname;
 #define System_TypeGetTypeFromHandle(handle) handle
 #define System_ObjectGetHashCode(object) object
 //Math
-#define System_MathFSqrt(flot) sqrtf(flot)
+#define System_MathFSqrt(flot) __builtin_sqrtf(flot)
+#define System_MathMin(a,b) ((a) < (b) ? (a) : (b))
+#define System_MathMax(a,b) ((a) > (b) ? (a) : (b))
+// SIMD: .NET vectors are GCC vectors, and operations on them are preformed lane by lane. T is the type of the result.
+#define SIMD_VECTOR(T,BYTES) typeof(T __attribute__((vector_size(BYTES))))
+#define SIMD_BYTES(v) typeof(uint8_t __attribute__((vector_size(sizeof(v)))))
+#define SIMD_LANES(v) (sizeof(v) / sizeof((v)[0]))
+#define SIMD_MAP(T,lane) ({T r_; for(size_t i_ = 0; i_ < SIMD_LANES(r_); i_++) r_[i_] = (lane); r_;})
+#define SIMD_MAP1(T,a,lane) ({typeof(a) a_ = (a); SIMD_MAP(T,lane);})
+#define SIMD_MAP2(T,a,b,lane) ({typeof(a) a_ = (a); typeof(b) b_ = (b); SIMD_MAP(T,lane);})
+#define SIMD_Add(T,a,b) SIMD_MAP2(T,a,b,a_[i_] + b_[i_])
+#define SIMD_Subtract(T,a,b) SIMD_MAP2(T,a,b,a_[i_] - b_[i_])
+#define SIMD_Multiply(T,a,b) SIMD_MAP2(T,a,b,a_[i_] * b_[i_])
+#define SIMD_Divide(T,a,b) SIMD_MAP2(T,a,b,a_[i_] / b_[i_])
+#define SIMD_BitwiseAnd(T,a,b) SIMD_MAP2(T,a,b,a_[i_] & b_[i_])
+#define SIMD_BitwiseOr(T,a,b) SIMD_MAP2(T,a,b,a_[i_] | b_[i_])
+#define SIMD_Xor(T,a,b) SIMD_MAP2(T,a,b,a_[i_] ^ b_[i_])
+#define SIMD_Min(T,a,b) SIMD_MAP2(T,a,b,a_[i_] < b_[i_] ? a_[i_] : b_[i_])
+#define SIMD_Max(T,a,b) SIMD_MAP2(T,a,b,a_[i_] > b_[i_] ? a_[i_] : b_[i_])
+#define SIMD_OnesComplement(T,a) SIMD_MAP1(T,a,~a_[i_])
+#define SIMD_Negate(T,a) SIMD_MAP1(T,a,-a_[i_])
+#define SIMD_Abs(T,a) SIMD_MAP1(T,a,a_[i_] < 0 ? -a_[i_] : a_[i_])
+#define SIMD_Sqrt(T,a) SIMD_MAP1(T,a,__builtin_sqrt(a_[i_]))
+#define SIMD_Floor(T,a) SIMD_MAP1(T,a,__builtin_floor(a_[i_]))
+#define SIMD_Ceiling(T,a) SIMD_MAP1(T,a,__builtin_ceil(a_[i_]))
+// Lanes of comparison results are either all ones or all zeroes, so they are computed using GCC vector comparisons.
+#define SIMD_Equals(T,a,b) ((T)((a) == (b)))
+#define SIMD_LessThan(T,a,b) ((T)((a) < (b)))
+#define SIMD_LessThanOrEqual(T,a,b) ((T)((a) <= (b)))
+#define SIMD_GreaterThan(T,a,b) ((T)((a) > (b)))
+#define SIMD_GreaterThanOrEqual(T,a,b) ((T)((a) >= (b)))
+#define SIMD_ConditionalSelect(T,mask,a,b) ({SIMD_BYTES(T) m_ = (SIMD_BYTES(T))(mask); (T)((m_ & (SIMD_BYTES(T))(a)) | (~m_ & (SIMD_BYTES(T))(b)));})
+#define SIMD_Create(T,val) ({typeof(val) v_ = (val); SIMD_MAP(T,v_);})
+#define SIMD_GetElement(T,v,idx) ((v)[idx])
+#define SIMD_WithElement(T,v,idx,val) ({T v_ = (v); v_[idx] = (val); v_;})
+#define SIMD_Sum(T,v) ({typeof(v) v_ = (v); T r_ = 0; for(size_t i_ = 0; i_ < SIMD_LANES(v_); i_++) r_ += v_[i_]; r_;})
+#define SIMD_ExtractMostSignificantBits(T,v) ({typeof(v) v_ = (v); T r_ = 0; for(size_t i_ = 0; i_ < SIMD_LANES(v_); i_++) r_ |= (T)(v_[i_] < 0) << i_; r_;})
+// Exceptions: each protected block pushes a frame, which a throw jumps back to. The frame is popped by the throw, or
+// when leaving the protected block. `unwind_frame` and `rust_throw` are defined by the environment.
+#define unwind_push(frame) ((frame)->prev = unwind_top, unwind_top = (frame))
+#define System_Exception_ctor(self,message)
 //Types
 
 typedef char* System_String;
 typedef struct TypeInfo{
     int32_t hash;
 };
-void *System_Runtime_InteropServices_NativeMemoryAlignedRealloc(void* old, size_t new_size, size_t align){
-	// Reallocating such buffers is not supported yet!
-	abort();
-}
 // Used for startup
 #define System_Arrayget_Length(_) 0
 #define black_box(val) val
\ No newline at end of file
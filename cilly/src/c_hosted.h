@@ -0,0 +1,26 @@
+// Hosted environment: the generated code uses libc directly.
+
+// String
+#define System_Stringget_Length(arg) (strlen(arg) - 1)
+#define System_Runtime_InteropServices_MarshalPtrToStringUTF8(ptr,len) strndup((const char*)(ptr),(size_t)(len))
+// IO
+#define System_ConsoleWrite(chr) putc(chr,stdout)
+// Allocation
+#define System_Runtime_InteropServices_NativeMemoryAlignedAlloc _mm_malloc
+#define System_Runtime_InteropServices_NativeMemoryAlignedFree(ptr) _mm_free(ptr)
+static void *System_Runtime_InteropServices_NativeMemoryAlignedRealloc(void* old, size_t new_size, size_t align){
+	// Reallocating such buffers is not supported yet!
+	abort();
+}
+// Exceptions
+typedef struct unwind_frame{jmp_buf buf;struct unwind_frame* prev;} unwind_frame;
+extern _Thread_local unwind_frame* unwind_top;
+__attribute__((noreturn)) static void rust_throw(void){
+	unwind_frame* frame = unwind_top;
+	if(frame == NULL){
+		fputs("Unhandled exception\n",stderr);
+		abort();
+	}
+	unwind_top = frame->prev;
+	longjmp(frame->buf,1);
+}
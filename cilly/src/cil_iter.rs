@@ -98,6 +98,7 @@ impl<'a> Iterator for CILIter<'a> {
                     | CILNode::Not(a)
                     | CILNode::Neg(a)
                     | CILNode::LDLen { arr: a }
+                    | CILNode::IsInst { obj: a, .. }
                     | CILNode::BlackBox(a)
                     | CILNode::LocAlloc { size: a },
                 ) => {
@@ -209,6 +210,7 @@ impl<'a> Iterator for CILIter<'a> {
                     | CILRoot::Break
                     | CILRoot::Nop
                     | CILRoot::ReThrow
+                    | CILRoot::StoreCaught { .. }
                     | CILRoot::JumpingPad { .. },
                 ) => {
                     self.elems.pop();
@@ -123,6 +123,7 @@ impl<'a> Iterator for CILIterMut<'a> {
                     | CILNode::Not(a)
                     | CILNode::Neg(a)
                     | CILNode::LDLen { arr: a }
+                    | CILNode::IsInst { obj: a, .. }
                     | CILNode::LocAlloc { size: a } => {
                         if *idx == 1 {
                             *idx += 1;
@@ -414,6 +415,7 @@ impl<'a> Iterator for CILIterMut<'a> {
                     | CILRoot::VoidRet
                     | CILRoot::Nop
                     | CILRoot::ReThrow
+                    | CILRoot::StoreCaught { .. }
                     | CILRoot::Break => {
                         self.elems.pop();
                         continue;
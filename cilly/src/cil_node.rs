@@ -181,6 +181,11 @@ pub enum CILNode {
         arr: Box<Self>,
         idx: Box<Self>,
     },
+    /// Checks if an object is an instance of `class`. Returns the object cast to `class`, or null if it is not one.
+    IsInst {
+        obj: Box<Self>,
+        class: Box<DotnetTypeRef>,
+    },
     PointerToConstValue(u128),
     /// Unsafe, low-level internal op for checking the state of the CIL eval stack. WILL cause serious issues if not used **very** carefully, and only inside the `inspect` arm of `InspectValue`.
     GetStackTop,
@@ -240,7 +245,7 @@ impl CILNode {
     #[must_use]
     pub fn select(tpe: Type, a: Self, b: Self, predictate: Self) -> Self {
         match tpe {
-            Type::U128 | Type::I128 => call!(
+            Type::U128 | Type::I128 | Type::F128 => call!(
                 CallSite::builtin(
                     "select_u128".into(),
                     FnSig::new(&[Type::U128, Type::U128, Type::Bool], Type::U128),
@@ -383,6 +388,7 @@ impl CILNode {
             }
             Self::LDStaticField(_static_field) => (),
             Self::LDLen { arr } => arr.opt(opt_count),
+            Self::IsInst { obj, class: _ } => obj.opt(opt_count),
             Self::LDElelemRef { arr, idx } =>{
                 idx.opt(opt_count);
                 arr.opt(opt_count);
@@ -582,6 +588,7 @@ impl CILNode {
             Self::LDLen { arr } =>{
                arr.allocate_tmps(curr_loc, locals);
             }
+            Self::IsInst { obj, class: _ } => obj.allocate_tmps(curr_loc, locals),
             Self::LDElelemRef { arr, idx }=>{
                 arr.allocate_tmps(curr_loc, locals);
                 idx.allocate_tmps(curr_loc, locals);
@@ -125,6 +125,11 @@ pub enum CILRoot {
     VoidRet,
     Throw(CILNode),
     ReThrow,
+    /// Stores the exception object caught by the enclosing handler in `local`. The object is stored when the handler is
+    /// entered, so this may appear anywhere within the handler.
+    StoreCaught {
+        local: u32,
+    },
     CallI {
         sig: FnSig,
         fn_ptr: CILNode,
@@ -385,7 +390,7 @@ impl CILRoot {
             Self::Pop { tree } => tree.opt(opt_count),
             Self::VoidRet => (),
             Self::Throw(ops) => ops.opt(opt_count),
-            Self::ReThrow => (),
+            Self::ReThrow | Self::StoreCaught { .. } => (),
             Self::CallI {
                 sig: _,
                 fn_ptr,
@@ -451,12 +456,67 @@ impl CILRoot {
             | Self::BNe {
                 target, sub_target, ..
             }
+            | Self::BLt {
+                target, sub_target, ..
+            }
+            | Self::BLtUn {
+                target, sub_target, ..
+            }
+            | Self::BGt {
+                target, sub_target, ..
+            }
+            | Self::BGtUn {
+                target, sub_target, ..
+            }
+            | Self::BLe {
+                target, sub_target, ..
+            }
+            | Self::BGe {
+                target, sub_target, ..
+            }
             | Self::GoTo { target, sub_target } => {
                 targets.push((*target, *sub_target));
             }
             _ => (),
         }
     }
+    /// Returns the target and sub-target of this root, if it is a jump.
+    pub fn target_mut(&mut self) -> Option<(&mut u32, &mut u32)> {
+        match self {
+            Self::BTrue {
+                target, sub_target, ..
+            }
+            | Self::BFalse {
+                target, sub_target, ..
+            }
+            | Self::BEq {
+                target, sub_target, ..
+            }
+            | Self::BNe {
+                target, sub_target, ..
+            }
+            | Self::BLt {
+                target, sub_target, ..
+            }
+            | Self::BLtUn {
+                target, sub_target, ..
+            }
+            | Self::BGt {
+                target, sub_target, ..
+            }
+            | Self::BGtUn {
+                target, sub_target, ..
+            }
+            | Self::BLe {
+                target, sub_target, ..
+            }
+            | Self::BGe {
+                target, sub_target, ..
+            }
+            | Self::GoTo { target, sub_target } => Some((target, sub_target)),
+            _ => None,
+        }
+    }
     pub fn fix_for_exception_handler(&mut self, id: u32) {
         match self {
             Self::BTrue {
@@ -471,6 +531,24 @@ impl CILRoot {
             | Self::BNe {
                 target, sub_target, ..
             }
+            | Self::BLt {
+                target, sub_target, ..
+            }
+            | Self::BLtUn {
+                target, sub_target, ..
+            }
+            | Self::BGt {
+                target, sub_target, ..
+            }
+            | Self::BGtUn {
+                target, sub_target, ..
+            }
+            | Self::BLe {
+                target, sub_target, ..
+            }
+            | Self::BGe {
+                target, sub_target, ..
+            }
             | Self::GoTo { target, sub_target } => {
                 assert_eq!(
                     *sub_target, 0,
@@ -573,7 +651,7 @@ impl CILRoot {
             }
             Self::VoidRet => (),
 
-            Self::ReThrow => (),
+            Self::ReThrow | Self::StoreCaught { .. } => (),
             Self::CallI {
                 sig: _,
                 fn_ptr,
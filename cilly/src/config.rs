@@ -0,0 +1,468 @@
+//! The configuration of the codegen and the linker. Options are loaded from(in order of increasing priority):
+//! 1. Their defaults.
+//! 2. The project configuration file, [`CONFIG_FILE_NAME`], searched for in the directory of the crate being built and all its parents.
+//!    The path to it can also be set explicitly, using the `CLR_CONFIG` environment variable.
+//! 3. `-C llvm-args=key=value` options passed to rustc. The codegen forwards them to the linker(which rustc runs as its child) trough
+//!    the [`FORWARDED_ARGS_VAR`] environment variable, so both always see the same configuration.
+//! 4. Environment variables, named like the keys, but in upper case(`abort_on_error` is set by `ABORT_ON_ERROR`).
+use crate::c_exporter::COutputKind;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+/// The name of the project configuration file.
+pub const CONFIG_FILE_NAME: &str = "rustc_codegen_clr.toml";
+/// The environment variable used to forward the `-C llvm-args` options from the codegen to the linker.
+pub const FORWARDED_ARGS_VAR: &str = "CLR_FORWARDED_ARGS";
+/// Separates the forwarded args. An ASCII unit separator can't appear in a sane option, unlike spaces or commas.
+const FORWARDED_ARGS_SEPARATOR: char = '\x1f';
+/// Where the value of an option came from.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ConfigSource {
+    Default,
+    File,
+    Args,
+    Env,
+}
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::File => write!(f, "config file"),
+            Self::Args => write!(f, "rustc args"),
+            Self::Env => write!(f, "env"),
+        }
+    }
+}
+/// An error encountered while loading the configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    UnknownKey {
+        key: String,
+        source: ConfigSource,
+    },
+    InvalidValue {
+        key: &'static str,
+        value: String,
+        source: ConfigSource,
+    },
+}
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, err) => write!(
+                f,
+                "Could not read the config file {}: {err}",
+                path.display()
+            ),
+            Self::Parse(path, err) => write!(
+                f,
+                "Could not parse the config file {}: {err}",
+                path.display()
+            ),
+            Self::UnknownKey { key, source } => write!(
+                f,
+                "Unknown config option {key:?}(from {source}). Valid options are: {keys}",
+                keys = Config::KEYS.join(", ")
+            ),
+            Self::InvalidValue { key, value, source } => write!(
+                f,
+                "Config option {key:?} has an invalid value {value}(from {source})."
+            ),
+        }
+    }
+}
+impl std::error::Error for ConfigError {}
+/// A type which can be used as the value of an option.
+trait OptionValue: Sized {
+    fn from_toml(value: &toml::Value) -> Option<Self>;
+    fn from_str(value: &str) -> Option<Self>;
+    fn display(&self) -> String;
+}
+impl OptionValue for bool {
+    fn from_toml(value: &toml::Value) -> Option<Self> {
+        value.as_bool()
+    }
+    fn from_str(value: &str) -> Option<Self> {
+        match value {
+            "0" | "false" | "False" | "FALSE" => Some(false),
+            "1" | "true" | "True" | "TRUE" => Some(true),
+            _ => None,
+        }
+    }
+    fn display(&self) -> String {
+        self.to_string()
+    }
+}
+impl OptionValue for String {
+    fn from_toml(value: &toml::Value) -> Option<Self> {
+        value.as_str().map(Into::into)
+    }
+    fn from_str(value: &str) -> Option<Self> {
+        Some(value.into())
+    }
+    fn display(&self) -> String {
+        format!("{self:?}")
+    }
+}
+impl OptionValue for usize {
+    fn from_toml(value: &toml::Value) -> Option<Self> {
+        value.as_integer().and_then(|value| value.try_into().ok())
+    }
+    fn from_str(value: &str) -> Option<Self> {
+        value.parse().ok()
+    }
+    fn display(&self) -> String {
+        self.to_string()
+    }
+}
+impl OptionValue for COutputKind {
+    fn from_toml(value: &toml::Value) -> Option<Self> {
+        value.as_str().and_then(Self::from_name)
+    }
+    fn from_str(value: &str) -> Option<Self> {
+        Self::from_name(value)
+    }
+    fn display(&self) -> String {
+        format!("{:?}", self.name())
+    }
+}
+/// Where an option is set from.
+enum RawValue<'a> {
+    Toml(&'a toml::Value),
+    Str(&'a str),
+}
+impl RawValue<'_> {
+    fn parse<T: OptionValue>(
+        &self,
+        key: &'static str,
+        source: ConfigSource,
+    ) -> Result<T, ConfigError> {
+        match self {
+            Self::Toml(value) => T::from_toml(value),
+            Self::Str(value) => T::from_str(value),
+        }
+        .ok_or_else(|| ConfigError::InvalidValue {
+            key,
+            value: match self {
+                Self::Toml(value) => value.to_string(),
+                Self::Str(value) => format!("{value:?}"),
+            },
+            source,
+        })
+    }
+}
+macro_rules! config {
+    ($($(#[doc = $doc:literal])* $key:ident : $tpe:ty = $default:expr;)*) => {
+        /// The effective configuration of the codegen and the linker.
+        #[derive(Clone, Debug)]
+        pub struct Config {
+            $($(#[doc = $doc])* pub $key: $tpe,)*
+            /// Where each of the options was set from.
+            sources: BTreeMap<&'static str, ConfigSource>,
+        }
+        impl Default for Config {
+            fn default() -> Self {
+                Self {
+                    $($key: $default.into(),)*
+                    sources: BTreeMap::new(),
+                }
+            }
+        }
+        impl Config {
+            /// The names of all the options.
+            pub const KEYS: &'static [&'static str] = &[$(stringify!($key)),*];
+            /// Sets the option `key` to `value`.
+            fn set(&mut self, key: &str, value: &RawValue, source: ConfigSource) -> Result<(), ConfigError> {
+                match key {
+                    $(stringify!($key) => {
+                        self.$key = value.parse(stringify!($key), source)?;
+                        self.sources.insert(stringify!($key), source);
+                    })*
+                    _ => return Err(ConfigError::UnknownKey { key: key.into(), source }),
+                }
+                Ok(())
+            }
+            /// Returns the name, value and source of each option.
+            pub fn entries(&self) -> Vec<(&'static str, String, ConfigSource)> {
+                vec![$((
+                    stringify!($key),
+                    self.$key.display(),
+                    self.sources.get(stringify!($key)).copied().unwrap_or(ConfigSource::Default),
+                )),*]
+            }
+        }
+    };
+}
+config! {
+    /// Should the codegen stop working when ecountering an error, or try to press on, replacing unusuported code with exceptions throws?
+    abort_on_error: bool = false;
+    /// Tells the codegen to never emmit try/catch statements.
+    no_unwind: bool = false;
+    /// Allows the optimizer to inline calls to small functions.
+    inline_simple_functions: bool = false;
+    /// Turns on the local removal optimization.
+    remove_unsued_locals: bool = false;
+    /// Turns on allocation checks/debug info.
+    check_allocations: bool = false;
+    /// Typechecks all methods
+    verify_methods: bool = false;
+    /// Turns on the struct spliting optimzation.
+    split_local_structs: bool = false;
+    /// Should the codegen continue working after it encoutnered a miscompilation?
+    allow_miscompilations: bool = true;
+    /// Tells the codegen to insert comments containing the MIR statemtens after each one of them.
+    insert_mir_debug_comments: bool = false;
+    /// Prints local types of all compiled MIR functions.
+    print_local_types: bool = false;
+    /// Tells the codegen to insert additional checks on each variable asigement.
+    validte_values: bool = false;
+    /// Tells the codegen to optmize the emiited CIL. Turning it off disables all the passes below.
+    optimize_cil: bool = true;
+    /// Replaces reads of locals which always hold the same constant with that constant. The SSA based passes below
+    /// are off by default, until the test suite passes with them turned on.
+    const_propagation: bool = false;
+    /// Replaces reads of locals which hold a copy of another local or argument with reads of the original.
+    copy_propagation: bool = false;
+    /// Reuses the results of identical computations, instead of repeating them.
+    common_subexpression_elimination: bool = false;
+    /// Removes stores to locals which are never read.
+    dead_store_elimination: bool = false;
+    /// Tells the codegen to escape class and method names.
+    escape_names: bool = false;
+    /// Tells the codegen to use the mono runtime for tests.
+    test_with_mono: bool = false;
+    /// Tells the codegen to emmit C source files.
+    c_mode: bool = false;
+    /// The sanitizers the C source is built with, as a comma-separated list passed to `-fsanitize`(like
+    /// `undefined,address`). `true` or `1` means `undefined`, and `false` or `0` turns them off.
+    c_sanitize: String = "";
+    /// The C compiler used in C mode: `gcc`, `clang`, `tcc`, or a path to one of them.
+    c_compiler: String = "gcc";
+    /// The optimization level of the C compiler, passed to it as `-O{c_opt_level}`.
+    c_opt_level: String = "1";
+    /// Additional whitespace-separated flags passed to the C compiler.
+    c_flags: String = "";
+    /// What C mode builds: an `executable`, an `object` file, a `static_lib`, or only the C `source`.
+    c_output: COutputKind = COutputKind::Executable;
+    /// The number of C source files methods are split into. Those files are compiled in parallel.
+    c_units: usize = 1_usize;
+    /// Emits C code for a freestanding environment(like bare metal or WebAssembly), which does not use libc.
+    /// Executables can't be started without libc, so `c_output = "executable"` builds an object file instead.
+    c_freestanding: bool = false;
+    /// Tells the codegen to randomize TEST type layout.
+    randomize_layout: bool = false;
+    /// Tells the codegen compile linked static libraries into a shared library, which will be bundled with the .NET executable.
+    native_passtrough: bool = false;
+    /// Tells the codegen to preform additonal checks before saving the .
+    enforce_cil_valid: bool = false;
+    /// Tells codegen to check if references it assigns are valid.
+    check_refs: bool = false;
+    /// Checks the geneareted CIL for type safety.
+    typecheck_cil: bool = false;
+    /// Tells the print each CIL op before it is executed.
+    trace_cil_ops: bool = false;
+    /// Tells codegen to insert memory consistency checks after each call. If `insert_mir_debug_comments` is enabled, the consistency checks will be run also after each MIR statement.
+    mem_checks: bool = false;
+    /// Tells codegen to display source file info when executing each statement.
+    debug_sfi: bool = false;
+    /// Tells the codegen to remove dead code before export.
+    dead_code_elimination: bool = true;
+    /// Tells the linker to also remove the types which are not used by any reachable method, static or type.
+    dead_type_elimination: bool = false;
+    /// The name of the public class containing the .NET wrappers of functions exported from Rust libraries. If empty,
+    /// it is derived from the name of the library.
+    exports_class: String = "";
+    /// Makes the linker print why the methods, static fields and types whose name contains this string are kept.
+    why_kept: String = "";
+    /// Makes the linker write a report of the size of the final assembly, grouped by crate and module, to this path.
+    size_report: String = "";
+    /// Specifies the path to the IL assembler.
+    ilasm_path: String = "ilasm";
+    /// Changes `.locals` into `.locals init`. Causes the runtime to always initialize local variables.
+    /// Try turining on in cause of issues. If it fixes them, then their root cause is use of uninitailized memory.
+    always_init_locals: bool = false;
+    /// Tells codegen the print each pointer it dereferences.
+    print_ptrs: bool = false;
+    /// Preapends each function call with a debug message
+    trace_calls: bool = false;
+    /// Tells the linker to write the final assembly directly, without using ILASM. Both ways produce a Portable PDB with
+    /// sequence points and local variable names.
+    direct_pe: bool = false;
+    /// Prints the effective configuration once it is loaded.
+    print_config: bool = false;
+}
+impl Config {
+    /// Applies the options from the TOML file at `path`.
+    fn apply_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let source =
+            std::fs::read_to_string(path).map_err(|err| ConfigError::Io(path.into(), err))?;
+        self.apply_toml(&source)
+            .map_err(|err| err.unwrap_or_else(|err| ConfigError::Parse(path.into(), err)))
+    }
+    /// Applies the options from the TOML document `source`. Returns `Err(Err(_))` if the document is not valid TOML.
+    fn apply_toml(&mut self, source: &str) -> Result<(), Result<ConfigError, toml::de::Error>> {
+        let table: toml::Table = source.parse().map_err(Err)?;
+        for (key, value) in &table {
+            self.set(key, &RawValue::Toml(value), ConfigSource::File)
+                .map_err(Ok)?;
+        }
+        Ok(())
+    }
+    /// Applies options passed as `key=value` or `key`(meaning `key=true`) rustc arguments.
+    fn apply_args(&mut self, args: &[String]) -> Result<(), ConfigError> {
+        for arg in args {
+            let arg = arg.trim_start_matches('-');
+            let (key, value) = arg.split_once('=').unwrap_or((arg, "true"));
+            self.set(key, &RawValue::Str(value), ConfigSource::Args)?;
+        }
+        Ok(())
+    }
+    /// Applies the options set by environment variables.
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        for key in Self::KEYS {
+            if let Ok(value) = std::env::var(key.to_uppercase()) {
+                self.set(key, &RawValue::Str(&value), ConfigSource::Env)?;
+            }
+        }
+        Ok(())
+    }
+    /// Loads the configuration, applying `args` passed to rustc.
+    /// # Errors
+    /// Returns an error if the config file can't be read, or any of the options is unknown or invalid.
+    pub fn load(args: &[String]) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        if let Some(path) = config_file() {
+            config.apply_file(&path)?;
+        }
+        config.apply_args(args)?;
+        config.apply_env()?;
+        Ok(config)
+    }
+}
+impl Display for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (key, value, source) in self.entries() {
+            writeln!(f, "{key} = {value} # {source}")?;
+        }
+        Ok(())
+    }
+}
+/// Returns the path to the project configuration file, if there is one.
+fn config_file() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("CLR_CONFIG") {
+        return Some(path.into());
+    }
+    // Cargo sets this for rustc, and the linker inherits it.
+    let start = std::env::var("CARGO_MANIFEST_DIR")
+        .map(PathBuf::from)
+        .or_else(|_| std::env::current_dir())
+        .ok()?;
+    start
+        .ancestors()
+        .map(|dir| dir.join(CONFIG_FILE_NAME))
+        .find(|path| path.is_file())
+}
+/// Joins `args` into the value of [`FORWARDED_ARGS_VAR`].
+fn encode_forwarded(args: &[String]) -> String {
+    args.join(&FORWARDED_ARGS_SEPARATOR.to_string())
+}
+/// Splits the value of [`FORWARDED_ARGS_VAR`] back into args.
+fn decode_forwarded(value: &str) -> Vec<String> {
+    value
+        .split(FORWARDED_ARGS_SEPARATOR)
+        .filter(|arg| !arg.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+static CONFIG: OnceLock<Config> = OnceLock::new();
+/// Loads the configuration, applying `args` passed to rustc. Has no effect if the configuration was already loaded.
+///
+/// Non-empty `args` are forwarded to child processes(the linker) trough [`FORWARDED_ARGS_VAR`]. When `args` are empty,
+/// the forwarded ones(if any) are used instead.
+/// # Errors
+/// Returns an error if any of the options is unknown or invalid.
+pub fn init(args: &[String]) -> Result<&'static Config, ConfigError> {
+    if let Some(config) = CONFIG.get() {
+        return Ok(config);
+    }
+    let forwarded;
+    let args = if args.is_empty() {
+        forwarded = std::env::var(FORWARDED_ARGS_VAR)
+            .map_or_else(|_| Vec::new(), |value| decode_forwarded(&value));
+        &forwarded
+    } else {
+        std::env::set_var(FORWARDED_ARGS_VAR, encode_forwarded(args));
+        args
+    };
+    let config = Config::load(args)?;
+    if config.print_config {
+        eprintln!("Effective configuration:\n{config}");
+    }
+    Ok(CONFIG.get_or_init(|| config))
+}
+/// Returns the configuration, loading it if needed.
+/// # Panics
+/// Panics if the configuration is invalid.
+#[must_use]
+pub fn config() -> &'static Config {
+    init(&[]).unwrap_or_else(|err| panic!("{err}"))
+}
+#[test]
+fn config_sources() {
+    let mut config = Config::default();
+    config
+        .apply_toml("abort_on_error = true\nilasm_path = \"/opt/ilasm\"")
+        .unwrap();
+    config
+        .apply_args(&["optimize_cil=false".into(), "c_mode".into()])
+        .unwrap();
+    assert!(config.abort_on_error && config.c_mode && !config.optimize_cil);
+    assert_eq!(config.ilasm_path, "/opt/ilasm");
+    assert!(config
+        .entries()
+        .contains(&("abort_on_error", "true".into(), ConfigSource::File)));
+    // Unknown keys and mistyped values are rejected.
+    assert!(matches!(
+        config.apply_toml("abort_on_eror = true"),
+        Err(Ok(ConfigError::UnknownKey { .. }))
+    ));
+    assert!(matches!(
+        config.apply_args(&["no_unwind=maybe".into()]),
+        Err(ConfigError::InvalidValue { .. })
+    ));
+    config
+        .apply_toml("c_output = \"static_lib\"\nc_compiler = \"clang\"")
+        .unwrap();
+    assert_eq!(config.c_output, COutputKind::StaticLib);
+    config.apply_args(&["c_units=8".into()]).unwrap();
+    assert_eq!(config.c_units, 8);
+    assert!(config.apply_toml("c_units = -1").is_err());
+    assert!(matches!(
+        config.apply_args(&["c_output=dll".into()]),
+        Err(ConfigError::InvalidValue { .. })
+    ));
+}
+#[test]
+fn forwarded_args() {
+    let args: Vec<String> = vec![
+        "c_mode".into(),
+        "c_flags=-O2 -g".into(),
+        "escape_names=true".into(),
+    ];
+    assert_eq!(decode_forwarded(&encode_forwarded(&args)), args);
+    assert_eq!(decode_forwarded(""), Vec::<String>::new());
+    // The linker sees the same values the codegen did.
+    let mut codegen = Config::default();
+    codegen.apply_args(&args).unwrap();
+    let mut linker = Config::default();
+    linker
+        .apply_args(&decode_forwarded(&encode_forwarded(&args)))
+        .unwrap();
+    assert_eq!(codegen.entries(), linker.entries());
+}
@@ -0,0 +1,255 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    call_site::CallSite,
+    ilasm_op::{dotnet_type_ref_extends, non_void_type_cil},
+    DotnetTypeRef, FnSig, IString, Type,
+};
+/// `System.Runtime.CompilerServices.MethodImplOptions.NoInlining`
+pub const NO_INLINING: i32 = 0x0008;
+/// `System.Runtime.CompilerServices.MethodImplOptions.AggressiveInlining`
+pub const AGGRESSIVE_INLINING: i32 = 0x0100;
+/// A fixed argument passed to the constructor of a custom attribute.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub enum AttributeArg {
+    Bool(bool),
+    U8(u8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    String(IString),
+    /// A value of an enum with an `int32` underlying type.
+    Enum(DotnetTypeRef, i32),
+}
+impl AttributeArg {
+    /// The type of the constructor parameter this argument is passed as.
+    #[must_use]
+    pub fn tpe(&self) -> Type {
+        match self {
+            Self::Bool(_) => Type::Bool,
+            Self::U8(_) => Type::U8,
+            Self::I16(_) => Type::I16,
+            Self::I32(_) => Type::I32,
+            Self::I64(_) => Type::I64,
+            Self::String(_) => Type::DotnetType(DotnetTypeRef::string_type().into()),
+            Self::Enum(tpe, _) => Type::DotnetType(tpe.clone().with_valuetype(true).into()),
+        }
+    }
+    /// Encodes this argument as a fixed argument of a custom attribute blob(II.23.3).
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Bool(val) => out.push(u8::from(*val)),
+            Self::U8(val) => out.push(*val),
+            Self::I16(val) => out.extend(val.to_le_bytes()),
+            Self::I32(val) | Self::Enum(_, val) => out.extend(val.to_le_bytes()),
+            Self::I64(val) => out.extend(val.to_le_bytes()),
+            Self::String(val) => {
+                let len = u32::try_from(val.len()).expect("Attribute string too long!");
+                match len {
+                    0..=0x7F => out.push(len as u8),
+                    0x80..=0x3FFF => out.extend((len as u16 | 0x8000).to_be_bytes()),
+                    _ => out.extend((len | 0xC000_0000).to_be_bytes()),
+                }
+                out.extend(val.as_bytes());
+            }
+        }
+    }
+}
+/// A .NET custom attribute, applied to a type, method or field.
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub struct CustomAttribute {
+    tpe: DotnetTypeRef,
+    args: Vec<AttributeArg>,
+}
+impl CustomAttribute {
+    /// Creates an attribute of class `tpe`, constructed using the constructor accepting `args`.
+    #[must_use]
+    pub fn new(tpe: DotnetTypeRef, args: impl Into<Vec<AttributeArg>>) -> Self {
+        Self {
+            tpe: tpe.with_valuetype(false),
+            args: args.into(),
+        }
+    }
+    /// `[MethodImpl(options)]`. A pseudo-attribute: exporters turn it into method implementation flags.
+    #[must_use]
+    pub fn method_impl(options: i32) -> Self {
+        let options_tpe = DotnetTypeRef::new(
+            Some("System.Runtime"),
+            "System.Runtime.CompilerServices.MethodImplOptions",
+        );
+        Self::new(
+            DotnetTypeRef::new(
+                Some("System.Runtime"),
+                "System.Runtime.CompilerServices.MethodImplAttribute",
+            ),
+            [AttributeArg::Enum(options_tpe, options)],
+        )
+    }
+    /// `[StructLayout(kind)]`. A pseudo-attribute: exporters turn it into type layout flags.
+    #[must_use]
+    pub fn struct_layout(kind: LayoutKind) -> Self {
+        let kind_tpe = DotnetTypeRef::new(
+            Some("System.Runtime"),
+            "System.Runtime.InteropServices.LayoutKind",
+        );
+        Self::new(
+            DotnetTypeRef::new(
+                Some("System.Runtime"),
+                "System.Runtime.InteropServices.StructLayoutAttribute",
+            ),
+            [AttributeArg::Enum(kind_tpe, kind as i32)],
+        )
+    }
+    /// `[Obsolete(message)]`
+    #[must_use]
+    pub fn obsolete(message: &str) -> Self {
+        Self::new(
+            DotnetTypeRef::new(Some("System.Runtime"), "System.ObsoleteAttribute"),
+            [AttributeArg::String(message.into())],
+        )
+    }
+    /// `[UnmanagedCallersOnly]`, which allows native code to call a static method through a function pointer.
+    #[must_use]
+    pub fn unmanaged_callers_only() -> Self {
+        Self::new(
+            DotnetTypeRef::new(
+                Some("System.Runtime.InteropServices"),
+                "System.Runtime.InteropServices.UnmanagedCallersOnlyAttribute",
+            ),
+            [],
+        )
+    }
+    /// `[SkipLocalsInit]`
+    #[must_use]
+    pub fn skip_locals_init() -> Self {
+        Self::new(
+            DotnetTypeRef::new(
+                Some("System.Runtime"),
+                "System.Runtime.CompilerServices.SkipLocalsInitAttribute",
+            ),
+            [],
+        )
+    }
+    /// `[ThreadStatic]`
+    #[must_use]
+    pub fn thread_static() -> Self {
+        Self::new(
+            DotnetTypeRef::new(Some("System.Runtime"), "System.ThreadStaticAttribute"),
+            [],
+        )
+    }
+    /// The class of this attribute.
+    #[must_use]
+    pub fn tpe(&self) -> &DotnetTypeRef {
+        &self.tpe
+    }
+    #[must_use]
+    pub fn args(&self) -> &[AttributeArg] {
+        &self.args
+    }
+    /// The constructor used to create this attribute.
+    #[must_use]
+    pub fn ctor(&self) -> CallSite {
+        let inputs: Vec<_> = std::iter::once(Type::DotnetType(self.tpe.clone().into()))
+            .chain(self.args.iter().map(AttributeArg::tpe))
+            .collect();
+        CallSite::new(
+            Some(self.tpe.clone()),
+            ".ctor".into(),
+            FnSig::new(inputs, Type::Void),
+            false,
+        )
+    }
+    /// The value blob of this attribute(II.23.3): a prolog, the fixed arguments, and no named arguments.
+    #[must_use]
+    pub fn blob(&self) -> Vec<u8> {
+        let mut out = vec![0x01, 0x00];
+        for arg in &self.args {
+            arg.encode(&mut out);
+        }
+        out.extend(0_u16.to_le_bytes());
+        out
+    }
+    /// If this is the `MethodImpl` pseudo-attribute, returns the `MethodImplOptions` it sets.
+    #[must_use]
+    pub fn method_impl_options(&self) -> Option<i32> {
+        if self.tpe.name_path() != "System.Runtime.CompilerServices.MethodImplAttribute" {
+            return None;
+        }
+        match self.args.as_slice() {
+            [AttributeArg::Enum(_, options) | AttributeArg::I32(options)] => Some(*options),
+            [AttributeArg::I16(options)] => Some(i32::from(*options)),
+            _ => None,
+        }
+    }
+    /// If this is the `StructLayout` pseudo-attribute, returns the layout it requests.
+    #[must_use]
+    pub fn layout_kind(&self) -> Option<LayoutKind> {
+        if self.tpe.name_path() != "System.Runtime.InteropServices.StructLayoutAttribute" {
+            return None;
+        }
+        let kind = match self.args.as_slice() {
+            [AttributeArg::Enum(_, kind) | AttributeArg::I32(kind)] => *kind,
+            [AttributeArg::I16(kind)] => i32::from(*kind),
+            _ => return None,
+        };
+        match kind {
+            0 => Some(LayoutKind::Sequential),
+            2 => Some(LayoutKind::Explicit),
+            3 => Some(LayoutKind::Auto),
+            _ => None,
+        }
+    }
+    /// Pseudo-attributes are not stored as custom attributes, but as flags of the item they are applied to.
+    #[must_use]
+    pub fn is_pseudo(&self) -> bool {
+        self.method_impl_options().is_some() || self.layout_kind().is_some()
+    }
+    /// The ILASM directive applying this attribute.
+    #[must_use]
+    pub fn ilasm(&self) -> String {
+        let mut inputs = String::new();
+        for (idx, arg) in self.args.iter().enumerate() {
+            if idx != 0 {
+                inputs.push(',');
+            }
+            inputs.push_str(&non_void_type_cil(&arg.tpe()));
+        }
+        let blob: Vec<_> = self
+            .blob()
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        format!(
+            ".custom instance void {tpe}::.ctor({inputs}) = ({blob})",
+            tpe = dotnet_type_ref_extends(&self.tpe),
+            blob = blob.join(" ")
+        )
+    }
+}
+/// `System.Runtime.InteropServices.LayoutKind`
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Debug)]
+pub enum LayoutKind {
+    Sequential = 0,
+    Explicit = 2,
+    Auto = 3,
+}
+#[test]
+fn attribute_blob() {
+    let obsolete = CustomAttribute::obsolete("old");
+    assert_eq!(
+        obsolete.blob(),
+        [0x01, 0x00, 0x03, b'o', b'l', b'd', 0x00, 0x00]
+    );
+    assert!(!obsolete.is_pseudo());
+    let inline = CustomAttribute::method_impl(AGGRESSIVE_INLINING);
+    assert_eq!(inline.method_impl_options(), Some(AGGRESSIVE_INLINING));
+    assert_eq!(
+        inline.blob(),
+        [0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00]
+    );
+    assert_eq!(
+        CustomAttribute::thread_static().ilasm(),
+        ".custom instance void [System.Runtime]'System.ThreadStaticAttribute'::.ctor() = (01 00 00 00)"
+    );
+}
@@ -76,6 +76,50 @@ impl DotnetTypeRef {
         .with_valuetype(false)
     }
     #[must_use]
+    pub fn half() -> Self {
+        Self::new(Some("System.Runtime"), "System.Half")
+    }
+    /// The hardware accelerated vector `Vector{bits}<T>`, with lanes of type `elem`.
+    #[must_use]
+    pub fn vector(bits: u32, elem: Type) -> Self {
+        let mut vector = Self::new(
+            Some("System.Runtime.Intrinsics"),
+            format!("System.Runtime.Intrinsics.Vector{bits}`1"),
+        );
+        vector.set_generics([elem]);
+        vector
+    }
+    /// The static class containing the operations on `Vector{bits}<T>`.
+    #[must_use]
+    pub fn vector_helpers(bits: u32) -> Self {
+        Self::new(
+            Some("System.Runtime.Intrinsics"),
+            format!("System.Runtime.Intrinsics.Vector{bits}"),
+        )
+        .with_valuetype(false)
+    }
+    /// If this is a vector created using [`Self::vector`], returns its size in bits and the type of its lanes.
+    #[must_use]
+    pub fn as_vector(&self) -> Option<(u32, &Type)> {
+        if self.asm() != Some("System.Runtime.Intrinsics") || self.generics.len() != 1 {
+            return None;
+        }
+        let bits = self
+            .name_path()
+            .strip_prefix("System.Runtime.Intrinsics.Vector")?
+            .strip_suffix("`1")?;
+        Some((bits.parse().ok()?, &self.generics[0]))
+    }
+    /// Checks if this is a class created using [`Self::vector_helpers`].
+    #[must_use]
+    pub fn is_vector_helpers(&self) -> bool {
+        self.asm() == Some("System.Runtime.Intrinsics")
+            && self
+                .name_path()
+                .strip_prefix("System.Runtime.Intrinsics.Vector")
+                .is_some_and(|bits| bits.parse::<u32>().is_ok())
+    }
+    #[must_use]
     pub fn uint_128() -> Self {
         Self::new(Some("System.Runtime"), "System.UInt128")
     }
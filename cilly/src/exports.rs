@@ -0,0 +1,481 @@
+//! Generates an idiomatic .NET API for the functions a Rust library exports(`#[no_mangle] pub extern fn`), so that
+//! .NET projects can reference the final assembly directly. Exported functions get a wrapper method with a PascalCase
+//! name in a public class, which converts .NET strings and arrays to Rust string slices and slices.
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    access_modifier::AccessModifer,
+    asm::Assembly,
+    basic_block::{BasicBlock, Handler},
+    call,
+    call_site::CallSite,
+    cil_node::CILNode,
+    cil_root::CILRoot,
+    cil_tree::CILTree,
+    conv_i32, conv_usize,
+    field_desc::FieldDescriptor,
+    method::{Method, MethodType},
+    type_def::TypeDef,
+    DotnetTypeRef, FnSig, IString, Type,
+};
+
+/// How an argument or the return value of an exported function is exposed to .NET.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub enum ExportedType {
+    /// Passed as-is.
+    Direct,
+    /// A `&str`, exposed as a `string`.
+    Str,
+    /// A `&[T]` or `&mut [T]`, exposed as an array of `T`. Only supported for arguments.
+    Slice(Type),
+}
+/// Describes how a function exported from Rust is exposed to .NET.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct ExportInfo {
+    inputs: Vec<ExportedType>,
+    output: ExportedType,
+}
+impl ExportInfo {
+    /// Creates a new export info, describing a function with arguments exposed as `inputs`, returning `output`.
+    #[must_use]
+    pub fn new(inputs: Vec<ExportedType>, output: ExportedType) -> Self {
+        Self { inputs, output }
+    }
+    /// Returns how the arguments of the function are exposed.
+    #[must_use]
+    pub fn inputs(&self) -> &[ExportedType] {
+        &self.inputs
+    }
+    /// Returns how the return value of the function is exposed.
+    #[must_use]
+    pub fn output(&self) -> &ExportedType {
+        &self.output
+    }
+}
+/// Converts a Rust identifier, like `add_numbers`, to PascalCase(`AddNumbers`).
+#[must_use]
+pub fn pascal_case(name: &str) -> String {
+    let mut res: String = name
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|first| first.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect();
+    if !res.starts_with(|c: char| c.is_alphabetic()) {
+        res.insert(0, '_');
+    }
+    res
+}
+fn gc_handle_type() -> DotnetTypeRef {
+    DotnetTypeRef::new(
+        Some("System.Runtime"),
+        "System.Runtime.InteropServices.GCHandleType",
+    )
+}
+fn encoding() -> DotnetTypeRef {
+    DotnetTypeRef::new(Some("System.Runtime"), "System.Text.Encoding").with_valuetype(false)
+}
+/// Sets the fields of the fat pointer in local `fat_ptr` to `data_pointer` and `metadata`.
+fn set_fat_ptr(
+    trees: &mut Vec<CILTree>,
+    fat_ptr: u32,
+    fat_ptr_tpe: &Type,
+    data_pointer: CILNode,
+    metadata: CILNode,
+) {
+    let owner = fat_ptr_tpe
+        .as_dotnet()
+        .expect("Slices should be passed as fat pointers");
+    trees.push(
+        CILRoot::SetField {
+            addr: CILNode::LDLocA(fat_ptr),
+            value: data_pointer,
+            desc: FieldDescriptor::new(
+                owner.clone(),
+                Type::Ptr(Type::Void.into()),
+                "data_pointer".into(),
+            ),
+        }
+        .into(),
+    );
+    trees.push(
+        CILRoot::SetField {
+            addr: CILNode::LDLocA(fat_ptr),
+            value: metadata,
+            desc: FieldDescriptor::new(owner, Type::USize, "metadata".into()),
+        }
+        .into(),
+    );
+}
+/// Creates the .NET-facing wrapper of `method`, exposed as described by `info`. Returns `None` if the signature of
+/// `method` does not match `info`.
+#[must_use]
+pub fn wrapper(method: &Method, info: &ExportInfo) -> Option<Method> {
+    let inputs = method.sig().inputs();
+    if inputs.len() != info.inputs().len() || matches!(info.output(), ExportedType::Slice(_)) {
+        return None;
+    }
+    let mut locals: Vec<(Option<IString>, Type)> = vec![];
+    let mut alloc_local = |tpe: Type| {
+        locals.push((None, tpe));
+        u32::try_from(locals.len() - 1).unwrap()
+    };
+    let mut wrapper_inputs = Vec::with_capacity(inputs.len());
+    let mut args = Vec::with_capacity(inputs.len());
+    let mut trees: Vec<CILTree> = vec![];
+    let mut cleanup: Vec<CILTree> = vec![];
+    for (arg, (tpe, exported)) in inputs.iter().zip(info.inputs()).enumerate() {
+        let arg = u32::try_from(arg).unwrap();
+        match exported {
+            ExportedType::Direct => {
+                wrapper_inputs.push(tpe.clone());
+                args.push(CILNode::LDArg(arg));
+            }
+            ExportedType::Str => {
+                wrapper_inputs.push(DotnetTypeRef::string_type().into());
+                // The string is converted to a null-terminated UTF8 buffer, which is freed after the call.
+                let buffer = alloc_local(Type::ISize);
+                let fat_ptr = alloc_local(tpe.clone());
+                trees.push(
+                    CILRoot::STLoc {
+                        local: buffer,
+                        tree: call!(CallSite::mstring_to_ptr(), [CILNode::LDArg(arg)]),
+                    }
+                    .into(),
+                );
+                let byte_count = CILNode::CallVirt {
+                    args: [
+                        call!(
+                            CallSite::new(
+                                Some(encoding()),
+                                "get_UTF8".into(),
+                                FnSig::new(&[], Type::from(encoding())),
+                                true,
+                            ),
+                            []
+                        ),
+                        CILNode::LDArg(arg),
+                    ]
+                    .into(),
+                    site: CallSite::new(
+                        Some(encoding()),
+                        "GetByteCount".into(),
+                        FnSig::new(
+                            &[encoding().into(), DotnetTypeRef::string_type().into()],
+                            Type::I32,
+                        ),
+                        false,
+                    )
+                    .into(),
+                };
+                set_fat_ptr(
+                    &mut trees,
+                    fat_ptr,
+                    tpe,
+                    CILNode::LDLoc(buffer),
+                    conv_usize!(byte_count),
+                );
+                cleanup.push(
+                    CILRoot::Call {
+                        site: CallSite::new_extern(
+                            DotnetTypeRef::marshal(),
+                            "FreeCoTaskMem".into(),
+                            FnSig::new(&[Type::ISize], Type::Void),
+                            true,
+                        ),
+                        args: [CILNode::LDLoc(buffer)].into(),
+                    }
+                    .into(),
+                );
+                args.push(CILNode::LDLoc(fat_ptr));
+            }
+            ExportedType::Slice(element) => {
+                wrapper_inputs.push(Type::ManagedArray {
+                    element: element.clone().into(),
+                    dims: std::num::NonZeroU8::new(1).unwrap(),
+                });
+                // The array is pinned for the duration of the call.
+                let handle = alloc_local(DotnetTypeRef::gc_handle().into());
+                let fat_ptr = alloc_local(tpe.clone());
+                trees.push(
+                    CILRoot::STLoc {
+                        local: handle,
+                        tree: call!(
+                            CallSite::new(
+                                Some(DotnetTypeRef::gc_handle()),
+                                "Alloc".into(),
+                                FnSig::new(
+                                    &[DotnetTypeRef::object_type().into(), gc_handle_type().into()],
+                                    Type::from(DotnetTypeRef::gc_handle())
+                                ),
+                                true,
+                            ),
+                            // GCHandleType.Pinned
+                            [CILNode::LDArg(arg), CILNode::LdcI32(3)]
+                        ),
+                    }
+                    .into(),
+                );
+                let handle_ref =
+                    Type::ManagedReference(Box::new(DotnetTypeRef::gc_handle().into()));
+                let data_pointer = call!(
+                    CallSite::new(
+                        Some(DotnetTypeRef::gc_handle()),
+                        "AddrOfPinnedObject".into(),
+                        FnSig::new(&[handle_ref.clone()], Type::ISize),
+                        false,
+                    ),
+                    [CILNode::LDLocA(handle)]
+                );
+                let len = conv_usize!(CILNode::LDLen {
+                    arr: CILNode::LDArg(arg).into()
+                });
+                set_fat_ptr(&mut trees, fat_ptr, tpe, data_pointer, len);
+                cleanup.push(
+                    CILRoot::Call {
+                        site: CallSite::new(
+                            Some(DotnetTypeRef::gc_handle()),
+                            "Free".into(),
+                            FnSig::new(&[handle_ref], Type::Void),
+                            false,
+                        ),
+                        args: [CILNode::LDLocA(handle)].into(),
+                    }
+                    .into(),
+                );
+                args.push(CILNode::LDLoc(fat_ptr));
+            }
+        }
+    }
+    let output = method.sig().output();
+    let site = method.call_site();
+    let mut call: Vec<CILTree> = vec![];
+    let (result, wrapper_output) = if *output == Type::Void {
+        call.push(
+            CILRoot::Call {
+                site,
+                args: args.into(),
+            }
+            .into(),
+        );
+        (None, Type::Void)
+    } else {
+        let result = alloc_local(output.clone());
+        call.push(
+            CILRoot::STLoc {
+                local: result,
+                tree: call!(site, args),
+            }
+            .into(),
+        );
+        let wrapper_output = match info.output() {
+            ExportedType::Str => DotnetTypeRef::string_type().into(),
+            _ => output.clone(),
+        };
+        (Some(result), wrapper_output)
+    };
+    let ret = match (result, info.output()) {
+        (None, _) => CILRoot::VoidRet,
+        (Some(result), ExportedType::Str) => {
+            let owner = output.as_dotnet()?;
+            let field = |tpe: Type, name: &str| {
+                Box::new(FieldDescriptor::new(owner.clone(), tpe, name.into()))
+            };
+            CILRoot::Ret {
+                tree: call!(
+                    CallSite::new_extern(
+                        DotnetTypeRef::marshal(),
+                        "PtrToStringUTF8".into(),
+                        FnSig::new(
+                            &[Type::ISize, Type::I32],
+                            Type::from(DotnetTypeRef::string_type())
+                        ),
+                        true,
+                    ),
+                    [
+                        CILNode::LDField {
+                            addr: CILNode::LDLocA(result).into(),
+                            field: field(Type::Ptr(Type::Void.into()), "data_pointer"),
+                        },
+                        conv_i32!(CILNode::LDField {
+                            addr: CILNode::LDLocA(result).into(),
+                            field: field(Type::USize, "metadata"),
+                        })
+                    ]
+                ),
+            }
+        }
+        (Some(result), _) => CILRoot::Ret {
+            tree: CILNode::LDLoc(result),
+        },
+    };
+    let blocks = if cleanup.is_empty() {
+        trees.extend(call);
+        trees.push(ret.into());
+        vec![BasicBlock::new(trees, 0, None)]
+    } else {
+        // The call is protected, so the buffers are freed and the arrays unpinned even if it throws. The arguments
+        // are converted before entering the protected block: cleaning up after a failed conversion would free
+        // handles that were never allocated.
+        trees.push(
+            CILRoot::GoTo {
+                target: 1,
+                sub_target: 0,
+            }
+            .into(),
+        );
+        call.push(
+            CILRoot::GoTo {
+                target: 3,
+                sub_target: 0,
+            }
+            .into(),
+        );
+        let mut rethrow = cleanup.clone();
+        rethrow.push(CILRoot::ReThrow.into());
+        let mut protected = BasicBlock::new(call, 1, Some(Handler::RawID(2)));
+        protected.resolve_exception_handlers(&[BasicBlock::new(rethrow, 2, None)]);
+        cleanup.push(ret.into());
+        vec![
+            BasicBlock::new(trees, 0, None),
+            protected,
+            BasicBlock::new(cleanup, 3, None),
+        ]
+    };
+    let arg_names = if method.arg_names().len() == inputs.len() {
+        method.arg_names().to_vec()
+    } else {
+        vec![None; inputs.len()]
+    };
+    Some(Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(wrapper_inputs, wrapper_output),
+        &pascal_case(method.name()),
+        locals,
+        blocks,
+        arg_names,
+    ))
+}
+/// Picks the name of the wrapper of the exported function `name`: its `PascalCase` form, unless another wrapper already
+/// uses it(`add_numbers` and `addNumbers` both become `AddNumbers`). Then, the original name is used, followed by a
+/// number if needed.
+fn wrapper_name(name: &str, taken: &mut HashSet<String>) -> String {
+    let pascal = pascal_case(name);
+    let res = if taken.contains(&pascal) {
+        if taken.contains(name) {
+            // One of the first `taken.len() + 1` candidates is always free.
+            (1..=taken.len() + 1)
+                .map(|index| format!("{name}{index}"))
+                .find(|candidate| !taken.contains(candidate))
+                .unwrap()
+        } else {
+            name.to_owned()
+        }
+    } else {
+        pascal
+    };
+    taken.insert(res.clone());
+    res
+}
+/// Adds a public class named `class`, with wrappers for all the exported functions of `asm`. Returns the number of
+/// wrapped functions. If there are none, the class is not added.
+pub fn add_export_wrappers(asm: &mut Assembly, class: &str) -> usize {
+    let mut exported: Vec<&Method> = asm
+        .methods()
+        .filter(|method| method.export_info().is_some())
+        .collect();
+    exported.sort_by(|a, b| a.name().cmp(b.name()));
+    let mut taken = HashSet::new();
+    let wrappers: Vec<Method> = exported
+        .into_iter()
+        .filter_map(|method| {
+            let mut wrapper = wrapper(method, method.export_info()?)?;
+            wrapper.set_name(&wrapper_name(method.name(), &mut taken));
+            Some(wrapper)
+        })
+        .collect();
+    if wrappers.is_empty() {
+        return 0;
+    }
+    let count = wrappers.len();
+    asm.add_typedef(TypeDef::new(
+        AccessModifer::Public,
+        class.into(),
+        vec![],
+        vec![],
+        wrappers,
+        None,
+        0,
+        Some(DotnetTypeRef::object_type()),
+        None,
+    ));
+    count
+}
+#[test]
+fn wrap_str_export() {
+    assert_eq!(pascal_case("add_numbers"), "AddNumbers");
+    assert_eq!(pascal_case("__rust_fn"), "RustFn");
+    assert_eq!(pascal_case("_2d"), "_2d");
+    let fat_ptr = Type::DotnetType(Box::new(DotnetTypeRef::new::<&str, _>(None, "FatPtru8")));
+    let mut exported = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[fat_ptr, Type::I32], Type::USize),
+        "count_chars",
+        vec![],
+        vec![BasicBlock::new(
+            vec![CILRoot::Ret {
+                tree: conv_usize!(CILNode::LDArg(1)),
+            }
+            .into()],
+            0,
+            None,
+        )],
+        vec![Some("text".into()), Some("limit".into())],
+    );
+    exported.add_attribute(crate::method::Attribute::Export(ExportInfo::new(
+        vec![ExportedType::Str, ExportedType::Direct],
+        ExportedType::Direct,
+    )));
+    let mut asm = Assembly::empty();
+    asm.add_method(exported);
+    assert_eq!(add_export_wrappers(&mut asm, "MyLib"), 1);
+    let (_, class) = asm.types().find(|(name, _)| &***name == "MyLib").unwrap();
+    let wrapper = class.methods().next().unwrap();
+    assert_eq!(wrapper.name(), "CountChars");
+    assert_eq!(
+        wrapper.sig().inputs(),
+        [DotnetTypeRef::string_type().into(), Type::I32]
+    );
+    assert_eq!(
+        wrapper.arg_names(),
+        [Some("text".into()), Some("limit".into())]
+    );
+    // The string buffer is freed on both the normal and the exceptional path.
+    let frees = |block: &BasicBlock| {
+        block.trees().iter().any(|tree| {
+            matches!(tree.root(), CILRoot::Call { site, .. } if site.name() == "FreeCoTaskMem")
+        })
+    };
+    let blocks = wrapper.blocks();
+    let handler = blocks[1].handler().and_then(Handler::as_blocks).unwrap();
+    assert!(handler.iter().any(frees));
+    assert!(blocks[2..].iter().any(frees));
+    assert!(!frees(&blocks[0]) && !frees(&blocks[1]));
+}
+#[test]
+fn export_name_collisions() {
+    let mut taken = HashSet::new();
+    assert_eq!(wrapper_name("addNumbers", &mut taken), "AddNumbers");
+    assert_eq!(wrapper_name("add_numbers", &mut taken), "add_numbers");
+    assert_eq!(wrapper_name("AddNumbers", &mut taken), "AddNumbers1");
+    assert_eq!(wrapper_name("sub", &mut taken), "Sub");
+}
@@ -7,6 +7,17 @@ use crate::Type;
 pub struct FnSig {
     inputs: Vec<Type>,
     output: Type,
+    call_conv: CallConv,
+}
+/// The calling convention used when calling a function trough a pointer.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize, Eq, Hash, Debug, Default)]
+pub enum CallConv {
+    /// The default, managed calling convention.
+    #[default]
+    Managed,
+    /// The native C calling convention. Pointers with this convention point to native functions, or to stubs marked
+    /// with `[UnmanagedCallersOnly]`.
+    C,
 }
 impl FnSig {
     #[must_use]
@@ -24,8 +35,20 @@ impl FnSig {
         Self {
             inputs: inputs.into(),
             output: output.into(),
+            call_conv: CallConv::Managed,
         }
     }
+    /// Returns this signature, with its calling convention set to `call_conv`.
+    #[must_use]
+    pub fn with_call_conv(mut self, call_conv: CallConv) -> Self {
+        self.call_conv = call_conv;
+        self
+    }
+    /// The calling convention of this signature. Only relevant for function pointers.
+    #[must_use]
+    pub const fn call_conv(&self) -> CallConv {
+        self.call_conv
+    }
     /// Sets the input list of this method.
     pub fn set_inputs(&mut self, inputs: Vec<Type>) {
         self.inputs = inputs;
@@ -2,6 +2,7 @@ use crate::{
     access_modifier::AccessModifer,
     asm::AssemblyExternRef,
     asm_exporter::{AssemblyExportError, AssemblyExporter},
+    custom_attribute::{CustomAttribute, LayoutKind},
     ilasm_op::{non_void_type_cil, type_cil},
     method::Method,
     type_def::TypeDef,
@@ -28,11 +29,11 @@ impl Default for ILASMExporter {
             "rust_mod",
             always_init_locals(),
             *ILASM_FLAVOUR,
-            *PRINT_PTRS,
+            crate::config::config().print_ptrs,
             ilasm_path(),
-            *ESCAPE_NAMES,
+            crate::config::config().escape_names,
             get_runtime_config(),
-            *TRACE_CALLS,
+            crate::config::config().trace_calls,
         )
     }
 }
@@ -75,21 +76,10 @@ lazy_static! {
     )
     };
 }
-lazy_static! {
-    #[doc = "Tells the codegen to escape class and method names."]pub static ref ESCAPE_NAMES:bool = {
-        std::env::vars().into_iter().find_map(|(key,value)|if key == stringify!(ESCAPE_NAMES){
-            Some(value)
-        }else {
-            None
-        }).map(|value|match value.as_ref(){
-            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(ESCAPE_NAMES),value),
-        }).unwrap_or(false)
-    };
-}
 lazy_static! {
     pub static ref ILASM_FLAVOUR: IlasmFlavour = {
         if String::from_utf8_lossy(
-            &std::process::Command::new(&*ILASM_PATH)
+            &std::process::Command::new(ilasm_path())
                 .output()
                 .unwrap()
                 .stdout,
@@ -102,43 +92,12 @@ lazy_static! {
         }
     };
 }
-
-lazy_static! {
-    #[doc = "Specifies the path to the IL assembler."]
-    pub static ref ILASM_PATH:String = {
-        std::env::vars().find_map(|(key,value)|if key == "ILASM_PATH"{Some(value)}else{None}).unwrap_or("ilasm".into())
-    };
-}
-
-lazy_static! {
-    #[doc = "Changes `.locals` into `.locals init`. Causes the runtime to always initialize local variables.\nTry turining on in cause of issues. If it fixes them, then their root cause is use of uninitailized memory."]pub static ref ALWAYS_INIT_LOCALS:bool = {
-        std::env::vars().into_iter().find_map(|(key,value)|if key == stringify!(ALWAYS_INIT_LOCALS){
-            Some(value)
-        }else {
-            None
-        }).map(|value|match value.as_ref(){
-            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(ALWAYS_INIT_LOCALS),value),
-        }).unwrap_or(false)
-    };
-}
-lazy_static! {
-    #[doc = "Tells codegen the print each pointer it dereferences."]
-    pub static ref PRINT_PTRS:bool = {
-        std::env::vars().into_iter().find_map(|(key,value)|if key == stringify!(PRINT_PTRS){
-            Some(value)
-        }else {
-            None
-        }).map(|value|match value.as_ref(){
-            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(PRINT_PTRS),value),
-        }).unwrap_or(false)
-    };
-}
-
+/// Specifies the path to the IL assembler.
 pub fn ilasm_path() -> &'static str {
-    ILASM_PATH.as_str()
+    crate::config::config().ilasm_path.as_str()
 }
 pub fn always_init_locals() -> bool {
-    *ALWAYS_INIT_LOCALS
+    crate::config::config().always_init_locals
 }
 impl ILASMExporter {
     pub fn init(
@@ -181,6 +140,11 @@ impl AssemblyExporter for ILASMExporter {
         )
         .expect("Could not write global!");
     }
+    fn add_thread_local(&mut self, tpe: &Type, name: &str) {
+        self.add_global(tpe, name);
+        writeln!(self.methods, "{}", CustomAttribute::thread_static().ilasm())
+            .expect("Could not write global!");
+    }
 
     fn add_extern_ref(&mut self, asm_name: &str, asm_ref_data: &AssemblyExternRef) {
         let (v1, v2, v3, v4) = asm_ref_data.version();
@@ -247,7 +211,7 @@ impl AssemblyExporter for ILASMExporter {
             "-output:{out_path}",
             out_path = out_path.clone().to_string_lossy()
         );
-        let args: [String; 6] = [
+        let mut args: Vec<String> = vec![
             asm_type.into(),
             target,
             cil_path.clone().to_string_lossy().to_string(),
@@ -255,6 +219,10 @@ impl AssemblyExporter for ILASMExporter {
             "-OPTIMIZE".into(),
             "-FOLD".into(),
         ];
+        // The `.line` directives end up in a PDB. Modern ILASM can only write the portable format outside Windows.
+        if matches!(self.flavour, IlasmFlavour::Modern) {
+            args.push("-PDBFMT=PORTABLE".into());
+        }
 
         let out = std::process::Command::new(self.ilasm_path.as_ref())
             .args(args)
@@ -332,16 +300,25 @@ fn type_def_cli(
     } else {
         ""
     };
-    let explicit = if tpe.explicit_offsets().is_some() || tpe.explict_size().is_some() {
-        "explicit"
-    } else {
-        ""
+    let explicit = match tpe.layout_kind() {
+        Some(LayoutKind::Sequential) => "sequential",
+        Some(LayoutKind::Explicit) => "explicit",
+        Some(LayoutKind::Auto) => "auto",
+        None if tpe.explicit_offsets().is_some() || tpe.explict_size().is_some() => "explicit",
+        None => "",
     };
     let nested = if is_nested { "nested" } else { "" };
     writeln!(
         w,
         ".class {nested} {access} {explicit} ansi {sealed} '{name}' extends {extends}{{"
     )?;
+    for attr in tpe
+        .custom_attributes()
+        .iter()
+        .filter(|attr| !attr.is_pseudo())
+    {
+        writeln!(w, "{}", attr.ilasm())?;
+    }
     if let Some(size) = tpe.explict_size() {
         writeln!(w, ".size {size}")?;
     }
@@ -379,15 +356,3 @@ fn type_def_cli(
     writeln!(w, "}}")?;
     Ok(())
 }
-
-lazy_static! {
-    #[doc = "Preapends each function call with a debug message"]pub static ref TRACE_CALLS:bool = {
-        std::env::vars().into_iter().find_map(|(key,value)|if key == stringify!(TRACE_CALLS){
-            Some(value)
-        }else {
-            None
-        }).map(|value|match value.as_ref(){
-            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(TRACE_CALLS),value),
-        }).unwrap_or(false)
-    };
-}
@@ -1,7 +1,8 @@
 use std::{any::type_name, borrow::Cow, fmt::Write, fs::write};
 
 use crate::{
-    cil_iter::CILIterTrait, cil_node::CILNode, cil_root::CILRoot, DotnetTypeRef, IlasmFlavour, Type,
+    cil_iter::CILIterTrait, cil_node::CILNode, cil_root::CILRoot, CallConv, DotnetTypeRef,
+    IlasmFlavour, Type,
 };
 #[derive(Copy, Clone)]
 pub struct DepthSetting(u32);
@@ -465,7 +466,7 @@ fn export_node(
                 input_string.push(',');
                 input_string.push_str(&non_void_type_cil(arg));
             }
-            let callconv = "";
+            let callconv = call_conv_cil(fn_ptr_and_sig.0.call_conv());
             //call native uint RustModule::check_calli_nonull(native uint)\n
             write!(
                 out,
@@ -480,6 +481,15 @@ fn export_node(
         CILNode::LDIndU64 { ptr } => un_op!(out, ptr, depth, il_flavour, "ldind.u8"),
         CILNode::LDLen { arr } => un_op!(out, arr, depth, il_flavour, "ldlen"),
         CILNode::LDElelemRef { arr, idx } => bi_op!(out, arr, idx, depth, il_flavour, "ldelem.ref"),
+        CILNode::IsInst { obj, class } => {
+            export_node(out, obj, depth.incremented(), il_flavour)?;
+            depth.pad(out)?;
+            write!(
+                out,
+                "isinst {tpe}",
+                tpe = non_void_type_cil(&Type::DotnetType(class.clone()))
+            )
+        }
         CILNode::PointerToConstValue(_) => todo!(),
         CILNode::GetStackTop => {
             depth.pad(out)?;
@@ -823,6 +833,8 @@ pub fn export_root(
             depth.pad(out)?;
             write!(out, "rethrow")
         }
+        // Stored when entering the handler, see `basic_block::export`.
+        CILRoot::StoreCaught { .. } => Ok(()),
         CILRoot::CallI { sig, fn_ptr, args } => {
             for arg in args {
                 export_node(out, arg, depth.incremented(), il_flavour)?;
@@ -839,7 +851,7 @@ pub fn export_root(
                 input_string.push(',');
                 input_string.push_str(&non_void_type_cil(arg));
             }
-            let callconv = "";
+            let callconv = call_conv_cil(sig.call_conv());
             write!(out,"call native uint RustModule::check_calli_nonull(native uint)\ncalli {callconv} {output} ({input_string})",output = type_cil(sig.output()))
         }
         CILRoot::JumpingPad { source, target } => {
@@ -912,6 +924,12 @@ add"
     );
 }
 
+fn call_conv_cil(call_conv: CallConv) -> &'static str {
+    match call_conv {
+        CallConv::Managed => "",
+        CallConv::C => "unmanaged cdecl ",
+    }
+}
 pub fn non_void_type_cil(tpe: &Type) -> Cow<'static, str> {
     match tpe {
         Type::Void => "valuetype RustVoid".into(),
@@ -931,7 +949,8 @@ pub fn type_cil(tpe: &Type) -> Cow<'static, str> {
                 input_string.push_str(&non_void_type_cil(arg));
             }
             format!(
-                "method {output}*({input_string})",
+                "method {callconv}{output}*({input_string})",
+                callconv = call_conv_cil(sig.call_conv()),
                 output = type_cil(sig.output())
             )
             .into()
@@ -940,7 +959,7 @@ pub fn type_cil(tpe: &Type) -> Cow<'static, str> {
         Type::Void => "void".into(),
         Type::I8 => "int8".into(),
         Type::U8 => "uint8".into(),
-        Type::F16 => "valuetype [System.Runtime]System.Numerics.Half".into(),
+        Type::F16 => "valuetype [System.Runtime]System.Half".into(),
         Type::I16 => "int16".into(),
         Type::U16 => "uint16".into(),
         Type::F32 => "float32".into(),
@@ -950,7 +969,7 @@ pub fn type_cil(tpe: &Type) -> Cow<'static, str> {
         Type::I64 => "int64".into(),
         Type::U64 => "uint64".into(),
         Type::I128 => "valuetype [System.Runtime]System.Int128".into(),
-        Type::U128 => "valuetype [System.Runtime]System.UInt128".into(),
+        Type::U128 | Type::F128 => "valuetype [System.Runtime]System.UInt128".into(),
         Type::ISize => "native int".into(),
         Type::USize => "native uint".into(),
         Type::Ptr(inner) => format!("{inner}*", inner = type_cil(inner)).into(),
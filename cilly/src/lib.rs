@@ -20,12 +20,21 @@ pub mod cil_iter_mut;
 pub mod cil_node;
 pub mod cil_root;
 pub mod cil_tree;
+pub mod config;
+pub mod custom_attribute;
 pub mod entrypoint;
+pub mod exports;
 pub mod ilasm_exporter;
 pub mod ilasm_op;
 pub mod method;
+pub mod native_callbacks;
+pub mod opt;
+pub mod pe_exporter;
+pub mod reachability;
 pub mod static_field_desc;
+pub mod symbol_map;
 pub mod type_def;
+pub mod unwind;
 #[must_use]
 /// Returns the name of a fixed-size array
 pub fn arr_name(element_count: usize, element: &Type) -> IString {
@@ -54,13 +63,20 @@ pub fn mangle(tpe: &Type) -> std::borrow::Cow<'static, str> {
         Type::F16 => "f16".into(),
         Type::F32 => "f32".into(),
         Type::F64 => "f64".into(),
+        Type::F128 => "f128".into(),
         Type::Ptr(inner) => format!("p{inner}", inner = mangle(inner)).into(),
         Type::DotnetType(tpe) => {
-            assert!(
-                tpe.generics().is_empty(),
-                "Arrays of generic .NET types not supported yet"
-            );
-            tpe.name_path().replace('.', "_").into()
+            let name = tpe.name_path().replace(['.', '`'], "_");
+            if tpe.generics().is_empty() {
+                name.into()
+            } else {
+                format!(
+                    "{name}g{count}{generics}",
+                    count = tpe.generics().len(),
+                    generics = tpe.generics().iter().map(mangle).collect::<String>()
+                )
+                .into()
+            }
         }
         Type::ManagedArray { element, dims } => format!("a{}{}", dims, mangle(element)).into(),
         Type::DotnetChar => "c".into(),
@@ -83,23 +99,11 @@ pub fn mangle(tpe: &Type) -> std::borrow::Cow<'static, str> {
 }
 #[must_use]
 pub fn mem_checks() -> bool {
-    *crate::MEM_CHECKS
+    crate::config::config().mem_checks
 }
 #[must_use]
 pub fn debig_sfi() -> bool {
-    *crate::DEBUG_SFI
-}
-use lazy_static::lazy_static;
-lazy_static! {
-    #[doc = "Tells codegen to insert memory consistency checks after each call. If INSERT_MIR_DEBUG_COMMENTS is enabled, the consistency checks will be run also after each MIR statement."]pub static ref MEM_CHECKS:bool = {
-        std::env::vars().find_map(|(key,value)|if key == stringify!(MEM_CHECKS){
-            Some(value)
-        }else {
-            None
-        }).is_some_and(|value|match value.as_ref(){
-            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(MEM_CHECKS),value),
-        })
-    };
+    crate::config::config().debug_sfi
 }
 #[derive(Clone, Copy)]
 pub enum IlasmFlavour {
@@ -128,15 +132,3 @@ pub fn sfi_debug_print(sfi: &SFI) -> String {
        // col = sfi.1,
     )
 }
-
-lazy_static! {
-    #[doc = "Tells codegen to display source file info when executing each statement. "]pub static ref DEBUG_SFI:bool = {
-        std::env::vars().find_map(|(key,value)|if key == stringify!(DEBUG_SFI){
-            Some(value)
-        }else {
-            None
-        }).is_some_and(|value|match value.as_ref(){
-            "0"|"false"|"False"|"FALSE" => false,"1"|"true"|"True"|"TRUE" => true,_ => panic!("Boolean enviroment variable {} has invalid value {}",stringify!(DEBUG_SFI),value),
-        })
-    };
-}
@@ -16,6 +16,7 @@ use crate::{
     cil_node::CILNode,
     cil_root::CILRoot,
     cil_tree::CILTree,
+    custom_attribute::{CustomAttribute, AGGRESSIVE_INLINING, NO_INLINING},
     ilasm_op::{non_void_type_cil, type_cil, DepthSetting},
     static_field_desc::StaticFieldDescriptor,
     DotnetTypeRef, FnSig, IString, IlasmFlavour, Type,
@@ -47,6 +48,10 @@ impl Hash for Method {
 pub enum Attribute {
     /// Set if the function is the assemblys entrypoint.
     EntryPoint,
+    /// Set if the function is exported from Rust, and should get a .NET-facing wrapper.
+    Export(crate::exports::ExportInfo),
+    /// A .NET custom attribute, such as `[MethodImpl]` or `[UnmanagedCallersOnly]`.
+    Custom(crate::custom_attribute::CustomAttribute),
 }
 
 impl Method {
@@ -56,19 +61,9 @@ impl Method {
         max.unwrap_or(6)
     }
 
+    /// Optimizes this method, using the passes enabled in the config.
     pub fn opt(&mut self) {
-        for tree in self
-            .blocks
-            .iter_mut()
-            .flat_map(|block| block.all_trees_mut())
-        {
-            let mut opt_counter: usize = 1;
-            while opt_counter > 0 {
-                // Reset `opt_counter`
-                opt_counter = 0;
-                tree.opt(&mut opt_counter);
-            }
-        }
+        crate::opt::run_passes(self);
     }
     /// Iterates over each `CILNode` and `CILRoot`.
     pub fn iter_cil(&self) -> impl Iterator<Item = CILIterElem> {
@@ -180,6 +175,28 @@ impl Method {
             .iter()
             .any(|attr| *attr == Attribute::EntryPoint)
     }
+    /// Returns the information about how this method is exposed to .NET, if it is exported from Rust.
+    #[must_use]
+    pub fn export_info(&self) -> Option<&crate::exports::ExportInfo> {
+        self.attributes.iter().find_map(|attr| match attr {
+            Attribute::Export(info) => Some(info),
+            Attribute::EntryPoint | Attribute::Custom(_) => None,
+        })
+    }
+    /// Iterates over the .NET custom attributes of this method.
+    pub fn custom_attributes(&self) -> impl Iterator<Item = &CustomAttribute> {
+        self.attributes.iter().filter_map(|attr| match attr {
+            Attribute::Custom(attr) => Some(attr),
+            Attribute::EntryPoint | Attribute::Export(_) => None,
+        })
+    }
+    /// The `MethodImplOptions` set by all the `[MethodImpl]` attributes of this method.
+    #[must_use]
+    pub fn method_impl_options(&self) -> i32 {
+        self.custom_attributes()
+            .filter_map(CustomAttribute::method_impl_options)
+            .fold(0, |options, attr| options | attr)
+    }
     /// A list of function inputs, in a CIL compatible format. Does not include the implict `this` parameter for instance and virtual methods.
     pub fn explicit_inputs(&self) -> &[Type] {
         if self.is_static() {
@@ -424,10 +441,24 @@ impl Method {
                 }
             }
         }
-        writeln!(w, "){{")?;
+        let options = self.method_impl_options();
+        let no_inlining = if options & NO_INLINING != 0 {
+            " noinlining"
+        } else {
+            ""
+        };
+        let aggressive_inlining = if options & AGGRESSIVE_INLINING != 0 {
+            " aggressiveinlining"
+        } else {
+            ""
+        };
+        writeln!(w, ") cil managed{no_inlining}{aggressive_inlining}{{")?;
         if self.is_entrypoint() {
             writeln!(w, ".entrypoint")?;
         }
+        for attr in self.custom_attributes().filter(|attr| !attr.is_pseudo()) {
+            writeln!(w, "{}", attr.ilasm())?;
+        }
         if init_locals {
             writeln!(w, "\t.locals init(")?;
         } else {
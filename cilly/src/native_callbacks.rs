@@ -0,0 +1,155 @@
+//! Lets native code call back into Rust functions. Pointers to `extern "C"` functions use the C calling convention:
+//! instead of the address of the managed method itself, they hold the address of a stub marked with
+//! `[UnmanagedCallersOnly]`, which forwards the call to the managed method. Such pointers can be passed to native
+//! libraries(e.g. as a `qsort` comparator), and get called using an unmanaged `calli`.
+use crate::{
+    access_modifier::AccessModifer,
+    asm::Assembly,
+    basic_block::BasicBlock,
+    call_site::CallSite,
+    cil_node::CILNode,
+    cil_root::CILRoot,
+    custom_attribute::CustomAttribute,
+    method::{Attribute, Method, MethodType},
+    CallConv, FnSig, IString, Type,
+};
+
+/// Appended to the name of a function to get the name of its unmanaged stub.
+pub const UNMANAGED_STUB_SUFFIX: &str = "_unmanaged_stub";
+/// Converts `sig` to a signature which can be used with the C calling convention. `bool` is not blittable, so it is
+/// passed as an `u8` instead.
+#[must_use]
+pub fn unmanaged_sig(sig: &FnSig) -> FnSig {
+    let blittable = |tpe: &Type| match tpe {
+        Type::Bool => Type::U8,
+        _ => tpe.clone(),
+    };
+    FnSig::new(
+        sig.inputs().iter().map(blittable).collect::<Vec<_>>(),
+        blittable(sig.output()),
+    )
+    .with_call_conv(CallConv::C)
+}
+/// The call site of the `[UnmanagedCallersOnly]` stub of the static function `name`, with signature `sig`.
+/// Loading the address of this call site results in a pointer callable from native code.
+#[must_use]
+pub fn unmanaged_stub_site(name: &str, sig: &FnSig) -> CallSite {
+    CallSite::new(
+        None,
+        format!("{name}{UNMANAGED_STUB_SUFFIX}").into(),
+        unmanaged_sig(sig).with_call_conv(CallConv::Managed),
+        true,
+    )
+}
+/// Creates the stub described by `stub_site`, forwarding calls to the function it was created for, which has the
+/// signature `target_sig`.
+#[must_use]
+pub fn unmanaged_stub(stub_site: &CallSite, target_sig: &FnSig) -> Option<Method> {
+    let target: IString = stub_site.name().strip_suffix(UNMANAGED_STUB_SUFFIX)?.into();
+    let site = CallSite::new(None, target, target_sig.clone(), true);
+    let args: Box<[CILNode]> = (0..target_sig.inputs().len())
+        .map(|arg| CILNode::LDArg(arg as u32))
+        .collect();
+    let trees = if *target_sig.output() == Type::Void {
+        vec![CILRoot::Call { site, args }.into(), CILRoot::VoidRet.into()]
+    } else {
+        vec![CILRoot::Ret {
+            tree: CILNode::Call {
+                site: site.into(),
+                args,
+            },
+        }
+        .into()]
+    };
+    let mut stub = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        stub_site.signature().clone(),
+        stub_site.name(),
+        vec![],
+        vec![BasicBlock::new(trees, 0, None)],
+        vec![],
+    );
+    stub.add_attribute(Attribute::Custom(CustomAttribute::unmanaged_callers_only()));
+    Some(stub)
+}
+/// Adds the unmanaged stubs of all functions whose address is taken as a pointer with the C calling convention.
+/// Must run before calls to undefined functions get resolved, since the stubs of native functions call them.
+pub fn add_unmanaged_stubs(asm: &mut Assembly) -> usize {
+    let stubs: Vec<Method> = asm
+        .call_sites()
+        .into_iter()
+        .filter(|site| site.is_static() && site.class().is_none())
+        .filter(|site| site.name().ends_with(UNMANAGED_STUB_SUFFIX) && !asm.contains_fn(site))
+        .filter_map(|site| {
+            let target = &site.name()[..site.name().len() - UNMANAGED_STUB_SUFFIX.len()];
+            // The stub may use a different signature than the function it forwards to(`bool` vs `u8`). Functions
+            // which are not defined are native, and get imported with the signature of the stub.
+            let target_sig = asm
+                .methods()
+                .filter(|method| method.is_static() && method.name() == target)
+                .map(Method::sig)
+                .find(|sig| unmanaged_stub_site(target, sig) == *site)
+                .unwrap_or(site.signature());
+            unmanaged_stub(site, target_sig)
+        })
+        .collect();
+    let count = stubs.len();
+    stubs.into_iter().for_each(|stub| asm.add_method(stub));
+    count
+}
+#[test]
+fn stub_forwards_call() {
+    let site = unmanaged_stub_site("compare", &FnSig::new([Type::I32, Type::Bool], Type::Bool));
+    assert_eq!(site.name(), "compare_unmanaged_stub");
+    assert_eq!(site.signature().inputs(), [Type::I32, Type::U8]);
+    assert_eq!(site.signature().call_conv(), CallConv::Managed);
+    let mut asm = Assembly::empty();
+    let caller = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(
+            [],
+            Type::DelegatePtr(unmanaged_sig(site.signature()).into()),
+        ),
+        "get_compare",
+        vec![],
+        vec![BasicBlock::new(
+            vec![CILRoot::Ret {
+                tree: CILNode::LDFtn(site.clone().into()),
+            }
+            .into()],
+            0,
+            None,
+        )],
+        vec![],
+    );
+    asm.add_method(caller);
+    let target_sig = FnSig::new([Type::I32, Type::Bool], Type::Bool);
+    let target = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        target_sig.clone(),
+        "compare",
+        vec![],
+        vec![BasicBlock::new(
+            vec![CILRoot::Ret {
+                tree: CILNode::LdFalse,
+            }
+            .into()],
+            0,
+            None,
+        )],
+        vec![],
+    );
+    asm.add_method(target);
+    assert_eq!(add_unmanaged_stubs(&mut asm), 1);
+    assert!(asm.contains_fn(&site));
+    let stub = asm
+        .methods()
+        .find(|method| method.name() == site.name())
+        .unwrap();
+    assert_eq!(stub.custom_attributes().count(), 1);
+    assert_eq!(stub.calls()[0].signature(), &target_sig);
+    assert_eq!(add_unmanaged_stubs(&mut asm), 0);
+}
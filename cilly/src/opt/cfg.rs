@@ -0,0 +1,259 @@
+//! The control flow graph of a method, and its dominator tree.
+use std::collections::HashMap;
+
+use crate::{basic_block::BasicBlock, cil_root::CILRoot};
+
+/// The control flow graph of a method without exception handlers. Blocks are referred to by their position in the block
+/// list of the method, and the first block is the entry.
+pub struct Cfg {
+    succs: Vec<Vec<usize>>,
+    preds: Vec<Vec<usize>>,
+    /// Number of trees in each block which may execute. Trees after an unconditional jump are dead.
+    live_len: Vec<usize>,
+    /// Reachable blocks, in reverse postorder.
+    rpo: Vec<usize>,
+    /// The immediate dominator of each reachable block. `None` for the entry and unreachable blocks.
+    idom: Vec<Option<usize>>,
+}
+/// Returns true if the control never gets past `root`.
+pub(super) fn is_unconditional(root: &CILRoot) -> bool {
+    matches!(
+        root,
+        CILRoot::GoTo { .. }
+            | CILRoot::Ret { .. }
+            | CILRoot::VoidRet
+            | CILRoot::Throw(_)
+            | CILRoot::ReThrow
+    )
+}
+impl Cfg {
+    /// Builds the CFG of `blocks`. Returns `None` if the blocks have exception handlers, or jump to blocks which don't exist.
+    #[must_use]
+    pub fn new(blocks: &[BasicBlock]) -> Option<Self> {
+        let positions: HashMap<u32, usize> = blocks
+            .iter()
+            .enumerate()
+            .map(|(idx, block)| (block.id(), idx))
+            .collect();
+        let mut succs = vec![Vec::new(); blocks.len()];
+        let mut live_len = vec![0; blocks.len()];
+        for (idx, block) in blocks.iter().enumerate() {
+            if block.handler().is_some() {
+                return None;
+            }
+            let mut falls_through = true;
+            for tree in block.trees() {
+                live_len[idx] += 1;
+                let mut targets = vec![];
+                tree.targets(&mut targets);
+                for (target, sub_target) in targets {
+                    if sub_target != 0 {
+                        return None;
+                    }
+                    let target = *positions.get(&target)?;
+                    if !succs[idx].contains(&target) {
+                        succs[idx].push(target);
+                    }
+                }
+                if matches!(tree.root(), CILRoot::JumpingPad { .. }) {
+                    return None;
+                }
+                if is_unconditional(tree.root()) {
+                    falls_through = false;
+                    break;
+                }
+            }
+            // The exporter emits blocks in order, so a block without a terminator continues into the next one.
+            if falls_through && idx + 1 < blocks.len() && !succs[idx].contains(&(idx + 1)) {
+                succs[idx].push(idx + 1);
+            }
+        }
+        let mut preds = vec![Vec::new(); blocks.len()];
+        for (idx, block_succs) in succs.iter().enumerate() {
+            for succ in block_succs {
+                preds[*succ].push(idx);
+            }
+        }
+        let mut cfg = Self {
+            succs,
+            preds,
+            live_len,
+            rpo: vec![],
+            idom: vec![None; blocks.len()],
+        };
+        if !blocks.is_empty() {
+            cfg.compute_rpo();
+            cfg.compute_dominators();
+        }
+        Some(cfg)
+    }
+    fn compute_rpo(&mut self) {
+        let mut visited = vec![false; self.succs.len()];
+        let mut postorder = vec![];
+        // (block, index of the next successor to visit)
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        while let Some((block, next)) = stack.last_mut() {
+            if let Some(&succ) = self.succs[*block].get(*next) {
+                *next += 1;
+                if !visited[succ] {
+                    visited[succ] = true;
+                    stack.push((succ, 0));
+                }
+            } else {
+                postorder.push(*block);
+                stack.pop();
+            }
+        }
+        postorder.reverse();
+        self.rpo = postorder;
+    }
+    /// Computes the dominator tree using the algorithm from "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy.
+    fn compute_dominators(&mut self) {
+        let mut order = vec![usize::MAX; self.succs.len()];
+        for (idx, block) in self.rpo.iter().enumerate() {
+            order[*block] = idx;
+        }
+        // The entry dominates itself while the tree is being built.
+        let mut idom: Vec<Option<usize>> = vec![None; self.succs.len()];
+        idom[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in self.rpo.iter().skip(1) {
+                let mut new_idom: Option<usize> = None;
+                for &pred in &self.preds[block] {
+                    if idom[pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(mut a) => {
+                            let mut b = pred;
+                            while a != b {
+                                while order[a] > order[b] {
+                                    a = idom[a].unwrap();
+                                }
+                                while order[b] > order[a] {
+                                    b = idom[b].unwrap();
+                                }
+                            }
+                            a
+                        }
+                    });
+                }
+                if new_idom.is_some() && idom[block] != new_idom {
+                    idom[block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+        self.idom = idom;
+    }
+    /// Returns the blocks `block` may jump to.
+    #[must_use]
+    pub fn succs(&self, block: usize) -> &[usize] {
+        &self.succs[block]
+    }
+    /// Returns the blocks which may jump to `block`.
+    #[must_use]
+    pub fn preds(&self, block: usize) -> &[usize] {
+        &self.preds[block]
+    }
+    /// Returns the number of trees at the start of `block` which may execute.
+    #[must_use]
+    pub fn live_len(&self, block: usize) -> usize {
+        self.live_len[block]
+    }
+    /// Returns all the reachable blocks, in reverse postorder.
+    #[must_use]
+    pub fn rpo(&self) -> &[usize] {
+        &self.rpo
+    }
+    /// Checks if `block` can be reached from the entry.
+    #[must_use]
+    pub fn is_reachable(&self, block: usize) -> bool {
+        block == 0 || self.idom[block].is_some()
+    }
+    /// Returns the immediate dominator of `block`, or `None` for the entry and unreachable blocks.
+    #[must_use]
+    pub fn idom(&self, block: usize) -> Option<usize> {
+        self.idom[block]
+    }
+    /// Checks if every path from the entry to `b` goes trough `a`.
+    #[must_use]
+    pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom[b] {
+                Some(idom) => b = idom,
+                None => return false,
+            }
+        }
+    }
+    /// Computes the dominance frontier of each block. The entry block is treated as if it was dominated by a virtual
+    /// root, so that it ends up in the frontier of blocks which jump back to it.
+    #[must_use]
+    pub fn dominance_frontiers(&self) -> Vec<Vec<usize>> {
+        let mut frontiers = vec![Vec::new(); self.succs.len()];
+        for &block in &self.rpo {
+            let is_join = self.preds[block].len() >= 2 || (block == 0 && !self.preds[0].is_empty());
+            if !is_join {
+                continue;
+            }
+            for &pred in &self.preds[block] {
+                if !self.is_reachable(pred) {
+                    continue;
+                }
+                let mut runner = Some(pred);
+                while let Some(curr) = runner {
+                    if Some(curr) == self.idom[block] {
+                        break;
+                    }
+                    if !frontiers[curr].contains(&block) {
+                        frontiers[curr].push(block);
+                    }
+                    runner = self.idom[curr];
+                }
+            }
+        }
+        frontiers
+    }
+}
+#[test]
+fn diamond_dominators() {
+    use crate::cil_node::CILNode;
+    let blocks = vec![
+        BasicBlock::new(
+            vec![
+                CILRoot::BTrue {
+                    target: 2,
+                    sub_target: 0,
+                    cond: CILNode::LDArg(0),
+                }
+                .into(),
+                CILRoot::GoTo {
+                    target: 1,
+                    sub_target: 0,
+                }
+                .into(),
+            ],
+            0,
+            None,
+        ),
+        // Falls trough into block 2.
+        BasicBlock::new(vec![], 1, None),
+        BasicBlock::new(vec![CILRoot::VoidRet.into()], 2, None),
+        BasicBlock::new(vec![CILRoot::VoidRet.into()], 3, None),
+    ];
+    let cfg = Cfg::new(&blocks).unwrap();
+    assert_eq!(cfg.succs(0), [2, 1]);
+    assert_eq!(cfg.succs(1), [2]);
+    assert_eq!(cfg.idom(1), Some(0));
+    assert_eq!(cfg.idom(2), Some(0));
+    assert!(!cfg.is_reachable(3));
+    assert_eq!(cfg.dominance_frontiers()[1], [2]);
+}
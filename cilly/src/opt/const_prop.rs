@@ -0,0 +1,296 @@
+//! Constant propagation: replaces reads of locals which always hold the same constant with that constant, and folds
+//! arithmetic on constants.
+use std::{
+    collections::HashMap,
+    ops::{BitAnd, BitOr, BitXor},
+};
+
+use crate::{cil_iter_mut::CILIterElemMut, cil_node::CILNode, method::Method, Type};
+
+use super::ssa::{replace_loads, Ssa, Value};
+
+/// The state of a SSA value during constant propagation.
+#[derive(Clone, PartialEq, Debug)]
+enum Lattice {
+    /// No value has reached this phi yet.
+    Undef,
+    Const(CILNode),
+    /// May hold different values.
+    Overdefined,
+}
+impl Lattice {
+    fn meet(self, other: Self) -> Self {
+        match (self, other) {
+            (Self::Undef, other) | (other, Self::Undef) => other,
+            (Self::Const(a), Self::Const(b)) if a == b => Self::Const(a),
+            _ => Self::Overdefined,
+        }
+    }
+}
+/// Checks if storing `constant` in a local of type `tpe`, and reading it back, yields `constant` again.
+fn survives_store(tpe: &Type, constant: &CILNode) -> bool {
+    let int = match constant {
+        CILNode::LdcI32(value) => i64::from(*value),
+        CILNode::LdcU32(value) => i64::from(*value as i32),
+        CILNode::LdTrue => 1,
+        CILNode::LdFalse => 0,
+        CILNode::LdcI64(_) | CILNode::LdcU64(_) => {
+            return matches!(tpe, Type::I64 | Type::U64);
+        }
+        CILNode::LdcF32(_) => return *tpe == Type::F32,
+        CILNode::LdcF64(_) => return *tpe == Type::F64,
+        _ => return false,
+    };
+    match tpe {
+        Type::I32 | Type::U32 => !matches!(constant, CILNode::LdTrue | CILNode::LdFalse),
+        Type::Bool => matches!(int, 0 | 1),
+        Type::I8 => i8::try_from(int).is_ok(),
+        Type::U8 => u8::try_from(int).is_ok(),
+        Type::I16 => i16::try_from(int).is_ok(),
+        Type::U16 => u16::try_from(int).is_ok(),
+        _ => false,
+    }
+}
+/// Folds a binary operation on two constants of the same kind.
+fn fold(node: &CILNode) -> Option<CILNode> {
+    macro_rules! fold_ints {
+        ($a:expr, $b:expr, $op:ident) => {
+            match ($a, $b) {
+                (CILNode::LdcI32(a), CILNode::LdcI32(b)) => Some(CILNode::LdcI32(a.$op(*b))),
+                (CILNode::LdcU32(a), CILNode::LdcU32(b)) => Some(CILNode::LdcU32(a.$op(*b))),
+                (CILNode::LdcI64(a), CILNode::LdcI64(b)) => Some(CILNode::LdcI64(a.$op(*b))),
+                (CILNode::LdcU64(a), CILNode::LdcU64(b)) => Some(CILNode::LdcU64(a.$op(*b))),
+                _ => None,
+            }
+        };
+    }
+    match node {
+        CILNode::Add(a, b) => fold_ints!(a.as_ref(), b.as_ref(), wrapping_add),
+        CILNode::Sub(a, b) => fold_ints!(a.as_ref(), b.as_ref(), wrapping_sub),
+        CILNode::Mul(a, b) => fold_ints!(a.as_ref(), b.as_ref(), wrapping_mul),
+        CILNode::And(a, b) => fold_ints!(a.as_ref(), b.as_ref(), bitand),
+        CILNode::Or(a, b) => fold_ints!(a.as_ref(), b.as_ref(), bitor),
+        CILNode::XOr(a, b) => fold_ints!(a.as_ref(), b.as_ref(), bitxor),
+        _ => None,
+    }
+}
+/// Folds all the constant expressions in `method`. Returns true if anything changed.
+fn fold_constants(method: &mut Method) -> bool {
+    let can_fold = method.iter_cil().any(
+        |elem| matches!(elem, crate::cil_iter::CILIterElem::Node(node) if fold(node).is_some()),
+    );
+    if !can_fold {
+        return false;
+    }
+    let mut blocks = method.blocks_mut();
+    for tree in blocks
+        .iter_mut()
+        .flat_map(crate::basic_block::BasicBlock::trees_mut)
+    {
+        // Folding a node may make its parent foldable, and parents are visited first.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for elem in &mut *tree.root_mut() {
+                if let CILIterElemMut::Node(node) = elem {
+                    if let Some(folded) = fold(node) {
+                        *node = folded;
+                        changed = true;
+                    }
+                }
+            }
+        }
+    }
+    true
+}
+/// Replaces reads of locals holding a known constant with that constant. Returns true if anything changed.
+pub fn const_prop(method: &mut Method) -> bool {
+    let mut changed = fold_constants(method);
+    let Some(ssa) = Ssa::new(method) else {
+        return changed;
+    };
+    let value_of = |value: Value, phis: &HashMap<(usize, u32), Lattice>| match value {
+        Value::Entry(_) => Lattice::Overdefined,
+        Value::Phi { block, local } => phis[&(block, local)].clone(),
+        Value::Def { .. } => match ssa.def_tree(method, value) {
+            Some((local, tree)) if survives_store(&method.locals()[local as usize].1, tree) => {
+                Lattice::Const(tree.clone())
+            }
+            _ => Lattice::Overdefined,
+        },
+    };
+    let mut phis: HashMap<(usize, u32), Lattice> =
+        ssa.phis().map(|phi| (phi, Lattice::Undef)).collect();
+    let mut phi_changed = true;
+    while phi_changed {
+        phi_changed = false;
+        for (block, local) in ssa.phis() {
+            let new = ssa
+                .phi_operands(block, local)
+                .into_iter()
+                .fold(Lattice::Undef, |acc, operand| {
+                    acc.meet(value_of(operand, &phis))
+                });
+            if phis[&(block, local)] != new {
+                phis.insert((block, local), new);
+                phi_changed = true;
+            }
+        }
+    }
+    let mut constants = HashMap::new();
+    for (block, idx, tree) in ssa.live_trees(method) {
+        for elem in tree.root() {
+            if let crate::cil_iter::CILIterElem::Node(CILNode::LDLoc(local)) = elem {
+                if !ssa.is_tracked(*local) {
+                    continue;
+                }
+                if let Lattice::Const(constant) = value_of(ssa.reaching(block, idx, *local), &phis)
+                {
+                    constants.insert((block, idx, *local), constant);
+                }
+            }
+        }
+    }
+    changed |= replace_loads(method, &ssa, |block, idx, local| {
+        constants.remove(&(block, idx, local))
+    });
+    changed
+}
+#[test]
+fn propagate_trough_phi() {
+    use crate::{
+        access_modifier::AccessModifer, basic_block::BasicBlock, cil_root::CILRoot,
+        method::MethodType, FnSig,
+    };
+    let store = |local, tree| CILRoot::STLoc { local, tree }.into();
+    // if arg0 { loc0 = 2 } else { loc0 = 2 }; loc1 = 300; loc2 = 300; return loc0 + 1 + loc1 + loc2
+    let blocks = vec![
+        BasicBlock::new(
+            vec![CILRoot::BTrue {
+                target: 2,
+                sub_target: 0,
+                cond: CILNode::LDArg(0),
+            }
+            .into()],
+            0,
+            None,
+        ),
+        BasicBlock::new(
+            vec![
+                store(0, CILNode::LdcI32(2)),
+                CILRoot::GoTo {
+                    target: 3,
+                    sub_target: 0,
+                }
+                .into(),
+            ],
+            1,
+            None,
+        ),
+        BasicBlock::new(vec![store(0, CILNode::LdcI32(2))], 2, None),
+        BasicBlock::new(
+            vec![
+                // A `u8` can't hold 300, so this can't be propagated.
+                store(1, CILNode::LdcI32(300)),
+                store(2, CILNode::LdcI32(300)),
+                CILRoot::Ret {
+                    tree: CILNode::Add(
+                        Box::new(CILNode::Add(
+                            Box::new(CILNode::Add(
+                                Box::new(CILNode::LDLoc(0)),
+                                Box::new(CILNode::LdcI32(1)),
+                            )),
+                            Box::new(CILNode::LDLoc(1)),
+                        )),
+                        Box::new(CILNode::LDLoc(2)),
+                    ),
+                }
+                .into(),
+            ],
+            3,
+            None,
+        ),
+    ];
+    let mut method = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[Type::Bool], Type::I32),
+        "propagate_trough_phi",
+        vec![(None, Type::I32), (None, Type::U8), (None, Type::I32)],
+        blocks,
+        vec![None],
+    );
+    while const_prop(&mut method) {}
+    assert_eq!(
+        method.blocks()[3].trees()[2].root(),
+        &CILRoot::Ret {
+            tree: CILNode::Add(
+                Box::new(CILNode::Add(
+                    Box::new(CILNode::LdcI32(3)),
+                    Box::new(CILNode::LDLoc(1)),
+                )),
+                Box::new(CILNode::LdcI32(300)),
+            )
+        }
+    );
+}
+#[test]
+fn narrowing_stores() {
+    use crate::{
+        access_modifier::AccessModifer, basic_block::BasicBlock, cil_root::CILRoot,
+        method::MethodType, FnSig,
+    };
+    let add = |a, b| CILNode::Add(Box::new(CILNode::LdcI32(a)), Box::new(CILNode::LdcI32(b)));
+    let store = |local, tree| CILRoot::STLoc { local, tree }.into();
+    let load = |local| Box::new(CILNode::LDLoc(local));
+    // The sums are folded to `int32`s, which only survive being stored in locals which can hold them.
+    let blocks = vec![BasicBlock::new(
+        vec![
+            store(0, add(200, 100)),
+            store(1, add(200, 55)),
+            store(2, add(100, 28)),
+            store(3, add(100, 27)),
+            CILRoot::Ret {
+                tree: CILNode::Add(
+                    Box::new(CILNode::Add(load(0), load(1))),
+                    Box::new(CILNode::Add(load(2), load(3))),
+                ),
+            }
+            .into(),
+        ],
+        0,
+        None,
+    )];
+    let mut method = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[], Type::I32),
+        "narrowing_stores",
+        vec![
+            (None, Type::U8),
+            (None, Type::U8),
+            (None, Type::I8),
+            (None, Type::I8),
+        ],
+        blocks,
+        vec![],
+    );
+    while const_prop(&mut method) {}
+    let trees = method.blocks()[0].trees();
+    assert_eq!(
+        trees[0].root(),
+        &CILRoot::STLoc {
+            local: 0,
+            tree: CILNode::LdcI32(300)
+        }
+    );
+    assert_eq!(
+        trees[4].root(),
+        &CILRoot::Ret {
+            tree: CILNode::Add(
+                Box::new(CILNode::Add(load(0), Box::new(CILNode::LdcI32(255)))),
+                Box::new(CILNode::Add(load(2), Box::new(CILNode::LdcI32(127)))),
+            )
+        }
+    );
+}
@@ -0,0 +1,99 @@
+//! Copy propagation: replaces reads of locals holding a copy of another local or argument with reads of the original.
+use std::collections::HashMap;
+
+use crate::{cil_iter::CILIterElem, cil_node::CILNode, method::Method};
+
+use super::ssa::{replace_loads, Ssa, Value};
+
+/// Forwards copies of locals and arguments to their uses. Returns true if anything changed.
+pub fn copy_prop(method: &mut Method) -> bool {
+    let Some(ssa) = Ssa::new(method) else {
+        return false;
+    };
+    let locals = method.locals();
+    let inputs = method.sig().inputs();
+    let mut copies = HashMap::new();
+    for (block, idx, tree) in ssa.live_trees(method) {
+        for elem in tree.root() {
+            let CILIterElem::Node(CILNode::LDLoc(local)) = elem else {
+                continue;
+            };
+            if !ssa.is_tracked(*local) {
+                continue;
+            }
+            let value = ssa.reaching(block, idx, *local);
+            let Value::Def {
+                block: def_block,
+                tree: def_idx,
+            } = value
+            else {
+                continue;
+            };
+            let Some((_, source)) = ssa.def_tree(method, value) else {
+                continue;
+            };
+            let tpe = &locals[*local as usize].1;
+            let forwarded = match source {
+                // The copied local must still hold the same value here.
+                CILNode::LDLoc(original)
+                    if *original != *local
+                        && ssa.is_tracked(*original)
+                        && locals[*original as usize].1 == *tpe
+                        && ssa.reaching(def_block, def_idx, *original)
+                            == ssa.reaching(block, idx, *original) =>
+                {
+                    source.clone()
+                }
+                CILNode::LDArg(arg)
+                    if ssa.is_arg_immutable(*arg) && inputs[*arg as usize] == *tpe =>
+                {
+                    source.clone()
+                }
+                _ => continue,
+            };
+            copies.insert((block, idx, *local), forwarded);
+        }
+    }
+    replace_loads(method, &ssa, |block, idx, local| {
+        copies.remove(&(block, idx, local))
+    })
+}
+#[test]
+fn forward_copies() {
+    use crate::{
+        access_modifier::AccessModifer, basic_block::BasicBlock, cil_root::CILRoot,
+        method::MethodType, FnSig, Type,
+    };
+    let store = |local, tree| CILRoot::STLoc { local, tree }.into();
+    // loc0 = arg0; loc1 = loc0; loc0 = 5; return loc1 + loc0
+    let blocks = vec![BasicBlock::new(
+        vec![
+            store(0, CILNode::LDArg(0)),
+            store(1, CILNode::LDLoc(0)),
+            store(0, CILNode::LdcI32(5)),
+            CILRoot::Ret {
+                tree: CILNode::Add(Box::new(CILNode::LDLoc(1)), Box::new(CILNode::LDLoc(0))),
+            }
+            .into(),
+        ],
+        0,
+        None,
+    )];
+    let mut method = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[Type::I32], Type::I32),
+        "forward_copies",
+        vec![(None, Type::I32), (None, Type::I32)],
+        blocks,
+        vec![None],
+    );
+    while copy_prop(&mut method) {}
+    // `loc1` is a copy of `arg0`(trough `loc0`), but `loc0` was overwritten by then.
+    assert_eq!(
+        method.blocks()[0].trees()[3].root(),
+        &CILRoot::Ret {
+            tree: CILNode::Add(Box::new(CILNode::LDArg(0)), Box::new(CILNode::LDLoc(0))),
+        }
+    );
+}
@@ -0,0 +1,185 @@
+//! Common subexpression elimination: reuses the value of a local which already holds the result of an identical,
+//! side-effect-free computation.
+use std::collections::HashMap;
+
+use crate::{cil_iter::CILIterElem, cil_node::CILNode, cil_root::CILRoot, method::Method};
+
+use super::ssa::{Ssa, Value};
+
+/// Checks if `node` always evaluates to the same value, as long as the locals it reads don't change.
+fn is_pure(node: &CILNode, ssa: &Ssa) -> bool {
+    node.into_iter().all(|elem| match elem {
+        CILIterElem::Node(node) => match node {
+            CILNode::LDLoc(local) => ssa.is_tracked(*local),
+            CILNode::LDArg(arg) => ssa.is_arg_immutable(*arg),
+            CILNode::LDLocA(_)
+            | CILNode::LDArgA(_)
+            | CILNode::LdcI32(_)
+            | CILNode::LdcU32(_)
+            | CILNode::LdcI64(_)
+            | CILNode::LdcU64(_)
+            | CILNode::LdcF32(_)
+            | CILNode::LdcF64(_)
+            | CILNode::LdTrue
+            | CILNode::LdFalse
+            | CILNode::SizeOf(_)
+            | CILNode::LDFtn(_)
+            | CILNode::LDTypeToken(_)
+            | CILNode::LDFieldAdress { .. }
+            | CILNode::MRefToRawPtr(_)
+            | CILNode::TransmutePtr { .. }
+            | CILNode::Add(..)
+            | CILNode::And(..)
+            | CILNode::Sub(..)
+            | CILNode::Mul(..)
+            | CILNode::Div(..)
+            | CILNode::DivUn(..)
+            | CILNode::Rem(..)
+            | CILNode::RemUn(..)
+            | CILNode::Or(..)
+            | CILNode::XOr(..)
+            | CILNode::Shr(..)
+            | CILNode::Shl(..)
+            | CILNode::ShrUn(..)
+            | CILNode::Eq(..)
+            | CILNode::Lt(..)
+            | CILNode::LtUn(..)
+            | CILNode::Gt(..)
+            | CILNode::GtUn(..)
+            | CILNode::Neg(_)
+            | CILNode::Not(_)
+            | CILNode::ConvF32(_)
+            | CILNode::ConvF64(_)
+            | CILNode::ConvF64Un(_)
+            | CILNode::ConvU8(_)
+            | CILNode::ConvU16(_)
+            | CILNode::ConvU32(_)
+            | CILNode::ConvU64(_)
+            | CILNode::ZeroExtendToUSize(_)
+            | CILNode::ZeroExtendToISize(_)
+            | CILNode::ConvI8(_)
+            | CILNode::ConvI16(_)
+            | CILNode::ConvI32(_)
+            | CILNode::ConvI64(_)
+            | CILNode::ConvISize(_) => true,
+            _ => false,
+        },
+        CILIterElem::Root(_) => false,
+    })
+}
+/// A store of a pure computation to a local.
+struct Candidate<'a> {
+    block: usize,
+    tree: usize,
+    local: u32,
+    value: &'a CILNode,
+}
+/// Replaces computations, whose result is already stored in a local, with a read of that local. Returns true if
+/// anything changed.
+pub fn cse(method: &mut Method) -> bool {
+    let Some(ssa) = Ssa::new(method) else {
+        return false;
+    };
+    let candidates: Vec<Candidate> = ssa
+        .live_trees(method)
+        .filter_map(|(block, tree, root)| match root.root() {
+            // Copies and constants are handled by the propagation passes.
+            CILRoot::STLoc { local, tree: value }
+                if ssa.is_tracked(*local)
+                    && value.into_iter().nth(1).is_some()
+                    && is_pure(value, &ssa) =>
+            {
+                Some(Candidate {
+                    block,
+                    tree,
+                    local: *local,
+                    value,
+                })
+            }
+            _ => None,
+        })
+        .collect();
+    let locals = method.locals();
+    // Candidates are grouped by their textual representation, to avoid comparing every pair of them.
+    let mut seen: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut replacements = vec![];
+    for (idx, candidate) in candidates.iter().enumerate() {
+        let same_value = seen.entry(format!("{:?}", candidate.value)).or_default();
+        let earlier = same_value
+            .iter()
+            .map(|earlier| &candidates[*earlier])
+            .find(|earlier| {
+                let dominates = if earlier.block == candidate.block {
+                    earlier.tree < candidate.tree
+                } else {
+                    ssa.cfg().dominates(earlier.block, candidate.block)
+                };
+                dominates
+                && earlier.value == candidate.value
+                && locals[earlier.local as usize].1 == locals[candidate.local as usize].1
+                // The earlier result must still be in its local.
+                && ssa.reaching(candidate.block, candidate.tree, earlier.local)
+                    == Value::Def {
+                        block: earlier.block,
+                        tree: earlier.tree,
+                    }
+                // The computation must see the same values.
+                && candidate.value.into_iter().all(|elem| match elem {
+                    CILIterElem::Node(CILNode::LDLoc(local)) => {
+                        ssa.reaching(earlier.block, earlier.tree, *local)
+                            == ssa.reaching(candidate.block, candidate.tree, *local)
+                    }
+                    _ => true,
+                })
+            });
+        if let Some(earlier) = earlier {
+            replacements.push((candidate.block, candidate.tree, earlier.local));
+        }
+        same_value.push(idx);
+    }
+    if replacements.is_empty() {
+        return false;
+    }
+    let mut blocks = method.blocks_mut();
+    for (block, tree, local) in replacements {
+        if let CILRoot::STLoc { tree, .. } = blocks[block].trees_mut()[tree].root_mut() {
+            *tree = CILNode::LDLoc(local);
+        }
+    }
+    true
+}
+#[test]
+fn reuse_computation() {
+    use crate::{
+        access_modifier::AccessModifer, basic_block::BasicBlock, method::MethodType, FnSig, Type,
+    };
+    let store = |local, tree| CILRoot::STLoc { local, tree }.into();
+    let sum = || CILNode::Add(Box::new(CILNode::LDArg(0)), Box::new(CILNode::LDLoc(0)));
+    // loc0 = 1; loc1 = arg0 + loc0; loc2 = arg0 + loc0; loc0 = 2; loc3 = arg0 + loc0;
+    let blocks = vec![BasicBlock::new(
+        vec![
+            store(0, CILNode::LdcI32(1)),
+            store(1, sum()),
+            store(2, sum()),
+            store(0, CILNode::LdcI32(2)),
+            store(3, sum()),
+            CILRoot::VoidRet.into(),
+        ],
+        0,
+        None,
+    )];
+    let mut method = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[Type::I32], Type::Void),
+        "reuse_computation",
+        vec![(None, Type::I32); 4],
+        blocks,
+        vec![None],
+    );
+    assert!(cse(&mut method));
+    let trees = method.blocks()[0].trees();
+    assert_eq!(trees[2].root(), store(2, CILNode::LDLoc(1)).root());
+    // `loc0` changed, so the sum has to be recomputed.
+    assert_eq!(trees[4].root(), store(3, sum()).root());
+}
@@ -0,0 +1,124 @@
+//! Dead store elimination: removes stores to locals whose value is never read.
+use crate::{cil_iter::CILIterElem, cil_node::CILNode, cil_root::CILRoot, method::Method};
+
+use super::ssa::{Ssa, Value};
+
+/// Checks if evaluating `node` may have an effect other than producing a value. Loads are not considered to have
+/// side effects: if they fault, the program already has UB.
+#[must_use]
+pub fn may_have_side_effects(node: &CILNode) -> bool {
+    node.into_iter().any(|elem| {
+        matches!(
+            elem,
+            CILIterElem::Node(
+                CILNode::Call { .. }
+                    | CILNode::CallVirt { .. }
+                    | CILNode::CallI(_)
+                    | CILNode::NewObj { .. }
+                    | CILNode::LocAlloc { .. }
+                    | CILNode::LocAllocAligned { .. }
+                    | CILNode::SubTrees(..)
+                    | CILNode::TemporaryLocal(_)
+                    | CILNode::InspectValue { .. }
+                    | CILNode::GetStackTop
+            )
+        )
+    })
+}
+/// Removes stores whose value is never read. If the stored value has side effects, it is still computed, but
+/// discarded. Returns true if anything changed.
+pub fn dead_store_elim(method: &mut Method) -> bool {
+    let Some(ssa) = Ssa::new(method) else {
+        return false;
+    };
+    let used = ssa.used_values(method);
+    let dead: Vec<(usize, usize)> = ssa
+        .live_trees(method)
+        .filter(|(block, idx, tree)| match tree.root() {
+            CILRoot::STLoc { local, .. } => {
+                ssa.is_tracked(*local)
+                    && !used.contains(&Value::Def {
+                        block: *block,
+                        tree: *idx,
+                    })
+            }
+            _ => false,
+        })
+        .map(|(block, idx, _)| (block, idx))
+        .collect();
+    if dead.is_empty() {
+        return false;
+    }
+    let mut blocks = method.blocks_mut();
+    for (block, idx) in dead {
+        let root = blocks[block].trees_mut()[idx].root_mut();
+        let CILRoot::STLoc { tree, .. } = root else {
+            unreachable!()
+        };
+        *root = if may_have_side_effects(tree) {
+            CILRoot::Pop { tree: tree.clone() }
+        } else {
+            // Removed when the blocks are released.
+            CILRoot::Nop
+        };
+    }
+    true
+}
+#[test]
+fn remove_dead_stores() {
+    use crate::{
+        access_modifier::AccessModifer, basic_block::BasicBlock, call_site::CallSite,
+        method::MethodType, FnSig, Type,
+    };
+    let store = |local, tree| CILRoot::STLoc { local, tree }.into();
+    let call = CILNode::Call {
+        args: [].into(),
+        site: Box::new(CallSite::builtin(
+            "side_effect".into(),
+            FnSig::new(&[], Type::I32),
+            true,
+        )),
+    };
+    let blocks = vec![BasicBlock::new(
+        vec![
+            store(0, CILNode::LdcI32(1)),
+            store(1, call.clone()),
+            store(0, CILNode::LdcI32(2)),
+            CILRoot::Ret {
+                tree: CILNode::LDLoc(0),
+            }
+            .into(),
+        ],
+        0,
+        None,
+    )];
+    let mut method = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[], Type::I32),
+        "remove_dead_stores",
+        vec![(None, Type::I32), (None, Type::I32)],
+        blocks,
+        vec![],
+    );
+    assert!(dead_store_elim(&mut method));
+    assert!(!dead_store_elim(&mut method));
+    let roots: Vec<_> = method.blocks()[0]
+        .trees()
+        .iter()
+        .map(crate::cil_tree::CILTree::root)
+        .collect();
+    assert_eq!(
+        roots,
+        [
+            &CILRoot::Pop { tree: call },
+            &CILRoot::STLoc {
+                local: 0,
+                tree: CILNode::LdcI32(2)
+            },
+            &CILRoot::Ret {
+                tree: CILNode::LDLoc(0)
+            },
+        ]
+    );
+}
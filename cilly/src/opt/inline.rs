@@ -0,0 +1,331 @@
+//! Inlines calls to small methods defined in the same assembly.
+use crate::{
+    asm::Assembly, basic_block::BasicBlock, call_site::CallSite, cil_iter::CILIterElem,
+    cil_iter_mut::CILIterElemMut, cil_node::CILNode, cil_root::CILRoot, cil_tree::CILTree,
+    method::Method,
+};
+
+use super::{cfg::Cfg, ssa::is_supported};
+
+/// Methods with more nodes than this are not inlined.
+const MAX_INLINE_SIZE: usize = 48;
+/// How many calls may be inlined into a single method, to limit the growth of large methods.
+const MAX_INLINED_CALLS: usize = 16;
+/// Inlining is stopped once a method has this many locals, since CIL methods can have at most 0xFFFE of them.
+const MAX_LOCALS: usize = 0x4000;
+/// What happens to the value a call returns.
+enum CallResult {
+    /// It is stored in a local.
+    Store(u32),
+    /// It is discarded.
+    Discard,
+    /// It is returned by the caller.
+    Return,
+}
+/// Checks if `callee` is small and simple enough to be inlined.
+fn is_inlinable(callee: &Method) -> bool {
+    let blocks = callee.blocks();
+    if blocks.is_empty() || Cfg::new(blocks).is_none() {
+        return false;
+    }
+    let mut size = 0;
+    for tree in blocks.iter().flat_map(BasicBlock::trees) {
+        if !is_supported(tree) {
+            return false;
+        }
+        for elem in tree.root() {
+            size += 1;
+            // Stack allocations would not be freed until the caller returns, which could overflow the stack in loops.
+            if matches!(
+                elem,
+                CILIterElem::Node(CILNode::LocAlloc { .. } | CILNode::LocAllocAligned { .. })
+                    | CILIterElem::Root(CILRoot::ReThrow | CILRoot::JumpingPad { .. })
+            ) {
+                return false;
+            }
+        }
+    }
+    size <= MAX_INLINE_SIZE
+}
+/// Finds the first call in `method` which can be inlined.
+fn find_call<'a>(
+    method: &Method,
+    asm: &'a Assembly,
+) -> Option<(usize, usize, &'a Method, CallResult)> {
+    let own_site = method.call_site();
+    for (block_idx, block) in method.blocks().iter().enumerate() {
+        for (tree_idx, tree) in block.trees().iter().enumerate() {
+            let (site, args, result): (&CallSite, &[CILNode], _) = match tree.root() {
+                CILRoot::Call { site, args } => (site, args, CallResult::Discard),
+                CILRoot::Pop {
+                    tree: CILNode::Call { site, args },
+                } => (site, args, CallResult::Discard),
+                CILRoot::STLoc {
+                    local,
+                    tree: CILNode::Call { site, args },
+                } => (site, args, CallResult::Store(*local)),
+                CILRoot::Ret {
+                    tree: CILNode::Call { site, args },
+                } => (site, args, CallResult::Return),
+                _ => continue,
+            };
+            if *site == own_site {
+                continue;
+            }
+            let Some(callee) = asm.functions().get(site) else {
+                continue;
+            };
+            let result = match result {
+                CallResult::Return if callee.sig().output() != method.sig().output() => {
+                    continue;
+                }
+                CallResult::Store(local)
+                    if method.locals()[local as usize].1 != *callee.sig().output() =>
+                {
+                    continue;
+                }
+                result => result,
+            };
+            if args.len() == callee.sig().inputs().len() && is_inlinable(callee) {
+                return Some((block_idx, tree_idx, callee, result));
+            }
+        }
+    }
+    None
+}
+/// Rewrites a root of the callee, so that it uses the locals of the caller.
+fn remap_root(root: &mut CILRoot, local_offset: u32, arg_locals: &[u32]) {
+    for elem in &mut *root {
+        match elem {
+            CILIterElemMut::Root(root) => match root {
+                CILRoot::STLoc { local, .. } => *local += local_offset,
+                CILRoot::STArg { arg, tree } => {
+                    *root = CILRoot::STLoc {
+                        local: arg_locals[*arg as usize],
+                        tree: tree.clone(),
+                    };
+                }
+                _ => (),
+            },
+            CILIterElemMut::Node(node) => match node {
+                CILNode::LDLoc(local) | CILNode::LDLocA(local) => *local += local_offset,
+                CILNode::LDArg(arg) => *node = CILNode::LDLoc(arg_locals[*arg as usize]),
+                CILNode::LDArgA(arg) => *node = CILNode::LDLocA(arg_locals[*arg as usize]),
+                _ => (),
+            },
+        }
+    }
+}
+/// Inlines the call made by tree `tree_idx` of block `block_idx` of `method`.
+fn inline_call(
+    method: &mut Method,
+    block_idx: usize,
+    tree_idx: usize,
+    callee: &Method,
+    result: &CallResult,
+) {
+    let local_offset = method.locals().len() as u32;
+    for (_, tpe) in callee.locals() {
+        method.alloc_local(tpe.clone(), None);
+    }
+    let arg_locals: Vec<u32> = callee
+        .sig()
+        .inputs()
+        .iter()
+        .map(|tpe| method.alloc_local(tpe.clone(), None) as u32)
+        .collect();
+    let mut blocks = method.blocks_mut();
+    let next_id = blocks.iter().map(BasicBlock::id).max().unwrap_or(0) + 1;
+    let entry_id = next_id;
+    let continuation_id = next_id + callee.blocks().len() as u32;
+    // Split the caller block, and replace the call with the stores of its arguments.
+    let fallthrough_id = blocks.get(block_idx + 1).map(BasicBlock::id);
+    let trees = blocks[block_idx].trees_mut();
+    let mut continuation = trees.split_off(tree_idx + 1);
+    let call = trees.pop().unwrap();
+    let args = match call.root() {
+        CILRoot::Call { args, .. }
+        | CILRoot::Pop {
+            tree: CILNode::Call { args, .. },
+        }
+        | CILRoot::STLoc {
+            tree: CILNode::Call { args, .. },
+            ..
+        }
+        | CILRoot::Ret {
+            tree: CILNode::Call { args, .. },
+        } => args,
+        _ => unreachable!(),
+    };
+    for (arg, local) in args.iter().zip(&arg_locals) {
+        trees.push(
+            CILRoot::STLoc {
+                local: *local,
+                tree: arg.clone(),
+            }
+            .into(),
+        );
+    }
+    trees.push(
+        CILRoot::GoTo {
+            target: entry_id,
+            sub_target: 0,
+        }
+        .into(),
+    );
+    // Copy the callee, with its returns replaced by jumps to the rest of the caller block.
+    let return_to = CILRoot::GoTo {
+        target: continuation_id,
+        sub_target: 0,
+    };
+    for (idx, callee_block) in callee.blocks().iter().enumerate() {
+        let mut trees: Vec<CILTree> = vec![];
+        for tree in callee_block.trees() {
+            let mut root = tree.root().clone();
+            remap_root(&mut root, local_offset, &arg_locals);
+            if let Some((target, _)) = root.target_mut() {
+                let position = callee
+                    .blocks()
+                    .iter()
+                    .position(|block| block.id() == *target)
+                    .unwrap();
+                *target = next_id + position as u32;
+            }
+            match (root, result) {
+                (root @ (CILRoot::Ret { .. } | CILRoot::VoidRet), CallResult::Return) => {
+                    trees.push(root.into());
+                }
+                (CILRoot::Ret { tree }, CallResult::Store(local)) => {
+                    trees.push(
+                        CILRoot::STLoc {
+                            local: *local,
+                            tree,
+                        }
+                        .into(),
+                    );
+                    trees.push(return_to.clone().into());
+                }
+                (CILRoot::Ret { tree }, CallResult::Discard) => {
+                    trees.push(CILRoot::Pop { tree }.into());
+                    trees.push(return_to.clone().into());
+                }
+                (CILRoot::VoidRet, _) => trees.push(return_to.clone().into()),
+                (root, _) => trees.push(root.into()),
+            }
+        }
+        blocks.push(BasicBlock::new(trees, next_id + idx as u32, None));
+    }
+    // All returns of the callee return from the caller, so the rest of the caller block is dead.
+    if matches!(result, CallResult::Return) {
+        return;
+    }
+    // The rest of the caller block. It may have fallen trough into the next block, which is no longer after it.
+    let is_terminated = continuation
+        .iter()
+        .any(|tree| super::cfg::is_unconditional(tree.root()));
+    if let (false, Some(fallthrough_id)) = (is_terminated, fallthrough_id) {
+        continuation.push(
+            CILRoot::GoTo {
+                target: fallthrough_id,
+                sub_target: 0,
+            }
+            .into(),
+        );
+    }
+    blocks.push(BasicBlock::new(continuation, continuation_id, None));
+}
+/// Inlines calls to small methods defined in `asm`. Returns true if anything changed.
+pub fn inline(method: &mut Method, asm: &Assembly) -> bool {
+    if Cfg::new(method.blocks()).is_none() {
+        return false;
+    }
+    let mut inlined = 0;
+    while inlined < MAX_INLINED_CALLS && method.locals().len() < MAX_LOCALS {
+        let Some((block, tree, callee, result)) = find_call(method, asm) else {
+            break;
+        };
+        inline_call(method, block, tree, callee, &result);
+        inlined += 1;
+    }
+    inlined > 0
+}
+#[test]
+fn inline_add() {
+    use crate::{access_modifier::AccessModifer, method::MethodType, FnSig, Type};
+    let add = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[Type::I32, Type::I32], Type::I32),
+        "add",
+        vec![],
+        vec![BasicBlock::new(
+            vec![CILRoot::Ret {
+                tree: CILNode::Add(Box::new(CILNode::LDArg(0)), Box::new(CILNode::LDArg(1))),
+            }
+            .into()],
+            0,
+            None,
+        )],
+        vec![None, None],
+    );
+    let mut asm = Assembly::empty();
+    asm.add_method(add.clone());
+    let mut caller = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[], Type::I32),
+        "caller",
+        vec![(None, Type::I32)],
+        vec![BasicBlock::new(
+            vec![
+                CILRoot::STLoc {
+                    local: 0,
+                    tree: CILNode::Call {
+                        args: [CILNode::LdcI32(1), CILNode::LdcI32(2)].into(),
+                        site: Box::new(add.call_site()),
+                    },
+                }
+                .into(),
+                CILRoot::Ret {
+                    tree: CILNode::LDLoc(0),
+                }
+                .into(),
+            ],
+            0,
+            None,
+        )],
+        vec![],
+    );
+    assert!(inline(&mut caller, &asm));
+    assert!(caller.calls().is_empty());
+    assert_eq!(caller.blocks().len(), 3);
+    assert_eq!(
+        caller.blocks()[1].trees()[0].root(),
+        &CILRoot::STLoc {
+            local: 0,
+            tree: CILNode::Add(Box::new(CILNode::LDLoc(1)), Box::new(CILNode::LDLoc(2))),
+        }
+    );
+    // With the arguments and result forwarded, only the sum is left. The passes are off by default, so they are run
+    // directly.
+    let passes: [super::Pass; 4] = [
+        super::peephole,
+        super::const_prop,
+        super::copy_prop,
+        super::dead_store_elim,
+    ];
+    while passes
+        .iter()
+        .fold(false, |changed, pass| pass(&mut caller) | changed)
+    {}
+    let returned: Vec<_> = caller
+        .blocks()
+        .iter()
+        .flat_map(|block| block.trees())
+        .filter_map(|tree| match tree.root() {
+            CILRoot::Ret { tree } => Some(tree),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(returned, [&CILNode::LdcI32(3)]);
+}
@@ -0,0 +1,81 @@
+//! The CIL optimizer. It works on the SSA form of the local variables of a method, built over its control flow graph.
+//! Each pass can be turned on and off in the config.
+use crate::{asm::Assembly, config::config, method::Method};
+
+pub mod cfg;
+mod const_prop;
+mod copy_prop;
+mod cse;
+mod dead_store;
+mod inline;
+pub mod ssa;
+
+pub use const_prop::const_prop;
+pub use copy_prop::copy_prop;
+pub use cse::cse;
+pub use dead_store::{dead_store_elim, may_have_side_effects};
+pub use inline::inline;
+
+/// An optimization pass. Returns true if it changed the method.
+pub type Pass = fn(&mut Method) -> bool;
+/// The passes are repeated until the method stops changing, or this many times.
+const MAX_ROUNDS: usize = 8;
+/// Simplifies the trees of `method` one by one. Returns true if anything changed.
+pub fn peephole(method: &mut Method) -> bool {
+    let mut changed = false;
+    let mut blocks = method.blocks_mut();
+    for tree in blocks.iter_mut().flat_map(|block| block.all_trees_mut()) {
+        let mut opt_counter: usize = 1;
+        while opt_counter > 0 {
+            opt_counter = 0;
+            tree.opt(&mut opt_counter);
+            changed |= opt_counter > 0;
+        }
+    }
+    changed
+}
+/// Returns the passes enabled in the config, in the order they should run.
+#[must_use]
+pub fn enabled_passes() -> Vec<Pass> {
+    let config = config();
+    let mut passes: Vec<Pass> = vec![peephole];
+    if !config.optimize_cil {
+        return passes;
+    }
+    if config.const_propagation {
+        passes.push(const_prop);
+    }
+    if config.copy_propagation {
+        passes.push(copy_prop);
+    }
+    if config.common_subexpression_elimination {
+        passes.push(cse);
+    }
+    if config.dead_store_elimination {
+        passes.push(dead_store_elim);
+    }
+    passes
+}
+/// Runs the enabled passes on `method`, until it stops changing.
+pub fn run_passes(method: &mut Method) {
+    let passes = enabled_passes();
+    for _ in 0..MAX_ROUNDS {
+        let mut changed = false;
+        for pass in &passes {
+            changed |= pass(method);
+        }
+        if !changed {
+            break;
+        }
+    }
+}
+/// Optimizes `method`, inlining calls to other methods of `asm` if that is enabled. `method` should not be a part of
+/// `asm` while it is being optimized.
+pub fn opt_method(method: &mut Method, asm: &Assembly) {
+    let config = config();
+    if config.optimize_cil && config.inline_simple_functions {
+        inline(method, asm);
+    }
+    run_passes(method);
+    method.realloc_locals();
+}
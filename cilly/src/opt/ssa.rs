@@ -0,0 +1,324 @@
+//! SSA form of the local variables of a method.
+//!
+//! Each store to a local defines a new SSA value, and phi values are placed where the values coming from different
+//! paths meet. The method itself is not rewritten: passes ask which value of a local a given tree reads, and use
+//! that to decide if a transformation is valid.
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    cil_iter::CILIterElem, cil_iter_mut::CILIterElemMut, cil_node::CILNode, cil_root::CILRoot,
+    cil_tree::CILTree, method::Method,
+};
+
+use super::cfg::Cfg;
+
+/// A SSA value of a local variable.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Value {
+    /// The value a local has on method entry.
+    Entry(u32),
+    /// The value stored by the `STLoc` tree `tree` of `block`.
+    Def { block: usize, tree: usize },
+    /// The value of `local` at the start of `block`, which comes from one of its predecessors.
+    Phi { block: usize, local: u32 },
+}
+/// The SSA form of a method. Only locals whose address is never taken are tracked, since the others may change behind
+/// the back of the analysis.
+pub struct Ssa {
+    cfg: Cfg,
+    tracked: Vec<bool>,
+    /// Arguments which are never written to, or have their address taken.
+    immutable_args: Vec<bool>,
+    /// The trees of a block which store to a given local, in order.
+    defs: HashMap<(usize, u32), Vec<usize>>,
+    phis: HashSet<(usize, u32)>,
+}
+/// Checks if the optimizer can reason about `tree`. Trees which still contain subtrees or temporaries, or inspect the
+/// evaluation stack, are not supported.
+pub(super) fn is_supported(tree: &CILTree) -> bool {
+    !tree.root().into_iter().any(|elem| {
+        matches!(
+            elem,
+            CILIterElem::Node(
+                CILNode::SubTrees(..)
+                    | CILNode::TemporaryLocal(_)
+                    | CILNode::LoadTMPLocal
+                    | CILNode::LoadAddresOfTMPLocal
+                    | CILNode::InspectValue { .. }
+                    | CILNode::GetStackTop
+            ) | CILIterElem::Root(CILRoot::SetTMPLocal { .. })
+        )
+    })
+}
+impl Ssa {
+    /// Computes the SSA form of `method`. Returns `None` if the method is not supported by the optimizer, e.g. because
+    /// it contains exception handlers.
+    #[must_use]
+    pub fn new(method: &Method) -> Option<Self> {
+        let blocks = method.blocks();
+        let cfg = Cfg::new(blocks)?;
+        let mut tracked = vec![true; method.locals().len()];
+        let mut immutable_args = vec![true; method.sig().inputs().len()];
+        for tree in blocks.iter().flat_map(|block| block.trees()) {
+            if !is_supported(tree) {
+                return None;
+            }
+            for elem in tree.root() {
+                match elem {
+                    CILIterElem::Node(CILNode::LDLocA(local)) => tracked[*local as usize] = false,
+                    CILIterElem::Node(CILNode::LDArgA(arg))
+                    | CILIterElem::Root(CILRoot::STArg { arg, .. }) => {
+                        immutable_args[*arg as usize] = false;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let mut defs: HashMap<(usize, u32), Vec<usize>> = HashMap::new();
+        let mut def_blocks: HashMap<u32, Vec<usize>> = HashMap::new();
+        for &block in cfg.rpo() {
+            for (idx, tree) in blocks[block].trees()[..cfg.live_len(block)]
+                .iter()
+                .enumerate()
+            {
+                if let CILRoot::STLoc { local, .. } = tree.root() {
+                    if tracked[*local as usize] {
+                        defs.entry((block, *local)).or_default().push(idx);
+                        def_blocks.entry(*local).or_default().push(block);
+                    }
+                }
+            }
+        }
+        // Place phis at the iterated dominance frontier of the blocks defining each local.
+        let frontiers = cfg.dominance_frontiers();
+        let mut phis = HashSet::new();
+        for (local, mut worklist) in def_blocks {
+            while let Some(block) = worklist.pop() {
+                for &frontier in &frontiers[block] {
+                    if phis.insert((frontier, local)) {
+                        worklist.push(frontier);
+                    }
+                }
+            }
+        }
+        Some(Self {
+            cfg,
+            tracked,
+            immutable_args,
+            defs,
+            phis,
+        })
+    }
+    /// Returns the control flow graph of the method.
+    #[must_use]
+    pub fn cfg(&self) -> &Cfg {
+        &self.cfg
+    }
+    /// Checks if `local` is in SSA form.
+    #[must_use]
+    pub fn is_tracked(&self, local: u32) -> bool {
+        self.tracked[local as usize]
+    }
+    /// Checks if argument `arg` keeps its initial value for the whole method.
+    #[must_use]
+    pub fn is_arg_immutable(&self, arg: u32) -> bool {
+        self.immutable_args[arg as usize]
+    }
+    /// Returns the value of `local` tree `tree` of `block` sees. Stores made by that tree are not visible to it.
+    /// `tree` may be past the end of the block, to get the value the block leaves `local` with.
+    #[must_use]
+    pub fn reaching(&self, block: usize, tree: usize, local: u32) -> Value {
+        debug_assert!(self.is_tracked(local));
+        let mut block = block;
+        let mut tree = tree;
+        loop {
+            let last_def = self
+                .defs
+                .get(&(block, local))
+                .and_then(|defs| defs.iter().rev().find(|def| **def < tree));
+            if let Some(def) = last_def {
+                return Value::Def { block, tree: *def };
+            }
+            if self.phis.contains(&(block, local)) {
+                return Value::Phi { block, local };
+            }
+            match self.cfg.idom(block) {
+                Some(idom) => {
+                    block = idom;
+                    tree = usize::MAX;
+                }
+                None => return Value::Entry(local),
+            }
+        }
+    }
+    /// Returns all the phi values, as the block and local they belong to.
+    pub fn phis(&self) -> impl Iterator<Item = (usize, u32)> + '_ {
+        self.phis.iter().copied()
+    }
+    /// Returns the values a phi value may take.
+    #[must_use]
+    pub fn phi_operands(&self, block: usize, local: u32) -> Vec<Value> {
+        let mut operands: Vec<Value> = self
+            .cfg
+            .preds(block)
+            .iter()
+            .filter(|pred| self.cfg.is_reachable(**pred))
+            .map(|pred| self.reaching(*pred, usize::MAX, local))
+            .collect();
+        if block == 0 {
+            operands.push(Value::Entry(local));
+        }
+        operands
+    }
+    /// Returns all the positions of reachable trees of `method`, in reverse postorder of their blocks.
+    pub fn live_trees<'a>(
+        &'a self,
+        method: &'a Method,
+    ) -> impl Iterator<Item = (usize, usize, &'a CILTree)> {
+        self.cfg.rpo().iter().flat_map(move |&block| {
+            method.blocks()[block].trees()[..self.cfg.live_len(block)]
+                .iter()
+                .enumerate()
+                .map(move |(idx, tree)| (block, idx, tree))
+        })
+    }
+    /// Returns all the values which may be read, either directly or trough a phi.
+    #[must_use]
+    pub fn used_values(&self, method: &Method) -> HashSet<Value> {
+        let mut used = HashSet::new();
+        let mut worklist = vec![];
+        for (block, idx, tree) in self.live_trees(method) {
+            for elem in tree.root() {
+                if let CILIterElem::Node(CILNode::LDLoc(local)) = elem {
+                    if self.is_tracked(*local) {
+                        worklist.push(self.reaching(block, idx, *local));
+                    }
+                }
+            }
+        }
+        while let Some(value) = worklist.pop() {
+            if !used.insert(value) {
+                continue;
+            }
+            if let Value::Phi { block, local } = value {
+                worklist.extend(self.phi_operands(block, local));
+            }
+        }
+        used
+    }
+    /// Returns the local `value` is stored in, and the tree computing it, if `value` is a store.
+    #[must_use]
+    pub fn def_tree<'a>(&self, method: &'a Method, value: Value) -> Option<(u32, &'a CILNode)> {
+        let Value::Def { block, tree } = value else {
+            return None;
+        };
+        match method.blocks()[block].trees()[tree].root() {
+            CILRoot::STLoc { local, tree } => Some((*local, tree)),
+            _ => None,
+        }
+    }
+}
+/// Replaces the `LDLoc` nodes of the reachable trees of `method` with the node `replacement` returns for their block,
+/// tree and local. Returns true if anything changed.
+pub(super) fn replace_loads(
+    method: &mut Method,
+    ssa: &Ssa,
+    mut replacement: impl FnMut(usize, usize, u32) -> Option<CILNode>,
+) -> bool {
+    let mut replacements = HashMap::new();
+    for (block, idx, tree) in ssa.live_trees(method) {
+        for elem in tree.root() {
+            if let CILIterElem::Node(CILNode::LDLoc(local)) = elem {
+                if replacements.contains_key(&(block, idx, *local)) || !ssa.is_tracked(*local) {
+                    continue;
+                }
+                if let Some(new) = replacement(block, idx, *local) {
+                    replacements.insert((block, idx, *local), new);
+                }
+            }
+        }
+    }
+    if replacements.is_empty() {
+        return false;
+    }
+    let mut blocks = method.blocks_mut();
+    for (block_idx, block) in blocks.iter_mut().enumerate() {
+        for (idx, tree) in block.trees_mut().iter_mut().enumerate() {
+            for elem in &mut *tree.root_mut() {
+                if let CILIterElemMut::Node(node) = elem {
+                    if let CILNode::LDLoc(local) = *node {
+                        if let Some(new) = replacements.get(&(block_idx, idx, local)) {
+                            *node = new.clone();
+                        }
+                    }
+                }
+            }
+        }
+    }
+    true
+}
+#[test]
+fn loop_phi() {
+    use crate::{
+        access_modifier::AccessModifer, basic_block::BasicBlock, method::MethodType, FnSig, Type,
+    };
+    // loc0 = 0; loop { loc0 = loc0 + 1; if arg0 {continue} }; return loc0
+    let blocks = vec![
+        BasicBlock::new(
+            vec![CILRoot::STLoc {
+                local: 0,
+                tree: CILNode::LdcI32(0),
+            }
+            .into()],
+            0,
+            None,
+        ),
+        BasicBlock::new(
+            vec![
+                CILRoot::STLoc {
+                    local: 0,
+                    tree: CILNode::Add(Box::new(CILNode::LDLoc(0)), Box::new(CILNode::LdcI32(1))),
+                }
+                .into(),
+                CILRoot::BTrue {
+                    target: 1,
+                    sub_target: 0,
+                    cond: CILNode::LDArg(0),
+                }
+                .into(),
+            ],
+            1,
+            None,
+        ),
+        BasicBlock::new(
+            vec![CILRoot::Ret {
+                tree: CILNode::LDLoc(0),
+            }
+            .into()],
+            2,
+            None,
+        ),
+    ];
+    let method = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[Type::Bool], Type::I32),
+        "loop_phi",
+        vec![(None, Type::I32)],
+        blocks,
+        vec![None],
+    );
+    let ssa = Ssa::new(&method).unwrap();
+    let phi = Value::Phi { block: 1, local: 0 };
+    assert_eq!(ssa.reaching(1, 0, 0), phi);
+    assert_eq!(ssa.reaching(2, 0, 0), Value::Def { block: 1, tree: 0 });
+    assert_eq!(
+        ssa.phi_operands(1, 0),
+        [
+            Value::Def { block: 0, tree: 0 },
+            Value::Def { block: 1, tree: 0 }
+        ]
+    );
+    assert!(ssa.is_arg_immutable(0));
+    assert!(ssa.used_values(&method).contains(&phi));
+}
@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+/// Encodes `value` as an ECMA-335 compressed unsigned integer (II.23.2).
+/// # Panics
+/// Panics if `value` is too big to be compressed(larger than `0x1FFF_FFFF`).
+pub fn compress_u32(value: u32, out: &mut Vec<u8>) {
+    match value {
+        0..=0x7F => out.push(value as u8),
+        0x80..=0x3FFF => out.extend((0x8000 | value as u16).to_be_bytes()),
+        0x4000..=0x1FFF_FFFF => out.extend((0xC000_0000 | value).to_be_bytes()),
+        _ => panic!("Value {value:#x} can't be stored as a compressed integer."),
+    }
+}
+/// Encodes `value` as an ECMA-335 compressed signed integer (II.23.2): the two's complement is rotated left by one bit,
+/// so that the sign ends up in the lowest bit.
+/// # Panics
+/// Panics if `value` is outside of the `-0x1000_0000..0x1000_0000` range.
+pub fn compress_i32(value: i32, out: &mut Vec<u8>) {
+    let rotate = |bits: u32| ((value as u32) << 1 | u32::from(value < 0)) & ((1 << bits) - 1);
+    match value {
+        -0x40..=0x3F => out.push(rotate(7) as u8),
+        -0x2000..=0x1FFF => out.extend((0x8000 | rotate(14) as u16).to_be_bytes()),
+        -0x1000_0000..=0x0FFF_FFFF => out.extend((0xC000_0000 | rotate(29)).to_be_bytes()),
+        _ => panic!("Value {value:#x} can't be stored as a compressed signed integer."),
+    }
+}
+/// The `#Strings` heap: a list of null-terminated UTF-8 strings.
+pub struct StringHeap {
+    data: Vec<u8>,
+    map: HashMap<Box<str>, u32>,
+}
+impl StringHeap {
+    pub fn new() -> Self {
+        Self {
+            data: vec![0],
+            map: HashMap::new(),
+        }
+    }
+    /// Returns the index of `string`, adding it to the heap if needed. Empty strings always have an index of 0.
+    pub fn add(&mut self, string: &str) -> u32 {
+        if string.is_empty() {
+            return 0;
+        }
+        if let Some(idx) = self.map.get(string) {
+            return *idx;
+        }
+        let idx = self.data.len() as u32;
+        self.data.extend(string.as_bytes());
+        self.data.push(0);
+        self.map.insert(string.into(), idx);
+        idx
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+/// The `#Blob` heap: a list of length-prefixed byte arrays, used mostly for signatures.
+pub struct BlobHeap {
+    data: Vec<u8>,
+    map: HashMap<Vec<u8>, u32>,
+}
+impl BlobHeap {
+    pub fn new() -> Self {
+        Self {
+            data: vec![0],
+            map: HashMap::new(),
+        }
+    }
+    /// Returns the index of `blob`, adding it to the heap if needed. Empty blobs always have an index of 0.
+    pub fn add(&mut self, blob: &[u8]) -> u32 {
+        if blob.is_empty() {
+            return 0;
+        }
+        if let Some(idx) = self.map.get(blob) {
+            return *idx;
+        }
+        let idx = self.data.len() as u32;
+        compress_u32(blob.len() as u32, &mut self.data);
+        self.data.extend(blob);
+        self.map.insert(blob.to_vec(), idx);
+        idx
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+/// The `#US` heap, containing UTF-16 string literals loaded by `ldstr`.
+pub struct UserStringHeap {
+    data: Vec<u8>,
+    map: HashMap<Box<str>, u32>,
+}
+impl UserStringHeap {
+    pub fn new() -> Self {
+        Self {
+            data: vec![0],
+            map: HashMap::new(),
+        }
+    }
+    /// Returns the index of `string`, adding it to the heap if needed.
+    pub fn add(&mut self, string: &str) -> u32 {
+        if let Some(idx) = self.map.get(string) {
+            return *idx;
+        }
+        let idx = self.data.len() as u32;
+        let utf16: Vec<u16> = string.encode_utf16().collect();
+        compress_u32(utf16.len() as u32 * 2 + 1, &mut self.data);
+        // The trailing byte is set if any character needs special handling when compared (II.24.2.4).
+        let mut special = 0;
+        for char in utf16 {
+            if char > 0xFF || matches!(char, 0x01..=0x08 | 0x0E..=0x1F | 0x27 | 0x2D | 0x7F) {
+                special = 1;
+            }
+            self.data.extend(char.to_le_bytes());
+        }
+        self.data.push(special);
+        self.map.insert(string.into(), idx);
+        idx
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+/// The `#GUID` heap. Cilly only ever stores the module version id, and the PDB language GUID, there.
+pub struct GuidHeap {
+    data: Vec<u8>,
+}
+impl GuidHeap {
+    pub fn new() -> Self {
+        Self { data: vec![] }
+    }
+    /// Adds `guid` to the heap, and returns its 1-based index.
+    pub fn add(&mut self, guid: [u8; 16]) -> u32 {
+        self.data.extend(guid);
+        (self.data.len() / 16) as u32
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+#[test]
+fn compressed_ints() {
+    let encode = |value| {
+        let mut out = vec![];
+        compress_u32(value, &mut out);
+        out
+    };
+    // Examples from ECMA-335 II.23.2
+    assert_eq!(encode(0x03), [0x03]);
+    assert_eq!(encode(0x7F), [0x7F]);
+    assert_eq!(encode(0x80), [0x80, 0x80]);
+    assert_eq!(encode(0x2E57), [0xAE, 0x57]);
+    assert_eq!(encode(0x3FFF), [0xBF, 0xFF]);
+    assert_eq!(encode(0x4000), [0xC0, 0x00, 0x40, 0x00]);
+    assert_eq!(encode(0x1FFF_FFFF), [0xDF, 0xFF, 0xFF, 0xFF]);
+}
+#[test]
+fn compressed_signed_ints() {
+    let encode = |value| {
+        let mut out = vec![];
+        compress_i32(value, &mut out);
+        out
+    };
+    // Examples from ECMA-335 II.23.2
+    assert_eq!(encode(3), [0x06]);
+    assert_eq!(encode(-3), [0x7B]);
+    assert_eq!(encode(64), [0x80, 0x80]);
+    assert_eq!(encode(-64), [0x01]);
+    assert_eq!(encode(8192), [0xC0, 0x00, 0x40, 0x00]);
+    assert_eq!(encode(-8192), [0x80, 0x01]);
+    assert_eq!(encode(268_435_455), [0xDF, 0xFF, 0xFF, 0xFE]);
+    assert_eq!(encode(-268_435_456), [0xC0, 0x00, 0x00, 0x01]);
+}
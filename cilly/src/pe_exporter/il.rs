@@ -0,0 +1,669 @@
+//! Encodes method bodies as CIL bytecode(II.25.4, III). Mirrors the text emitted by [`crate::ilasm_op`], so both exporters produce equivalent code.
+use std::collections::HashMap;
+
+use crate::{
+    asm_exporter::AssemblyExportError,
+    basic_block::{caught_local, BasicBlock},
+    call_site::CallSite,
+    cil_node::CILNode,
+    cil_root::{CILRoot, SFI},
+    method::Method,
+    FnSig, Type,
+};
+
+use super::{encode_error, tables::Table, MetadataBuilder};
+
+/// A single exception handling clause, with offsets relative to the start of the method code.
+struct Clause {
+    try_start: u32,
+    try_end: u32,
+    handler_start: u32,
+    handler_end: u32,
+}
+/// Encodes the code of a single method.
+struct BodyEncoder<'a> {
+    builder: &'a mut MetadataBuilder,
+    code: Vec<u8>,
+    /// Offsets of labels, keyed by `(block, sub_block)`.
+    labels: HashMap<(u32, u32), u32>,
+    /// Positions of branch operands, which need to be patched once all labels are known.
+    fixups: Vec<(usize, (u32, u32))>,
+    clauses: Vec<Clause>,
+    /// Source locations, and the offsets of the code they describe.
+    sequence_points: Vec<(u32, SFI)>,
+}
+impl<'a> BodyEncoder<'a> {
+    fn op(&mut self, opcode: u8) {
+        self.code.push(opcode);
+    }
+    /// Emits a two-byte opcode, starting with the `0xFE` prefix.
+    fn op_fe(&mut self, opcode: u8) {
+        self.code.extend([0xFE, opcode]);
+    }
+    fn op_u8(&mut self, opcode: u8, operand: u8) {
+        self.code.extend([opcode, operand]);
+    }
+    fn op_u16(&mut self, opcode: u8, operand: u16) {
+        self.code.extend([0xFE, opcode]);
+        self.code.extend(operand.to_le_bytes());
+    }
+    fn op_u32(&mut self, opcode: u8, operand: u32) {
+        self.code.push(opcode);
+        self.code.extend(operand.to_le_bytes());
+    }
+    fn op_token(&mut self, opcode: u8, token: u32) {
+        self.op_u32(opcode, token);
+    }
+    fn label(&mut self, target: u32, sub_target: u32) {
+        self.labels
+            .insert((target, sub_target), self.code.len() as u32);
+    }
+    /// Emits a long-form branch to the label `bb_{target}_{sub_target}`.
+    fn branch(&mut self, opcode: u8, target: u32, sub_target: u32) {
+        self.code.push(opcode);
+        self.fixups.push((self.code.len(), (target, sub_target)));
+        self.code.extend([0; 4]);
+    }
+    fn ldc_i4(&mut self, value: i32) {
+        match value {
+            -1 => self.op(0x15),
+            0..=8 => self.op(0x16 + value as u8),
+            _ => match i8::try_from(value) {
+                Ok(value) => self.op_u8(0x1F, value as u8),
+                Err(_) => self.op_u32(0x20, value as u32),
+            },
+        }
+    }
+    fn ldc_i8(&mut self, value: u64) {
+        self.op(0x21);
+        self.code.extend(value.to_le_bytes());
+    }
+    fn call(&mut self, opcode: u8, site: &CallSite) -> Result<(), AssemblyExportError> {
+        let token = self.builder.method_token(site)?;
+        self.op_token(opcode, token);
+        Ok(())
+    }
+    fn calli(&mut self, sig: &FnSig) -> Result<(), AssemblyExportError> {
+        let token = self.builder.calli_sig_token(sig)?;
+        self.op_token(0x29, token);
+        Ok(())
+    }
+    fn stloc(&mut self, local: u32) {
+        match local {
+            0..=3 => self.op(0x0A + local as u8),
+            4..=255 => self.op_u8(0x13, local as u8),
+            _ => self.op_u16(0x0E, local as u16),
+        }
+    }
+    fn type_op(&mut self, opcode: u8, tpe: &Type) -> Result<(), AssemblyExportError> {
+        let token = self.builder.type_token(tpe)?;
+        self.op_token(opcode, token);
+        Ok(())
+    }
+    fn sizeof(&mut self, tpe: &Type) -> Result<(), AssemblyExportError> {
+        let token = self.builder.type_token(tpe)?;
+        self.op_fe(0x1C);
+        self.code.extend(token.to_le_bytes());
+        Ok(())
+    }
+    fn un_op(&mut self, val: &CILNode, opcode: u8) -> Result<(), AssemblyExportError> {
+        self.node(val)?;
+        self.op(opcode);
+        Ok(())
+    }
+    fn bi_op(&mut self, a: &CILNode, b: &CILNode, opcode: u8) -> Result<(), AssemblyExportError> {
+        self.node(a)?;
+        self.node(b)?;
+        self.op(opcode);
+        Ok(())
+    }
+    fn cmp_op(&mut self, a: &CILNode, b: &CILNode, opcode: u8) -> Result<(), AssemblyExportError> {
+        self.node(a)?;
+        self.node(b)?;
+        self.op_fe(opcode);
+        Ok(())
+    }
+    fn cond_branch(
+        &mut self,
+        a: &CILNode,
+        b: &CILNode,
+        opcode: u8,
+        target: u32,
+        sub_target: u32,
+    ) -> Result<(), AssemblyExportError> {
+        self.node(a)?;
+        self.node(b)?;
+        self.branch(opcode, target, sub_target);
+        Ok(())
+    }
+    fn node(&mut self, node: &CILNode) -> Result<(), AssemblyExportError> {
+        match node {
+            CILNode::LDLoc(local) => match local {
+                0..=3 => self.op(0x06 + *local as u8),
+                4..=255 => self.op_u8(0x11, *local as u8),
+                _ => self.op_u16(0x0C, *local as u16),
+            },
+            CILNode::LDArg(arg) => match arg {
+                0..=3 => self.op(0x02 + *arg as u8),
+                4..=255 => self.op_u8(0x0E, *arg as u8),
+                _ => self.op_u16(0x09, *arg as u16),
+            },
+            CILNode::LDLocA(local) => match local {
+                0..=255 => self.op_u8(0x12, *local as u8),
+                _ => self.op_u16(0x0D, *local as u16),
+            },
+            CILNode::LDArgA(arg) => match arg {
+                0..=255 => self.op_u8(0x0F, *arg as u8),
+                _ => self.op_u16(0x0A, *arg as u16),
+            },
+            CILNode::LDStaticField(desc) => {
+                let token = self.builder.static_field_token(desc)?;
+                self.op_token(0x7E, token);
+            }
+            CILNode::ConvF32(val) => self.un_op(val, 0x6B)?,
+            CILNode::ConvF64(val) => self.un_op(val, 0x6C)?,
+            CILNode::ConvF64Un(val) => self.un_op(val, 0x76)?,
+            CILNode::SizeOf(tpe) => self.sizeof(tpe)?,
+            CILNode::LDIndI8 { ptr } | CILNode::LDIndBool { ptr } => self.un_op(ptr, 0x46)?,
+            CILNode::LDIndU8 { ptr } => self.un_op(ptr, 0x47)?,
+            CILNode::LDIndI16 { ptr } => self.un_op(ptr, 0x48)?,
+            CILNode::LDIndU16 { ptr } => self.un_op(ptr, 0x49)?,
+            CILNode::LDIndI32 { ptr } => self.un_op(ptr, 0x4A)?,
+            CILNode::LDIndU32 { ptr } => self.un_op(ptr, 0x4B)?,
+            CILNode::LDIndI64 { ptr } | CILNode::LDIndU64 { ptr } => self.un_op(ptr, 0x4C)?,
+            CILNode::LDIndISize { ptr }
+            | CILNode::LDIndPtr { ptr, .. }
+            | CILNode::LDIndUSize { ptr } => self.un_op(ptr, 0x4D)?,
+            CILNode::LDIndF32 { ptr } => self.un_op(ptr, 0x4E)?,
+            CILNode::LDIndF64 { ptr } => self.un_op(ptr, 0x4F)?,
+            CILNode::LdObj { ptr, obj } => {
+                self.node(ptr)?;
+                self.type_op(0x71, obj)?;
+            }
+            CILNode::LDFieldAdress { addr, field } => {
+                self.node(addr)?;
+                let token = self.builder.field_token(field)?;
+                self.op_token(0x7C, token);
+            }
+            CILNode::LDField { addr, field } => {
+                self.node(addr)?;
+                let token = self.builder.field_token(field)?;
+                self.op_token(0x7B, token);
+            }
+            CILNode::Add(a, b) => self.bi_op(a, b, 0x58)?,
+            CILNode::Sub(a, b) => self.bi_op(a, b, 0x59)?,
+            CILNode::Mul(a, b) => self.bi_op(a, b, 0x5A)?,
+            CILNode::Div(a, b) => self.bi_op(a, b, 0x5B)?,
+            CILNode::DivUn(a, b) => self.bi_op(a, b, 0x5C)?,
+            CILNode::Rem(a, b) => self.bi_op(a, b, 0x5D)?,
+            CILNode::RemUn(a, b) => self.bi_op(a, b, 0x5E)?,
+            CILNode::And(a, b) => self.bi_op(a, b, 0x5F)?,
+            CILNode::Or(a, b) => self.bi_op(a, b, 0x60)?,
+            CILNode::XOr(a, b) => self.bi_op(a, b, 0x61)?,
+            CILNode::Shl(a, b) => self.bi_op(a, b, 0x62)?,
+            CILNode::Shr(a, b) => self.bi_op(a, b, 0x63)?,
+            CILNode::ShrUn(a, b) => self.bi_op(a, b, 0x64)?,
+            CILNode::Call { args, site } => {
+                if site.is_nop() {
+                    self.node(&args[0])?;
+                } else {
+                    for arg in args.iter() {
+                        self.node(arg)?;
+                    }
+                    self.call(0x28, site)?;
+                }
+            }
+            CILNode::CallVirt { args, site } => {
+                for arg in args.iter() {
+                    self.node(arg)?;
+                }
+                self.call(0x6F, site)?;
+            }
+            CILNode::NewObj { site, args } => {
+                for arg in args.iter() {
+                    self.node(arg)?;
+                }
+                self.call(0x73, site)?;
+            }
+            CILNode::LDFtn(site) => {
+                let token = self.builder.method_token(site)?;
+                self.op_fe(0x06);
+                self.code.extend(token.to_le_bytes());
+            }
+            // Small 64 bit constants are loaded using the shorter 32 bit forms, just like the ILASM exporter does.
+            CILNode::LdcI64(value) => match i32::try_from(*value) {
+                Ok(value) => self.ldc_i4(value),
+                Err(_) => self.ldc_i8(*value as u64),
+            },
+            CILNode::LdcU64(value) => {
+                if *value <= 8 {
+                    self.op(0x16 + *value as u8);
+                } else if *value < u64::from(i8::MAX as u8) {
+                    self.op_u8(0x1F, *value as u8);
+                } else if *value < u64::from(i32::MAX as u32) {
+                    self.op_u32(0x20, *value as u32);
+                } else {
+                    self.ldc_i8(*value);
+                }
+            }
+            CILNode::LdcI32(value) => self.ldc_i4(*value),
+            CILNode::LdcU32(value) => {
+                if *value <= 8 {
+                    self.op(0x16 + *value as u8);
+                } else if *value < u32::from(i8::MAX as u8) {
+                    self.op_u8(0x1F, *value as u8);
+                } else {
+                    self.op_u32(0x20, *value);
+                }
+            }
+            CILNode::LdcF32(value) => self.op_u32(0x22, value.to_bits()),
+            CILNode::LdcF64(value) => {
+                self.op(0x23);
+                self.code.extend(value.to_le_bytes());
+            }
+            CILNode::ConvU8(val) => self.un_op(val, 0xD2)?,
+            CILNode::ConvU16(val) => self.un_op(val, 0xD1)?,
+            CILNode::ConvU32(val) => self.un_op(val, 0x6D)?,
+            CILNode::ConvU64(val) => self.un_op(val, 0x6E)?,
+            CILNode::ZeroExtendToUSize(val)
+            | CILNode::ZeroExtendToISize(val)
+            | CILNode::MRefToRawPtr(val) => self.un_op(val, 0xE0)?,
+            CILNode::ConvI8(val) => self.un_op(val, 0x67)?,
+            CILNode::ConvI16(val) => self.un_op(val, 0x68)?,
+            CILNode::ConvI32(val) => self.un_op(val, 0x69)?,
+            CILNode::ConvI64(val) => self.un_op(val, 0x6A)?,
+            CILNode::ConvISize(val) => self.un_op(val, 0xD3)?,
+            CILNode::Neg(val) => self.un_op(val, 0x65)?,
+            CILNode::Not(val) => self.un_op(val, 0x66)?,
+            CILNode::Eq(a, b) => self.cmp_op(a, b, 0x01)?,
+            CILNode::Gt(a, b) => self.cmp_op(a, b, 0x02)?,
+            CILNode::GtUn(a, b) => self.cmp_op(a, b, 0x03)?,
+            CILNode::Lt(a, b) => self.cmp_op(a, b, 0x04)?,
+            CILNode::LtUn(a, b) => self.cmp_op(a, b, 0x05)?,
+            CILNode::LDTypeToken(tpe) => self.type_op(0xD0, tpe)?,
+            CILNode::LdStr(string) => {
+                let token = self.builder.user_string_token(string);
+                self.op_token(0x72, token);
+            }
+            CILNode::CallI(fn_ptr_and_sig) => {
+                let (sig, fn_ptr, args) = fn_ptr_and_sig.as_ref();
+                for arg in args.iter() {
+                    self.node(arg)?;
+                }
+                self.node(fn_ptr)?;
+                self.calli(sig)?;
+            }
+            CILNode::LDLen { arr } => self.un_op(arr, 0x8E)?,
+            CILNode::LDElelemRef { arr, idx } => self.bi_op(arr, idx, 0x9A)?,
+            CILNode::IsInst { obj, class } => {
+                self.node(obj)?;
+                self.type_op(0x75, &Type::DotnetType(class.clone()))?;
+            }
+            CILNode::GetStackTop => (),
+            CILNode::InspectValue { val, inspect } => {
+                self.node(val)?;
+                self.op(0x25);
+                for root in inspect.iter() {
+                    self.root(root)?;
+                }
+            }
+            CILNode::TransmutePtr { val, .. } => self.node(val)?,
+            CILNode::LdFalse => self.op(0x16),
+            CILNode::LdTrue => self.op(0x17),
+            CILNode::LocAllocAligned { tpe, align } => {
+                // Alloc buff
+                self.sizeof(tpe)?;
+                self.ldc_i8(*align);
+                self.op(0xD3);
+                self.op(0x58);
+                self.op_fe(0x0F);
+                // Adjust align
+                self.op(0x25);
+                self.ldc_i8(*align);
+                self.op(0x58);
+                self.ldc_i8(*align);
+                self.op(0x5D);
+                self.op(0x59);
+                self.ldc_i8(*align);
+                self.op(0x58);
+            }
+            CILNode::LocAlloc { size } => {
+                self.node(size)?;
+                self.op_fe(0x0F);
+            }
+            CILNode::TemporaryLocal(_)
+            | CILNode::SubTrees(_, _)
+            | CILNode::LoadAddresOfTMPLocal
+            | CILNode::LoadTMPLocal => {
+                return Err(encode_error(format!(
+                    "Unresolved temporary local at the export stage: {node:?}"
+                )))
+            }
+            CILNode::BlackBox(_)
+            | CILNode::LoadGlobalAllocPtr { .. }
+            | CILNode::PointerToConstValue(_) => {
+                return Err(encode_error(format!("Can't encode node {node:?}")))
+            }
+        }
+        Ok(())
+    }
+    fn root(&mut self, root: &CILRoot) -> Result<(), AssemblyExportError> {
+        match root {
+            CILRoot::STLoc { local, tree } => {
+                self.node(tree)?;
+                self.stloc(*local);
+            }
+            CILRoot::BTrue {
+                target,
+                sub_target,
+                cond,
+            } => {
+                self.node(cond)?;
+                self.branch(0x3A, *target, *sub_target);
+            }
+            CILRoot::BFalse {
+                target,
+                sub_target,
+                cond,
+            } => {
+                self.node(cond)?;
+                self.branch(0x39, *target, *sub_target);
+            }
+            CILRoot::BEq {
+                target,
+                sub_target,
+                a,
+                b,
+            } => self.cond_branch(a, b, 0x3B, *target, *sub_target)?,
+            CILRoot::BGe {
+                target,
+                sub_target,
+                a,
+                b,
+            } => self.cond_branch(a, b, 0x3C, *target, *sub_target)?,
+            CILRoot::BGt {
+                target,
+                sub_target,
+                a,
+                b,
+            } => self.cond_branch(a, b, 0x3D, *target, *sub_target)?,
+            CILRoot::BLe {
+                target,
+                sub_target,
+                a,
+                b,
+            } => self.cond_branch(a, b, 0x3E, *target, *sub_target)?,
+            CILRoot::BLt {
+                target,
+                sub_target,
+                a,
+                b,
+            } => self.cond_branch(a, b, 0x3F, *target, *sub_target)?,
+            CILRoot::BNe {
+                target,
+                sub_target,
+                a,
+                b,
+            } => self.cond_branch(a, b, 0x40, *target, *sub_target)?,
+            CILRoot::BGtUn {
+                target,
+                sub_target,
+                a,
+                b,
+            } => self.cond_branch(a, b, 0x42, *target, *sub_target)?,
+            CILRoot::BLtUn {
+                target,
+                sub_target,
+                a,
+                b,
+            } => self.cond_branch(a, b, 0x44, *target, *sub_target)?,
+            CILRoot::GoTo { target, sub_target } => self.branch(0x38, *target, *sub_target),
+            CILRoot::Call { site, args } => {
+                if !site.is_nop() {
+                    for arg in args.iter() {
+                        self.node(arg)?;
+                    }
+                    self.call(0x28, site)?;
+                }
+            }
+            CILRoot::CallVirt { site, args } => {
+                for arg in args.iter() {
+                    self.node(arg)?;
+                }
+                self.call(0x6F, site)?;
+            }
+            CILRoot::SetField { addr, value, desc } => {
+                self.node(addr)?;
+                self.node(value)?;
+                let token = self.builder.field_token(desc)?;
+                self.op_token(0x7D, token);
+            }
+            CILRoot::CpBlk { dst, src, len } => {
+                self.node(dst)?;
+                self.node(src)?;
+                self.node(len)?;
+                self.op_fe(0x17);
+            }
+            CILRoot::InitBlk { dst, val, count } => {
+                self.node(dst)?;
+                self.node(val)?;
+                self.node(count)?;
+                self.op_fe(0x18);
+            }
+            CILRoot::STIndI8(a, b) => self.bi_op(a, b, 0x52)?,
+            CILRoot::STIndI16(a, b) => self.bi_op(a, b, 0x53)?,
+            CILRoot::STIndI32(a, b) => self.bi_op(a, b, 0x54)?,
+            CILRoot::STIndI64(a, b) => self.bi_op(a, b, 0x55)?,
+            CILRoot::STIndF32(a, b) => self.bi_op(a, b, 0x56)?,
+            CILRoot::STIndF64(a, b) => self.bi_op(a, b, 0x57)?,
+            CILRoot::STIndISize(a, b) => self.bi_op(a, b, 0xDF)?,
+            CILRoot::STObj {
+                tpe,
+                addr_calc,
+                value_calc,
+            } => {
+                self.node(addr_calc)?;
+                self.node(value_calc)?;
+                self.type_op(0x81, tpe)?;
+            }
+            CILRoot::STArg { arg, tree } => {
+                self.node(tree)?;
+                match arg {
+                    0..=255 => self.op_u8(0x10, *arg as u8),
+                    _ => self.op_u16(0x0B, *arg as u16),
+                }
+            }
+            CILRoot::Break => self.op(0x01),
+            CILRoot::Nop => self.op(0x00),
+            CILRoot::Ret { tree } => {
+                self.node(tree)?;
+                self.op(0x2A);
+            }
+            CILRoot::Pop { tree } => {
+                self.node(tree)?;
+                self.op(0x26);
+            }
+            CILRoot::VoidRet => self.op(0x2A),
+            CILRoot::Throw(tree) => {
+                self.node(tree)?;
+                self.op(0x7A);
+            }
+            CILRoot::ReThrow => self.op_fe(0x1A),
+            // Stored when entering the handler, see `Self::block`.
+            CILRoot::StoreCaught { .. } => (),
+            CILRoot::CallI { sig, fn_ptr, args } => {
+                for arg in args.iter() {
+                    self.node(arg)?;
+                }
+                self.node(fn_ptr)?;
+                self.call(
+                    0x28,
+                    &CallSite::builtin(
+                        "check_calli_nonull".into(),
+                        FnSig::new(&[Type::USize], Type::USize),
+                        true,
+                    ),
+                )?;
+                self.calli(sig)?;
+            }
+            CILRoot::JumpingPad { source, target } => {
+                self.label(*source, *target);
+                self.branch(0xDD, *target, 0);
+            }
+            CILRoot::SetStaticField { descr, value } => {
+                self.node(value)?;
+                let token = self.builder.static_field_token(descr)?;
+                self.op_token(0x80, token);
+            }
+            CILRoot::SourceFileInfo(sfi) => {
+                let offset = self.code.len() as u32;
+                // No code was emitted since the last location, so it is superseded by this one.
+                if self
+                    .sequence_points
+                    .last()
+                    .is_some_and(|(last, _)| *last == offset)
+                {
+                    self.sequence_points.pop();
+                }
+                self.sequence_points.push((offset, sfi.clone()));
+            }
+            CILRoot::SetTMPLocal { .. } => {
+                return Err(encode_error(format!(
+                    "Unresolved temporary local at the export stage: {root:?}"
+                )))
+            }
+        }
+        Ok(())
+    }
+    fn block(&mut self, block: &BasicBlock) -> Result<(), AssemblyExportError> {
+        let try_start = self.code.len() as u32;
+        self.label(block.id(), 0);
+        for tree in block.trees() {
+            self.root(tree.root())?;
+        }
+        let Some(handler) = block.handler() else {
+            return Ok(());
+        };
+        let Some(handler) = handler.as_blocks() else {
+            return Err(encode_error(format!(
+                "Unresolved exception handler of block {}",
+                block.id()
+            )));
+        };
+        let try_end = self.code.len() as u32;
+        match caught_local(handler) {
+            Some(local) => self.stloc(local),
+            // The exception object is not used.
+            None => self.op(0x26),
+        }
+        for handler_block in handler {
+            self.label(block.id(), handler_block.id());
+            for tree in handler_block.trees() {
+                self.root(tree.root())?;
+            }
+        }
+        self.clauses.push(Clause {
+            try_start,
+            try_end,
+            handler_start: try_end,
+            handler_end: self.code.len() as u32,
+        });
+        Ok(())
+    }
+    fn resolve_labels(&mut self) -> Result<(), AssemblyExportError> {
+        for (pos, label) in &self.fixups {
+            let Some(target) = self.labels.get(label) else {
+                return Err(encode_error(format!(
+                    "Branch to undefined label bb_{}_{}",
+                    label.0, label.1
+                )));
+            };
+            let offset = i64::from(*target) - (*pos as i64 + 4);
+            self.code[*pos..*pos + 4].copy_from_slice(&(offset as i32).to_le_bytes());
+        }
+        Ok(())
+    }
+}
+/// A method body, along with the info needed to describe it in a PDB.
+pub struct EncodedBody {
+    pub bytes: Vec<u8>,
+    /// Size of the CIL code, excluding the header and exception handling sections.
+    pub code_size: u32,
+    /// The `StandAloneSig` token of the locals, or 0 if the method has none.
+    pub locals_sig: u32,
+    pub sequence_points: Vec<(u32, SFI)>,
+}
+/// Encodes the body of `method`, using a fat header and optionally followed by an exception handling section.
+pub fn encode_body(
+    builder: &mut MetadataBuilder,
+    method: &Method,
+    init_locals: bool,
+) -> Result<EncodedBody, AssemblyExportError> {
+    let locals_sig = builder.locals_sig_token(method)?;
+    let mut encoder = BodyEncoder {
+        builder,
+        code: Vec::new(),
+        labels: HashMap::new(),
+        fixups: Vec::new(),
+        clauses: Vec::new(),
+        sequence_points: Vec::new(),
+    };
+    for block in method.blocks() {
+        encoder.block(block)?;
+    }
+    encoder.resolve_labels()?;
+    let BodyEncoder {
+        builder,
+        code,
+        clauses,
+        mut sequence_points,
+        ..
+    } = encoder;
+    let code_size = code.len() as u32;
+    // Locations after the last instruction describe no code.
+    sequence_points.retain(|(offset, _)| *offset < code_size);
+    let catch_type = Table::TypeRef.token(builder.type_ref("System.Runtime", "System.Object"));
+    let mut out = Vec::with_capacity(12 + code.len());
+    // CorILMethod_FatFormat, with a header size of 3 dwords.
+    let mut flags: u16 = 0x3 | (3 << 12);
+    if !clauses.is_empty() {
+        // CorILMethod_MoreSects
+        flags |= 0x8;
+    }
+    if init_locals {
+        // CorILMethod_InitLocals
+        flags |= 0x10;
+    }
+    out.extend(flags.to_le_bytes());
+    out.extend((method.maxstack().min(u16::MAX as usize) as u16).to_le_bytes());
+    out.extend((code.len() as u32).to_le_bytes());
+    out.extend(locals_sig.to_le_bytes());
+    out.extend(code);
+    let mut body = EncodedBody {
+        bytes: out,
+        code_size,
+        locals_sig,
+        sequence_points,
+    };
+    if clauses.is_empty() {
+        return Ok(body);
+    }
+    let out = &mut body.bytes;
+    while out.len() % 4 != 0 {
+        out.push(0);
+    }
+    // CorILMethod_Sect_EHTable | CorILMethod_Sect_FatFormat
+    out.push(0x01 | 0x40);
+    let data_size = clauses.len() as u32 * 24 + 4;
+    out.extend(&data_size.to_le_bytes()[..3]);
+    for clause in clauses {
+        // COR_ILEXCEPTION_CLAUSE_EXCEPTION
+        out.extend(0_u32.to_le_bytes());
+        out.extend(clause.try_start.to_le_bytes());
+        out.extend((clause.try_end - clause.try_start).to_le_bytes());
+        out.extend(clause.handler_start.to_le_bytes());
+        out.extend((clause.handler_end - clause.handler_start).to_le_bytes());
+        out.extend(catch_type.to_le_bytes());
+    }
+    Ok(body)
+}
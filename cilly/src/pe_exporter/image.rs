@@ -0,0 +1,249 @@
+//! Writes the final PE/COFF image(II.25) around the already encoded method bodies and metadata.
+
+const FILE_ALIGN: u32 = 0x200;
+const SECTION_ALIGN: u32 = 0x2000;
+const TEXT_RVA: u32 = 0x2000;
+/// Size of the import address table, placed at the very start of `.text`.
+const IAT_SIZE: u32 = 8;
+const CLI_HEADER_SIZE: u32 = 72;
+/// Offset of the method bodies within the `.text` section.
+pub const BODIES_OFFSET: u32 = IAT_SIZE + CLI_HEADER_SIZE;
+/// Returns the RVA a method body at offset `body_offset` within the method body blob will have.
+pub const fn body_rva(body_offset: u32) -> u32 {
+    TEXT_RVA + BODIES_OFFSET + body_offset
+}
+fn align(value: u32, alignment: u32) -> u32 {
+    value.div_ceil(alignment) * alignment
+}
+fn pad_to(out: &mut Vec<u8>, alignment: usize) {
+    while out.len() % alignment != 0 {
+        out.push(0);
+    }
+}
+fn push_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend(value.to_le_bytes());
+}
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend(value.to_le_bytes());
+}
+/// The standard MS-DOS header and stub program, with `e_lfanew` pointing right past it, at `0x80`.
+const DOS_HEADER: [u8; 0x80] = [
+    0x4d, 0x5a, 0x90, 0x00, 0x03, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00,
+    0xb8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x00, 0x00, 0x00,
+    0x0e, 0x1f, 0xba, 0x0e, 0x00, 0xb4, 0x09, 0xcd, 0x21, 0xb8, 0x01, 0x4c, 0xcd, 0x21, 0x54, 0x68,
+    0x69, 0x73, 0x20, 0x70, 0x72, 0x6f, 0x67, 0x72, 0x61, 0x6d, 0x20, 0x63, 0x61, 0x6e, 0x6e, 0x6f,
+    0x74, 0x20, 0x62, 0x65, 0x20, 0x72, 0x75, 0x6e, 0x20, 0x69, 0x6e, 0x20, 0x44, 0x4f, 0x53, 0x20,
+    0x6d, 0x6f, 0x64, 0x65, 0x2e, 0x0d, 0x0d, 0x0a, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+/// Size of a single entry of the debug directory.
+const DEBUG_DIR_SIZE: u32 = 28;
+/// Identifies the Portable PDB holding the debug info of an image.
+pub struct PdbInfo<'a> {
+    /// The GUID of the PDB, followed by its timestamp.
+    pub id: [u8; 20],
+    pub path: &'a str,
+}
+impl PdbInfo<'_> {
+    /// The CodeView debug directory entry data, pointing to the PDB.
+    fn codeview(&self) -> Vec<u8> {
+        let mut out = b"RSDS".to_vec();
+        out.extend(&self.id[..16]);
+        // Age
+        push_u32(&mut out, 1);
+        out.extend(self.path.as_bytes());
+        out.push(0);
+        out
+    }
+}
+/// Lays out a complete, ILONLY .NET PE image containing `bodies` and `metadata`, optionally referencing the PDB `pdb`.
+pub fn write_image(
+    bodies: &[u8],
+    metadata: &[u8],
+    entrypoint_token: u32,
+    is_dll: bool,
+    pdb: Option<&PdbInfo>,
+) -> Vec<u8> {
+    let image_base: u32 = if is_dll { 0x1000_0000 } else { 0x0040_0000 };
+    // Layout of `.text`
+    let metadata_offset = align(BODIES_OFFSET + bodies.len() as u32, 4);
+    let debug_dir_offset = align(metadata_offset + metadata.len() as u32, 4);
+    let codeview = pdb.map(PdbInfo::codeview);
+    let (debug_dir_size, codeview_size) = match &codeview {
+        Some(codeview) => (DEBUG_DIR_SIZE, codeview.len() as u32),
+        None => (0, 0),
+    };
+    let codeview_offset = debug_dir_offset + debug_dir_size;
+    let import_dir_offset = align(codeview_offset + codeview_size, 4);
+    let ilt_offset = import_dir_offset + 40;
+    let hint_name_offset = ilt_offset + 8;
+    let entry_name: &[u8] = if is_dll {
+        b"_CorDllMain\0"
+    } else {
+        b"_CorExeMain\0"
+    };
+    let dll_name_offset = hint_name_offset + 2 + entry_name.len() as u32;
+    let dll_name: &[u8] = b"mscoree.dll\0";
+    // The jump operand needs to be 4 byte aligned, so the stub starts at an offset of 2 mod 4.
+    let stub_offset = align(dll_name_offset + dll_name.len() as u32, 4) + 2;
+    let text_size = stub_offset + 6;
+    let text_raw_size = align(text_size, FILE_ALIGN);
+    let stub_operand_rva = TEXT_RVA + stub_offset + 2;
+    let reloc_rva = align(TEXT_RVA + text_size, SECTION_ALIGN);
+    let reloc_size: u32 = 12;
+    let reloc_raw_size = align(reloc_size, FILE_ALIGN);
+    let image_size = align(reloc_rva + reloc_size, SECTION_ALIGN);
+    // Headers
+    let mut out = DOS_HEADER.to_vec();
+    out.extend(b"PE\0\0");
+    // COFF file header
+    push_u16(&mut out, 0x14c);
+    push_u16(&mut out, 2);
+    push_u32(&mut out, 0);
+    push_u32(&mut out, 0);
+    push_u32(&mut out, 0);
+    push_u16(&mut out, 0xE0);
+    push_u16(&mut out, if is_dll { 0x2102 } else { 0x0102 });
+    // Optional header, standard fields
+    push_u16(&mut out, 0x10b);
+    out.extend([8, 0]);
+    push_u32(&mut out, text_raw_size);
+    push_u32(&mut out, reloc_raw_size);
+    push_u32(&mut out, 0);
+    push_u32(&mut out, TEXT_RVA + stub_offset);
+    push_u32(&mut out, TEXT_RVA);
+    push_u32(&mut out, reloc_rva);
+    // Optional header, NT specific fields
+    push_u32(&mut out, image_base);
+    push_u32(&mut out, SECTION_ALIGN);
+    push_u32(&mut out, FILE_ALIGN);
+    push_u16(&mut out, 4);
+    push_u16(&mut out, 0);
+    push_u16(&mut out, 0);
+    push_u16(&mut out, 0);
+    push_u16(&mut out, 4);
+    push_u16(&mut out, 0);
+    push_u32(&mut out, 0);
+    push_u32(&mut out, image_size);
+    push_u32(&mut out, FILE_ALIGN);
+    push_u32(&mut out, 0);
+    // Console subsystem
+    push_u16(&mut out, 3);
+    // DYNAMIC_BASE | NX_COMPAT | NO_SEH | TERMINAL_SERVER_AWARE
+    push_u16(&mut out, 0x8540);
+    push_u32(&mut out, 0x10_0000);
+    push_u32(&mut out, 0x1000);
+    push_u32(&mut out, 0x10_0000);
+    push_u32(&mut out, 0x1000);
+    push_u32(&mut out, 0);
+    push_u32(&mut out, 16);
+    // Data directories
+    for dir in 0..16 {
+        let (rva, size) = match dir {
+            1 => (TEXT_RVA + import_dir_offset, 40),
+            5 => (reloc_rva, reloc_size),
+            6 if pdb.is_some() => (TEXT_RVA + debug_dir_offset, debug_dir_size),
+            12 => (TEXT_RVA, IAT_SIZE),
+            14 => (TEXT_RVA + IAT_SIZE, CLI_HEADER_SIZE),
+            _ => (0, 0),
+        };
+        push_u32(&mut out, rva);
+        push_u32(&mut out, size);
+    }
+    // Section headers
+    let sections = [
+        (
+            b".text\0\0\0",
+            text_size,
+            TEXT_RVA,
+            text_raw_size,
+            FILE_ALIGN,
+            0x6000_0020,
+        ),
+        (
+            b".reloc\0\0",
+            reloc_size,
+            reloc_rva,
+            reloc_raw_size,
+            FILE_ALIGN + text_raw_size,
+            0x4200_0040,
+        ),
+    ];
+    for (name, virtual_size, rva, raw_size, raw_ptr, characteristics) in sections {
+        out.extend(name);
+        push_u32(&mut out, virtual_size);
+        push_u32(&mut out, rva);
+        push_u32(&mut out, raw_size);
+        push_u32(&mut out, raw_ptr);
+        push_u32(&mut out, 0);
+        push_u32(&mut out, 0);
+        push_u16(&mut out, 0);
+        push_u16(&mut out, 0);
+        push_u32(&mut out, characteristics);
+    }
+    out.resize(FILE_ALIGN as usize, 0);
+    // .text section
+    let text_start = out.len();
+    push_u32(&mut out, TEXT_RVA + hint_name_offset);
+    push_u32(&mut out, 0);
+    // CLI header
+    push_u32(&mut out, CLI_HEADER_SIZE);
+    push_u16(&mut out, 2);
+    push_u16(&mut out, 5);
+    push_u32(&mut out, TEXT_RVA + metadata_offset);
+    push_u32(&mut out, metadata.len() as u32);
+    // COMIMAGE_FLAGS_ILONLY
+    push_u32(&mut out, 1);
+    push_u32(&mut out, entrypoint_token);
+    // Resources, StrongNameSignature, CodeManagerTable, VTableFixups, ExportAddressTableJumps, ManagedNativeHeader
+    out.extend([0; 6 * 8]);
+    out.extend(bodies);
+    pad_to(&mut out, 4);
+    debug_assert_eq!((out.len() - text_start) as u32, metadata_offset);
+    out.extend(metadata);
+    pad_to(&mut out, 4);
+    debug_assert_eq!((out.len() - text_start) as u32, debug_dir_offset);
+    if let (Some(pdb), Some(codeview)) = (pdb, codeview) {
+        // IMAGE_DEBUG_DIRECTORY of a CodeView entry, pointing to a Portable PDB.
+        push_u32(&mut out, 0);
+        out.extend(&pdb.id[16..]);
+        push_u16(&mut out, 0x0100);
+        push_u16(&mut out, 0x504D);
+        push_u32(&mut out, 2);
+        push_u32(&mut out, codeview_size);
+        push_u32(&mut out, TEXT_RVA + codeview_offset);
+        push_u32(&mut out, FILE_ALIGN + codeview_offset);
+        out.extend(codeview);
+        pad_to(&mut out, 4);
+    }
+    debug_assert_eq!((out.len() - text_start) as u32, import_dir_offset);
+    // Import directory table, followed by a null entry.
+    push_u32(&mut out, TEXT_RVA + ilt_offset);
+    push_u32(&mut out, 0);
+    push_u32(&mut out, 0);
+    push_u32(&mut out, TEXT_RVA + dll_name_offset);
+    push_u32(&mut out, TEXT_RVA);
+    out.extend([0; 20]);
+    // Import lookup table
+    push_u32(&mut out, TEXT_RVA + hint_name_offset);
+    push_u32(&mut out, 0);
+    // Hint/Name table
+    push_u16(&mut out, 0);
+    out.extend(entry_name);
+    out.extend(dll_name);
+    while out.len() - text_start < stub_offset as usize {
+        out.push(0);
+    }
+    // jmp dword ptr [IAT]
+    out.extend([0xFF, 0x25]);
+    push_u32(&mut out, image_base + TEXT_RVA);
+    out.resize(text_start + text_raw_size as usize, 0);
+    // .reloc section
+    push_u32(&mut out, stub_operand_rva & !0xFFF);
+    push_u32(&mut out, reloc_size);
+    push_u16(&mut out, (3 << 12) | (stub_operand_rva & 0xFFF) as u16);
+    push_u16(&mut out, 0);
+    out.resize(text_start + (text_raw_size + reloc_raw_size) as usize, 0);
+    out
+}
@@ -0,0 +1,1282 @@
+//! An [`AssemblyExporter`] which encodes the assembly directly into a PE file, without going trough ILASM.
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+    io::Write,
+    path::Path,
+};
+
+use crate::{
+    access_modifier::AccessModifer,
+    asm::AssemblyExternRef,
+    asm_exporter::{AssemblyExportError, AssemblyExporter},
+    basic_block::BasicBlock,
+    call_site::CallSite,
+    cil_node::CILNode,
+    cil_root::CILRoot,
+    custom_attribute::{CustomAttribute, LayoutKind},
+    field_desc::FieldDescriptor,
+    method::{Method, MethodType},
+    static_field_desc::StaticFieldDescriptor,
+    type_def::TypeDef,
+    CallConv, DotnetTypeRef, FnSig, IString, Type,
+};
+
+use self::{
+    heaps::{compress_u32, BlobHeap, GuidHeap, StringHeap, UserStringHeap},
+    pdb::PdbBuilder,
+    tables::{CodedIndex, Column, Table, Tables},
+};
+
+mod heaps;
+mod il;
+mod image;
+mod pdb;
+mod tables;
+
+/// Name of the class holding all the global methods and statics. Must match the name used by the ILASM exporter.
+const MODULE_CLASS: &str = "RustModule";
+
+#[must_use]
+/// A struct used to export an assembly by directly encoding it as a PE file, with ECMA-335 metadata.
+pub struct PEExporter {
+    asm_name: IString,
+    init_locals: bool,
+    runtime_config: IString,
+    types: Vec<TypeDef>,
+    methods: Vec<Method>,
+    extern_methods: Vec<(IString, IString, FnSig, bool)>,
+    extern_refs: Vec<(IString, AssemblyExternRef)>,
+    /// Static fields, and whether they are thread-local.
+    globals: Vec<(IString, Type, bool)>,
+}
+impl Default for PEExporter {
+    fn default() -> Self {
+        Self::init(
+            "rust_mod",
+            crate::ilasm_exporter::always_init_locals(),
+            crate::ilasm_exporter::get_runtime_config(),
+        )
+    }
+}
+impl PEExporter {
+    pub fn init(asm_name: &str, init_locals: bool, runtime_config: &str) -> Self {
+        Self {
+            asm_name: asm_name.into(),
+            init_locals,
+            runtime_config: runtime_config.into(),
+            types: vec![],
+            methods: vec![],
+            extern_methods: vec![],
+            extern_refs: vec![],
+            globals: vec![],
+        }
+    }
+}
+impl AssemblyExporter for PEExporter {
+    fn add_type(&mut self, tpe: &TypeDef) {
+        self.types.push(tpe.clone());
+    }
+    fn add_method(&mut self, method: &Method) {
+        self.methods.push(method.clone());
+    }
+    fn add_extern_method(&mut self, lib_path: &str, name: &str, sig: &FnSig, preserve_errno: bool) {
+        self.extern_methods
+            .push((lib_path.into(), name.into(), sig.clone(), preserve_errno));
+    }
+    fn add_extern_ref(&mut self, asm_name: &str, info: &AssemblyExternRef) {
+        self.extern_refs.push((asm_name.into(), *info));
+    }
+    fn add_global(&mut self, tpe: &Type, name: &str) {
+        self.globals.push((name.into(), tpe.clone(), false));
+    }
+    fn add_thread_local(&mut self, tpe: &Type, name: &str) {
+        self.globals.push((name.into(), tpe.clone(), true));
+    }
+    fn finalize(self, final_path: &Path, is_dll: bool) -> Result<(), AssemblyExportError> {
+        std::fs::File::create(final_path.with_extension("runtimeconfig.json"))?
+            .write_all(self.runtime_config.as_bytes())?;
+        let pdb_path = final_path.with_extension("pdb");
+        let (image, pdb) = self.encode(is_dll, &pdb_path.to_string_lossy())?;
+        std::fs::File::create(final_path)?.write_all(&image)?;
+        std::fs::File::create(pdb_path)?.write_all(&pdb)?;
+        Ok(())
+    }
+}
+/// Builds the error returned when the assembly can't be encoded.
+fn encode_error(msg: impl Into<IString>) -> AssemblyExportError {
+    AssemblyExportError::ExporterError(msg.into())
+}
+/// Key used to look up the method definitions: owning class(`None` for the module class), name and signature.
+type MethodKey = (Option<IString>, IString, FnSig);
+/// Holds the metadata heaps and tables, and resolves references to types, methods and fields into tokens.
+pub(crate) struct MetadataBuilder {
+    strings: StringHeap,
+    blobs: BlobHeap,
+    user_strings: UserStringHeap,
+    guids: GuidHeap,
+    tables: Tables,
+    typedefs: HashMap<IString, u32>,
+    typerefs: HashMap<(IString, IString), u32>,
+    typespecs: HashMap<Vec<u8>, u32>,
+    asm_refs: HashMap<IString, u32>,
+    module_refs: HashMap<IString, u32>,
+    member_refs: HashMap<(u32, IString, Vec<u8>), u32>,
+    method_specs: HashMap<(u32, Vec<u8>), u32>,
+    standalone_sigs: HashMap<Vec<u8>, u32>,
+    method_defs: HashMap<MethodKey, u32>,
+    field_defs: HashMap<(u32, IString), u32>,
+}
+impl MetadataBuilder {
+    fn new() -> Self {
+        Self {
+            strings: StringHeap::new(),
+            blobs: BlobHeap::new(),
+            user_strings: UserStringHeap::new(),
+            guids: GuidHeap::new(),
+            tables: Tables::new(),
+            typedefs: HashMap::new(),
+            typerefs: HashMap::new(),
+            typespecs: HashMap::new(),
+            asm_refs: HashMap::new(),
+            module_refs: HashMap::new(),
+            member_refs: HashMap::new(),
+            method_specs: HashMap::new(),
+            standalone_sigs: HashMap::new(),
+            method_defs: HashMap::new(),
+            field_defs: HashMap::new(),
+        }
+    }
+    /// Adds a reference to assembly `name`, if not already present.
+    fn asm_ref(&mut self, name: &str, version: (u16, u16, u16, u16)) -> u32 {
+        if let Some(row) = self.asm_refs.get(name) {
+            return *row;
+        }
+        let name_idx = self.strings.add(name);
+        let row = self.tables.push(
+            Table::AssemblyRef,
+            vec![
+                Column::U16(version.0),
+                Column::U16(version.1),
+                Column::U16(version.2),
+                Column::U16(version.3),
+                Column::U32(0),
+                Column::Blob(0),
+                Column::String(name_idx),
+                Column::String(0),
+                Column::Blob(0),
+            ],
+        );
+        self.asm_refs.insert(name.into(), row);
+        row
+    }
+    fn module_ref(&mut self, name: &str) -> u32 {
+        if let Some(row) = self.module_refs.get(name) {
+            return *row;
+        }
+        let name_idx = self.strings.add(name);
+        let row = self
+            .tables
+            .push(Table::ModuleRef, vec![Column::String(name_idx)]);
+        self.module_refs.insert(name.into(), row);
+        row
+    }
+    /// Returns the row of a `TypeRef` pointing to `name_path` in assembly `asm`.
+    fn type_ref(&mut self, asm: &str, name_path: &str) -> u32 {
+        let key = (IString::from(asm), IString::from(name_path));
+        if let Some(row) = self.typerefs.get(&key) {
+            return *row;
+        }
+        let scope = self.asm_ref(asm, (0, 0, 0, 0));
+        let (namespace, name) = name_path.rsplit_once('.').unwrap_or(("", name_path));
+        let namespace = self.strings.add(namespace);
+        let name = self.strings.add(name);
+        let row = self.tables.push(
+            Table::TypeRef,
+            vec![
+                Column::Coded(
+                    CodedIndex::ResolutionScope,
+                    CodedIndex::ResolutionScope.encode(Table::AssemblyRef, scope),
+                ),
+                Column::String(name),
+                Column::String(namespace),
+            ],
+        );
+        self.typerefs.insert(key, row);
+        row
+    }
+    /// Returns the table and row a non-generic .NET type reference points to.
+    fn type_def_or_ref(
+        &mut self,
+        tref: &DotnetTypeRef,
+    ) -> Result<(Table, u32), AssemblyExportError> {
+        if !tref.generics().is_empty() {
+            let mut sig = vec![];
+            self.encode_type(&Type::DotnetType(Box::new(tref.clone())), &mut sig)?;
+            return Ok((Table::TypeSpec, self.type_spec(sig)));
+        }
+        match tref.asm() {
+            Some(asm) => Ok((Table::TypeRef, self.type_ref(asm, tref.name_path()))),
+            None => self
+                .typedefs
+                .get(tref.name_path())
+                .map(|row| (Table::TypeDef, *row))
+                .ok_or_else(|| {
+                    encode_error(format!(
+                        "Reference to undefined type {:?}",
+                        tref.name_path()
+                    ))
+                }),
+        }
+    }
+    fn type_spec(&mut self, sig: Vec<u8>) -> u32 {
+        if let Some(row) = self.typespecs.get(&sig) {
+            return *row;
+        }
+        let blob = self.blobs.add(&sig);
+        let row = self.tables.push(Table::TypeSpec, vec![Column::Blob(blob)]);
+        self.typespecs.insert(sig, row);
+        row
+    }
+    /// Encodes a reference to a local type named `name` as a valuetype.
+    fn encode_local_valuetype(
+        &mut self,
+        name: &str,
+        out: &mut Vec<u8>,
+    ) -> Result<(), AssemblyExportError> {
+        let tref = DotnetTypeRef::new::<&str, _>(None, name);
+        self.encode_type(&Type::DotnetType(Box::new(tref)), out)
+    }
+    /// Encodes `tpe` as a type signature(II.23.2.12), replacing `void` with `RustVoid`.
+    pub(crate) fn encode_non_void(
+        &mut self,
+        tpe: &Type,
+        out: &mut Vec<u8>,
+    ) -> Result<(), AssemblyExportError> {
+        match tpe {
+            Type::Void => self.encode_local_valuetype("RustVoid", out),
+            _ => self.encode_type(tpe, out),
+        }
+    }
+    /// Encodes `tpe` as a type signature(II.23.2.12).
+    pub(crate) fn encode_type(
+        &mut self,
+        tpe: &Type,
+        out: &mut Vec<u8>,
+    ) -> Result<(), AssemblyExportError> {
+        match tpe {
+            Type::Void => out.push(0x01),
+            Type::Bool => out.push(0x02),
+            Type::DotnetChar => out.push(0x03),
+            Type::I8 => out.push(0x04),
+            Type::U8 => out.push(0x05),
+            Type::I16 => out.push(0x06),
+            Type::U16 => out.push(0x07),
+            Type::I32 => out.push(0x08),
+            Type::U32 => out.push(0x09),
+            Type::I64 => out.push(0x0A),
+            Type::U64 => out.push(0x0B),
+            Type::F32 => out.push(0x0C),
+            Type::F64 => out.push(0x0D),
+            Type::ISize => out.push(0x18),
+            Type::USize => out.push(0x19),
+            Type::F16 => self.encode_type(&DotnetTypeRef::half().into(), out)?,
+            Type::I128 => self.encode_type(&DotnetTypeRef::int_128().into(), out)?,
+            Type::U128 | Type::F128 => self.encode_type(&DotnetTypeRef::uint_128().into(), out)?,
+            Type::Ptr(inner) => {
+                out.push(0x0F);
+                self.encode_type(inner, out)?;
+            }
+            Type::ManagedReference(inner) => {
+                out.push(0x10);
+                self.encode_type(inner, out)?;
+            }
+            Type::DotnetType(tref) => {
+                if tref.asm() == Some("System.Runtime") && !tref.is_valuetype() {
+                    match tref.name_path() {
+                        "System.String" => {
+                            out.push(0x0E);
+                            return Ok(());
+                        }
+                        "System.Object" => {
+                            out.push(0x1C);
+                            return Ok(());
+                        }
+                        _ => (),
+                    }
+                }
+                let kind = if tref.is_valuetype() { 0x11 } else { 0x12 };
+                if tref.generics().is_empty() {
+                    out.push(kind);
+                } else {
+                    out.extend([0x15, kind]);
+                }
+                let mut generic_less = tref.as_ref().clone();
+                generic_less.set_generics(vec![]);
+                let (table, row) = self.type_def_or_ref(&generic_less)?;
+                compress_u32(CodedIndex::TypeDefOrRef.encode(table, row), out);
+                if !tref.generics().is_empty() {
+                    compress_u32(tref.generics().len() as u32, out);
+                    for generic in tref.generics() {
+                        self.encode_type(generic, out)?;
+                    }
+                }
+            }
+            Type::Unresolved => self.encode_local_valuetype("Unresolved", out)?,
+            Type::Foreign => self.encode_local_valuetype("Foreign", out)?,
+            Type::FnDef(name) => self.encode_local_valuetype(&format!("fn_{name}"), out)?,
+            Type::GenericArg(idx) => {
+                out.push(0x13);
+                compress_u32(*idx, out);
+            }
+            Type::CallGenericArg(idx) => {
+                out.push(0x1E);
+                compress_u32(*idx, out);
+            }
+            Type::MethodGenericArg(idx) => {
+                out.push(0x1E);
+                compress_u32(*idx as u32, out);
+            }
+            Type::DelegatePtr(sig) => {
+                out.push(0x1B);
+                self.encode_ptr_sig(sig, out)?;
+            }
+            Type::ManagedArray { element, dims } => {
+                if dims.get() == 1 {
+                    out.push(0x1D);
+                    self.encode_type(element, out)?;
+                } else {
+                    out.push(0x14);
+                    self.encode_type(element, out)?;
+                    // Rank, no sizes, no lower bounds.
+                    compress_u32(u32::from(dims.get()), out);
+                    out.extend([0, 0]);
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Encodes a `MethodDefSig` / `MethodRefSig` (II.23.2.1). `inputs` must not contain the implicit `this`.
+    fn encode_method_sig(
+        &mut self,
+        inputs: &[Type],
+        output: &Type,
+        is_static: bool,
+        generic_count: u32,
+        out: &mut Vec<u8>,
+    ) -> Result<(), AssemblyExportError> {
+        let mut conv = if is_static { 0x00 } else { 0x20 };
+        if generic_count > 0 {
+            conv |= 0x10;
+        }
+        out.push(conv);
+        if generic_count > 0 {
+            compress_u32(generic_count, out);
+        }
+        compress_u32(inputs.len() as u32, out);
+        self.encode_type(output, out)?;
+        for input in inputs {
+            self.encode_non_void(input, out)?;
+        }
+        Ok(())
+    }
+    /// Encodes the signature of a function pointer, or of a `calli`.
+    fn encode_ptr_sig(
+        &mut self,
+        sig: &FnSig,
+        out: &mut Vec<u8>,
+    ) -> Result<(), AssemblyExportError> {
+        let start = out.len();
+        self.encode_method_sig(sig.inputs(), sig.output(), true, 0, out)?;
+        match sig.call_conv() {
+            CallConv::Managed => (),
+            // C
+            CallConv::C => out[start] = 0x01,
+        }
+        Ok(())
+    }
+    fn method_def_sig(&mut self, method: &Method) -> Result<u32, AssemblyExportError> {
+        let mut sig = vec![];
+        self.encode_method_sig(
+            method.explicit_inputs(),
+            method.sig().output(),
+            method.is_static(),
+            0,
+            &mut sig,
+        )?;
+        Ok(self.blobs.add(&sig))
+    }
+    fn member_ref(&mut self, parent: u32, name: &str, sig: Vec<u8>) -> u32 {
+        let key = (parent, IString::from(name), sig);
+        if let Some(row) = self.member_refs.get(&key) {
+            return *row;
+        }
+        let name_idx = self.strings.add(name);
+        let blob = self.blobs.add(&key.2);
+        let row = self.tables.push(
+            Table::MemberRef,
+            vec![
+                Column::Coded(CodedIndex::MemberRefParent, parent),
+                Column::String(name_idx),
+                Column::Blob(blob),
+            ],
+        );
+        self.member_refs.insert(key, row);
+        row
+    }
+    fn member_ref_parent(&mut self, owner: &DotnetTypeRef) -> Result<u32, AssemblyExportError> {
+        let (table, row) = self.type_def_or_ref(owner)?;
+        Ok(CodedIndex::MemberRefParent.encode(table, row))
+    }
+    /// Returns the token of the method `site` points to.
+    pub(crate) fn method_token(&mut self, site: &CallSite) -> Result<u32, AssemblyExportError> {
+        let key: MethodKey = (
+            site.class()
+                .filter(|class| class.asm().is_none())
+                .map(|class| class.name_path().into()),
+            site.name().into(),
+            site.signature().clone(),
+        );
+        let local_def = if site.class().map_or(true, |class| class.asm().is_none()) {
+            self.method_defs.get(&key).copied()
+        } else {
+            None
+        };
+        let (table, row) = if let Some(row) = local_def {
+            (Table::MethodDef, row)
+        } else {
+            let Some(class) = site.class() else {
+                return Err(encode_error(format!(
+                    "Call to undefined method {name:?} with signature {sig:?}",
+                    name = site.name(),
+                    sig = site.signature()
+                )));
+            };
+            let mut sig = vec![];
+            // References to generic methods use the generic definition signature.
+            self.encode_method_sig(
+                site.explicit_inputs(),
+                site.signature().output(),
+                site.is_static(),
+                site.generics().len() as u32,
+                &mut sig,
+            )?;
+            let parent = self.member_ref_parent(class)?;
+            (Table::MemberRef, self.member_ref(parent, site.name(), sig))
+        };
+        if site.generics().is_empty() {
+            return Ok(table.token(row));
+        }
+        let mut instantiation = vec![0x0A];
+        compress_u32(site.generics().len() as u32, &mut instantiation);
+        for generic in site.generics() {
+            self.encode_type(generic, &mut instantiation)?;
+        }
+        let method = CodedIndex::MethodDefOrRef.encode(table, row);
+        let key = (method, instantiation);
+        if let Some(row) = self.method_specs.get(&key) {
+            return Ok(Table::MethodSpec.token(*row));
+        }
+        let blob = self.blobs.add(&key.1);
+        let row = self.tables.push(
+            Table::MethodSpec,
+            vec![
+                Column::Coded(CodedIndex::MethodDefOrRef, method),
+                Column::Blob(blob),
+            ],
+        );
+        self.method_specs.insert(key, row);
+        Ok(Table::MethodSpec.token(row))
+    }
+    fn field_sig(&mut self, tpe: &Type) -> Result<Vec<u8>, AssemblyExportError> {
+        let mut sig = vec![0x06];
+        self.encode_non_void(tpe, &mut sig)?;
+        Ok(sig)
+    }
+    /// Returns the token of the instance field `desc` points to.
+    pub(crate) fn field_token(
+        &mut self,
+        desc: &FieldDescriptor,
+    ) -> Result<u32, AssemblyExportError> {
+        let (table, owner) = self.type_def_or_ref(desc.owner())?;
+        if table == Table::TypeDef {
+            if let Some(row) = self.field_defs.get(&(owner, desc.name().into())) {
+                return Ok(Table::Field.token(*row));
+            }
+        }
+        let sig = self.field_sig(desc.tpe())?;
+        let parent = CodedIndex::MemberRefParent.encode(table, owner);
+        Ok(Table::MemberRef.token(self.member_ref(parent, desc.name(), sig)))
+    }
+    /// Returns the token of the static field `desc` points to.
+    pub(crate) fn static_field_token(
+        &mut self,
+        desc: &StaticFieldDescriptor,
+    ) -> Result<u32, AssemblyExportError> {
+        let (table, owner) = match desc.owner() {
+            Some(owner) => self.type_def_or_ref(owner)?,
+            None => (Table::TypeDef, self.typedefs[MODULE_CLASS]),
+        };
+        if table == Table::TypeDef {
+            if let Some(row) = self.field_defs.get(&(owner, desc.name().into())) {
+                return Ok(Table::Field.token(*row));
+            }
+        }
+        let sig = self.field_sig(desc.tpe())?;
+        let parent = CodedIndex::MemberRefParent.encode(table, owner);
+        Ok(Table::MemberRef.token(self.member_ref(parent, desc.name(), sig)))
+    }
+    /// Returns a `TypeDef`, `TypeRef` or `TypeSpec` token representing `tpe`.
+    pub(crate) fn type_token(&mut self, tpe: &Type) -> Result<u32, AssemblyExportError> {
+        if let Type::DotnetType(tref) = tpe {
+            let is_special = tref.asm() == Some("System.Runtime")
+                && !tref.is_valuetype()
+                && matches!(tref.name_path(), "System.String" | "System.Object");
+            if !is_special {
+                let (table, row) = self.type_def_or_ref(tref)?;
+                return Ok(table.token(row));
+            }
+        }
+        let mut sig = vec![];
+        self.encode_non_void(tpe, &mut sig)?;
+        Ok(Table::TypeSpec.token(self.type_spec(sig)))
+    }
+    /// Returns the `ldstr` token of `string`.
+    pub(crate) fn user_string_token(&mut self, string: &str) -> u32 {
+        0x7000_0000 | self.user_strings.add(string)
+    }
+    /// Returns a `StandAloneSig` token for a `calli` with signature `sig`.
+    pub(crate) fn calli_sig_token(&mut self, sig: &FnSig) -> Result<u32, AssemblyExportError> {
+        let mut blob = vec![];
+        self.encode_ptr_sig(sig, &mut blob)?;
+        Ok(Table::StandAloneSig.token(self.standalone_sig(blob)))
+    }
+    /// Returns the `StandAloneSig` token describing the locals of `method`, or 0 if it has none.
+    pub(crate) fn locals_sig_token(&mut self, method: &Method) -> Result<u32, AssemblyExportError> {
+        if method.locals().is_empty() {
+            return Ok(0);
+        }
+        let mut blob = vec![0x07];
+        compress_u32(method.locals().len() as u32, &mut blob);
+        for (_, local) in method.locals() {
+            self.encode_non_void(local, &mut blob)?;
+        }
+        Ok(Table::StandAloneSig.token(self.standalone_sig(blob)))
+    }
+    fn standalone_sig(&mut self, sig: Vec<u8>) -> u32 {
+        if let Some(row) = self.standalone_sigs.get(&sig) {
+            return *row;
+        }
+        let blob = self.blobs.add(&sig);
+        let row = self
+            .tables
+            .push(Table::StandAloneSig, vec![Column::Blob(blob)]);
+        self.standalone_sigs.insert(sig, row);
+        row
+    }
+    /// Adds a method definition to the type which is currently being defined, returning its row.
+    fn define_method(
+        &mut self,
+        owner: Option<&str>,
+        method: &Method,
+    ) -> Result<u32, AssemblyExportError> {
+        let mut flags: u16 = match method.access() {
+            AccessModifer::Private => 0x0001,
+            AccessModifer::Public | AccessModifer::MoudlePublic => 0x0006,
+        };
+        // hidebysig
+        flags |= 0x0080;
+        match method.method_type() {
+            MethodType::Static => flags |= 0x0010,
+            MethodType::Virtual => flags |= 0x0040,
+            MethodType::Instance => (),
+        }
+        if matches!(method.name(), ".ctor" | ".cctor") {
+            // specialname rtspecialname
+            flags |= 0x0800 | 0x1000;
+        }
+        let name = self.strings.add(method.name());
+        let sig = self.method_def_sig(method)?;
+        let param_list = self.tables.len(Table::Param) + 1;
+        // Only emit parameter names when they match the inputs, just like the ILASM exporter.
+        if method.arg_names().len() == method.explicit_inputs().len() {
+            for (idx, name) in method.arg_names().iter().enumerate() {
+                let Some(name) = name else {
+                    continue;
+                };
+                let name = self.strings.add(name);
+                self.tables.push(
+                    Table::Param,
+                    vec![
+                        Column::U16(0),
+                        Column::U16(idx as u16 + 1),
+                        Column::String(name),
+                    ],
+                );
+            }
+        }
+        let row = self.tables.push(
+            Table::MethodDef,
+            vec![
+                // The RVA gets patched in once the method body is laid out.
+                Column::U32(0),
+                Column::U16(method.method_impl_options() as u16),
+                Column::U16(flags),
+                Column::String(name),
+                Column::Blob(sig),
+                Column::Index(Table::Param, param_list),
+            ],
+        );
+        self.method_defs.insert(
+            (
+                owner.map(Into::into),
+                method.name().into(),
+                method.sig().clone(),
+            ),
+            row,
+        );
+        for attr in method.custom_attributes().filter(|attr| !attr.is_pseudo()) {
+            self.custom_attribute(
+                CodedIndex::HasCustomAttribute.encode(Table::MethodDef, row),
+                attr,
+            )?;
+        }
+        Ok(row)
+    }
+    /// Adds a P/Invoke method definition.
+    fn define_extern_method(
+        &mut self,
+        lib: &str,
+        name: &str,
+        sig: &FnSig,
+        preserve_errno: bool,
+    ) -> Result<u32, AssemblyExportError> {
+        let name_idx = self.strings.add(name);
+        let mut blob = vec![];
+        self.encode_method_sig(sig.inputs(), sig.output(), true, 0, &mut blob)?;
+        let blob = self.blobs.add(&blob);
+        let param_list = self.tables.len(Table::Param) + 1;
+        let row = self.tables.push(
+            Table::MethodDef,
+            vec![
+                Column::U32(0),
+                // preservesig
+                Column::U16(0x0080),
+                // private hidebysig static pinvokeimpl
+                Column::U16(0x0001 | 0x0080 | 0x0010 | 0x2000),
+                Column::String(name_idx),
+                Column::Blob(blob),
+                Column::Index(Table::Param, param_list),
+            ],
+        );
+        let scope = self.module_ref(lib);
+        // cdecl, and `lasterr` if requested.
+        let mapping_flags: u16 = 0x0200 | if preserve_errno { 0x0040 } else { 0 };
+        self.tables.push(
+            Table::ImplMap,
+            vec![
+                Column::U16(mapping_flags),
+                Column::Coded(
+                    CodedIndex::MemberForwarded,
+                    CodedIndex::MemberForwarded.encode(Table::MethodDef, row),
+                ),
+                Column::String(name_idx),
+                Column::Index(Table::ModuleRef, scope),
+            ],
+        );
+        self.method_defs
+            .insert((None, name.into(), sig.clone()), row);
+        Ok(row)
+    }
+    fn define_field(
+        &mut self,
+        owner: u32,
+        name: &str,
+        tpe: &Type,
+        flags: u16,
+    ) -> Result<u32, AssemblyExportError> {
+        let name_idx = self.strings.add(name);
+        let sig = self.field_sig(tpe)?;
+        let sig = self.blobs.add(&sig);
+        let row = self.tables.push(
+            Table::Field,
+            vec![
+                Column::U16(flags),
+                Column::String(name_idx),
+                Column::Blob(sig),
+            ],
+        );
+        self.field_defs.insert((owner, name.into()), row);
+        Ok(row)
+    }
+    /// Applies `attr` to `parent`, which is a `HasCustomAttribute` coded index.
+    fn custom_attribute(
+        &mut self,
+        parent: u32,
+        attr: &CustomAttribute,
+    ) -> Result<(), AssemblyExportError> {
+        let token = self.method_token(&attr.ctor())?;
+        let ctor_table = if token >> 24 == Table::MethodDef as u32 {
+            Table::MethodDef
+        } else {
+            Table::MemberRef
+        };
+        let value = self.blobs.add(&attr.blob());
+        self.tables.push(
+            Table::CustomAttribute,
+            vec![
+                Column::Coded(CodedIndex::HasCustomAttribute, parent),
+                Column::Coded(
+                    CodedIndex::CustomAttributeType,
+                    CodedIndex::CustomAttributeType.encode(ctor_table, token & 0x00FF_FFFF),
+                ),
+                Column::Blob(value),
+            ],
+        );
+        Ok(())
+    }
+    /// Serializes the metadata root and all the streams(II.24.2).
+    fn serialize_metadata(mut self) -> Vec<u8> {
+        let tables = self.tables.serialize(
+            self.strings.data().len() >= 0x1_0000,
+            self.guids.data().len() / 16 >= 0x1_0000,
+            self.blobs.data().len() >= 0x1_0000,
+        );
+        let streams: [(&[u8], &[u8]); 5] = [
+            (b"#~\0", &tables),
+            (b"#Strings\0", self.strings.data()),
+            (b"#US\0", self.user_strings.data()),
+            (b"#GUID\0", self.guids.data()),
+            (b"#Blob\0", self.blobs.data()),
+        ];
+        metadata_root(b"v4.0.30319\0\0", &streams).0
+    }
+}
+/// Serializes a metadata root(II.24.2.1), followed by `streams`. `version` must be null-padded to a multiple of 4 bytes.
+/// Returns the metadata and the offset of the first stream within it.
+fn metadata_root(version: &[u8], streams: &[(&[u8], &[u8])]) -> (Vec<u8>, usize) {
+    let header_size = 16
+        + version.len()
+        + 4
+        + streams
+            .iter()
+            .map(|(name, _)| 8 + name.len().div_ceil(4) * 4)
+            .sum::<usize>();
+    let mut out = Vec::new();
+    out.extend(0x424A_5342_u32.to_le_bytes());
+    out.extend(1_u16.to_le_bytes());
+    out.extend(1_u16.to_le_bytes());
+    out.extend(0_u32.to_le_bytes());
+    out.extend((version.len() as u32).to_le_bytes());
+    out.extend(version);
+    out.extend(0_u16.to_le_bytes());
+    out.extend((streams.len() as u16).to_le_bytes());
+    let mut offset = header_size;
+    for (name, data) in streams {
+        let size = data.len().div_ceil(4) * 4;
+        out.extend((offset as u32).to_le_bytes());
+        out.extend((size as u32).to_le_bytes());
+        out.extend(*name);
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+        offset += size;
+    }
+    debug_assert_eq!(out.len(), header_size);
+    for (_, data) in streams {
+        out.extend(*data);
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+    }
+    (out, header_size)
+}
+/// Returns a method which checks that a function pointer is not null before a `calli`. Mirrors the helper emitted by the ILASM exporter.
+fn check_calli_nonull() -> Method {
+    Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[Type::USize], Type::USize),
+        "check_calli_nonull",
+        vec![],
+        vec![
+            BasicBlock::new(
+                vec![
+                    CILRoot::BFalse {
+                        target: 1,
+                        sub_target: 0,
+                        cond: CILNode::LDArg(0),
+                    }
+                    .into(),
+                    CILRoot::Ret {
+                        tree: CILNode::LDArg(0),
+                    }
+                    .into(),
+                ],
+                0,
+                None,
+            ),
+            BasicBlock::new(
+                vec![CILRoot::Throw(CILNode::NewObj {
+                    site: CallSite::boxed(
+                        Some(
+                            DotnetTypeRef::new(
+                                Some("System.Runtime"),
+                                "System.NullReferenceException",
+                            )
+                            .with_valuetype(false),
+                        ),
+                        ".ctor".into(),
+                        FnSig::new(
+                            &[Type::DotnetType(Box::new(
+                                DotnetTypeRef::new(
+                                    Some("System.Runtime"),
+                                    "System.NullReferenceException",
+                                )
+                                .with_valuetype(false),
+                            ))],
+                            Type::Void,
+                        ),
+                        false,
+                    ),
+                    args: [].into(),
+                })
+                .into()],
+                1,
+                None,
+            ),
+        ],
+        vec![],
+    )
+}
+/// Flattens `tpe` and all its nested types, in the order they will be defined in. Nested types are refered to by their full path, like `Outer/Inner`.
+fn flatten_types<'a>(
+    tpe: &'a TypeDef,
+    enclosing: Option<(usize, &str)>,
+    out: &mut Vec<(&'a TypeDef, Option<usize>, String)>,
+) {
+    let idx = out.len();
+    let path = match enclosing {
+        Some((_, enclosing_path)) => format!("{enclosing_path}/{name}", name = tpe.name()),
+        None => tpe.name().to_owned(),
+    };
+    out.push((tpe, enclosing.map(|(idx, _)| idx), path.clone()));
+    for inner in tpe.inner_types() {
+        flatten_types(inner, Some((idx, &path)), out);
+    }
+}
+impl PEExporter {
+    /// Encodes the whole assembly as a PE image, and its debug info as a Portable PDB, which is expected to be saved at `pdb_path`.
+    fn encode(
+        self,
+        is_dll: bool,
+        pdb_path: &str,
+    ) -> Result<(Vec<u8>, Vec<u8>), AssemblyExportError> {
+        let mut builder = MetadataBuilder::new();
+        for (name, info) in &self.extern_refs {
+            let (v1, v2, v3, v4) = info.version();
+            builder.asm_ref(name, (v1, v2, v3, v4));
+        }
+        let mut types = vec![];
+        let mut sorted_types: Vec<_> = self.types.iter().collect();
+        // Sort types, to make the output deterministic.
+        sorted_types.sort_by(|a, b| a.name().cmp(b.name()));
+        for tpe in sorted_types {
+            flatten_types(tpe, None, &mut types);
+        }
+        // Row 1 is `<Module>`, row 2 the class holding all the global methods.
+        builder.typedefs.insert(MODULE_CLASS.into(), 2);
+        for (idx, (_, _, path)) in types.iter().enumerate() {
+            builder
+                .typedefs
+                .insert(path.as_str().into(), idx as u32 + 3);
+        }
+        let module_name = builder.strings.add("<Module>");
+        builder.tables.push(
+            Table::TypeDef,
+            vec![
+                Column::U32(0),
+                Column::String(module_name),
+                Column::String(0),
+                Column::Coded(CodedIndex::TypeDefOrRef, 0),
+                Column::Index(Table::Field, 1),
+                Column::Index(Table::MethodDef, 1),
+            ],
+        );
+        // The module class
+        let mut bodies: Vec<(u32, &Method)> = vec![];
+        let field_list = builder.tables.len(Table::Field) + 1;
+        let method_list = builder.tables.len(Table::MethodDef) + 1;
+        let mut globals: Vec<_> = self.globals.iter().collect();
+        globals.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, tpe, thread_local) in globals {
+            // public static
+            let field = builder.define_field(2, name, tpe, 0x0006 | 0x0010)?;
+            if *thread_local {
+                builder.custom_attribute(
+                    CodedIndex::HasCustomAttribute.encode(Table::Field, field),
+                    &CustomAttribute::thread_static(),
+                )?;
+            }
+        }
+        let helper = check_calli_nonull();
+        let mut methods: Vec<_> = self
+            .methods
+            .iter()
+            .chain(std::iter::once(&helper))
+            .collect();
+        methods.sort_by(|a, b| a.name().cmp(b.name()));
+        for method in methods {
+            let row = builder.define_method(None, method)?;
+            bodies.push((row, method));
+        }
+        for (lib, name, sig, preserve_errno) in &self.extern_methods {
+            builder.define_extern_method(lib, name, sig, *preserve_errno)?;
+        }
+        let object = builder.type_ref("System.Runtime", "System.Object");
+        let module_class = builder.strings.add(MODULE_CLASS);
+        builder.tables.push(
+            Table::TypeDef,
+            vec![
+                // beforefieldinit
+                Column::U32(0x0010_0000),
+                Column::String(module_class),
+                Column::String(0),
+                Column::Coded(
+                    CodedIndex::TypeDefOrRef,
+                    CodedIndex::TypeDefOrRef.encode(Table::TypeRef, object),
+                ),
+                Column::Index(Table::Field, field_list),
+                Column::Index(Table::MethodDef, method_list),
+            ],
+        );
+        // User-defined types
+        let value_type = builder.type_ref("System.Runtime", "System.ValueType");
+        for (idx, (tpe, enclosing, path)) in types.iter().enumerate() {
+            let row = idx as u32 + 3;
+            let field_list = builder.tables.len(Table::Field) + 1;
+            let method_list = builder.tables.len(Table::MethodDef) + 1;
+            if tpe.gargc() != 0 {
+                return Err(encode_error(format!(
+                    "Generic typedefs are not supported yet. tpe:{}",
+                    tpe.name()
+                )));
+            }
+            for (field_idx, (name, field_tpe)) in tpe.fields().iter().enumerate() {
+                let field = builder.define_field(row, name, field_tpe, 0x0006)?;
+                if let Some(offsets) = tpe.explicit_offsets() {
+                    builder.tables.push(
+                        Table::FieldLayout,
+                        vec![
+                            Column::U32(offsets[field_idx]),
+                            Column::Index(Table::Field, field),
+                        ],
+                    );
+                }
+            }
+            for method in tpe.methods() {
+                let method_row = builder.define_method(Some(path), method)?;
+                bodies.push((method_row, method));
+            }
+            let mut flags: u32 = match (enclosing, tpe.access_modifier()) {
+                (None, AccessModifer::Public) => 0x1,
+                (None, _) => 0x0,
+                (Some(_), AccessModifer::Public) => 0x2,
+                (Some(_), _) => 0x3,
+            };
+            match tpe.layout_kind() {
+                Some(LayoutKind::Sequential) => flags |= 0x8,
+                Some(LayoutKind::Explicit) => flags |= 0x10,
+                Some(LayoutKind::Auto) => (),
+                None if tpe.explicit_offsets().is_some() || tpe.explict_size().is_some() => {
+                    flags |= 0x10;
+                }
+                None => (),
+            }
+            if tpe.explicit_offsets().is_some() || tpe.extends().is_none() {
+                flags |= 0x100;
+            }
+            let extends = match tpe.extends() {
+                Some(extends) => {
+                    let (table, row) = builder.type_def_or_ref(extends)?;
+                    CodedIndex::TypeDefOrRef.encode(table, row)
+                }
+                None => CodedIndex::TypeDefOrRef.encode(Table::TypeRef, value_type),
+            };
+            let name = builder.strings.add(tpe.name());
+            let pushed = builder.tables.push(
+                Table::TypeDef,
+                vec![
+                    Column::U32(flags),
+                    Column::String(name),
+                    Column::String(0),
+                    Column::Coded(CodedIndex::TypeDefOrRef, extends),
+                    Column::Index(Table::Field, field_list),
+                    Column::Index(Table::MethodDef, method_list),
+                ],
+            );
+            debug_assert_eq!(pushed, row);
+            for attr in tpe
+                .custom_attributes()
+                .iter()
+                .filter(|attr| !attr.is_pseudo())
+            {
+                builder.custom_attribute(
+                    CodedIndex::HasCustomAttribute.encode(Table::TypeDef, row),
+                    attr,
+                )?;
+            }
+            if let Some(size) = tpe.explict_size() {
+                let size = u32::try_from(size.get())
+                    .map_err(|_| encode_error(format!("Type {} is too big", tpe.name())))?;
+                builder.tables.push(
+                    Table::ClassLayout,
+                    vec![
+                        Column::U16(0),
+                        Column::U32(size),
+                        Column::Index(Table::TypeDef, row),
+                    ],
+                );
+            }
+            if let Some(enclosing) = enclosing {
+                builder.tables.push(
+                    Table::NestedClass,
+                    vec![
+                        Column::Index(Table::TypeDef, row),
+                        Column::Index(Table::TypeDef, *enclosing as u32 + 3),
+                    ],
+                );
+            }
+        }
+        // Method bodies
+        let mut body_blob = Vec::new();
+        let mut entrypoint = 0;
+        let mut pdb = PdbBuilder::new();
+        for (row, method) in bodies {
+            if method.is_entrypoint() {
+                entrypoint = Table::MethodDef.token(row);
+            }
+            while body_blob.len() % 4 != 0 {
+                body_blob.push(0);
+            }
+            let rva = image::body_rva(body_blob.len() as u32);
+            *builder.tables.cell_mut(Table::MethodDef, row, 0) = Column::U32(rva);
+            let body = il::encode_body(&mut builder, method, self.init_locals)?;
+            pdb.add_method(row, method, &body);
+            body_blob.extend(body.bytes);
+        }
+        // Module & assembly
+        let asm_name = builder.strings.add(&self.asm_name);
+        let mut hasher = std::hash::DefaultHasher::new();
+        self.asm_name.hash(&mut hasher);
+        body_blob.hash(&mut hasher);
+        let hash_a = hasher.finish();
+        builder.tables.len(Table::MemberRef).hash(&mut hasher);
+        let hash_b = hasher.finish();
+        let mut mvid = [0; 16];
+        mvid[..8].copy_from_slice(&hash_a.to_le_bytes());
+        mvid[8..].copy_from_slice(&hash_b.to_le_bytes());
+        let mvid = builder.guids.add(mvid);
+        builder.tables.push(
+            Table::Module,
+            vec![
+                Column::U16(0),
+                Column::String(asm_name),
+                Column::Guid(mvid),
+                Column::Guid(0),
+                Column::Guid(0),
+            ],
+        );
+        builder.tables.push(
+            Table::Assembly,
+            vec![
+                // SHA1
+                Column::U32(0x8004),
+                Column::U16(0),
+                Column::U16(0),
+                Column::U16(0),
+                Column::U16(0),
+                Column::U32(0),
+                Column::Blob(0),
+                Column::String(asm_name),
+                Column::String(0),
+            ],
+        );
+        let type_system_rows: Vec<_> = Table::ALL
+            .into_iter()
+            .filter(|table| table.is_type_system())
+            .map(|table| (table, builder.tables.len(table)))
+            .collect();
+        let metadata = builder.serialize_metadata();
+        let (pdb_id, pdb) = pdb.serialize(&type_system_rows, entrypoint);
+        let pdb_info = image::PdbInfo {
+            id: pdb_id,
+            path: pdb_path,
+        };
+        let image = image::write_image(&body_blob, &metadata, entrypoint, is_dll, Some(&pdb_info));
+        Ok((image, pdb))
+    }
+}
+#[test]
+fn export_minimal() {
+    use crate::asm::Assembly;
+    let mut asm = Assembly::empty();
+    asm.add_typedef(TypeDef::nameonly("RustVoid"));
+    let main = Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[], Type::Void),
+        "main",
+        vec![(Some("local".into()), Type::I32)],
+        vec![BasicBlock::new(
+            vec![
+                CILRoot::source_info("/src/main.rs", 2..2, 5..20).into(),
+                CILRoot::STLoc {
+                    local: 0,
+                    tree: CILNode::Add(Box::new(CILNode::LdcI32(2)), Box::new(CILNode::LdcI32(2))),
+                }
+                .into(),
+                CILRoot::Call {
+                    site: CallSite::new_extern(
+                        DotnetTypeRef::console(),
+                        "WriteLine".into(),
+                        FnSig::new(&[Type::I32], Type::Void),
+                        true,
+                    ),
+                    args: [CILNode::LDLoc(0)].into(),
+                }
+                .into(),
+                CILRoot::VoidRet.into(),
+            ],
+            0,
+            None,
+        )],
+        vec![],
+    );
+    asm.add_method(main.clone());
+    asm.set_entrypoint(&main.call_site());
+    let mut exporter = PEExporter::init("minimal", false, "");
+    for (name, asm_ref) in asm.extern_refs() {
+        exporter.add_extern_ref(name, asm_ref);
+    }
+    for tpe in asm.types() {
+        exporter.add_type(tpe.1);
+    }
+    for method in asm.methods() {
+        exporter.add_method(method);
+    }
+    let (image, pdb) = exporter.encode(false, "minimal.pdb").unwrap();
+    assert_eq!(&image[..2], b"MZ");
+    assert_eq!(&image[0x80..0x84], b"PE\0\0");
+    assert_eq!(image.len() % 0x200, 0);
+    // The metadata root must be present.
+    assert!(image.windows(4).any(|window| window == b"BSJB"));
+    // The image must point to the PDB, and the PDB must hold the debug tables.
+    assert!(image.windows(4).any(|window| window == b"RSDS"));
+    assert_eq!(&pdb[..4], b"BSJB");
+    assert!(pdb.windows(8).any(|window| window == b"PDB v1.0"));
+    // Decode the image, and check the tables and the body of `main`.
+    let decoded = DecodedImage::new(&image);
+    assert_eq!(decoded.rows[0x00], 1);
+    // `<Module>`, the class holding global methods, and the types of the assembly.
+    assert_eq!(decoded.rows[0x02], asm.types().count() + 2);
+    // The methods of the assembly, and the `calli` null check helper.
+    assert_eq!(decoded.rows[0x06], asm.methods().count() + 1);
+    assert_eq!(decoded.rows[0x04], 0);
+    // ldc.i4.2 ldc.i4.2 add stloc.0 ldloc.0 call <MemberRef> ret
+    let main_row = (1..=decoded.bodies.len())
+        .find(|row| {
+            let code = &decoded.bodies[row - 1];
+            code.len() == 11
+                && code[..6] == [0x18, 0x18, 0x58, 0x0a, 0x06, 0x28]
+                && code[9] == 0x0a
+                && code[10] == 0x2a
+        })
+        .expect("The body of `main` is not encoded properly");
+    // The entrypoint wraps `main`.
+    let main_token = Table::MethodDef
+        .token(main_row.try_into().unwrap())
+        .to_le_bytes();
+    assert!(decoded.bodies[decoded.entrypoint - 1]
+        .windows(5)
+        .any(|call| call[0] == 0x28 && call[1..] == main_token));
+}
+/// The metadata table row counts and method bodies, decoded from an image with small heaps and tables.
+#[cfg(test)]
+struct DecodedImage {
+    rows: [usize; 64],
+    bodies: Vec<Vec<u8>>,
+    entrypoint: usize,
+}
+#[cfg(test)]
+impl DecodedImage {
+    fn new(image: &[u8]) -> Self {
+        let u16_at = |pos: usize| u16::from_le_bytes([image[pos], image[pos + 1]]) as usize;
+        let u32_at =
+            |pos: usize| u32::from_le_bytes(image[pos..pos + 4].try_into().unwrap()) as usize;
+        let pe = u32_at(0x3c);
+        let optional = pe + 24;
+        let section_table = optional + u16_at(pe + 20);
+        let file_offset = |rva: usize| {
+            (0..u16_at(pe + 6))
+                .map(|idx| section_table + idx * 40)
+                .find_map(|section| {
+                    let va = u32_at(section + 12);
+                    (va..va + u32_at(section + 8))
+                        .contains(&rva)
+                        .then(|| rva - va + u32_at(section + 20))
+                })
+                .unwrap()
+        };
+        // PE32 optional header: the CLI header is data directory 14.
+        assert_eq!(u16_at(optional), 0x10b);
+        let cli = file_offset(u32_at(optional + 96 + 14 * 8));
+        let metadata = file_offset(u32_at(cli + 8));
+        let version_len = u32_at(metadata + 12);
+        let mut stream = metadata + 16 + version_len + 4;
+        let mut table_stream = None;
+        for _ in 0..u16_at(metadata + 16 + version_len + 2) {
+            let name_start = stream + 8;
+            let name_len = image[name_start..].iter().position(|b| *b == 0).unwrap();
+            if &image[name_start..name_start + name_len] == b"#~" {
+                table_stream = Some(metadata + u32_at(stream));
+            }
+            stream = name_start + (name_len + 4) / 4 * 4;
+        }
+        let table_stream = table_stream.unwrap();
+        // All indices are 2 bytes big.
+        assert_eq!(image[table_stream + 6], 0);
+        let valid = u64::from_le_bytes(
+            image[table_stream + 8..table_stream + 16]
+                .try_into()
+                .unwrap(),
+        );
+        let mut rows = [0; 64];
+        let mut pos = table_stream + 24;
+        for (table, count) in rows.iter_mut().enumerate() {
+            if valid & (1 << table) != 0 {
+                *count = u32_at(pos);
+                pos += 4;
+            }
+        }
+        // Module, TypeRef, TypeDef and Field rows come before the MethodDef table.
+        let method_defs = pos + rows[0x00] * 10 + rows[0x01] * 6 + rows[0x02] * 14 + rows[0x04] * 6;
+        let bodies = (0..rows[0x06])
+            .map(|row| {
+                let body = file_offset(u32_at(method_defs + row * 14));
+                if image[body] & 0x3 == 0x2 {
+                    // Tiny header
+                    let size = (image[body] >> 2) as usize;
+                    image[body + 1..body + 1 + size].to_vec()
+                } else {
+                    image[body + 12..body + 12 + u32_at(body + 4)].to_vec()
+                }
+            })
+            .collect();
+        Self {
+            rows,
+            bodies,
+            entrypoint: u32_at(cli + 20) & 0x00ff_ffff,
+        }
+    }
+}
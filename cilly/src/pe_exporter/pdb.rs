@@ -0,0 +1,283 @@
+//! Builds a Portable PDB, holding the source locations and local variable names of the methods of an assembly.
+//! The format is an ECMA-335 metadata blob with its own set of debug tables, described in the
+//! [Portable PDB specification](https://github.com/dotnet/runtime/blob/main/docs/design/specs/PortablePdb-Metadata.md).
+use std::{
+    collections::HashMap,
+    hash::{Hash, Hasher},
+};
+
+use crate::{cil_root::SFI, method::Method, IString};
+
+use super::{
+    heaps::{compress_i32, compress_u32, BlobHeap, GuidHeap, StringHeap, UserStringHeap},
+    il::EncodedBody,
+    metadata_root,
+    tables::{Column, Table, Tables},
+};
+
+/// Rust has no registered language GUID, so cilly uses its own. Debuggers will not recognize it, and fall back to
+/// treating the source as plain text.
+const RUST_LANGUAGE: [u8; 16] = [
+    0x4a, 0x5e, 0x8f, 0x11, 0x3c, 0x70, 0x4b, 0x41, 0x9e, 0x0d, 0x2a, 0x6b, 0xc1, 0x73, 0x52, 0x94,
+];
+/// Lines at or above this limit can't be stored in a PDB.
+const MAX_LINE: u64 = 0x2000_0000;
+/// Columns at or above this limit can't be stored in a PDB.
+const MAX_COLUMN: u64 = 0x1_0000;
+/// Collects the debug info of all methods of an assembly.
+pub struct PdbBuilder {
+    strings: StringHeap,
+    blobs: BlobHeap,
+    guids: GuidHeap,
+    tables: Tables,
+    documents: HashMap<IString, u32>,
+    language: u32,
+    import_scope: u32,
+}
+impl PdbBuilder {
+    pub fn new() -> Self {
+        let mut guids = GuidHeap::new();
+        let language = guids.add(RUST_LANGUAGE);
+        let mut tables = Tables::new();
+        // Rust has no imports, so all methods share a single, empty, import scope.
+        let import_scope = tables.push(
+            Table::ImportScope,
+            vec![Column::Index(Table::ImportScope, 0), Column::Blob(0)],
+        );
+        Self {
+            strings: StringHeap::new(),
+            blobs: BlobHeap::new(),
+            guids,
+            tables,
+            documents: HashMap::new(),
+            language,
+            import_scope,
+        }
+    }
+    /// Returns the `Document` row of the source file at `path`, adding it if needed.
+    fn document(&mut self, path: &str) -> u32 {
+        if let Some(row) = self.documents.get(path) {
+            return *row;
+        }
+        let separator = if path.contains('/') || !path.contains('\\') {
+            '/'
+        } else {
+            '\\'
+        };
+        let mut name = vec![separator as u8];
+        for part in path.split(separator) {
+            compress_u32(self.blobs.add(part.as_bytes()), &mut name);
+        }
+        let name = self.blobs.add(&name);
+        // The hash algorithm is nil, so the hash is empty.
+        let row = self.tables.push(
+            Table::Document,
+            vec![
+                Column::Blob(name),
+                Column::Guid(0),
+                Column::Blob(0),
+                Column::Guid(self.language),
+            ],
+        );
+        self.documents.insert(path.into(), row);
+        row
+    }
+    /// Encodes the sequence points blob of a method, returning it and the document the method is in(0 if it spans multiple documents).
+    fn sequence_points(&mut self, locals_sig: u32, points: &[(u32, SFI)]) -> (u32, Vec<u8>) {
+        let documents: Vec<_> = points
+            .iter()
+            .map(|(_, sfi)| self.document(&sfi.2))
+            .collect();
+        let Some(&initial) = documents.first() else {
+            return (0, vec![]);
+        };
+        let is_single_document = documents.iter().all(|doc| *doc == initial);
+        let mut blob = vec![];
+        // The row of the locals signature, not its token.
+        compress_u32(locals_sig & 0x00FF_FFFF, &mut blob);
+        if !is_single_document {
+            compress_u32(initial, &mut blob);
+        }
+        let mut document = initial;
+        let mut prev_offset = None;
+        let mut prev_start = None;
+        for ((offset, sfi), doc) in points.iter().zip(documents) {
+            if doc != document {
+                blob.push(0);
+                compress_u32(doc, &mut blob);
+                document = doc;
+            }
+            compress_u32(offset - prev_offset.unwrap_or(0), &mut blob);
+            prev_offset = Some(*offset);
+            let (lines, columns, _) = sfi.as_ref();
+            if lines.start == 0 || lines.start >= MAX_LINE {
+                // A hidden sequence point
+                blob.extend([0, 0]);
+                continue;
+            }
+            let start_line = lines.start as u32;
+            let end_line = lines.end.clamp(lines.start, MAX_LINE - 1) as u32;
+            let start_column = columns.start.min(MAX_COLUMN - 2) as u32;
+            let mut end_column = columns.end.min(MAX_COLUMN - 1) as u32;
+            if start_line == end_line {
+                // Single-line sequence points must span at least one column.
+                end_column = end_column.max(start_column + 1);
+                compress_u32(0, &mut blob);
+                compress_u32(end_column - start_column, &mut blob);
+            } else {
+                compress_u32(end_line - start_line, &mut blob);
+                compress_i32(end_column as i32 - start_column as i32, &mut blob);
+            }
+            match prev_start {
+                None => {
+                    compress_u32(start_line, &mut blob);
+                    compress_u32(start_column, &mut blob);
+                }
+                Some((prev_line, prev_column)) => {
+                    compress_i32(start_line as i32 - prev_line as i32, &mut blob);
+                    compress_i32(start_column as i32 - prev_column as i32, &mut blob);
+                }
+            }
+            prev_start = Some((start_line, start_column));
+        }
+        (if is_single_document { initial } else { 0 }, blob)
+    }
+    /// Adds the debug info of `method`, defined in the `MethodDef` row `method_row`. Methods must be added in the order of their rows.
+    pub fn add_method(&mut self, method_row: u32, method: &Method, body: &EncodedBody) {
+        self.pad_methods(method_row - 1);
+        debug_assert_eq!(
+            self.tables.len(Table::MethodDebugInformation),
+            method_row - 1,
+            "Methods must be added in order"
+        );
+        let (document, blob) = self.sequence_points(body.locals_sig, &body.sequence_points);
+        let blob = self.blobs.add(&blob);
+        self.tables.push(
+            Table::MethodDebugInformation,
+            vec![Column::Index(Table::Document, document), Column::Blob(blob)],
+        );
+        let variable_list = self.tables.len(Table::LocalVariable) + 1;
+        for (idx, (name, _)) in method.locals().iter().enumerate() {
+            let (Some(name), Ok(idx)) = (name, u16::try_from(idx)) else {
+                continue;
+            };
+            let name = self.strings.add(name);
+            self.tables.push(
+                Table::LocalVariable,
+                vec![Column::U16(0), Column::U16(idx), Column::String(name)],
+            );
+        }
+        if self.tables.len(Table::LocalVariable) + 1 == variable_list {
+            return;
+        }
+        self.tables.push(
+            Table::LocalScope,
+            vec![
+                Column::Index(Table::MethodDef, method_row),
+                Column::Index(Table::ImportScope, self.import_scope),
+                Column::Index(Table::LocalVariable, variable_list),
+                Column::Index(Table::LocalConstant, 1),
+                Column::U32(0),
+                Column::U32(body.code_size),
+            ],
+        );
+    }
+    /// Adds empty debug info for methods without a body, so that the `MethodDebugInformation` table has `count` rows.
+    fn pad_methods(&mut self, count: u32) {
+        while self.tables.len(Table::MethodDebugInformation) < count {
+            self.tables.push(
+                Table::MethodDebugInformation,
+                vec![Column::Index(Table::Document, 0), Column::Blob(0)],
+            );
+        }
+    }
+    /// Serializes the PDB. `type_system_rows` are the row counts of the tables of the assembly. Returns the PDB id,
+    /// which must be stored in the debug directory of the assembly, and the PDB itself.
+    pub fn serialize(
+        mut self,
+        type_system_rows: &[(Table, u32)],
+        entrypoint_token: u32,
+    ) -> ([u8; 20], Vec<u8>) {
+        let method_count = type_system_rows
+            .iter()
+            .find(|(table, _)| *table == Table::MethodDef)
+            .map_or(0, |(_, rows)| *rows);
+        self.pad_methods(method_count);
+        // The #Pdb stream(the id gets filled in once the whole PDB is known).
+        let mut pdb_stream = vec![0; 20];
+        pdb_stream.extend(entrypoint_token.to_le_bytes());
+        let referenced = type_system_rows
+            .iter()
+            .filter(|(_, rows)| *rows > 0)
+            .fold(0_u64, |referenced, (table, _)| {
+                referenced | (1 << *table as u64)
+            });
+        pdb_stream.extend(referenced.to_le_bytes());
+        for (table, rows) in type_system_rows {
+            if *rows > 0 {
+                pdb_stream.extend(rows.to_le_bytes());
+                self.tables.set_external_len(*table, *rows);
+            }
+        }
+        let tables = self.tables.serialize(
+            self.strings.data().len() >= 0x1_0000,
+            self.guids.data().len() / 16 >= 0x1_0000,
+            self.blobs.data().len() >= 0x1_0000,
+        );
+        let user_strings = UserStringHeap::new();
+        let streams: [(&[u8], &[u8]); 6] = [
+            (b"#Pdb\0", &pdb_stream),
+            (b"#~\0", &tables),
+            (b"#Strings\0", self.strings.data()),
+            (b"#US\0", user_strings.data()),
+            (b"#GUID\0", self.guids.data()),
+            (b"#Blob\0", self.blobs.data()),
+        ];
+        let (mut pdb, pdb_stream_offset) = metadata_root(b"PDB v1.0\0\0\0\0", &streams);
+        // The id is derived from the content of the PDB, so identical builds produce identical PDBs.
+        let mut hasher = std::hash::DefaultHasher::new();
+        pdb.hash(&mut hasher);
+        let hash_a = hasher.finish();
+        pdb.len().hash(&mut hasher);
+        let hash_b = hasher.finish();
+        let mut id = [0; 20];
+        id[..8].copy_from_slice(&hash_a.to_le_bytes());
+        id[8..16].copy_from_slice(&hash_b.to_le_bytes());
+        id[16..].copy_from_slice(&(hash_a as u32 ^ (hash_b >> 32) as u32).to_le_bytes());
+        // Mark the GUID as a version 4 one, just like the .NET compilers do for content-derived ids.
+        id[7] = (id[7] & 0x0F) | 0x40;
+        id[8] = (id[8] & 0x3F) | 0x80;
+        pdb[pdb_stream_offset..pdb_stream_offset + 20].copy_from_slice(&id);
+        (id, pdb)
+    }
+}
+#[test]
+fn sequence_point_encoding() {
+    let mut pdb = PdbBuilder::new();
+    let sfi = |lines: std::ops::Range<u64>, columns: std::ops::Range<u64>, file: &str| -> SFI {
+        Box::new((lines, columns, file.into()))
+    };
+    let points = [
+        (0, sfi(3..3, 5..10, "/src/main.rs")),
+        (4, sfi(4..6, 9..2, "/src/main.rs")),
+        (7, sfi(0..0, 0..1, "/src/main.rs")),
+        (9, sfi(2..2, 1..1, "/src/lib.rs")),
+    ];
+    let (document, blob) = pdb.sequence_points(0x1100_0002, &points);
+    // Spans two documents, so the initial one is stored in the blob.
+    assert_eq!(document, 0);
+    assert_eq!(
+        blob,
+        [
+            // Header: locals signature and the initial document
+            2, 1, //
+            // IL offset, lines, columns, start line, start column
+            0, 0, 5, 3, 5, //
+            4, 2, 0x73, 2, 8, //
+            // Hidden
+            3, 0, 0, //
+            // Document change, then the end column is clamped to be after the start one.
+            0, 2, 2, 0, 1, 0x7D, 0x71,
+        ]
+    );
+}
@@ -0,0 +1,301 @@
+/// Metadata tables(II.22) emitted by the PE exporter, followed by the Portable PDB debug tables. The discriminant is the table number.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum Table {
+    Module = 0x00,
+    TypeRef = 0x01,
+    TypeDef = 0x02,
+    Field = 0x04,
+    MethodDef = 0x06,
+    Param = 0x08,
+    MemberRef = 0x0A,
+    CustomAttribute = 0x0C,
+    ClassLayout = 0x0F,
+    FieldLayout = 0x10,
+    StandAloneSig = 0x11,
+    ModuleRef = 0x1A,
+    TypeSpec = 0x1B,
+    ImplMap = 0x1C,
+    Assembly = 0x20,
+    AssemblyRef = 0x23,
+    NestedClass = 0x29,
+    MethodSpec = 0x2B,
+    Document = 0x30,
+    MethodDebugInformation = 0x31,
+    LocalScope = 0x32,
+    LocalVariable = 0x33,
+    LocalConstant = 0x34,
+    ImportScope = 0x35,
+}
+impl Table {
+    /// All tables, in the order they must be serialized in.
+    pub const ALL: [Self; 24] = [
+        Self::Module,
+        Self::TypeRef,
+        Self::TypeDef,
+        Self::Field,
+        Self::MethodDef,
+        Self::Param,
+        Self::MemberRef,
+        Self::CustomAttribute,
+        Self::ClassLayout,
+        Self::FieldLayout,
+        Self::StandAloneSig,
+        Self::ModuleRef,
+        Self::TypeSpec,
+        Self::ImplMap,
+        Self::Assembly,
+        Self::AssemblyRef,
+        Self::NestedClass,
+        Self::MethodSpec,
+        Self::Document,
+        Self::MethodDebugInformation,
+        Self::LocalScope,
+        Self::LocalVariable,
+        Self::LocalConstant,
+        Self::ImportScope,
+    ];
+    /// Returns true if this table describes the types and members of a module, and not their debug info.
+    pub fn is_type_system(self) -> bool {
+        (self as u32) < 0x30
+    }
+    /// Returns the metadata token of the row `row` of this table.
+    pub fn token(self, row: u32) -> u32 {
+        ((self as u32) << 24) | row
+    }
+    /// Tables which must be sorted by their key column.
+    fn sorted_by(self) -> Option<usize> {
+        match self {
+            Self::CustomAttribute => Some(0),
+            Self::ClassLayout => Some(2),
+            Self::FieldLayout => Some(1),
+            Self::ImplMap => Some(1),
+            Self::NestedClass => Some(0),
+            Self::LocalScope => Some(0),
+            _ => None,
+        }
+    }
+}
+/// Coded indices(II.24.2.6), which can point to rows in one of several tables.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum CodedIndex {
+    TypeDefOrRef,
+    MemberRefParent,
+    MemberForwarded,
+    ResolutionScope,
+    MethodDefOrRef,
+    HasCustomAttribute,
+    CustomAttributeType,
+}
+impl CodedIndex {
+    /// The tables this index can point into. Position in the slice is the tag value, `None` marks an unused tag.
+    fn tables(self) -> &'static [Option<Table>] {
+        match self {
+            Self::TypeDefOrRef => &[
+                Some(Table::TypeDef),
+                Some(Table::TypeRef),
+                Some(Table::TypeSpec),
+            ],
+            Self::MemberRefParent => &[
+                Some(Table::TypeDef),
+                Some(Table::TypeRef),
+                Some(Table::ModuleRef),
+                Some(Table::MethodDef),
+                Some(Table::TypeSpec),
+            ],
+            Self::MemberForwarded => &[Some(Table::Field), Some(Table::MethodDef)],
+            Self::ResolutionScope => &[
+                Some(Table::Module),
+                Some(Table::ModuleRef),
+                Some(Table::AssemblyRef),
+                Some(Table::TypeRef),
+            ],
+            Self::MethodDefOrRef => &[Some(Table::MethodDef), Some(Table::MemberRef)],
+            // Tables this exporter never emits are left as `None`, but still count towards the tag size.
+            Self::HasCustomAttribute => &[
+                Some(Table::MethodDef),
+                Some(Table::Field),
+                Some(Table::TypeRef),
+                Some(Table::TypeDef),
+                Some(Table::Param),
+                None, // InterfaceImpl
+                Some(Table::MemberRef),
+                Some(Table::Module),
+                None, // Permission
+                None, // Property
+                None, // Event
+                Some(Table::StandAloneSig),
+                Some(Table::ModuleRef),
+                Some(Table::TypeSpec),
+                Some(Table::Assembly),
+                Some(Table::AssemblyRef),
+                None, // File
+                None, // ExportedType
+                None, // ManifestResource
+                None, // GenericParam
+                None, // GenericParamConstraint
+                Some(Table::MethodSpec),
+            ],
+            Self::CustomAttributeType => &[
+                None,
+                None,
+                Some(Table::MethodDef),
+                Some(Table::MemberRef),
+                None,
+            ],
+        }
+    }
+    fn tag_bits(self) -> u32 {
+        let len = self.tables().len() as u32;
+        u32::BITS - (len - 1).leading_zeros()
+    }
+    /// Encodes a reference to row `row` of table `table`.
+    /// # Panics
+    /// Panics if `table` can't be pointed to by this coded index.
+    pub fn encode(self, table: Table, row: u32) -> u32 {
+        let tag = self
+            .tables()
+            .iter()
+            .position(|tbl| *tbl == Some(table))
+            .unwrap_or_else(|| panic!("{table:?} can't be referenced by a {self:?} index"));
+        (row << self.tag_bits()) | tag as u32
+    }
+}
+/// A single cell of a metadata table.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Column {
+    U16(u16),
+    U32(u32),
+    String(u32),
+    Blob(u32),
+    Guid(u32),
+    Index(Table, u32),
+    Coded(CodedIndex, u32),
+}
+/// All the metadata tables of a module.
+pub struct Tables {
+    rows: Vec<Vec<Vec<Column>>>,
+    /// Row counts of tables stored in another metadata blob. A PDB refers to the type system tables of its assembly,
+    /// so the size of those indices depends on the row counts of the assembly.
+    external_rows: Vec<u32>,
+}
+impl Tables {
+    pub fn new() -> Self {
+        Self {
+            rows: vec![Vec::new(); 0x36],
+            external_rows: vec![0; 0x36],
+        }
+    }
+    /// Marks `table` as stored in another metadata blob, which contains `rows` rows.
+    pub fn set_external_len(&mut self, table: Table, rows: u32) {
+        self.external_rows[table as usize] = rows;
+    }
+    /// Appends a row to `table`, returning its 1-based index.
+    pub fn push(&mut self, table: Table, row: Vec<Column>) -> u32 {
+        let rows = &mut self.rows[table as usize];
+        rows.push(row);
+        rows.len() as u32
+    }
+    /// Returns the number of rows in `table`.
+    pub fn len(&self, table: Table) -> u32 {
+        self.rows[table as usize].len() as u32
+    }
+    /// Returns a mutable reference to the cell `column` in the 1-based row `row` of `table`.
+    pub fn cell_mut(&mut self, table: Table, row: u32, column: usize) -> &mut Column {
+        &mut self.rows[table as usize][row as usize - 1][column]
+    }
+    /// Returns the number of rows `table` has, either in this metadata blob or in the one it refers to.
+    fn row_count(&self, table: Table) -> u32 {
+        self.len(table).max(self.external_rows[table as usize])
+    }
+    fn index_size(&self, table: Table) -> usize {
+        if self.row_count(table) < 0x1_0000 {
+            2
+        } else {
+            4
+        }
+    }
+    fn coded_size(&self, coded: CodedIndex) -> usize {
+        let max_rows = coded
+            .tables()
+            .iter()
+            .flatten()
+            .map(|table| self.row_count(*table))
+            .max()
+            .unwrap_or(0);
+        if max_rows < (1 << (16 - coded.tag_bits())) {
+            2
+        } else {
+            4
+        }
+    }
+    /// Serializes the `#~` stream. `wide_*` specify if the heap indices are 4 bytes long.
+    pub fn serialize(&mut self, wide_strings: bool, wide_guids: bool, wide_blobs: bool) -> Vec<u8> {
+        for table in Table::ALL {
+            if let Some(key) = table.sorted_by() {
+                self.rows[table as usize].sort_by_key(|row| row[key]);
+            }
+        }
+        let mut out = Vec::new();
+        // Reserved
+        out.extend(0_u32.to_le_bytes());
+        // Major and minor version
+        out.extend([2, 0]);
+        let heap_sizes =
+            u8::from(wide_strings) | (u8::from(wide_guids) << 1) | (u8::from(wide_blobs) << 2);
+        out.push(heap_sizes);
+        // Reserved
+        out.push(1);
+        let valid = Table::ALL
+            .iter()
+            .filter(|table| self.len(**table) > 0)
+            .fold(0_u64, |valid, table| valid | (1 << *table as u64));
+        let sorted = Table::ALL
+            .iter()
+            .filter(|table| table.sorted_by().is_some())
+            .fold(0_u64, |sorted, table| sorted | (1 << *table as u64));
+        out.extend(valid.to_le_bytes());
+        out.extend(sorted.to_le_bytes());
+        for table in Table::ALL {
+            if self.len(table) > 0 {
+                out.extend(self.len(table).to_le_bytes());
+            }
+        }
+        for table in Table::ALL {
+            for row in &self.rows[table as usize] {
+                for column in row {
+                    let (value, size) = match *column {
+                        Column::U16(value) => (u32::from(value), 2),
+                        Column::U32(value) => (value, 4),
+                        Column::String(idx) => (idx, if wide_strings { 4 } else { 2 }),
+                        Column::Guid(idx) => (idx, if wide_guids { 4 } else { 2 }),
+                        Column::Blob(idx) => (idx, if wide_blobs { 4 } else { 2 }),
+                        Column::Index(table, idx) => (idx, self.index_size(table)),
+                        Column::Coded(coded, idx) => (idx, self.coded_size(coded)),
+                    };
+                    out.extend(&value.to_le_bytes()[..size]);
+                }
+            }
+        }
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+        out
+    }
+}
+#[test]
+fn coded_index_encoding() {
+    assert_eq!(
+        CodedIndex::TypeDefOrRef.encode(Table::TypeRef, 3),
+        (3 << 2) | 1
+    );
+    assert_eq!(CodedIndex::MemberRefParent.tag_bits(), 3);
+    assert_eq!(CodedIndex::HasCustomAttribute.tag_bits(), 5);
+    assert_eq!(
+        CodedIndex::CustomAttributeType.encode(Table::MemberRef, 2),
+        (2 << 3) | 3
+    );
+    assert_eq!(
+        CodedIndex::MethodDefOrRef.encode(Table::MemberRef, 7),
+        (7 << 1) | 1
+    );
+}
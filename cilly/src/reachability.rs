@@ -0,0 +1,523 @@
+//! Whole-program reachability analysis. Starting from the roots of an assembly (its entrypoint, static initializer,
+//! types and exported methods), it finds all the methods, static fields and types which may be used at runtime,
+//! remembering why each one of them was kept.
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+
+use crate::{
+    access_modifier::AccessModifer, asm::Assembly, call_site::CallSite, cil_iter::CILIterElem,
+    cil_node::CILNode, cil_root::CILRoot, cil_tree::CILTree, method::Method,
+    static_field_desc::StaticFieldDescriptor, DotnetTypeRef, IString, Type,
+};
+
+/// Something an assembly defines, and which can be removed from it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Item {
+    Method(CallSite),
+    Static(StaticFieldDescriptor),
+    /// A type defined in the assembly, referred to by its name.
+    Type(IString),
+}
+impl Item {
+    /// Returns the name of this item.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Method(site) => site.name(),
+            Self::Static(descr) => descr.name(),
+            Self::Type(name) => name,
+        }
+    }
+}
+impl std::fmt::Display for Item {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Method(site) => write!(f, "method {}", site.name()),
+            Self::Static(descr) => write!(f, "static {}", descr.name()),
+            Self::Type(name) => write!(f, "type {name}"),
+        }
+    }
+}
+/// Why an item is a root of the analysis.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RootKind {
+    /// The entrypoint of an executable.
+    Entrypoint,
+    /// The static initializer(`.cctor`) of the assembly.
+    StaticInitializer,
+    /// A public method of a library.
+    Exported,
+    /// A type, which is kept because types are not pruned.
+    Type,
+    /// An item the exporters rely on.
+    Mandatory,
+}
+/// Why an item is kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Reason {
+    Root(RootKind),
+    /// The item is used by another, reachable, item.
+    UsedBy(Item),
+}
+/// The result of the reachability analysis.
+pub struct Reachability {
+    reasons: HashMap<Item, Reason>,
+}
+/// Collects the names of the types defined by the assembly that `tpe` refers to.
+fn type_refs(tpe: &Type, out: &mut Vec<IString>) {
+    match tpe {
+        Type::DotnetType(tref) => dotnet_refs(tref, out),
+        Type::Ptr(inner) | Type::ManagedReference(inner) => type_refs(inner, out),
+        Type::ManagedArray { element, .. } => type_refs(element, out),
+        Type::DelegatePtr(sig) => sig
+            .inputs()
+            .iter()
+            .chain(std::iter::once(sig.output()))
+            .for_each(|tpe| type_refs(tpe, out)),
+        _ => (),
+    }
+}
+/// Collects the names of the types defined by the assembly that `tref` refers to.
+fn dotnet_refs(tref: &DotnetTypeRef, out: &mut Vec<IString>) {
+    if tref.asm().is_none() {
+        // Nested types are kept alive by the type containing them.
+        let name = tref.name_path();
+        let name = name.split_once('\\').map_or(name, |(outer, _)| outer);
+        out.push(name.into());
+    }
+    for generic in tref.generics() {
+        type_refs(generic, out);
+    }
+}
+fn site_refs(site: &CallSite, out: &mut Vec<IString>) {
+    if let Some(class) = site.class() {
+        dotnet_refs(class, out);
+    }
+    site.signature()
+        .inputs()
+        .iter()
+        .chain(std::iter::once(site.signature().output()))
+        .for_each(|tpe| type_refs(tpe, out));
+}
+/// Returns the items a tree uses.
+fn tree_edges(asm: &Assembly, tree: &CILTree, out: &mut Vec<Item>) {
+    let mut types = vec![];
+    let mut sites = vec![];
+    let mut statics = vec![];
+    for elem in tree.root() {
+        match elem {
+            CILIterElem::Node(node) => match node {
+                CILNode::Call { site, .. }
+                | CILNode::CallVirt { site, .. }
+                | CILNode::NewObj { site, .. }
+                | CILNode::LDFtn(site) => sites.push(site.as_ref()),
+                CILNode::LDStaticField(descr) => statics.push(descr.as_ref()),
+                CILNode::LDFieldAdress { field, .. } | CILNode::LDField { field, .. } => {
+                    dotnet_refs(field.owner(), &mut types);
+                    type_refs(field.tpe(), &mut types);
+                }
+                CILNode::SizeOf(tpe)
+                | CILNode::LDTypeToken(tpe)
+                | CILNode::LDIndPtr {
+                    loaded_ptr: tpe, ..
+                }
+                | CILNode::LdObj { obj: tpe, .. }
+                | CILNode::TransmutePtr { new_ptr: tpe, .. }
+                | CILNode::LocAllocAligned { tpe, .. } => type_refs(tpe, &mut types),
+                CILNode::TemporaryLocal(tmp) => type_refs(&tmp.0, &mut types),
+                CILNode::CallI(call) => call
+                    .0
+                    .inputs()
+                    .iter()
+                    .chain(std::iter::once(call.0.output()))
+                    .for_each(|tpe| type_refs(tpe, &mut types)),
+                _ => (),
+            },
+            CILIterElem::Root(root) => match root {
+                CILRoot::Call { site, .. } | CILRoot::CallVirt { site, .. } => sites.push(site),
+                CILRoot::SetStaticField { descr, .. } => statics.push(descr),
+                CILRoot::SetField { desc, .. } => {
+                    dotnet_refs(desc.owner(), &mut types);
+                    type_refs(desc.tpe(), &mut types);
+                }
+                CILRoot::STObj { tpe, .. } => type_refs(tpe, &mut types),
+                CILRoot::CallI { sig, .. } => sig
+                    .inputs()
+                    .iter()
+                    .chain(std::iter::once(sig.output()))
+                    .for_each(|tpe| type_refs(tpe, &mut types)),
+                _ => (),
+            },
+        }
+    }
+    for site in sites {
+        site_refs(site, &mut types);
+        if asm.functions().contains_key(site) {
+            out.push(Item::Method(site.clone()));
+        }
+    }
+    for descr in statics {
+        type_refs(descr.tpe(), &mut types);
+        match descr.owner() {
+            Some(owner) => dotnet_refs(owner, &mut types),
+            None if asm.static_fields().contains_key(descr.name()) => {
+                out.push(Item::Static(descr.clone()));
+            }
+            None => (),
+        }
+    }
+    out.extend(types.into_iter().map(Item::Type));
+}
+/// Returns the static field a tree of the static initializer initializes.
+fn initialized_static(tree: &CILTree) -> Option<&StaticFieldDescriptor> {
+    match tree.root() {
+        CILRoot::SetStaticField { descr, .. } if descr.owner().is_none() => Some(descr),
+        _ => None,
+    }
+}
+fn method_type_edges(method: &Method, out: &mut Vec<Item>) {
+    let mut types = vec![];
+    method
+        .sig()
+        .inputs()
+        .iter()
+        .chain(std::iter::once(method.sig().output()))
+        .chain(method.locals().iter().map(|(_, tpe)| tpe))
+        .for_each(|tpe| type_refs(tpe, &mut types));
+    out.extend(types.into_iter().map(Item::Type));
+}
+impl Reachability {
+    /// Finds everything reachable from the roots of `asm`. If `keep_public` is set, public methods are roots too, which
+    /// is what libraries need. If `keep_types` is set, all the types of `asm` are roots.
+    #[must_use]
+    pub fn new(asm: &Assembly, keep_public: bool, keep_types: bool) -> Self {
+        let cctor_site = asm.cctor().map(Method::call_site);
+        let mut reasons: HashMap<Item, Reason> = HashMap::new();
+        let mut queue: VecDeque<Item> = VecDeque::new();
+        let mut root = |item: Item, kind: RootKind, queue: &mut VecDeque<Item>| {
+            if !reasons.contains_key(&item) {
+                reasons.insert(item.clone(), Reason::Root(kind));
+                queue.push_back(item);
+            }
+        };
+        if let Some(entrypoint) = asm.entrypoint() {
+            root(
+                Item::Method(entrypoint.clone()),
+                RootKind::Entrypoint,
+                &mut queue,
+            );
+        }
+        if let Some(cctor) = &cctor_site {
+            root(
+                Item::Method(cctor.clone()),
+                RootKind::StaticInitializer,
+                &mut queue,
+            );
+        }
+        if keep_public {
+            for method in asm
+                .methods()
+                .filter(|method| method.access() == AccessModifer::Public)
+            {
+                root(
+                    Item::Method(method.call_site()),
+                    RootKind::Exported,
+                    &mut queue,
+                );
+            }
+        }
+        if keep_types {
+            for (name, _) in asm.types() {
+                root(Item::Type(name.clone()), RootKind::Type, &mut queue);
+            }
+        }
+        if asm.types().any(|(name, _)| &**name == "RustVoid") {
+            root(
+                Item::Type("RustVoid".into()),
+                RootKind::Mandatory,
+                &mut queue,
+            );
+        }
+        // The initializers of statics in the `.cctor` are only needed if the static is.
+        let mut initializers: HashMap<&str, Vec<&CILTree>> = HashMap::new();
+        if let Some(cctor) = asm.cctor() {
+            for tree in cctor
+                .blocks()
+                .iter()
+                .flat_map(crate::basic_block::BasicBlock::trees)
+            {
+                if let Some(descr) = initialized_static(tree) {
+                    initializers.entry(descr.name()).or_default().push(tree);
+                }
+            }
+        }
+        let mut edges = vec![];
+        while let Some(item) = queue.pop_front() {
+            match &item {
+                Item::Method(site) => {
+                    let Some(method) = asm.functions().get(site) else {
+                        continue;
+                    };
+                    let is_cctor = Some(site) == cctor_site.as_ref();
+                    for tree in method
+                        .blocks()
+                        .iter()
+                        .flat_map(crate::basic_block::BasicBlock::trees)
+                    {
+                        if is_cctor && initialized_static(tree).is_some() {
+                            continue;
+                        }
+                        tree_edges(asm, tree, &mut edges);
+                    }
+                    method_type_edges(method, &mut edges);
+                }
+                Item::Static(descr) => {
+                    let mut types = vec![];
+                    type_refs(descr.tpe(), &mut types);
+                    edges.extend(types.into_iter().map(Item::Type));
+                    for tree in initializers.get(descr.name()).into_iter().flatten() {
+                        tree_edges(asm, tree, &mut edges);
+                    }
+                }
+                Item::Type(name) => {
+                    let Some(type_def) = asm
+                        .types()
+                        .find(|(tpe, _)| *tpe == name)
+                        .map(|(_, def)| def)
+                    else {
+                        continue;
+                    };
+                    let mut types = vec![];
+                    for tpe in type_def.all_types() {
+                        type_refs(tpe, &mut types);
+                    }
+                    if let Some(extends) = type_def.extends() {
+                        dotnet_refs(extends, &mut types);
+                    }
+                    edges.extend(types.into_iter().map(Item::Type));
+                    for method in type_def.methods() {
+                        for tree in method
+                            .blocks()
+                            .iter()
+                            .flat_map(crate::basic_block::BasicBlock::trees)
+                        {
+                            tree_edges(asm, tree, &mut edges);
+                        }
+                        method_type_edges(method, &mut edges);
+                    }
+                }
+            }
+            for edge in std::mem::take(&mut edges) {
+                if edge == item || reasons.contains_key(&edge) {
+                    continue;
+                }
+                reasons.insert(edge.clone(), Reason::UsedBy(item.clone()));
+                queue.push_back(edge);
+            }
+        }
+        Self { reasons }
+    }
+    /// Checks if `item` is reachable.
+    #[must_use]
+    pub fn is_kept(&self, item: &Item) -> bool {
+        self.reasons.contains_key(item)
+    }
+    /// Returns the reason `item` is kept, or `None` if it is not reachable.
+    #[must_use]
+    pub fn reason(&self, item: &Item) -> Option<&Reason> {
+        self.reasons.get(item)
+    }
+    /// Returns an iterator over all the reachable items.
+    pub fn kept(&self) -> impl Iterator<Item = &Item> {
+        self.reasons.keys()
+    }
+    /// Returns the chain of items leading from a root to `item`. The root is the last element of the chain.
+    #[must_use]
+    pub fn chain(&self, item: &Item) -> Option<(Vec<&Item>, RootKind)> {
+        let (mut curr, _) = self.reasons.get_key_value(item)?;
+        let mut chain = vec![curr];
+        loop {
+            match &self.reasons[curr] {
+                Reason::Root(kind) => return Some((chain, *kind)),
+                Reason::UsedBy(user) => {
+                    curr = self.reasons.get_key_value(user).unwrap().0;
+                    chain.push(curr);
+                }
+            }
+        }
+    }
+    /// Explains why the items whose name contains `name` are kept. Returns `None` if no such item is kept.
+    #[must_use]
+    pub fn why_kept(&self, name: &str) -> Option<String> {
+        let mut matching: Vec<&Item> = self
+            .kept()
+            .filter(|item| item.name().contains(name))
+            .collect();
+        if matching.is_empty() {
+            return None;
+        }
+        matching.sort_by_cached_key(ToString::to_string);
+        let mut out = String::new();
+        for item in matching {
+            let (chain, root) = self.chain(item).unwrap();
+            writeln!(out, "{item} is kept because:").unwrap();
+            for (user, used) in chain.iter().skip(1).zip(&chain) {
+                writeln!(out, "    {used} is used by {user}").unwrap();
+            }
+            writeln!(out, "    {} is a root({root:?})", chain.last().unwrap()).unwrap();
+        }
+        Some(out)
+    }
+}
+/// The size of the methods of a crate or module.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SizeInfo {
+    pub methods: usize,
+    /// The number of CIL nodes and roots the methods are made of.
+    pub nodes: usize,
+}
+impl SizeInfo {
+    fn add(&mut self, method: &Method) {
+        self.methods += 1;
+        self.nodes += method
+            .blocks()
+            .iter()
+            .flat_map(crate::basic_block::BasicBlock::trees)
+            .map(|tree| tree.root().into_iter().count())
+            .sum::<usize>();
+    }
+}
+/// Returns the path of the module a method with the (mangled) name `name` is defined in, or `None` if it is not a Rust
+/// function.
+#[must_use]
+pub fn module_path(name: &str) -> Option<Vec<String>> {
+    let name = name.replace("_ds_", "$").replace("_dot_", ".");
+    let demangled = format!("{:#}", rustc_demangle::try_demangle(&name).ok()?);
+    // For methods of trait impls, like `<alloc::vec::Vec<u8> as core::ops::drop::Drop>::drop`, this is the path of the
+    // type the trait is implemented for.
+    let path = demangled.trim_start_matches('<');
+    let path = &path[..path.find(['<', '>', ' ']).unwrap_or(path.len())];
+    let mut segments: Vec<String> = path.split("::").map(str::to_owned).collect();
+    // The last segment is the name of the function or type.
+    if segments.len() > 1 {
+        segments.pop();
+    }
+    Some(segments)
+}
+/// Computes the size of the methods of `asm`, grouped by the crate and module they come from.
+#[must_use]
+pub fn size_by_module(asm: &Assembly) -> HashMap<String, (SizeInfo, HashMap<String, SizeInfo>)> {
+    let mut crates: HashMap<String, (SizeInfo, HashMap<String, SizeInfo>)> = HashMap::new();
+    for method in asm
+        .methods()
+        .chain(asm.types().flat_map(|(_, tpe)| tpe.methods()))
+    {
+        let path = module_path(method.name()).unwrap_or_else(|| vec!["<non-rust>".into()]);
+        let (crate_size, modules) = crates.entry(path[0].clone()).or_default();
+        crate_size.add(method);
+        modules.entry(path.join("::")).or_default().add(method);
+    }
+    crates
+}
+/// Writes a report of the size of `asm`, grouped by crate and module, with the biggest ones first.
+#[must_use]
+pub fn size_report(asm: &Assembly) -> String {
+    let crates = size_by_module(asm);
+    let total = crates
+        .values()
+        .fold(SizeInfo::default(), |acc, (size, _)| SizeInfo {
+            methods: acc.methods + size.methods,
+            nodes: acc.nodes + size.nodes,
+        });
+    let share = |size: &SizeInfo| size.nodes as f64 * 100.0 / total.nodes.max(1) as f64;
+    let mut out = String::new();
+    writeln!(
+        out,
+        "{} methods, {} CIL nodes, {} static fields, {} types",
+        total.methods,
+        total.nodes,
+        asm.static_fields().len(),
+        asm.types().count()
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "{:>10} {:>8} {:>7}  crate/module",
+        "nodes", "methods", "share"
+    )
+    .unwrap();
+    let mut crates: Vec<_> = crates.into_iter().collect();
+    crates.sort_by(|a, b| b.1 .0.nodes.cmp(&a.1 .0.nodes).then(a.0.cmp(&b.0)));
+    for (name, (size, modules)) in crates {
+        writeln!(
+            out,
+            "{:>10} {:>8} {:>6.2}%  {name}",
+            size.nodes,
+            size.methods,
+            share(&size)
+        )
+        .unwrap();
+        let mut modules: Vec<_> = modules.into_iter().collect();
+        modules.sort_by(|a, b| b.1.nodes.cmp(&a.1.nodes).then(a.0.cmp(&b.0)));
+        for (module, size) in modules {
+            writeln!(
+                out,
+                "{:>10} {:>8} {:>6.2}%    {module}",
+                size.nodes,
+                size.methods,
+                share(&size)
+            )
+            .unwrap();
+        }
+    }
+    out
+}
+#[test]
+fn why_kept_chain() {
+    use crate::{basic_block::BasicBlock, method::MethodType, FnSig};
+    let method = |name: &str, trees: Vec<CILTree>| {
+        Method::new(
+            AccessModifer::Private,
+            MethodType::Static,
+            FnSig::new(&[], Type::Void),
+            name,
+            vec![],
+            vec![BasicBlock::new(trees, 0, None)],
+            vec![],
+        )
+    };
+    let call = |callee: &Method| {
+        CILRoot::Call {
+            site: callee.call_site(),
+            args: [].into(),
+        }
+        .into()
+    };
+    let leaf = method("leaf", vec![CILRoot::VoidRet.into()]);
+    let middle = method("middle", vec![call(&leaf), CILRoot::VoidRet.into()]);
+    let main = method("main", vec![call(&middle), CILRoot::VoidRet.into()]);
+    let dead = method("dead", vec![call(&leaf), CILRoot::VoidRet.into()]);
+    let mut asm = Assembly::empty();
+    asm.set_entrypoint(&main.call_site());
+    for method in [&leaf, &middle, &main, &dead] {
+        asm.add_method(method.clone());
+    }
+    let reachability = Reachability::new(&asm, false, true);
+    assert!(!reachability.is_kept(&Item::Method(dead.call_site())));
+    let leaf_item = Item::Method(leaf.call_site());
+    let (chain, root) = reachability.chain(&leaf_item).unwrap();
+    assert_eq!(root, RootKind::Entrypoint);
+    let chain: Vec<_> = chain.into_iter().map(Item::name).collect();
+    assert_eq!(chain, ["leaf", "middle", "main", "entrypoint"]);
+    assert!(reachability
+        .why_kept("leaf")
+        .unwrap()
+        .contains("method leaf is used by method middle"));
+    // Only public methods are exported.
+    assert!(!Reachability::new(&asm, true, true).is_kept(&Item::Method(dead.call_site())));
+    assert_eq!(
+        module_path("_ZN4core3fmt5write17h0123456789abcdefE").unwrap(),
+        ["core", "fmt"]
+    );
+    assert_eq!(module_path("main"), None);
+}
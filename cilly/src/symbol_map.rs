@@ -0,0 +1,204 @@
+//! Maps the names of methods back to the Rust functions they were compiled from, so that .NET stack traces can be read.
+//!
+//! Methods are named after the mangled symbols of Rust functions, which may be escaped further. A symbol map stores,
+//! for each method, the demangled path of its function, the crate it comes from, and the span of its source code. It is
+//! written as text, one method per line, with tab-separated columns.
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::{asm::Assembly, basic_block::BasicBlock, cil_root::CILRoot, method::Method};
+
+/// The Rust function a method was compiled from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    /// The demangled path of the function, e.g. `core::panicking::panic`.
+    pub path: String,
+    /// The crate the function comes from.
+    pub krate: String,
+    /// The source file and lines the function spans, e.g. `src/main.rs:3-7`, if known.
+    pub span: Option<String>,
+}
+/// Demangles the (possibly escaped) name of a method, returning `None` if it is not a Rust symbol.
+#[must_use]
+pub fn demangle(name: &str) -> Option<String> {
+    let name = name.replace("_ds_", "$").replace("_dot_", ".");
+    Some(format!("{:#}", rustc_demangle::try_demangle(&name).ok()?))
+}
+/// Returns the source span of `method`: the file of its first source location, and the lines of that file it covers.
+fn source_span(method: &Method) -> Option<String> {
+    let mut sfis = method
+        .blocks()
+        .iter()
+        .flat_map(BasicBlock::trees)
+        .filter_map(|tree| match tree.root() {
+            CILRoot::SourceFileInfo(sfi) if !sfi.2.is_empty() => Some(sfi),
+            _ => None,
+        });
+    let first = sfis.next()?;
+    let file = &first.2;
+    let lines = sfis
+        .filter(|sfi| sfi.2 == *file)
+        .fold(first.0.clone(), |lines: Range<u64>, sfi| {
+            lines.start.min(sfi.0.start)..lines.end.max(sfi.0.end)
+        });
+    if lines.start == lines.end {
+        Some(format!("{file}:{}", lines.start))
+    } else {
+        Some(format!("{file}:{}-{}", lines.start, lines.end))
+    }
+}
+/// Maps the names of methods, as they appear in the final assembly, to the Rust functions they were compiled from.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    symbols: HashMap<String, Symbol>,
+}
+impl SymbolMap {
+    /// Creates the symbol map of `asm`. `escape_names` must match the value used when exporting it.
+    #[must_use]
+    pub fn from_assembly(asm: &Assembly, escape_names: bool) -> Self {
+        let symbols = asm
+            .methods()
+            .filter_map(|method| {
+                let path = demangle(method.name())?;
+                let krate = path.trim_start_matches('<');
+                let krate = krate[..krate.find("::").unwrap_or(krate.len())].to_owned();
+                let name = if escape_names {
+                    crate::asm_exporter::escape_class_name(method.name())
+                } else {
+                    method.name().to_owned()
+                };
+                let span = source_span(method);
+                Some((name, Symbol { path, krate, span }))
+            })
+            .collect();
+        Self { symbols }
+    }
+    /// Parses a symbol map, previously written using its `Display` implementation. Malformed lines are skipped.
+    #[must_use]
+    pub fn parse(map: &str) -> Self {
+        let symbols = map
+            .lines()
+            .filter_map(|line| {
+                let mut columns = line.split('\t');
+                let name = columns.next()?.to_owned();
+                let path = columns.next()?.to_owned();
+                let krate = columns.next()?.to_owned();
+                let span = columns
+                    .next()
+                    .filter(|span| !span.is_empty())
+                    .map(str::to_owned);
+                Some((name, Symbol { path, krate, span }))
+            })
+            .collect();
+        Self { symbols }
+    }
+    /// Returns the Rust function the method named `name` was compiled from.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.get(name)
+    }
+    /// The number of methods in this map.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+    /// Checks if this map contains no methods.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+    /// Finds the method called in a stack frame. The runtime may prefix it with the name of its class, separated by a
+    /// dot, but mangled names may contain dots too: so, all the suffixes of `frame` following a dot are checked.
+    fn lookup(&self, frame: &str) -> Option<&Symbol> {
+        std::iter::once(frame)
+            .chain(frame.match_indices('.').map(|(idx, _)| &frame[idx + 1..]))
+            .find_map(|name| self.get(name))
+    }
+    /// Rewrites a single line of a .NET stack trace(`   at method(args) in file:line 1`), replacing the name of the
+    /// method with the path of its Rust function and the crate it comes from. If the runtime did not know where the
+    /// method is defined, the span from the map is used instead. Returns `None` if the line is not a known frame.
+    #[must_use]
+    pub fn symbolicate_frame(&self, line: &str) -> Option<String> {
+        let indent = line.len() - line.trim_start().len();
+        let frame = line[indent..].strip_prefix("at ")?;
+        let args_start = frame.find('(')?;
+        let symbol = self.lookup(&frame[..args_start])?;
+        let rest = &frame[args_start..];
+        let location = match (rest.find(") in "), &symbol.span) {
+            (Some(args_end), _) => rest[args_end + 1..].to_owned(),
+            (None, Some(span)) => format!(" in {span}"),
+            (None, None) => String::new(),
+        };
+        Some(format!(
+            "{}at {} [{}]{location}",
+            &line[..indent],
+            symbol.path,
+            symbol.krate
+        ))
+    }
+    /// Rewrites all the frames of a .NET stack trace which belong to methods in this map. Other lines are unchanged.
+    #[must_use]
+    pub fn symbolicate(&self, trace: &str) -> String {
+        trace.lines().fold(String::new(), |mut out, line| {
+            match self.symbolicate_frame(line) {
+                Some(frame) => out.push_str(&frame),
+                None => out.push_str(line),
+            }
+            out.push('\n');
+            out
+        })
+    }
+}
+impl std::fmt::Display for SymbolMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut names: Vec<_> = self.symbols.keys().collect();
+        names.sort();
+        for name in names {
+            let symbol = &self.symbols[name];
+            writeln!(
+                f,
+                "{name}\t{}\t{}\t{}",
+                symbol.path,
+                symbol.krate,
+                symbol.span.as_deref().unwrap_or("")
+            )?;
+        }
+        Ok(())
+    }
+}
+#[test]
+fn symbolicate_trace() {
+    use crate::{access_modifier::AccessModifer, method::MethodType, FnSig, Type};
+    let name = "_ZN4core9panicking5panic17h0123456789abcdefE";
+    let trees = vec![
+        CILRoot::source_info("library/core/src/panicking.rs", 140..141, 5..10).into(),
+        CILRoot::source_info("library/core/src/panicking.rs", 146..147, 1..2).into(),
+        CILRoot::VoidRet.into(),
+    ];
+    let mut asm = Assembly::empty();
+    asm.add_method(Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        FnSig::new(&[], Type::Void),
+        name,
+        vec![],
+        vec![BasicBlock::new(trees, 0, None)],
+        vec![],
+    ));
+    let map = SymbolMap::from_assembly(&asm, false);
+    let symbol = map.get(name).unwrap();
+    assert_eq!(symbol.path, "core::panicking::panic");
+    assert_eq!(symbol.krate, "core");
+    assert_eq!(
+        symbol.span.as_deref(),
+        Some("library/core/src/panicking.rs:140-147")
+    );
+    assert_eq!(SymbolMap::parse(&map.to_string()), map);
+    let trace = format!(
+        "Unhandled exception.\n   at {name}()\n   at Other.Method(Int32 a) in a.cs:line 3\n"
+    );
+    assert_eq!(
+        map.symbolicate(&trace),
+        "Unhandled exception.\n   at core::panicking::panic [core] in library/core/src/panicking.rs:140-147\n   at Other.Method(Int32 a) in a.cs:line 3\n"
+    );
+}
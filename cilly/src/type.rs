@@ -11,6 +11,8 @@ pub enum Type {
     F16,
     F32,
     F64,
+    /// A 128 bit float, stored as its bits. Operations on it are implemented in software.
+    F128,
     // Unsigned intiegers
     U8,
     U16,
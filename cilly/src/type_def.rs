@@ -2,7 +2,12 @@ use std::num::NonZeroU64;
 
 use serde::{Deserialize, Serialize};
 
-use crate::{access_modifier::AccessModifer, method::Method, DotnetTypeRef, IString, Type};
+use crate::{
+    access_modifier::AccessModifer,
+    custom_attribute::{CustomAttribute, LayoutKind},
+    method::Method,
+    DotnetTypeRef, IString, Type,
+};
 
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Debug)]
 pub struct TypeDef {
@@ -15,6 +20,7 @@ pub struct TypeDef {
     gargc: u32,
     extends: Option<DotnetTypeRef>,
     explict_size: Option<NonZeroU64>,
+    attributes: Vec<CustomAttribute>,
     //requires_aligement_adjustements:bool,
 }
 impl TypeDef {
@@ -67,6 +73,20 @@ impl TypeDef {
     pub fn methods(&self) -> impl Iterator<Item = &Method> {
         self.functions.iter()
     }
+    pub fn add_attribute(&mut self, attr: CustomAttribute) {
+        self.attributes.push(attr);
+    }
+    #[must_use]
+    pub fn custom_attributes(&self) -> &[CustomAttribute] {
+        &self.attributes
+    }
+    /// The layout requested by a `[StructLayout]` attribute, if this type has one.
+    #[must_use]
+    pub fn layout_kind(&self) -> Option<LayoutKind> {
+        self.attributes
+            .iter()
+            .find_map(CustomAttribute::layout_kind)
+    }
     #[must_use]
     pub fn nameonly(name: &str) -> Self {
         Self {
@@ -79,6 +99,7 @@ impl TypeDef {
             extends: None,
             explicit_offsets: None,
             explict_size: None,
+            attributes: vec![],
         }
     }
     #[must_use]
@@ -103,6 +124,7 @@ impl TypeDef {
             gargc,
             extends,
             explict_size,
+            attributes: vec![],
         };
         //TODO:consider having this enabled only for debug
         res.sanity_check();
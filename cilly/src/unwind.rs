@@ -0,0 +1,455 @@
+//! Support for Rust panics.
+//!
+//! A panic is raised by `_Unwind_RaiseException`, which throws a `RustException`. This exception holds the pointer to
+//! the exception object created by the panic runtime, which in turn owns the boxed `dyn Any + Send` payload.
+//! `catch_unwind` hands that pointer back to the panic runtime, so the payload it returns is the exact same one the panic
+//! started with.
+//!
+//! cilly only supports catch-all exception handlers, so `catch_unwind` checks if the caught object is a `RustException`,
+//! and rethrows anything else. In C mode, exceptions carry no object: there, the pointer to the exception object in
+//! flight is stored in a thread static instead, and gets cleared once the panic is caught. Exceptions caught while it
+//! is not set did not come from Rust, and get rethrown.
+use crate::{
+    access_modifier::AccessModifer,
+    asm::Assembly,
+    basic_block::{BasicBlock, Handler},
+    call_site::CallSite,
+    cil_node::CILNode,
+    cil_root::CILRoot,
+    cil_tree::CILTree,
+    conv_i32, conv_isize, conv_usize,
+    field_desc::FieldDescriptor,
+    ldc_i32,
+    method::{Method, MethodType},
+    static_field_desc::StaticFieldDescriptor,
+    type_def::TypeDef,
+    DotnetTypeRef, FnSig, Type,
+};
+
+/// The name of the thread static holding the message of the panic currently being raised.
+pub const PANIC_MESSAGE: &str = "rust_panic_message";
+/// The name of the thread static holding a pointer to the exception object of the Rust panic in flight.
+pub const EXCEPTION_IN_FLIGHT: &str = "rust_exception_in_flight";
+/// The name of the builtin implementing the `catch_unwind` intrinsic.
+pub const CATCH_UNWIND: &str = "catch_unwind";
+/// The managed exception used to propagate Rust panics.
+#[must_use]
+pub fn rust_exception() -> DotnetTypeRef {
+    DotnetTypeRef::new::<&str, _>(None, "RustException").with_valuetype(false)
+}
+fn system_exception() -> DotnetTypeRef {
+    DotnetTypeRef::new(Some("System.Runtime"), "System.Exception").with_valuetype(false)
+}
+/// The thread static holding the message of the panic currently being raised, or null.
+#[must_use]
+pub fn panic_message() -> StaticFieldDescriptor {
+    StaticFieldDescriptor::new(
+        None,
+        Type::from(DotnetTypeRef::string_type()),
+        PANIC_MESSAGE.into(),
+    )
+}
+/// The thread static holding a pointer to the exception object of the Rust panic in flight, or 0.
+#[must_use]
+pub fn exception_in_flight() -> StaticFieldDescriptor {
+    StaticFieldDescriptor::new(None, Type::ISize, EXCEPTION_IN_FLIGHT.into())
+}
+/// The name of the builtin setting the message of the next raised panic.
+pub const SET_PANIC_MESSAGE: &str = "set_panic_message";
+/// Sets the message of the next raised panic to the UTF-8 string at `ptr`, `len` bytes long. If `ptr` is null, the
+/// panic payload is not a string, and a generic message is used instead.
+#[must_use]
+pub fn set_panic_message(ptr: CILNode, len: CILNode) -> CILRoot {
+    CILRoot::Call {
+        site: set_panic_message_site(),
+        args: [conv_isize!(ptr), conv_usize!(len)].into(),
+    }
+}
+fn set_panic_message_site() -> CallSite {
+    CallSite::builtin(
+        SET_PANIC_MESSAGE.into(),
+        FnSig::new(&[Type::ISize, Type::USize], Type::Void),
+        true,
+    )
+}
+fn set_panic_message_method() -> Method {
+    let message = CILNode::Call {
+        site: CallSite::boxed(
+            Some(DotnetTypeRef::marshal()),
+            "PtrToStringUTF8".into(),
+            FnSig::new(
+                &[Type::ISize, Type::I32],
+                Type::from(DotnetTypeRef::string_type()),
+            ),
+            true,
+        ),
+        args: [CILNode::LDArg(0), conv_i32!(CILNode::LDArg(1))].into(),
+    };
+    let site = set_panic_message_site();
+    Method::new(
+        AccessModifer::Private,
+        MethodType::Static,
+        site.signature().clone(),
+        site.name(),
+        vec![],
+        vec![
+            BasicBlock::new(
+                vec![
+                    CILRoot::BTrue {
+                        target: 1,
+                        sub_target: 0,
+                        cond: CILNode::Eq(
+                            CILNode::LDArg(0).into(),
+                            conv_isize!(ldc_i32!(0)).into(),
+                        ),
+                    }
+                    .into(),
+                    CILRoot::SetStaticField {
+                        descr: panic_message(),
+                        value: message,
+                    }
+                    .into(),
+                    CILRoot::VoidRet.into(),
+                ],
+                0,
+                None,
+            ),
+            BasicBlock::new(
+                vec![
+                    CILRoot::SetStaticField {
+                        descr: panic_message(),
+                        value: CILNode::LdStr("Rust panic".into()),
+                    }
+                    .into(),
+                    CILRoot::VoidRet.into(),
+                ],
+                1,
+                None,
+            ),
+        ],
+        vec![Some("ptr".into()), Some("len".into())],
+    )
+}
+/// The signature of the `try_fn` argument of `catch_unwind`.
+fn try_fn_sig() -> FnSig {
+    FnSig::new(&[Type::Ptr(Type::U8.into())], Type::Void)
+}
+/// The signature of the `catch_fn` argument of `catch_unwind`.
+fn catch_fn_sig() -> FnSig {
+    FnSig::new(
+        &[Type::Ptr(Type::U8.into()), Type::Ptr(Type::U8.into())],
+        Type::Void,
+    )
+}
+/// The call site of the builtin implementing the `catch_unwind` intrinsic.
+#[must_use]
+pub fn catch_unwind_site() -> CallSite {
+    CallSite::builtin(
+        CATCH_UNWIND.into(),
+        FnSig::new(
+            &[
+                Type::DelegatePtr(try_fn_sig().into()),
+                Type::Ptr(Type::U8.into()),
+                Type::DelegatePtr(catch_fn_sig().into()),
+            ],
+            Type::I32,
+        ),
+        true,
+    )
+}
+fn rust_exception_def() -> TypeDef {
+    let ctor = Method::new(
+        AccessModifer::Public,
+        MethodType::Instance,
+        FnSig::new(
+            &[
+                Type::DotnetType(rust_exception().into()),
+                Type::ISize,
+                Type::from(DotnetTypeRef::string_type()),
+            ],
+            Type::Void,
+        ),
+        ".ctor",
+        vec![],
+        vec![BasicBlock::new(
+            vec![
+                CILRoot::Call {
+                    site: CallSite::new(
+                        Some(system_exception()),
+                        ".ctor".into(),
+                        FnSig::new(
+                            &[
+                                Type::DotnetType(system_exception().into()),
+                                Type::from(DotnetTypeRef::string_type()),
+                            ],
+                            Type::Void,
+                        ),
+                        false,
+                    ),
+                    args: [CILNode::LDArg(0), CILNode::LDArg(2)].into(),
+                }
+                .into(),
+                CILRoot::SetField {
+                    addr: CILNode::LDArg(0),
+                    value: CILNode::LDArg(1),
+                    desc: FieldDescriptor::new(rust_exception(), Type::ISize, "exception".into()),
+                }
+                .into(),
+                CILRoot::VoidRet.into(),
+            ],
+            0,
+            None,
+        )],
+        vec![Some("exception".into()), Some("message".into())],
+    );
+    TypeDef::new(
+        AccessModifer::Public,
+        "RustException".into(),
+        vec![],
+        vec![("exception".into(), Type::ISize)],
+        vec![ctor],
+        None,
+        0,
+        Some(system_exception()),
+        None,
+    )
+}
+/// Implements `_Unwind_RaiseException`, called as `call`. Instead of unwinding the stack, it throws a `RustException`.
+#[must_use]
+pub fn raise_exception(call: &CallSite, c_mode: bool) -> Method {
+    let mut trees = Vec::new();
+    if c_mode {
+        trees.push(
+            CILRoot::SetStaticField {
+                descr: exception_in_flight(),
+                value: conv_isize!(CILNode::LDArg(0)),
+            }
+            .into(),
+        );
+    }
+    trees.push(
+        CILRoot::Throw(CILNode::NewObj {
+            site: CallSite::boxed(
+                Some(rust_exception()),
+                ".ctor".into(),
+                FnSig::new(
+                    &[
+                        Type::DotnetType(rust_exception().into()),
+                        Type::ISize,
+                        Type::from(DotnetTypeRef::string_type()),
+                    ],
+                    Type::Void,
+                ),
+                false,
+            ),
+            args: [
+                conv_isize!(CILNode::LDArg(0)),
+                CILNode::LDStaticField(panic_message().into()),
+            ]
+            .into(),
+        })
+        .into(),
+    );
+    Method::new(
+        AccessModifer::Private,
+        MethodType::Static,
+        call.signature().clone(),
+        call.name(),
+        vec![],
+        vec![BasicBlock::new(trees, 0, None)],
+        vec![Some("exception".into())],
+    )
+}
+/// Checks if the exception caught by the handler of `catch_unwind` is a Rust panic, jumping to block 4 if it is not.
+/// Otherwise, stores the pointer to its exception object in local 0.
+fn recognize_panic(c_mode: bool) -> Vec<CILTree> {
+    if c_mode {
+        vec![
+            CILRoot::BTrue {
+                target: 4,
+                sub_target: 0,
+                cond: CILNode::Eq(
+                    CILNode::LDStaticField(exception_in_flight().into()).into(),
+                    conv_isize!(ldc_i32!(0)).into(),
+                ),
+            }
+            .into(),
+            CILRoot::STLoc {
+                local: 0,
+                tree: CILNode::LDStaticField(exception_in_flight().into()),
+            }
+            .into(),
+            CILRoot::SetStaticField {
+                descr: exception_in_flight(),
+                value: conv_isize!(ldc_i32!(0)),
+            }
+            .into(),
+        ]
+    } else {
+        vec![
+            CILRoot::StoreCaught { local: 1 }.into(),
+            CILRoot::STLoc {
+                local: 2,
+                tree: CILNode::IsInst {
+                    obj: CILNode::LDLoc(1).into(),
+                    class: rust_exception().into(),
+                },
+            }
+            .into(),
+            CILRoot::BFalse {
+                target: 4,
+                sub_target: 0,
+                cond: CILNode::LDLoc(2),
+            }
+            .into(),
+            CILRoot::STLoc {
+                local: 0,
+                tree: CILNode::LDField {
+                    addr: CILNode::LDLoc(2).into(),
+                    field: FieldDescriptor::new(rust_exception(), Type::ISize, "exception".into())
+                        .into(),
+                },
+            }
+            .into(),
+        ]
+    }
+}
+/// Implements the `catch_unwind` intrinsic: calls `try_fn(data)`, returning 0. If a Rust panic occurs, calls
+/// `catch_fn(data, exception)` and returns 1.
+fn catch_unwind(c_mode: bool) -> Method {
+    let call_try = CILRoot::CallI {
+        sig: try_fn_sig(),
+        fn_ptr: CILNode::LDArg(0),
+        args: [CILNode::LDArg(1)].into(),
+    };
+    let blocks = if crate::config::config().no_unwind {
+        vec![BasicBlock::new(
+            vec![call_try.into(), CILRoot::Ret { tree: ldc_i32!(0) }.into()],
+            0,
+            None,
+        )]
+    } else {
+        let mut catch = recognize_panic(c_mode);
+        catch.extend([
+            CILRoot::CallI {
+                sig: catch_fn_sig(),
+                fn_ptr: CILNode::LDArg(2),
+                args: [
+                    CILNode::LDArg(1),
+                    CILNode::TransmutePtr {
+                        val: CILNode::LDLoc(0).into(),
+                        new_ptr: Type::Ptr(Type::U8.into()).into(),
+                    },
+                ]
+                .into(),
+            }
+            .into(),
+            CILRoot::JumpingPad {
+                source: 0,
+                target: 3,
+            }
+            .into(),
+        ]);
+        let handler = [
+            BasicBlock::new(catch, 2, None),
+            // Not a Rust panic.
+            BasicBlock::new(vec![CILRoot::ReThrow.into()], 4, None),
+        ];
+        let mut try_block = BasicBlock::new(
+            vec![
+                call_try.into(),
+                CILRoot::GoTo {
+                    target: 1,
+                    sub_target: 0,
+                }
+                .into(),
+            ],
+            0,
+            Some(Handler::RawID(2)),
+        );
+        try_block.resolve_exception_handlers(&handler);
+        vec![
+            try_block,
+            BasicBlock::new(vec![CILRoot::Ret { tree: ldc_i32!(0) }.into()], 1, None),
+            BasicBlock::new(vec![CILRoot::Ret { tree: ldc_i32!(1) }.into()], 3, None),
+        ]
+    };
+    let mut locals = vec![(Some("exception".into()), Type::ISize)];
+    if !c_mode {
+        locals.extend([
+            (
+                Some("caught".into()),
+                Type::DotnetType(DotnetTypeRef::object_type().into()),
+            ),
+            (
+                Some("rust_exception".into()),
+                Type::DotnetType(rust_exception().into()),
+            ),
+        ]);
+    }
+    let site = catch_unwind_site();
+    Method::new(
+        AccessModifer::Private,
+        MethodType::Static,
+        site.signature().clone(),
+        site.name(),
+        locals,
+        blocks,
+        vec![
+            Some("try_fn".into()),
+            Some("data".into()),
+            Some("catch_fn".into()),
+        ],
+    )
+}
+/// Adds the `RustException` type, the statics tracking panics in flight, and the builtins used to raise and catch them.
+///
+/// `c_mode` selects how `catch_unwind` recognizes Rust panics, and must match the mode passed to [`raise_exception`].
+pub fn add_unwind_support(asm: &mut Assembly, c_mode: bool) {
+    asm.add_typedef(rust_exception_def());
+    asm.add_thread_static(Type::from(DotnetTypeRef::string_type()), PANIC_MESSAGE);
+    if c_mode {
+        asm.add_thread_static(Type::ISize, EXCEPTION_IN_FLIGHT);
+    }
+    asm.add_method(set_panic_message_method());
+    asm.add_method(catch_unwind(c_mode));
+}
+#[test]
+fn catch_unwind_handler() {
+    for c_mode in [false, true] {
+        let mut asm = Assembly::empty();
+        add_unwind_support(&mut asm, c_mode);
+        assert!(asm.contains_fn(&catch_unwind_site()));
+        assert_eq!(asm.is_thread_static(EXCEPTION_IN_FLIGHT), c_mode);
+        let method = asm
+            .methods()
+            .find(|method| method.name() == CATCH_UNWIND)
+            .unwrap();
+        let handler = method.blocks()[0]
+            .handler()
+            .and_then(Handler::as_blocks)
+            .unwrap();
+        // The jumpstarter, the block calling `catch_fn`, and the block rethrowing foreign exceptions.
+        assert_eq!(handler.len(), 3);
+        // Outside of C mode, the caught object itself is checked to be a `RustException`.
+        assert_eq!(
+            crate::basic_block::caught_local(handler),
+            (!c_mode).then_some(1)
+        );
+        let checks_class = handler
+            .iter()
+            .flat_map(|block| {
+                block
+                    .trees()
+                    .iter()
+                    .flat_map(|tree| tree.root().into_iter())
+            })
+            .any(|elem| {
+                matches!(
+                    elem,
+                    crate::cil_iter::CILIterElem::Node(CILNode::IsInst { .. })
+                )
+            });
+        assert_eq!(checks_class, !c_mode);
+    }
+}
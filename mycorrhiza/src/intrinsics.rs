@@ -70,6 +70,37 @@ impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str>
     pub fn to_mstring(self) -> crate::system::MString {
         self.instance0::<"ToString", crate::system::MString>()
     }
+    /// Upcasts this reference to `System.Object`. Always valid, since every class derives from it.
+    #[inline(always)]
+    pub fn as_object(self) -> crate::system::Object {
+        unsafe { core::mem::transmute::<Self, crate::system::Object>(self) }
+    }
+}
+/// A .NET struct. Only structs with the size of a pointer(like `GCHandle`) are supported. Instance methods get a
+/// pointer to the struct as `this`.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct RustcCLRInteropManagedStruct<
+    const ASSEMBLY: &'static str,
+    const CLASS_PATH: &'static str,
+> {
+    size_hint: usize,
+}
+impl<const ASSEMBLY: &'static str, const CLASS_PATH: &'static str>
+    RustcCLRInteropManagedStruct<ASSEMBLY, CLASS_PATH>
+{
+    #[inline(always)]
+    pub fn static1<const METHOD: &'static str, Arg1, Ret>(arg1: Arg1) -> Ret {
+        rustc_clr_interop_managed_call1_::<ASSEMBLY, CLASS_PATH, true, METHOD, true, Ret, Arg1>(
+            arg1,
+        )
+    }
+    #[inline(always)]
+    pub fn instance0<const METHOD: &'static str, Ret>(&mut self) -> Ret {
+        rustc_clr_interop_managed_call1_::<ASSEMBLY, CLASS_PATH, true, METHOD, false, Ret, *mut Self>(
+            self,
+        )
+    }
 }
 #[derive(Clone, Copy)]
 #[repr(C)]
@@ -12,6 +12,8 @@ pub mod intrinsics;
 pub mod std;
 /// Wrappers around types from the `System` namespace
 pub mod system;
+/// Bridges between Rust futures and .NET tasks
+pub mod task;
 /// C# `char` type
 pub type DotNetChar = crate::intrinsics::RustcCLRInteropManagedChar;
 
@@ -2,5 +2,45 @@ pub mod console;
 pub mod diagnostics;
 pub mod runtime;
 pub mod text;
+pub mod threading;
+pub type Object =
+    crate::intrinsics::RustcCLRInteropManagedClass<"System.Runtime", "System.Object">;
+impl Object {
+    /// A null reference.
+    #[inline(always)]
+    pub fn null() -> Self {
+        unsafe { core::mem::transmute::<usize, Self>(0) }
+    }
+}
 pub type MString =
     crate::intrinsics::RustcCLRInteropManagedClass<"System.Runtime", "System.String">;
+impl MString {
+    /// Creates a .NET string from a Rust one.
+    #[inline(always)]
+    pub fn new(string: &str) -> Self {
+        runtime::interop_services::Marshal::ptr_to_string_utf8(string.as_ptr(), string.len())
+    }
+    /// Returns the interned string equal to `self`. All interned strings with the same value are the same object.
+    #[inline(always)]
+    pub fn intern(self) -> Self {
+        Self::static1::<"Intern", Self, Self>(self)
+    }
+}
+/// A delegate taking no arguments.
+pub type Action =
+    crate::intrinsics::RustcCLRInteropManagedClass<"System.Runtime", "System.Action">;
+impl Action {
+    /// Creates a delegate calling `callback`.
+    #[inline(always)]
+    pub fn new(callback: fn()) -> Self {
+        Self::ctor2::<Object, isize>(Object::null(), callback as usize as isize)
+    }
+}
+pub type Exception =
+    crate::intrinsics::RustcCLRInteropManagedClass<"System.Runtime", "System.Exception">;
+impl Exception {
+    #[inline(always)]
+    pub fn new(message: MString) -> Self {
+        Self::ctor1(message)
+    }
+}
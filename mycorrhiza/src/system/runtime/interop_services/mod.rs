@@ -1 +1,50 @@
-
+pub type Marshal = crate::intrinsics::RustcCLRInteropManagedClass<
+    "System.Runtime.InteropServices",
+    "System.Runtime.InteropServices.Marshal",
+>;
+impl Marshal {
+    #[inline(always)]
+    pub fn alloc_hglobal(size: usize) -> *mut u8 {
+        Self::static1::<"AllocHGlobal", isize, isize>(size as isize) as *mut u8
+    }
+    #[inline(always)]
+    pub fn free_hglobal(ptr: *mut u8) {
+        Self::static1::<"FreeHGlobal", isize, ()>(ptr as isize)
+    }
+    /// Decodes `len` bytes of UTF8 at `ptr` into a .NET string.
+    #[inline(always)]
+    pub fn ptr_to_string_utf8(ptr: *const u8, len: usize) -> crate::system::MString {
+        Self::static2::<"PtrToStringUTF8", isize, i32, crate::system::MString>(
+            ptr as isize,
+            len as i32,
+        )
+    }
+}
+/// A handle to a .NET object, which keeps it alive until freed. Can be converted to and from a pointer, so Rust code
+/// can store references to objects in memory the GC does not scan.
+pub type GCHandle = crate::intrinsics::RustcCLRInteropManagedStruct<
+    "System.Runtime",
+    "System.Runtime.InteropServices.GCHandle",
+>;
+impl GCHandle {
+    #[inline(always)]
+    pub fn alloc(target: crate::system::Object) -> Self {
+        Self::static1::<"Alloc", crate::system::Object, Self>(target)
+    }
+    #[inline(always)]
+    pub fn to_intptr(self) -> isize {
+        Self::static1::<"ToIntPtr", Self, isize>(self)
+    }
+    #[inline(always)]
+    pub fn from_intptr(ptr: isize) -> Self {
+        Self::static1::<"FromIntPtr", isize, Self>(ptr)
+    }
+    #[inline(always)]
+    pub fn target(&mut self) -> crate::system::Object {
+        self.instance0::<"get_Target", crate::system::Object>()
+    }
+    #[inline(always)]
+    pub fn free(&mut self) {
+        self.instance0::<"Free", ()>()
+    }
+}
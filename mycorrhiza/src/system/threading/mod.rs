@@ -0,0 +1,61 @@
+pub mod tasks;
+use crate::system::Object;
+pub type Thread = crate::intrinsics::RustcCLRInteropManagedClass<
+    "System.Threading.Thread",
+    "System.Threading.Thread",
+>;
+impl Thread {
+    /// Lets the OS run another thread. Returns `false` if there was no other thread ready to run.
+    #[inline(always)]
+    pub fn yield_now() -> bool {
+        Self::static0::<"Yield", bool>()
+    }
+    #[inline(always)]
+    pub fn sleep(milliseconds: i32) {
+        Self::static1::<"Sleep", i32, ()>(milliseconds)
+    }
+}
+pub type Monitor =
+    crate::intrinsics::RustcCLRInteropManagedClass<"System.Threading", "System.Threading.Monitor">;
+impl Monitor {
+    #[inline(always)]
+    pub fn enter(obj: Object) {
+        Self::static1::<"Enter", Object, ()>(obj)
+    }
+    #[inline(always)]
+    pub fn exit(obj: Object) {
+        Self::static1::<"Exit", Object, ()>(obj)
+    }
+    /// Releases the lock on `obj`, and blocks until another thread pulses it. The lock is reacquired before returning.
+    #[inline(always)]
+    pub fn wait(obj: Object) -> bool {
+        Self::static1::<"Wait", Object, bool>(obj)
+    }
+    #[inline(always)]
+    pub fn pulse_all(obj: Object) {
+        Self::static1::<"PulseAll", Object, ()>(obj)
+    }
+}
+/// A delegate run by the thread pool, getting the state passed when it was queued.
+pub type WaitCallback = crate::intrinsics::RustcCLRInteropManagedClass<
+    "System.Threading.ThreadPool",
+    "System.Threading.WaitCallback",
+>;
+impl WaitCallback {
+    /// Creates a delegate calling `callback`.
+    #[inline(always)]
+    pub fn new(callback: fn(Object)) -> Self {
+        Self::ctor2::<Object, isize>(Object::null(), callback as usize as isize)
+    }
+}
+pub type ThreadPool = crate::intrinsics::RustcCLRInteropManagedClass<
+    "System.Threading.ThreadPool",
+    "System.Threading.ThreadPool",
+>;
+impl ThreadPool {
+    /// Runs `callback(state)` on a thread pool thread.
+    #[inline(always)]
+    pub fn unsafe_queue_user_work_item(callback: WaitCallback, state: Object) -> bool {
+        Self::static2::<"UnsafeQueueUserWorkItem", WaitCallback, Object, bool>(callback, state)
+    }
+}
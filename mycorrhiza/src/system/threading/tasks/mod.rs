@@ -0,0 +1,69 @@
+use crate::system::{Action, Exception};
+pub type Task =
+    crate::intrinsics::RustcCLRInteropManagedClass<"System.Runtime", "System.Threading.Tasks.Task">;
+impl Task {
+    /// A task that has already completed successfully.
+    #[inline(always)]
+    pub fn completed() -> Self {
+        Self::static0::<"get_CompletedTask", Self>()
+    }
+    /// A task that completes after `milliseconds` pass, without blocking any thread.
+    #[inline(always)]
+    pub fn delay(milliseconds: i32) -> Self {
+        Self::static1::<"Delay", i32, Self>(milliseconds)
+    }
+    #[inline(always)]
+    pub fn is_completed(self) -> bool {
+        self.instance0::<"get_IsCompleted", bool>()
+    }
+    #[inline(always)]
+    pub fn is_faulted(self) -> bool {
+        self.instance0::<"get_IsFaulted", bool>()
+    }
+    #[inline(always)]
+    pub fn is_canceled(self) -> bool {
+        self.instance0::<"get_IsCanceled", bool>()
+    }
+    /// Schedules `continuation` to run once the task completes(right away, if it already did).
+    #[inline(always)]
+    pub fn unsafe_on_completed(mut self, continuation: Action) {
+        // The `TaskAwaiter` struct only holds the awaited task, so a pointer to the task is a valid `this`.
+        crate::intrinsics::rustc_clr_interop_managed_call2_::<
+            "System.Runtime",
+            "System.Runtime.CompilerServices.TaskAwaiter",
+            true,
+            "UnsafeOnCompleted",
+            false,
+            (),
+            *mut Self,
+            Action,
+        >(&mut self, continuation)
+    }
+}
+pub type TaskCompletionSource = crate::intrinsics::RustcCLRInteropManagedClass<
+    "System.Runtime",
+    "System.Threading.Tasks.TaskCompletionSource",
+>;
+impl TaskCompletionSource {
+    #[inline(always)]
+    pub fn new() -> Self {
+        Self::ctor0()
+    }
+    /// The task controlled by this source.
+    #[inline(always)]
+    pub fn task(self) -> Task {
+        self.instance0::<"get_Task", Task>()
+    }
+    #[inline(always)]
+    pub fn try_set_result(self) -> bool {
+        self.instance0::<"TrySetResult", bool>()
+    }
+    #[inline(always)]
+    pub fn try_set_exception(self, exception: Exception) -> bool {
+        self.instance1::<"TrySetException", Exception, bool>(exception)
+    }
+    #[inline(always)]
+    pub fn try_set_canceled(self) -> bool {
+        self.instance0::<"TrySetCanceled", bool>()
+    }
+}
@@ -0,0 +1,431 @@
+use crate::system::runtime::interop_services::{GCHandle, Marshal};
+use crate::system::threading::tasks::{Task, TaskCompletionSource};
+use crate::system::threading::{Monitor, ThreadPool, WaitCallback};
+use crate::system::{Action, Exception, MString, Object};
+use core::cell::UnsafeCell;
+use core::future::{Future, IntoFuture};
+use core::marker::PhantomPinned;
+use core::mem::ManuallyDrop;
+use core::pin::{pin, Pin};
+use core::ptr::null_mut;
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+/// A minimal spinlock, guarding the intrusive lists below. Critical sections only update a few pointers.
+struct SpinLock<T> {
+    locked: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+// SAFETY: the value is only accessed with the lock held.
+unsafe impl<T> Sync for SpinLock<T> {}
+impl<T> SpinLock<T> {
+    const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+    fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        while self
+            .locked
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            core::hint::spin_loop();
+        }
+        // SAFETY: the lock is held, so this is the only reference.
+        let res = f(unsafe { &mut *self.value.get() });
+        self.locked.store(0, Ordering::Release);
+        res
+    }
+}
+/// Incremented each time any waker created by [`block_on`] is woken up.
+/// A shared counter can't dangle, and can't lose wakeups: at worst, an executor polls its future for no reason.
+static WAKE_COUNT: AtomicUsize = AtomicUsize::new(0);
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake, drop_waker);
+fn clone_waker(_: *const ()) -> RawWaker {
+    RawWaker::new(core::ptr::null(), &VTABLE)
+}
+/// The object [`block_on`] waits on. Interned, so every thread gets the same one.
+fn wake_monitor() -> Object {
+    MString::new("mycorrhiza.task.block_on")
+        .intern()
+        .as_object()
+}
+fn wake(_: *const ()) {
+    let monitor = wake_monitor();
+    Monitor::enter(monitor);
+    WAKE_COUNT.fetch_add(1, Ordering::Release);
+    Monitor::pulse_all(monitor);
+    Monitor::exit(monitor);
+}
+fn drop_waker(_: *const ()) {}
+/// Runs `fut` to completion on the current thread. While the future is pending, the thread sleeps until it is woken.
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    // SAFETY: the vtable functions ignore the data pointer, so they can't access invalid memory.
+    let waker = unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) };
+    let mut cx = Context::from_waker(&waker);
+    let mut fut = pin!(fut);
+    loop {
+        let wake_count = WAKE_COUNT.load(Ordering::Acquire);
+        if let Poll::Ready(val) = fut.as_mut().poll(&mut cx) {
+            return val;
+        }
+        // The count is incremented with the monitor held, so a wakeup can't happen between the check and the wait.
+        let monitor = wake_monitor();
+        Monitor::enter(monitor);
+        while WAKE_COUNT.load(Ordering::Acquire) == wake_count {
+            Monitor::wait(monitor);
+        }
+        Monitor::exit(monitor);
+    }
+}
+/// The reason a .NET [`Task`] did not complete successfully.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskError {
+    /// The task threw an exception.
+    Faulted,
+    /// The task was canceled.
+    Canceled,
+}
+/// All the [`ManagedTask`]s waiting for their .NET task to complete. Walked by [`wake_completed`].
+static PENDING: SpinLock<*mut ManagedTask> = SpinLock::new(null_mut());
+/// A future which completes when the underlying .NET [`Task`] does.
+pub struct ManagedTask {
+    task: Task,
+    /// The waker passed to the last poll. Guarded by [`PENDING`].
+    waker: Option<Waker>,
+    /// The neighbours in the list of pending tasks. Guarded by [`PENDING`].
+    prev: *mut ManagedTask,
+    next: *mut ManagedTask,
+    linked: bool,
+    /// Set once a continuation waking this future was registered on `task`.
+    registered: bool,
+    /// Pending tasks are linked into [`PENDING`], so they must not move.
+    _pinned: PhantomPinned,
+}
+// SAFETY: the list pointers are only accessed with `PENDING` locked.
+unsafe impl Send for ManagedTask {}
+impl ManagedTask {
+    pub fn new(task: Task) -> Self {
+        Self {
+            task,
+            waker: None,
+            prev: null_mut(),
+            next: null_mut(),
+            linked: false,
+            registered: false,
+            _pinned: PhantomPinned,
+        }
+    }
+    pub fn task(&self) -> Task {
+        self.task
+    }
+    /// Links `node` into the list starting at `head`, if it is not there already.
+    unsafe fn link(head: &mut *mut Self, node: *mut Self) {
+        if (*node).linked {
+            return;
+        }
+        (*node).prev = null_mut();
+        (*node).next = *head;
+        if !head.is_null() {
+            (**head).prev = node;
+        }
+        *head = node;
+        (*node).linked = true;
+    }
+    /// Removes `node` from the list starting at `head`, if it is there.
+    unsafe fn unlink(head: &mut *mut Self, node: *mut Self) {
+        if !(*node).linked {
+            return;
+        }
+        let (prev, next) = ((*node).prev, (*node).next);
+        if prev.is_null() {
+            *head = next;
+        } else {
+            (*prev).next = next;
+        }
+        if !next.is_null() {
+            (*next).prev = prev;
+        }
+        (*node).linked = false;
+    }
+}
+/// Registered as the continuation of awaited tasks. Wakes the futures whose tasks completed.
+fn wake_completed() {
+    // Wakers are woken with the lock released, since waking may poll or drop other pending tasks.
+    loop {
+        let waker = PENDING.with(|head| {
+            let mut node = *head;
+            while !node.is_null() {
+                // SAFETY: linked nodes are pinned, and unlink themselves before being dropped.
+                unsafe {
+                    if (*node).task.is_completed() {
+                        ManagedTask::unlink(head, node);
+                        return Some((*node).waker.take());
+                    }
+                    node = (*node).next;
+                }
+            }
+            None
+        });
+        match waker {
+            Some(waker) => waker.into_iter().for_each(Waker::wake),
+            None => return,
+        }
+    }
+}
+impl Future for ManagedTask {
+    type Output = Result<(), TaskError>;
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // SAFETY: the future is never moved out of. Its address is only shared with `PENDING`, which it leaves when
+        // dropped.
+        let this = unsafe { self.get_unchecked_mut() };
+        let task = this.task;
+        if !task.is_completed() {
+            let old_waker = PENDING.with(|head| {
+                let old_waker = this.waker.replace(cx.waker().clone());
+                // SAFETY: `this` is pinned.
+                unsafe { ManagedTask::link(head, this) };
+                old_waker
+            });
+            drop(old_waker);
+            // The future is linked before the continuation is registered, so a task completing in between can't be
+            // missed.
+            if !this.registered {
+                this.registered = true;
+                task.unsafe_on_completed(Action::new(wake_completed));
+            }
+            return Poll::Pending;
+        }
+        if task.is_canceled() {
+            Poll::Ready(Err(TaskError::Canceled))
+        } else if task.is_faulted() {
+            Poll::Ready(Err(TaskError::Faulted))
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+impl Drop for ManagedTask {
+    fn drop(&mut self) {
+        if self.registered {
+            let node: *mut Self = self;
+            // SAFETY: only the list itself is accessed trough `node`.
+            PENDING.with(|head| unsafe { ManagedTask::unlink(head, node) });
+        }
+    }
+}
+impl IntoFuture for Task {
+    type Output = Result<(), TaskError>;
+    type IntoFuture = ManagedTask;
+    fn into_future(self) -> ManagedTask {
+        ManagedTask::new(self)
+    }
+}
+/// Completes after `milliseconds` pass, using a .NET timer.
+pub fn delay(milliseconds: i32) -> ManagedTask {
+    ManagedTask::new(Task::delay(milliseconds))
+}
+// The states of a spawned future.
+/// Not running, and not scheduled to run.
+const IDLE: usize = 0;
+/// In the ready queue, waiting for a thread pool thread.
+const SCHEDULED: usize = 1;
+/// Being polled.
+const RUNNING: usize = 2;
+/// Woken while being polled, so it gets polled again.
+const WOKEN: usize = 3;
+/// Completed, and its task got the result.
+const DONE: usize = 4;
+/// The type-erased part of a future spawned by [`into_task`], driven by the .NET thread pool.
+struct Header {
+    state: AtomicUsize,
+    /// References held by wakers and the ready queue. The future is freed once the last one is dropped.
+    refs: AtomicUsize,
+    /// The next future in the ready queue.
+    next_ready: *mut Header,
+    /// A [`GCHandle`] keeping the `TaskCompletionSource` alive, converted to a pointer.
+    source: isize,
+    /// Polls the future. Returns `true` once it completed.
+    poll: unsafe fn(*mut Header) -> bool,
+    /// Frees the future, canceling its task if it did not complete.
+    free: unsafe fn(*mut Header),
+}
+#[repr(C)]
+struct Spawned<F> {
+    header: Header,
+    /// Dropped as soon as it completes.
+    fut: ManuallyDrop<F>,
+}
+/// The futures waiting for a thread pool thread to poll them.
+static READY: SpinLock<*mut Header> = SpinLock::new(null_mut());
+static SPAWNED_VTABLE: RawWakerVTable = RawWakerVTable::new(
+    clone_spawned,
+    wake_spawned,
+    wake_spawned_by_ref,
+    drop_spawned,
+);
+/// Pushes `header` into the ready queue, which takes over a reference to it, and queues a thread pool work item
+/// polling it.
+unsafe fn schedule(header: *mut Header) {
+    READY.with(|head| {
+        (*header).next_ready = *head;
+        *head = header;
+    });
+    ThreadPool::unsafe_queue_user_work_item(WaitCallback::new(run_ready), Object::null());
+}
+/// Run by the thread pool: polls one future from the ready queue.
+fn run_ready(_state: Object) {
+    let header = READY.with(|head| {
+        let header = *head;
+        if !header.is_null() {
+            // SAFETY: futures in the queue are alive, since the queue holds a reference to them.
+            *head = unsafe { (*header).next_ready };
+        }
+        header
+    });
+    if header.is_null() {
+        return;
+    }
+    // SAFETY: the reference taken over from the queue keeps the future alive until `release`.
+    unsafe {
+        (*header).state.store(RUNNING, Ordering::Release);
+        loop {
+            if ((*header).poll)(header) {
+                (*header).state.store(DONE, Ordering::Release);
+                break;
+            }
+            if (*header)
+                .state
+                .compare_exchange(RUNNING, IDLE, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                break;
+            }
+            // Woken while being polled.
+            (*header).state.store(RUNNING, Ordering::Release);
+        }
+        release(header);
+    }
+}
+/// Drops a reference to `header`, freeing it if it was the last one.
+unsafe fn release(header: *mut Header) {
+    if (*header).refs.fetch_sub(1, Ordering::Release) == 1 {
+        fence(Ordering::Acquire);
+        ((*header).free)(header);
+    }
+}
+unsafe fn clone_spawned(data: *const ()) -> RawWaker {
+    let header = data.cast::<Header>();
+    (*header).refs.fetch_add(1, Ordering::Relaxed);
+    RawWaker::new(data, &SPAWNED_VTABLE)
+}
+unsafe fn wake_spawned(data: *const ()) {
+    wake_spawned_by_ref(data);
+    drop_spawned(data);
+}
+unsafe fn wake_spawned_by_ref(data: *const ()) {
+    let header = data.cast_mut().cast::<Header>();
+    loop {
+        let state = (*header).state.load(Ordering::Acquire);
+        let next = match state {
+            IDLE => SCHEDULED,
+            RUNNING => WOKEN,
+            // Already going to be polled, or completed.
+            _ => return,
+        };
+        if (*header)
+            .state
+            .compare_exchange(state, next, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            if next == SCHEDULED {
+                (*header).refs.fetch_add(1, Ordering::Relaxed);
+                schedule(header);
+            }
+            return;
+        }
+    }
+}
+unsafe fn drop_spawned(data: *const ()) {
+    release(data.cast_mut().cast::<Header>());
+}
+/// Takes the `TaskCompletionSource` out of the handle in `header`, freeing the handle.
+unsafe fn take_source(header: *mut Header) -> TaskCompletionSource {
+    let mut handle = GCHandle::from_intptr((*header).source);
+    let source = core::mem::transmute::<Object, TaskCompletionSource>(handle.target());
+    handle.free();
+    source
+}
+unsafe fn poll_spawned<F: Future<Output = Result<(), Exception>>>(header: *mut Header) -> bool {
+    let spawned = header.cast::<Spawned<F>>();
+    // The waker borrows the reference held by `run_ready`, so it must not be dropped.
+    let waker = ManuallyDrop::new(Waker::from_raw(RawWaker::new(
+        header.cast_const().cast(),
+        &SPAWNED_VTABLE,
+    )));
+    let mut cx = Context::from_waker(&waker);
+    // SAFETY: the future lives in memory which is never moved, and is dropped in place.
+    let fut = Pin::new_unchecked(&mut *(*spawned).fut);
+    let Poll::Ready(res) = fut.poll(&mut cx) else {
+        return false;
+    };
+    ManuallyDrop::drop(&mut (*spawned).fut);
+    let source = take_source(header);
+    match res {
+        Ok(()) => source.try_set_result(),
+        Err(exception) => source.try_set_exception(exception),
+    };
+    true
+}
+unsafe fn free_spawned<F>(header: *mut Header) {
+    let spawned = header.cast::<Spawned<F>>();
+    if (*header).state.load(Ordering::Acquire) != DONE {
+        // Nothing can wake the future anymore, so it would never complete.
+        ManuallyDrop::drop(&mut (*spawned).fut);
+        take_source(header).try_set_canceled();
+    }
+    Marshal::free_hglobal(spawned.cast());
+}
+/// Drives `fut` on the .NET thread pool, returning the task completed with its result.
+fn spawn<F: Future<Output = Result<(), Exception>> + Send + 'static>(fut: F) -> Task {
+    assert!(
+        core::mem::align_of::<Spawned<F>>() <= 8,
+        "Futures with an alignment above 8 can't be spawned"
+    );
+    let source = TaskCompletionSource::new();
+    let task = source.task();
+    let spawned = Marshal::alloc_hglobal(core::mem::size_of::<Spawned<F>>()).cast::<Spawned<F>>();
+    // SAFETY: the memory was just allocated, and is big enough. The ready queue holds the only reference.
+    unsafe {
+        spawned.write(Spawned {
+            header: Header {
+                state: AtomicUsize::new(SCHEDULED),
+                refs: AtomicUsize::new(1),
+                next_ready: null_mut(),
+                source: GCHandle::alloc(source.as_object()).to_intptr(),
+                poll: poll_spawned::<F>,
+                free: free_spawned::<F>,
+            },
+            fut: ManuallyDrop::new(fut),
+        });
+        schedule(spawned.cast());
+    }
+    task
+}
+/// Exposes `fut` to .NET as a [`Task`], which C# code can `await`. The future is driven by the .NET thread pool, and
+/// the task completes when it does. If nothing can wake the future anymore, the task is canceled.
+pub fn into_task<F: Future<Output = ()> + Send + 'static>(fut: F) -> Task {
+    spawn(async move {
+        fut.await;
+        Ok(())
+    })
+}
+/// Exposes a fallible `fut` to .NET as a [`Task`]. If the future returns an error, the task is faulted with the
+/// exception created by `to_exception`.
+pub fn try_into_task<E, F: Future<Output = Result<(), E>> + Send + 'static>(
+    fut: F,
+    to_exception: impl FnOnce(E) -> Exception + Send + 'static,
+) -> Task {
+    spawn(async move { fut.await.map_err(to_exception) })
+}
@@ -10,7 +10,9 @@ use cilly::{
 };
 use rustc_index::IndexVec;
 use rustc_middle::mir::{AggregateKind, Operand, Place};
-use rustc_middle::ty::{AdtDef, AdtKind, GenericArg, Instance, List, ParamEnv, Ty, TyCtxt, TyKind};
+use rustc_middle::ty::{
+    AdtDef, AdtKind, CoroutineArgs, GenericArg, Instance, List, ParamEnv, Ty, TyCtxt, TyKind,
+};
 use rustc_target::abi::FieldIdx;
 /// Returns the CIL ops to create the aggreagate value specifed by `aggregate_kind` at `target_location`. Uses indivlidual values specifed by `value_index`
 pub fn handle_aggregate<'tyctx>(
@@ -200,6 +202,70 @@ pub fn handle_aggregate<'tyctx>(
                 )),
             )
         }
+        AggregateKind::Coroutine(_def_id, _args) => {
+            let coroutine_ty = crate::utilis::monomorphize(
+                &method_instance,
+                target_location.ty(method, tyctx),
+                tyctx,
+            )
+            .ty;
+            let coroutine_type = tycache.type_from_cache(coroutine_ty, tyctx, method_instance);
+            let Some(coroutine_dotnet) = coroutine_type.as_dotnet() else {
+                // A coroutine without any state is a ZST, so there is nothing to initialize.
+                return place_get(target_location, tyctx, method, method_instance, tycache);
+            };
+            let coroutine_getter = super::place::place_adress(
+                target_location,
+                tyctx,
+                method,
+                method_instance,
+                tycache,
+            );
+            let mut sub_trees = vec![];
+            // Set the upvars...
+            for (index, value) in value_index.iter_enumerated() {
+                let field_ty =
+                    crate::utilis::monomorphize(&method_instance, value.ty(method, tyctx), tyctx);
+                let field_ty = tycache.type_from_cache(field_ty, tyctx, method_instance);
+                if field_ty == Type::Void {
+                    continue;
+                }
+                sub_trees.push(CILRoot::SetField {
+                    addr: coroutine_getter.clone(),
+                    value: handle_operand(value, tyctx, method, method_instance, tycache),
+                    desc: FieldDescriptor::new(
+                        coroutine_dotnet.clone(),
+                        field_ty,
+                        format!("f_{}", index.as_u32()).into(),
+                    ),
+                });
+            }
+            // ... and mark the coroutine as not yet resumed.
+            let layout = tyctx
+                .layout_of(rustc_middle::ty::ParamEnvAnd {
+                    param_env: ParamEnv::reveal_all(),
+                    value: coroutine_ty,
+                })
+                .expect("Could not get type layout!");
+            sub_trees.push(set_discr(
+                layout.layout,
+                CoroutineArgs::UNRESUMED,
+                coroutine_getter,
+                &coroutine_dotnet,
+                tyctx,
+                coroutine_ty,
+            ));
+            CILNode::SubTrees(
+                sub_trees.into(),
+                Box::new(place_get(
+                    target_location,
+                    tyctx,
+                    method,
+                    method_instance,
+                    tycache,
+                )),
+            )
+        }
         AggregateKind::RawPtr(ptr, muta) => {
             let fat_ptr = Ty::new_ptr(
                 tyctx,
@@ -298,7 +364,28 @@ fn aggregate_adt<'tyctx>(
             );
 
             let mut sub_trees = Vec::new();
+            let is_vector =
+                crate::simd::vector_type(adt_type, tyctx, method_instance, type_cache).is_some();
             for field in fields {
+                if is_vector {
+                    let (field_ty, field_addr) = crate::simd::field_address(
+                        adt_type,
+                        field.0,
+                        obj_getter.clone(),
+                        tyctx,
+                        method_instance,
+                        type_cache,
+                    );
+                    sub_trees.push(crate::place::ptr_set_op(
+                        field_ty.into(),
+                        tyctx,
+                        &method_instance,
+                        type_cache,
+                        field_addr,
+                        field.1,
+                    ));
+                    continue;
+                }
                 let field_def = adt
                     .all_fields()
                     .nth(field.0 as usize)
@@ -1,7 +1,7 @@
 use crate::{
     basic_block::handler_for_block,
     cil::span_source_info,
-    codegen_error::{CodegenError, MethodCodegenError},
+    codegen_error::{panic_message, CodegenError, MethodCodegenError, Placeholder},
     r#type::{TyCache, Type},
     rustc_middle::dep_graph::DepContext,
     utilis::field_descrptor,
@@ -16,19 +16,23 @@ use cilly::{
     cil_node::CILNode,
     cil_root::CILRoot,
     cil_tree::CILTree,
-    conv_isize, conv_usize, ldc_u32, ldc_u64,
-    method::{Method, MethodType},
+    conv_isize, conv_usize,
+    custom_attribute::{CustomAttribute, AGGRESSIVE_INLINING, NO_INLINING},
+    ldc_u32, ldc_u64,
+    method::{Attribute, Method, MethodType},
     static_field_desc::StaticFieldDescriptor,
     FnSig,
 };
 use rustc_middle::{
+    middle::codegen_fn_attrs::InlineAttr,
     mir::{
         interpret::{AllocId, Allocation, GlobalAlloc},
         mono::MonoItem,
         Local, LocalDecl, Statement, Terminator,
     },
-    ty::{Instance, ParamEnv, TyCtxt, TyKind},
+    ty::{Instance, InstanceDef, ParamEnv, TyCtxt, TyKind},
 };
+use rustc_span::def_id::DefId;
 
 type LocalDefList = Vec<(Option<IString>, Type)>;
 type ArgsDebugInfo = Vec<Option<IString>>;
@@ -59,7 +63,7 @@ fn locals_from_mir<'tyctx>(
     for (local_id, local) in locals.iter().enumerate() {
         if local_id == 0 || local_id > argc {
             let ty = crate::utilis::monomorphize(method_instance, local.ty, tyctx);
-            if *crate::config::PRINT_LOCAL_TYPES {
+            if crate::config::config().print_local_types {
                 println!(
                     "Local type {ty:?},non-morphic: {non_morph}",
                     non_morph = local.ty
@@ -149,15 +153,11 @@ fn allocation_initializer_method(
                 // If it is a function, patch its pointer up.
                 let call_info =
                     crate::call_info::CallInfo::sig_from_instance_(finstance, tyctx, tycache);
-                let function_name = crate::utilis::function_name(tyctx.symbol_name(finstance));
 
                 trees.push(
                     CILRoot::STIndISize(
                         CILNode::LDLoc(1) + conv_usize!(ldc_u32!(offset)),
-                        CILNode::LDFtn(
-                            CallSite::new(None, function_name, call_info.sig().clone(), true)
-                                .into(),
-                        ),
+                        crate::function_sig::fn_ptr(finstance, call_info.sig().clone(), tyctx),
                     )
                     .into(),
                 );
@@ -210,8 +210,9 @@ pub fn terminator_to_ops<'tcx>(
     tcx: TyCtxt<'tcx>,
     instance: Instance<'tcx>,
     type_cache: &mut TyCache,
+    name: &str,
 ) -> Vec<CILTree> {
-    let terminator = if *crate::config::ABORT_ON_ERROR {
+    let terminator = if crate::config::config().abort_on_error {
         crate::terminator::handle_terminator(term, mir, tcx, mir, instance, type_cache)
     } else {
         match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
@@ -220,15 +221,23 @@ pub fn terminator_to_ops<'tcx>(
             Ok(ok) => ok,
             Err(payload) => {
                 type_cache.recover_from_panic();
-                let msg = if let Some(msg) = payload.downcast_ref::<&str>() {
+                let (msg, cause) = if let Some(msg) = panic_message(&*payload) {
                     rustc_middle::ty::print::with_no_trimmed_paths! {
-                    format!("Tried to execute terminator {term:?} whose compialtion message {msg:?}!")}
+                    (format!("Tried to execute terminator {term:?} whose compialtion message {msg:?}!"), msg.to_owned())}
                 } else {
                     eprintln!("handle_terminator panicked with a non-string message!");
                     rustc_middle::ty::print::with_no_trimmed_paths! {
-                    format!("Tried to execute terminator {term:?} whose compialtion failed with a no-string message!")
+                    (format!("Tried to execute terminator {term:?} whose compialtion failed with a no-string message!"), "handle_terminator panicked with a non-string message!".to_owned())
                     }
                 };
+                MethodCodegenError::from_span(
+                    tcx,
+                    term.source_info.span,
+                    name,
+                    cause,
+                    Placeholder::Terminator(msg.clone()),
+                )
+                .report();
                 vec![CILRoot::throw(&msg).into()]
             }
         }
@@ -244,7 +253,7 @@ pub fn statement_to_ops<'tcx>(
     instance: Instance<'tcx>,
     type_cache: &mut TyCache,
 ) -> Result<Option<CILTree>, CodegenError> {
-    if *crate::config::ABORT_ON_ERROR {
+    if crate::config::config().abort_on_error {
         Ok(crate::statement::handle_statement(
             statement, tcx, mir, instance, type_cache,
         ))
@@ -254,7 +263,7 @@ pub fn statement_to_ops<'tcx>(
         })) {
             Ok(success) => Ok(success),
             Err(payload) => {
-                if let Some(msg) = payload.downcast_ref::<&str>() {
+                if let Some(msg) = panic_message(&*payload) {
                     Err(crate::codegen_error::CodegenError::from_panic_message(msg))
                 } else {
                     Err(crate::codegen_error::CodegenError::from_panic_message(
@@ -276,10 +285,14 @@ pub fn add_fn<'tyctx>(
     if crate::utilis::is_function_magic(name) {
         return Ok(());
     }
-    if let TyKind::FnDef(_, _) = instance.ty(tyctx, ParamEnv::reveal_all()).kind() {
+    if let InstanceDef::ThreadLocalShim(_) = instance.def {
+        // Returns a pointer to a thread local. Its type is the type of the static, and not a `FnDef`.
+    } else if let TyKind::FnDef(_, _) = instance.ty(tyctx, ParamEnv::reveal_all()).kind() {
         //ALL OK.
     } else if let TyKind::Closure(_, _) = instance.ty(tyctx, ParamEnv::reveal_all()).kind() {
         //println!("CLOSURE")
+    } else if let TyKind::Coroutine(_, _) = instance.ty(tyctx, ParamEnv::reveal_all()).kind() {
+        // The resume function of a coroutine state machine.
     } else {
         eprintln!("fn item {instance:?} is not a function definition type. Skippping.");
         return Ok(());
@@ -316,7 +329,7 @@ pub fn add_fn<'tyctx>(
         //ops.push(CILOp::Label(last_bb_id as u32));
         let mut trees = Vec::new();
         for statement in &block_data.statements {
-            if *crate::config::INSERT_MIR_DEBUG_COMMENTS {
+            if crate::config::config().insert_mir_debug_comments {
                 rustc_middle::ty::print::with_no_trimmed_paths! {trees.push(CILRoot::debug(&format!("{statement:?}")).into())};
             }
 
@@ -327,7 +340,16 @@ pub fn add_fn<'tyctx>(
                     rustc_middle::ty::print::with_no_trimmed_paths! {eprintln!(
                         "Method \"{name}\" failed to compile statement {statement:?} with message {err:?}"
                     )};
-                    rustc_middle::ty::print::with_no_trimmed_paths! {Some(CILRoot::throw(&format!("Tired to run a statement {statement:?} which failed to compile with error message {err:?}.")).into())}
+                    let msg = rustc_middle::ty::print::with_no_trimmed_paths! {format!("Tired to run a statement {statement:?} which failed to compile with error message {err:?}.")};
+                    MethodCodegenError::from_span(
+                        tyctx,
+                        statement.source_info.span,
+                        name,
+                        err.message(),
+                        Placeholder::Statement(msg.clone()),
+                    )
+                    .report();
+                    Some(CILRoot::throw(&msg).into())
                 }
             };
             // Only save debuginfo for statements which result in ops.
@@ -342,10 +364,10 @@ pub fn add_fn<'tyctx>(
         }
         match &block_data.terminator {
             Some(term) => {
-                if *crate::config::INSERT_MIR_DEBUG_COMMENTS {
+                if crate::config::config().insert_mir_debug_comments {
                     rustc_middle::ty::print::with_no_trimmed_paths! {trees.push(CILRoot::debug(&format!("{term:?}")).into())};
                 }
-                let term_trees = terminator_to_ops(term, mir, tyctx, instance, cache);
+                let term_trees = terminator_to_ops(term, mir, tyctx, instance, cache, name);
                 if !term_trees.is_empty() {
                     trees.push(span_source_info(tyctx, term.source_info.span).into());
                 }
@@ -414,11 +436,29 @@ pub fn add_fn<'tyctx>(
         normal_bbs,
         arg_names,
     );
+    if let Some(export) = crate::function_sig::export_info(instance, tyctx, cache) {
+        method.add_attribute(Attribute::Export(export));
+    }
+    if let InstanceDef::Item(def_id) = instance.def {
+        let impl_options = match tyctx.codegen_fn_attrs(def_id).inline {
+            InlineAttr::Always => Some(AGGRESSIVE_INLINING),
+            InlineAttr::Never => Some(NO_INLINING),
+            InlineAttr::None | InlineAttr::Hint => None,
+        };
+        if let Some(impl_options) = impl_options {
+            method.add_attribute(Attribute::Custom(CustomAttribute::method_impl(
+                impl_options,
+            )));
+        }
+    }
+    if name == "__rust_start_panic" {
+        crate::unwind::record_panic_message(&mut method, asm, instance, tyctx, cache);
+    }
     crate::method::resolve_global_allocations(&mut method, asm, tyctx, cache);
 
     method.allocate_temporaries();
 
-    if *crate::config::TYPECHECK_CIL {
+    if crate::config::config().typecheck_cil {
         match method.validate() {
             Ok(()) => (),
             Err(msg) => eprintln!(
@@ -455,14 +495,23 @@ pub fn checked_add_fn<'tcx>(
         Ok(success) => success,
         Err(payload) => {
             cache.recover_from_panic();
-            if let Some(msg) = payload.downcast_ref::<&str>() {
+            let msg = if let Some(msg) = panic_message(&*payload) {
                 eprintln!("could not compile method {name}. fn_add panicked with unhandled message: {msg:?}");
                 //self.add_method(Method::missing_because(format!("could not compile method {name}. fn_add panicked with unhandled message: {msg:?}")));
-                Ok(())
+                msg.to_owned()
             } else {
                 eprintln!("could not compile method {name}. fn_add panicked with no message.");
-                Ok(())
-            }
+                "fn_add panicked with no message.".to_owned()
+            };
+            MethodCodegenError::from_span(
+                tcx,
+                tcx.def_span(instance.def_id()),
+                name,
+                msg,
+                Placeholder::MissingMethod,
+            )
+            .report();
+            Ok(())
         }
     }
 }
@@ -495,6 +544,11 @@ pub fn add_item<'tcx>(
                 "compile static initializer",
                 item.symbol_name(tcx).to_string(),
             );
+            if tcx.is_thread_local_static(stotic) {
+                add_thread_local(asm, stotic, tcx, cache);
+                drop(static_compile_timer);
+                return Ok(());
+            }
             let alloc = tcx.eval_static_initializer(stotic).unwrap();
             let alloc_id = tcx.reserve_and_set_memory_alloc(alloc);
 
@@ -506,6 +560,66 @@ pub fn add_item<'tcx>(
         }
     }
 }
+/// Returns the method which returns a pointer to the current thread's copy of the thread local static `def_id`.
+pub fn thread_local_getter(def_id: DefId, tcx: TyCtxt<'_>) -> CallSite {
+    let symbol = crate::utilis::function_name(tcx.symbol_name(Instance::mono(tcx, def_id)));
+    CallSite::builtin(
+        format!("tls_get_{symbol}").into(),
+        FnSig::new(&[], Type::Ptr(Type::U8.into())),
+        true,
+    )
+}
+/// Adds the thread-static field holding the thread local static `def_id`, and its getter. Each thread gets its own
+/// copy of the static, which is allocated and initialized the first time that thread accesses it.
+pub fn add_thread_local(asm: &mut Assembly, def_id: DefId, tcx: TyCtxt<'_>, tycache: &mut TyCache) {
+    let getter = thread_local_getter(def_id, tcx);
+    let symbol = crate::utilis::function_name(tcx.symbol_name(Instance::mono(tcx, def_id)));
+    let tls_fld: IString = format!("tls_{symbol}").into();
+    let alloc = tcx.eval_static_initializer(def_id).unwrap();
+    let init_method = allocation_initializer_method(alloc.inner(), &tls_fld, tcx, asm, tycache);
+    let field_desc = StaticFieldDescriptor::new(None, Type::Ptr(Type::U8.into()), tls_fld.clone());
+    let init = BasicBlock::new(
+        vec![
+            CILRoot::BTrue {
+                target: 1,
+                sub_target: 0,
+                cond: CILNode::LDStaticField(field_desc.clone().into()),
+            }
+            .into(),
+            CILRoot::SetStaticField {
+                descr: field_desc.clone(),
+                value: call!(init_method.call_site(), []),
+            }
+            .into(),
+            CILRoot::GoTo {
+                target: 1,
+                sub_target: 0,
+            }
+            .into(),
+        ],
+        0,
+        None,
+    );
+    let ret = BasicBlock::new(
+        vec![CILRoot::Ret {
+            tree: CILNode::LDStaticField(field_desc.into()),
+        }
+        .into()],
+        1,
+        None,
+    );
+    asm.add_method(Method::new(
+        AccessModifer::Public,
+        MethodType::Static,
+        getter.signature().clone(),
+        getter.name(),
+        vec![],
+        vec![init, ret],
+        vec![],
+    ));
+    asm.add_method(init_method);
+    asm.add_thread_static(Type::Ptr(Type::U8.into()), &tls_fld);
+}
 /// Adds a static field and initialized for allocation represented by `alloc_id`.
 pub fn add_allocation(
     asm: &mut Assembly,
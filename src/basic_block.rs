@@ -31,7 +31,7 @@ fn simplify_handler<'tyctx>(
     method_instance: &Instance<'tyctx>,
     method: &Body<'tyctx>,
 ) -> Option<u32> {
-    if *crate::config::NO_UNWIND {
+    if crate::config::config().no_unwind {
         return None;
     }
     let handler = handler?;
@@ -0,0 +1,341 @@
+use std::{io::Write, process::Command, sync::atomic::AtomicU64};
+mod reduce;
+static LINES: AtomicU64 = AtomicU64::new(0);
+/// A backend, besides native `rustc`, a fuzz program can be built with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Backend {
+    /// A .NET assembly, ran using `dotnet`.
+    Dotnet,
+    /// The final cilly assembly, ran using the cilly interpreter.
+    Interpreter,
+    /// C source emitted by the `CExporter`, built with the system C compiler.
+    C,
+}
+/// How the result of a backend differs from the native build.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DivergenceKind {
+    /// The program could not be built.
+    Build,
+    /// The program crashed, or was killed after a timeout.
+    Crash,
+    /// The program printed something different.
+    Output,
+}
+impl Backend {
+    const ALL: [Self; 3] = [Self::Interpreter, Self::C, Self::Dotnet];
+    fn name(self) -> &'static str {
+        match self {
+            Self::Dotnet => "dotnet",
+            Self::Interpreter => "interpreter",
+            Self::C => "c",
+        }
+    }
+    /// Returns true if this backend can be used on this machine.
+    fn is_available(self) -> bool {
+        match self {
+            Self::Dotnet => Command::new("dotnet").output().is_ok(),
+            Self::Interpreter | Self::C => true,
+        }
+    }
+    /// Builds and runs the fuzz program `{test_dir}{stem}.rs` with this backend. Returns its stdout, or a description of the faliure.
+    fn run(self, stem: &str, test_dir: &str) -> Result<String, (DivergenceKind, String)> {
+        let rust_src = format!("{test_dir}{stem}.rs");
+        let wrapper = format!("{test_dir}{stem}_{name}", name = self.name());
+        let exe = format!("{wrapper}.exe");
+        let mut cmd = Command::new("rustc");
+        cmd.current_dir(test_dir)
+            .arg("-O")
+            .args(rustc_codegen_clr::compile_test::rustc_args().iter())
+            .args([&rust_src, "-o", &exe]);
+        match self {
+            Self::Dotnet => (),
+            // The interpreter only needs the final cilly assembly, so the linker can skip ILASM.
+            Self::Interpreter => {
+                cmd.env("DIRECT_PE", "1");
+            }
+            Self::C => {
+                cmd.env("C_MODE", "1");
+            }
+        }
+        let out = cmd
+            .output()
+            .map_err(|err| (DivergenceKind::Build, err.to_string()))?;
+        if !out.status.success() {
+            return Err((
+                DivergenceKind::Build,
+                format!("build failed:\n{}", String::from_utf8_lossy(&out.stderr)),
+            ));
+        }
+        let mut run = Command::new("timeout");
+        run.current_dir(test_dir).args(["-v", "5"]);
+        match self {
+            Self::Dotnet => {
+                return std::panic::catch_unwind(|| {
+                    rustc_codegen_clr::compile_test::test_dotnet_executable(&wrapper, test_dir)
+                })
+                .map_err(|_| (DivergenceKind::Crash, "dotnet run failed".to_owned()));
+            }
+            Self::Interpreter => run
+                .arg(&*rustc_codegen_clr::compile_test::CILLY_INTERPRETER)
+                .arg(std::path::Path::new(&exe).with_extension("cilly")),
+            Self::C => run.arg(&exe),
+        };
+        let out = run
+            .output()
+            .map_err(|err| (DivergenceKind::Crash, err.to_string()))?;
+        let stdout = String::from_utf8_lossy(&out.stdout).to_string();
+        if !out.status.success() {
+            return Err((
+                DivergenceKind::Crash,
+                format!(
+                    "exited with {status} after printing {lines} lines. stderr:\n{stderr}",
+                    status = out.status,
+                    lines = stdout.lines().count(),
+                    stderr = String::from_utf8_lossy(&out.stderr)
+                ),
+            ));
+        }
+        Ok(stdout)
+    }
+}
+/// Describes the first line on which `got` differs from `expected`, if any.
+fn first_divergence(expected: &str, got: &str) -> Option<String> {
+    let mut expected = expected.lines();
+    let mut got = got.lines();
+    for line in 1.. {
+        match (expected.next(), got.next()) {
+            (None, None) => return None,
+            (expected, got) if expected == got => (),
+            (expected, got) => {
+                return Some(format!(
+                    "first divergence on line {line}: expected {expected:?}, got {got:?}"
+                ))
+            }
+        }
+    }
+    unreachable!()
+}
+/// Compiles the fuzz program `{test_dir}{stem}.rs` natively, and returns its output. Returns `None` if the program does not compile.
+fn run_native(stem: &str, is_release: bool, test_dir: &str) -> Option<String> {
+    let rustc_opt_flag = if is_release { "-O" } else { "-g" };
+    let rust_src = format!("{test_dir}{stem}.rs");
+    let native_exec = format!("{test_dir}{stem}.elf");
+    let mut cmd = std::process::Command::new("rustc");
+    //.env("RUST_TARGET_PATH","../../")
+    cmd.current_dir(test_dir).args([
+        rustc_opt_flag,
+        &rust_src,
+        "-o",
+        &native_exec,
+        "--edition",
+        "2021",
+    ]);
+    let out = cmd.output().expect("failed to execute process");
+    // If stderr is not empty, then something went wrong, so print the stdout and stderr for debuging.
+    if !out.stderr.is_empty() {
+        let stdout =
+            String::from_utf8(out.stdout).expect("rustc error contained non-UTF8 characters.");
+        let stderr =
+            String::from_utf8(out.stderr).expect("rustc error contained non-UTF8 characters.");
+
+        eprintln!("stdout:\n{stdout}\nstderr:\n{stderr}");
+    }
+    if !out.status.success() {
+        return None;
+    }
+    let rust_out = std::process::Command::new(&native_exec)
+        .current_dir(test_dir)
+        .output()
+        .expect("failed to execute process");
+    Some(String::from_utf8(rust_out.stdout).expect("rust error contained non-UTF8 characters."))
+}
+fn run_test(test_id: u64, is_release: bool) -> Option<f64> {
+    match std::panic::catch_unwind(|| run_test_impl(test_id, is_release)) {
+        Ok(inner) => inner,
+        Err(_) => Some(1.0),
+    }
+}
+fn run_test_impl(test_id: u64, is_release: bool) -> Option<f64> {
+    let test_dir = "/tmp/fuzz/";
+    let rust_src = format!("/tmp/fuzz/fuzz{test_id}.rs");
+    let dotnet_exe = format!("/tmp/fuzz/fuzz{test_id}.exe");
+    let dotnet_wrapper = format!("/tmp/fuzz/fuzz{test_id}");
+    // Ensures the test directory is present
+    std::fs::create_dir_all(test_dir).expect("Could not setup the test env");
+    // Builds the backend if neceasry
+    rustc_codegen_clr::compile_test::RUSTC_BUILD_STATUS
+        .as_ref()
+        .expect("Could not build rustc!");
+    // Compiles the test project
+    let mut cmd = std::process::Command::new("rustc");
+    //.env("RUST_TARGET_PATH","../../")
+    let rustc_args = rustc_codegen_clr::compile_test::rustc_args();
+    cmd.current_dir(test_dir)
+        .arg("-O")
+        .args(rustc_args.iter())
+        .args([&rust_src, "-o", &dotnet_exe]);
+
+    let out = cmd.output().expect("failed to execute process");
+    // If stderr is not empty, then something went wrong, so print the stdout and stderr for debuging.
+    if !out.stderr.is_empty() {
+        let stdout =
+            String::from_utf8(out.stdout).expect("rustc error contained non-UTF8 characters.");
+        let stderr =
+            String::from_utf8(out.stderr).expect("rustc error contained non-UTF8 characters.");
+        eprintln!("stdout:\n{stdout}\nstderr:\n{stderr}");
+    }
+
+    //super::peverify(exec_path, test_dir);
+
+    let dotnet_out =
+        rustc_codegen_clr::compile_test::test_dotnet_executable(&dotnet_wrapper, test_dir);
+    // Compiles the project with native rust
+    let rust_out = run_native(&format!("fuzz{test_id}"), is_release, test_dir)
+        .expect("Could not build the test natively");
+
+    if rust_out == dotnet_out {
+        //std::fs::remove_file(rust_src).unwrap();
+        std::fs::remove_file(dotnet_exe).unwrap();
+        std::fs::remove_file(format!("{test_dir}fuzz{test_id}.elf")).unwrap();
+        //std::fs::remove_file(dotnet_wrapper).unwrap();
+        None
+    } else {
+        Some(strsim::jaro(&rust_out, &dotnet_out))
+    }
+}
+/// A backend whose result differs from the native build, how it differs, and a description of the difference.
+type Divergence = (Backend, DivergenceKind, String);
+/// Runs the fuzz program `/tmp/fuzz/{stem}.rs` with `backends`, and compares their output with the output of the native
+/// build. Returns the backends which diverged, and how they diverged. Programs which can't be built natively never diverge.
+fn run_differential(stem: &str, is_release: bool, backends: &[Backend]) -> Vec<Divergence> {
+    let test_dir = "/tmp/fuzz/";
+    rustc_codegen_clr::compile_test::RUSTC_BUILD_STATUS
+        .as_ref()
+        .expect("Could not build rustc!");
+    let Some(rust_out) = run_native(stem, is_release, test_dir) else {
+        return vec![];
+    };
+    backends
+        .iter()
+        .filter_map(|backend| {
+            let (kind, divergence) = match backend.run(stem, test_dir) {
+                Ok(out) => (DivergenceKind::Output, first_divergence(&rust_out, &out)?),
+                Err(err) => err,
+            };
+            Some((*backend, kind, divergence))
+        })
+        .collect()
+}
+fn gen_file(test_id: u64, generator: &str) {
+    let rust_src = format!("/tmp/fuzz/fuzz{test_id}.rs");
+    let cout = Command::new(generator)
+        .arg("-p")
+        .arg(&format!("{test_id}"))
+        .output()
+        .unwrap();
+    assert!(cout.stderr.is_empty());
+    let src = cout.stdout;
+    LINES.fetch_add(
+        bytecount::count(&src, b'\n') as u64,
+        std::sync::atomic::Ordering::Relaxed,
+    );
+    let mut file = std::fs::File::create(rust_src).unwrap();
+    file.write_all(b"#![allow(dead_code,unused_variables)]")
+        .unwrap();
+    file.write_all(&src).unwrap();
+}
+fn test(test_id: u64, generator: &str) -> Option<(u64, f64)> {
+    gen_file(test_id, generator);
+    let res = run_test(test_id, false)
+        .or(run_test(test_id, true))
+        .map(|sim| (test_id, 1.0 - sim));
+    if res.is_none() {
+        std::fs::remove_file(format!("/tmp/fuzz/fuzz{test_id}.rs")).unwrap();
+    }
+    std::fs::remove_file(format!("/tmp/fuzz/fuzz{test_id}.il")).unwrap();
+    std::fs::remove_file(format!("/tmp/fuzz/fuzz{test_id}.runtimeconfig.json")).unwrap();
+    // Try removing the .mdb, if present.
+    let _ = std::fs::remove_file(format!("/tmp/fuzz/fuzz{test_id}.exe.mdb"));
+    res
+}
+fn test_differential(
+    test_id: u64,
+    generator: &str,
+    backends: &[Backend],
+) -> Option<(u64, Vec<Divergence>)> {
+    gen_file(test_id, generator);
+    let stem = format!("fuzz{test_id}");
+    let mut divergences = run_differential(&stem, false, backends);
+    if divergences.is_empty() {
+        divergences = run_differential(&stem, true, backends);
+    }
+    if divergences.is_empty() {
+        std::fs::remove_file(format!("/tmp/fuzz/fuzz{test_id}.rs")).unwrap();
+        std::fs::remove_file(format!("/tmp/fuzz/fuzz{test_id}.elf")).unwrap();
+        None
+    } else {
+        Some((test_id, divergences))
+    }
+}
+fn main() {
+    use rayon::iter::{IntoParallelIterator, ParallelIterator};
+    // In differential mode, each program is also ran using the cilly interpreter and the C backend, so no .NET
+    // runtime is required.
+    let differential = std::env::args().any(|arg| arg == "--differential");
+    let args: Vec<String> = std::env::args()
+        .filter(|arg| arg != "--differential")
+        .collect();
+    if args[1] == "--reduce" {
+        let src = std::path::Path::new(&args[2]);
+        let out = args.get(3).map_or_else(
+            || {
+                src.with_file_name(format!(
+                    "{}_reduced.rs",
+                    src.file_stem().unwrap().to_string_lossy()
+                ))
+            },
+            std::path::PathBuf::from,
+        );
+        reduce::reduce_file(src, &out);
+        return;
+    }
+    let generator = args[1].clone();
+    let search_start = str::parse::<u64>(&args[2]).unwrap();
+    let search_end = args
+        .get(3)
+        .map_or(search_start + 1, |str| str::parse::<u64>(str).unwrap());
+
+    std::fs::create_dir_all("/tmp/fuzz").unwrap();
+    let test_cases = search_end - search_start;
+    if differential {
+        let backends: Vec<_> = Backend::ALL
+            .into_iter()
+            .filter(|backend| backend.is_available())
+            .collect();
+        let mut faliures: Box<[_]> = (search_start..search_end)
+            .into_par_iter()
+            .map(|i| test_differential(i, &generator, &backends))
+            .flatten()
+            .collect();
+        faliures.sort_by_key(|(test_id, _)| *test_id);
+        println!("Created {test_cases} test cases, totaling {LINES:?} LOC.");
+        for (test_id, divergences) in faliures.iter() {
+            for (backend, _, divergence) in divergences {
+                println!(
+                    "fuzz{test_id} {backend}: {divergence}",
+                    backend = backend.name()
+                );
+            }
+        }
+        return;
+    }
+    let mut faliures: Box<[_]> = (search_start..search_end)
+        .into_par_iter()
+        .map(|i| test(i, &generator))
+        .flatten()
+        .collect();
+    faliures.sort_by(|(_, err_a), (_, err_b)| err_a.partial_cmp(err_b).unwrap());
+    println!(
+        "Created {test_cases} test cases, totaling {LINES:?} LOC, found faliures:{faliures:?}"
+    );
+}
@@ -0,0 +1,371 @@
+//! Reduces a miscompiled fuzz program to a minimal reproducer. The reducer repeatedly deletes calls, basic blocks and
+//! statements from the custom MIR functions of the program, and keeps every deletion which preserves the divergence
+//! between the native build and the faulty backend.
+use std::{collections::HashSet, io::Write, ops::Range, path::Path};
+
+use crate::{run_differential, Backend, DivergenceKind};
+/// Name of the file candidate programs are written to, in the fuzz directory.
+const STEM: &str = "reduce";
+const TEST_DIR: &str = "/tmp/fuzz/";
+/// A single change to a program: replaces `lines` with `replacement`, or deletes them.
+#[derive(Clone, Debug)]
+struct Edit {
+    lines: Range<usize>,
+    replacement: Option<String>,
+}
+/// Returns the number of braces opened(positive) or closed(negative) by `line`, ignoring the ones in char and string literals.
+fn brace_delta(line: &str) -> i32 {
+    let mut delta = 0;
+    let mut chars = line.chars();
+    while let Some(curr) = chars.next() {
+        match curr {
+            '{' => delta += 1,
+            '}' => delta -= 1,
+            '\'' | '"' => {
+                // Skip to the end of the literal.
+                while let Some(inner) = chars.next() {
+                    if inner == '\\' {
+                        chars.next();
+                    } else if inner == curr {
+                        break;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    delta
+}
+/// A custom MIR function.
+struct MirFn {
+    /// Name of the function.
+    name: String,
+    /// All the lines of this function, including its attributes.
+    lines: Range<usize>,
+    /// The name(`None` for the entry block) and the lines of the body of each block.
+    blocks: Vec<(Option<String>, Range<usize>)>,
+}
+/// Finds all the custom MIR functions in a program.
+fn mir_fns(lines: &[String]) -> Vec<MirFn> {
+    let mut fns = vec![];
+    let mut idx = 0;
+    while idx < lines.len() {
+        if !lines[idx].trim_start().starts_with("#[custom_mir(") {
+            idx += 1;
+            continue;
+        }
+        let start = idx;
+        let header = lines.get(idx + 1).map_or("", |line| line.as_str());
+        let name = header
+            .split_once("fn ")
+            .and_then(|(_, rest)| rest.split_once('('))
+            .map_or_else(String::new, |(name, _)| name.trim().to_owned());
+        let mut depth = 0;
+        let mut blocks = vec![];
+        let mut block: Option<(Option<String>, usize)> = None;
+        idx += 1;
+        while idx < lines.len() {
+            let line = lines[idx].trim();
+            let delta = brace_delta(line);
+            // The body of the `mir!` macro is at depth 2, so blocks start at it.
+            if depth == 2 && delta > 0 && block.is_none() {
+                if line == "{" {
+                    block = Some((None, idx));
+                } else if let Some((name, "{")) = line.split_once(" = ").map(|(a, b)| (a, b.trim()))
+                {
+                    if name.starts_with("bb") {
+                        block = Some((Some(name.to_owned()), idx));
+                    }
+                }
+            }
+            depth += delta;
+            if depth == 2 {
+                if let Some((name, block_start)) = block.take() {
+                    blocks.push((name, block_start + 1..idx));
+                }
+            }
+            idx += 1;
+            if depth == 0 && delta < 0 {
+                break;
+            }
+        }
+        fns.push(MirFn {
+            name,
+            lines: start..idx,
+            blocks,
+        });
+    }
+    fns
+}
+/// Calls to other functions, which get replaced with a jump to the block the call returns to.
+fn call_edits(lines: &[String]) -> Vec<Edit> {
+    mir_fns(lines)
+        .iter()
+        .flat_map(|mir_fn| mir_fn.blocks.iter())
+        .filter_map(|(_, body)| {
+            let terminator = body
+                .clone()
+                .rev()
+                .find(|idx| !lines[*idx].trim().is_empty())?;
+            let line = lines[terminator].trim();
+            if !line.starts_with("Call(") {
+                return None;
+            }
+            let target = line.split_once("ReturnTo(")?.1.split_once(')')?.0;
+            Some(Edit {
+                lines: terminator..terminator + 1,
+                replacement: Some(format!("Goto({target})")),
+            })
+        })
+        .collect()
+}
+/// Basic blocks, whose bodies get replaced with a return.
+fn block_edits(lines: &[String]) -> Vec<Edit> {
+    mir_fns(lines)
+        .iter()
+        .flat_map(|mir_fn| mir_fn.blocks.iter())
+        .filter(|(_, body)| body.len() != 1 || lines[body.start].trim() != "Return()")
+        .map(|(_, body)| Edit {
+            lines: body.clone(),
+            replacement: Some("Return()".into()),
+        })
+        .collect()
+}
+/// Statements, which get deleted.
+fn statement_edits(lines: &[String]) -> Vec<Edit> {
+    mir_fns(lines)
+        .iter()
+        .flat_map(|mir_fn| mir_fn.blocks.iter())
+        .flat_map(|(_, body)| body.clone())
+        .filter(|idx| lines[*idx].trim().ends_with(';'))
+        .map(|idx| Edit {
+            lines: idx..idx + 1,
+            replacement: None,
+        })
+        .collect()
+}
+/// Returns true if `word` appears in `line`, and is not a part of a longer identifier.
+fn contains_word(line: &str, word: &str) -> bool {
+    line.match_indices(word).any(|(idx, _)| {
+        let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+        let before = line[..idx].chars().next_back();
+        let after = line[idx + word.len()..].chars().next();
+        !before.is_some_and(is_ident) && !after.is_some_and(is_ident)
+    })
+}
+/// Blocks which are no longer jumped to, and functions which are no longer called, get deleted.
+fn dead_code_edits(lines: &[String]) -> Vec<Edit> {
+    let fns = mir_fns(lines);
+    let mut edits = vec![];
+    for mir_fn in &fns {
+        let called = lines
+            .iter()
+            .enumerate()
+            .any(|(idx, line)| !mir_fn.lines.contains(&idx) && contains_word(line, &mir_fn.name));
+        if !called {
+            edits.push(Edit {
+                lines: mir_fn.lines.clone(),
+                replacement: None,
+            });
+            continue;
+        }
+        for (name, body) in &mir_fn.blocks {
+            let Some(name) = name else { continue };
+            // The block header is the line before the body, and the closing brace is the line after it.
+            let block = body.start - 1..body.end + 1;
+            let used = mir_fn
+                .lines
+                .clone()
+                .any(|idx| !block.contains(&idx) && contains_word(&lines[idx], name));
+            if !used {
+                edits.push(Edit {
+                    lines: block,
+                    replacement: None,
+                });
+            }
+        }
+    }
+    edits
+}
+/// Splits a statement, or the destination of a call, into the local it initializes, and the parts of it which are
+/// only read. Assignments through pointers don't initialize any local.
+fn split_assignment(line: &str) -> (Option<&str>, String) {
+    let line = line.trim();
+    let stmt = line.strip_prefix("Call(").unwrap_or(line);
+    match stmt.split_once(" = ") {
+        Some((place, value)) if !place.starts_with('(') && !place.contains(' ') => {
+            let local = place.split(['.', '[']).next().unwrap_or(place);
+            (Some(local), format!("{} {value}", &place[local.len()..]))
+        }
+        _ => (None, line.to_owned()),
+    }
+}
+/// Finds the locals, and return values(`RET`), which are read but never initialized, along with the function they are
+/// in. Programs reading them are UB, so their divergence can't be trusted.
+fn uninit_reads(lines: &[String]) -> HashSet<(String, String)> {
+    let mut res = HashSet::new();
+    for mir_fn in mir_fns(lines) {
+        let body = &lines[mir_fn.lines.clone()];
+        let mut locals: Vec<&str> = body
+            .iter()
+            .filter_map(|line| line.trim().strip_prefix("let ")?.split_once(':'))
+            // Reading uninitialized unit values is fine.
+            .filter(|(_, tpe)| tpe.trim() != "();")
+            .map(|(local, _)| local.trim())
+            .collect();
+        if body.iter().any(|line| {
+            line.trim()
+                .strip_prefix("type RET = ")
+                .is_some_and(|tpe| tpe != "();")
+        }) {
+            locals.push("RET");
+        }
+        for local in locals {
+            let mut initialized = false;
+            let mut read = false;
+            for line in body {
+                let trimmed = line.trim();
+                if trimmed.starts_with("let ") || trimmed.starts_with("type RET") {
+                    continue;
+                }
+                let (target, rest) = split_assignment(line);
+                initialized |= target == Some(local);
+                read |= contains_word(&rest, local) || (local == "RET" && trimmed == "Return()");
+            }
+            if read && !initialized {
+                res.insert((mir_fn.name.clone(), local.to_owned()));
+            }
+        }
+    }
+    res
+}
+/// Applies non-overlapping `edits` to `lines`.
+fn apply(lines: &[String], edits: &[Edit]) -> Vec<String> {
+    let mut edits = edits.to_vec();
+    edits.sort_by_key(|edit| std::cmp::Reverse(edit.lines.start));
+    let mut res = lines.to_vec();
+    for edit in edits {
+        res.splice(edit.lines, edit.replacement);
+    }
+    res
+}
+/// Decides if a candidate program still diverges the same way the original one did. Output divergences must happen on
+/// the same line, with the same values, so that edits introducing UB don't produce a different, bogus divergence.
+/// Edits which remove the initialization of a local that is still read are rejected upfront.
+struct Oracle {
+    backend: Backend,
+    kind: DivergenceKind,
+    description: String,
+    /// Uninitialized reads the original program already had, which the flow-insensitive check can't tell apart
+    /// from ones introduced by edits.
+    uninit_reads: HashSet<(String, String)>,
+    is_release: bool,
+    runs: usize,
+}
+impl Oracle {
+    fn is_interesting(&mut self, lines: &[String]) -> bool {
+        if !uninit_reads(lines).is_subset(&self.uninit_reads) {
+            return false;
+        }
+        self.runs += 1;
+        write_program(lines);
+        run_differential(STEM, self.is_release, &[self.backend])
+            .iter()
+            .any(|(_, kind, description)| {
+                *kind == self.kind
+                    && (*kind != DivergenceKind::Output || *description == self.description)
+            })
+    }
+}
+fn write_program(lines: &[String]) {
+    let mut file = std::fs::File::create(format!("{TEST_DIR}{STEM}.rs")).unwrap();
+    for line in lines {
+        writeln!(file, "{line}").unwrap();
+    }
+}
+/// Tries applying the edits `units` produces, in chunks of decreasing size. Returns true if any of them was kept.
+fn reduce_pass(
+    lines: &mut Vec<String>,
+    oracle: &mut Oracle,
+    units: impl Fn(&[String]) -> Vec<Edit>,
+) -> bool {
+    let mut progress = false;
+    let mut chunk = units(lines).len().div_ceil(2).max(1);
+    loop {
+        let mut start = 0;
+        let mut edits = units(lines);
+        while start < edits.len() {
+            let end = (start + chunk).min(edits.len());
+            let candidate = apply(lines, &edits[start..end]);
+            if oracle.is_interesting(&candidate) {
+                *lines = candidate;
+                progress = true;
+                edits = units(lines);
+            } else {
+                start = end;
+            }
+        }
+        if chunk == 1 {
+            return progress;
+        }
+        chunk = chunk.div_ceil(2);
+    }
+}
+/// Reduces the program at `src`, and writes the reduced program to `out`.
+pub fn reduce_file(src: &Path, out: &Path) {
+    std::fs::create_dir_all(TEST_DIR).unwrap();
+    let mut lines: Vec<String> = std::fs::read_to_string(src)
+        .expect("Could not read the program to reduce")
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    let backends: Vec<_> = Backend::ALL
+        .into_iter()
+        .filter(|backend| backend.is_available())
+        .collect();
+    write_program(&lines);
+    let divergence = [false, true].into_iter().find_map(|is_release| {
+        run_differential(STEM, is_release, &backends)
+            .into_iter()
+            .next()
+            .map(|divergence| (is_release, divergence))
+    });
+    let Some((is_release, (backend, kind, description))) = divergence else {
+        eprintln!("{src:?} does not diverge on any backend, so it can't be reduced.");
+        return;
+    };
+    eprintln!(
+        "Reducing {src:?}, which diverges on {backend}: {description}",
+        backend = backend.name()
+    );
+    let mut oracle = Oracle {
+        backend,
+        kind,
+        description,
+        uninit_reads: uninit_reads(&lines),
+        is_release,
+        runs: 0,
+    };
+    let original_len = lines.len();
+    loop {
+        let mut progress = reduce_pass(&mut lines, &mut oracle, call_edits);
+        progress |= reduce_pass(&mut lines, &mut oracle, dead_code_edits);
+        progress |= reduce_pass(&mut lines, &mut oracle, block_edits);
+        progress |= reduce_pass(&mut lines, &mut oracle, dead_code_edits);
+        progress |= reduce_pass(&mut lines, &mut oracle, statement_edits);
+        eprintln!(
+            "{len} lines left after {runs} runs.",
+            len = lines.len(),
+            runs = oracle.runs
+        );
+        if !progress {
+            break;
+        }
+    }
+    write_program(&lines);
+    std::fs::copy(format!("{TEST_DIR}{STEM}.rs"), out).expect("Could not save the reduced program");
+    println!(
+        "Reduced {src:?} from {original_len} to {len} lines, saved to {out:?}.",
+        len = lines.len()
+    );
+}
@@ -5,7 +5,7 @@ use cilly::field_desc::FieldDescriptor;
 use cilly::{DotnetTypeRef, Type};
 use rustc_hir::lang_items::LangItem;
 use rustc_middle::mir::{BinOp, Operand};
-use rustc_middle::ty::{Instance, IntTy, List, ParamEnv, Ty, TyCtxt, TyKind, UintTy};
+use rustc_middle::ty::{FloatTy, Instance, IntTy, List, ParamEnv, Ty, TyCtxt, TyKind, UintTy};
 
 use crate::r#type::TyCache;
 use cilly::fn_sig::FnSig;
@@ -38,6 +38,31 @@ pub(crate) fn binop<'tyctx>(
     let ops_b = crate::operand::handle_operand(operand_b, tyctx, method, method_instance, tycache);
     let ty_a = operand_a.ty(&method.local_decls, tyctx);
     let ty_b = operand_b.ty(&method.local_decls, tyctx);
+    binop_values(
+        binop,
+        ty_a,
+        ty_b,
+        ops_a,
+        ops_b,
+        tyctx,
+        method_instance,
+        tycache,
+    )
+}
+/// Preforms an unchecked binary operation on the values `ops_a` and `ops_b`, of types `ty_a` and `ty_b`.
+pub(crate) fn binop_values<'tyctx>(
+    binop: BinOp,
+    ty_a: Ty<'tyctx>,
+    ty_b: Ty<'tyctx>,
+    ops_a: CILNode,
+    ops_b: CILNode,
+    tyctx: TyCtxt<'tyctx>,
+    method_instance: Instance<'tyctx>,
+    tycache: &mut TyCache,
+) -> CILNode {
+    if let TyKind::Float(float_ty @ (FloatTy::F16 | FloatTy::F128)) = ty_a.kind() {
+        return crate::float::binop(binop, &crate::r#type::from_float(float_ty), ops_a, ops_b);
+    }
     match binop {
         BinOp::AddWithOverflow => {
             if ty_a.is_signed() {
@@ -0,0 +1,339 @@
+//! Software routines for `f128` which `compiler_builtins` does not provide.
+use cilly::{
+    access_modifier::AccessModifer,
+    asm::Assembly,
+    basic_block::BasicBlock,
+    call,
+    call_site::CallSite,
+    cil_node::CILNode,
+    cil_root::CILRoot,
+    eq,
+    fn_sig::FnSig,
+    ldc_i32, lt,
+    method::{Method, MethodType},
+    DotnetTypeRef, Type,
+};
+use rustc_middle::ty::UintTy;
+/// The name of the builtin computing the remainder of an `f128` division.
+pub const FMOD_F128: &str = "fmod_f128";
+const SIGN: u128 = 1 << 127;
+const ABS: u128 = !SIGN;
+const INFINITY: u128 = 0x7FFF << 112;
+const QUIET_NAN: u128 = 0xFFFF << 111;
+/// The implicit leading bit of a normal significand.
+const IMPLICIT: u128 = 1 << 112;
+const MANTISSA: u128 = IMPLICIT - 1;
+const SX: u32 = 0;
+const UX: u32 = 1;
+const UY: u32 = 2;
+const MX: u32 = 3;
+const MY: u32 = 4;
+const EX: u32 = 5;
+const EY: u32 = 6;
+const BITS: u32 = 7;
+const RES: u32 = 8;
+fn u128_const(value: u128) -> CILNode {
+    crate::constant::load_const_uint(value, &UintTy::U128)
+}
+/// Calls the operator `name` of `System.UInt128`, with a second operand of type `rhs`.
+fn u128_op(name: &str, rhs: Type, output: Type, a: CILNode, b: CILNode) -> CILNode {
+    call!(
+        CallSite::new_extern(
+            DotnetTypeRef::uint_128(),
+            name.into(),
+            FnSig::new(&[Type::U128, rhs], output),
+            true,
+        ),
+        [a, b]
+    )
+}
+fn and(a: CILNode, b: CILNode) -> CILNode {
+    u128_op("op_BitwiseAnd", Type::U128, Type::U128, a, b)
+}
+fn or(a: CILNode, b: CILNode) -> CILNode {
+    u128_op("op_BitwiseOr", Type::U128, Type::U128, a, b)
+}
+fn sub(a: CILNode, b: CILNode) -> CILNode {
+    u128_op("op_Subtraction", Type::U128, Type::U128, a, b)
+}
+fn shl(a: CILNode, b: CILNode) -> CILNode {
+    u128_op("op_LeftShift", Type::I32, Type::U128, a, b)
+}
+fn shr(a: CILNode, b: CILNode) -> CILNode {
+    u128_op("op_RightShift", Type::I32, Type::U128, a, b)
+}
+fn u128_lt(a: CILNode, b: CILNode) -> CILNode {
+    u128_op("op_LessThan", Type::U128, Type::Bool, a, b)
+}
+fn u128_eq(a: CILNode, b: CILNode) -> CILNode {
+    u128_op("op_Equality", Type::U128, Type::Bool, a, b)
+}
+/// Loads the bits of the `f128` at `addr`.
+fn bits_of(addr: CILNode) -> CILNode {
+    CILNode::LdObj {
+        ptr: Box::new(CILNode::TransmutePtr {
+            val: Box::new(addr),
+            new_ptr: Box::new(Type::Ptr(Type::U128.into())),
+        }),
+        obj: Box::new(Type::U128),
+    }
+}
+fn goto(target: u32) -> CILRoot {
+    CILRoot::GoTo {
+        target,
+        sub_target: 0,
+    }
+}
+fn set(local: u32, tree: CILNode) -> CILRoot {
+    CILRoot::STLoc { local, tree }
+}
+/// Normalizes the significand `m` of a float with the biased exponent `e`, so that its implicit bit is set. Subnormals
+/// get an exponent below 1 instead. Starts at block `id`, and continues at block `next`.
+fn normalize(m: u32, e: u32, bits: u32, id: u32, next: u32) -> [BasicBlock; 3] {
+    let unpack = [
+        set(
+            e,
+            crate::casts::int_to_int(
+                Type::U128,
+                &Type::I32,
+                shr(CILNode::LDLoc(bits), ldc_i32!(112)),
+            ),
+        ),
+        set(m, and(CILNode::LDLoc(bits), u128_const(MANTISSA))),
+        CILRoot::BTrue {
+            target: id + 1,
+            sub_target: 0,
+            cond: eq!(CILNode::LDLoc(e), ldc_i32!(0)),
+        },
+        set(m, or(CILNode::LDLoc(m), u128_const(IMPLICIT))),
+        goto(next),
+    ];
+    let shift = [
+        CILRoot::BFalse {
+            target: next,
+            sub_target: 0,
+            cond: u128_eq(and(CILNode::LDLoc(m), u128_const(IMPLICIT)), u128_const(0)),
+        },
+        set(m, shl(CILNode::LDLoc(m), ldc_i32!(1))),
+        set(e, CILNode::LDLoc(e) - ldc_i32!(1)),
+        goto(id + 2),
+    ];
+    [
+        BasicBlock::new(unpack.into_iter().map(Into::into).collect(), id, None),
+        // Subnormals have the same scale as floats with an exponent of 1.
+        BasicBlock::new(
+            vec![set(e, ldc_i32!(1)).into(), goto(id + 2).into()],
+            id + 1,
+            None,
+        ),
+        BasicBlock::new(shift.into_iter().map(Into::into).collect(), id + 2, None),
+    ]
+}
+/// Adds `fmod_f128`, which computes the remainder of `x / y` rounded towards zero, like C's `fmod`. The result is always
+/// exact, so it is computed by long division of the significands.
+pub fn fmod_f128(asm: &mut Assembly) {
+    let sign = |tree: CILNode| or(tree, CILNode::LDLoc(SX));
+    let mut blocks = vec![
+        BasicBlock::new(
+            vec![
+                set(SX, and(bits_of(CILNode::LDArgA(0)), u128_const(SIGN))).into(),
+                set(UX, and(bits_of(CILNode::LDArgA(0)), u128_const(ABS))).into(),
+                set(UY, and(bits_of(CILNode::LDArgA(1)), u128_const(ABS))).into(),
+                // `y` is zero or NaN, or `x` is infinite or NaN.
+                CILRoot::BTrue {
+                    target: 1,
+                    sub_target: 0,
+                    cond: u128_eq(CILNode::LDLoc(UY), u128_const(0)),
+                }
+                .into(),
+                CILRoot::BFalse {
+                    target: 1,
+                    sub_target: 0,
+                    cond: u128_lt(CILNode::LDLoc(UX), u128_const(INFINITY)),
+                }
+                .into(),
+                CILRoot::BTrue {
+                    target: 1,
+                    sub_target: 0,
+                    cond: u128_lt(u128_const(INFINITY), CILNode::LDLoc(UY)),
+                }
+                .into(),
+                // `|x| < |y|`
+                CILRoot::BTrue {
+                    target: 2,
+                    sub_target: 0,
+                    cond: u128_lt(CILNode::LDLoc(UX), CILNode::LDLoc(UY)),
+                }
+                .into(),
+                CILRoot::BTrue {
+                    target: 3,
+                    sub_target: 0,
+                    cond: u128_eq(CILNode::LDLoc(UX), CILNode::LDLoc(UY)),
+                }
+                .into(),
+                goto(4).into(),
+            ],
+            0,
+            None,
+        ),
+        BasicBlock::new(
+            vec![CILRoot::Ret {
+                tree: crate::float::from_bits(&Type::F128, QUIET_NAN),
+            }
+            .into()],
+            1,
+            None,
+        ),
+        BasicBlock::new(
+            vec![CILRoot::Ret {
+                tree: CILNode::LDArg(0),
+            }
+            .into()],
+            2,
+            None,
+        ),
+        // The result is a zero with the sign of `x`.
+        BasicBlock::new(
+            vec![set(BITS, CILNode::LDLoc(SX)).into(), goto(16).into()],
+            3,
+            None,
+        ),
+    ];
+    blocks.extend(normalize(MX, EX, UX, 4, 7));
+    blocks.extend(normalize(MY, EY, UY, 7, 10));
+    blocks.extend([
+        // Subtracts `my` from `mx` once per bit of the exponent difference. `mx < 2 * my` always holds.
+        BasicBlock::new(
+            vec![
+                CILRoot::BTrue {
+                    target: 11,
+                    sub_target: 0,
+                    cond: u128_lt(CILNode::LDLoc(MX), CILNode::LDLoc(MY)),
+                }
+                .into(),
+                set(MX, sub(CILNode::LDLoc(MX), CILNode::LDLoc(MY))).into(),
+                goto(11).into(),
+            ],
+            10,
+            None,
+        ),
+        BasicBlock::new(
+            vec![
+                CILRoot::BTrue {
+                    target: 12,
+                    sub_target: 0,
+                    cond: eq!(CILNode::LDLoc(EX), CILNode::LDLoc(EY)),
+                }
+                .into(),
+                set(MX, shl(CILNode::LDLoc(MX), ldc_i32!(1))).into(),
+                set(EX, CILNode::LDLoc(EX) - ldc_i32!(1)).into(),
+                goto(10).into(),
+            ],
+            11,
+            None,
+        ),
+        BasicBlock::new(
+            vec![
+                CILRoot::BTrue {
+                    target: 3,
+                    sub_target: 0,
+                    cond: u128_eq(CILNode::LDLoc(MX), u128_const(0)),
+                }
+                .into(),
+                goto(13).into(),
+            ],
+            12,
+            None,
+        ),
+        BasicBlock::new(
+            vec![
+                CILRoot::BFalse {
+                    target: 14,
+                    sub_target: 0,
+                    cond: u128_eq(and(CILNode::LDLoc(MX), u128_const(IMPLICIT)), u128_const(0)),
+                }
+                .into(),
+                set(MX, shl(CILNode::LDLoc(MX), ldc_i32!(1))).into(),
+                set(EX, CILNode::LDLoc(EX) - ldc_i32!(1)).into(),
+                goto(13).into(),
+            ],
+            13,
+            None,
+        ),
+        BasicBlock::new(
+            vec![
+                CILRoot::BTrue {
+                    target: 15,
+                    sub_target: 0,
+                    cond: lt!(CILNode::LDLoc(EX), ldc_i32!(1)),
+                }
+                .into(),
+                set(
+                    BITS,
+                    sign(or(
+                        and(CILNode::LDLoc(MX), u128_const(MANTISSA)),
+                        shl(
+                            crate::casts::int_to_int(Type::I32, &Type::U128, CILNode::LDLoc(EX)),
+                            ldc_i32!(112),
+                        ),
+                    )),
+                )
+                .into(),
+                goto(16).into(),
+            ],
+            14,
+            None,
+        ),
+        // The result is subnormal. It is exact, so no bits are lost.
+        BasicBlock::new(
+            vec![
+                set(
+                    BITS,
+                    sign(shr(CILNode::LDLoc(MX), ldc_i32!(1) - CILNode::LDLoc(EX))),
+                )
+                .into(),
+                goto(16).into(),
+            ],
+            15,
+            None,
+        ),
+        BasicBlock::new(
+            vec![
+                CILRoot::STObj {
+                    tpe: Box::new(Type::U128),
+                    addr_calc: CILNode::TransmutePtr {
+                        val: Box::new(CILNode::LDLocA(RES)),
+                        new_ptr: Box::new(Type::Ptr(Type::U128.into())),
+                    },
+                    value_calc: CILNode::LDLoc(BITS),
+                }
+                .into(),
+                CILRoot::Ret {
+                    tree: CILNode::LDLoc(RES),
+                }
+                .into(),
+            ],
+            16,
+            None,
+        ),
+    ]);
+    asm.add_method(Method::new(
+        AccessModifer::MoudlePublic,
+        MethodType::Static,
+        FnSig::new(&[Type::F128, Type::F128], Type::F128),
+        FMOD_F128,
+        vec![
+            (Some("sx".into()), Type::U128),
+            (Some("ux".into()), Type::U128),
+            (Some("uy".into()), Type::U128),
+            (Some("mx".into()), Type::U128),
+            (Some("my".into()), Type::U128),
+            (Some("ex".into()), Type::I32),
+            (Some("ey".into()), Type::I32),
+            (Some("bits".into()), Type::U128),
+            (Some("res".into()), Type::F128),
+        ],
+        blocks,
+        vec![Some("x".into()), Some("y".into())],
+    ));
+}
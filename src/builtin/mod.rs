@@ -9,7 +9,7 @@ use cilly::{
     cil_root::CILRoot,
     conv_usize,
     field_desc::FieldDescriptor,
-    fn_sig::FnSig,
+    fn_sig::{CallConv, FnSig},
     ld_field, ldc_i32, ldc_u32, ldc_u64, lt_un,
     method::{Method, MethodType},
     r#type::Type,
@@ -20,6 +20,7 @@ use cilly::{
 use rustc_middle::ty::TyCtxt;
 mod atomic;
 mod casts;
+pub(crate) mod f128;
 mod select;
 const MAX_ALLOC_SIZE: u64 = u32::MAX as u64;
 add_method_from_trees!(
@@ -218,6 +219,7 @@ pub fn insert_ffi_functions(asm: &mut Assembly, tyctx: TyCtxt) {
 
     casts::casts(asm);
     select::selects(asm);
+    f128::fmod_f128(asm);
     //malloc(asm);
     let mut marshal = DotnetTypeRef::new(
         Some("System.Runtime.InteropServices"),
@@ -235,7 +237,7 @@ pub fn insert_ffi_functions(asm: &mut Assembly, tyctx: TyCtxt) {
         FnSig::new(&[Type::USize, Type::USize], Type::Ptr(Type::U8.into())),
         "__rust_alloc",
         vec![],
-        if *crate::config::CHECK_ALLOCATIONS {
+        if crate::config::config().check_allocations {
             vec![
                 BasicBlock::new(
                     vec![CILRoot::BTrue {
@@ -284,7 +286,7 @@ pub fn insert_ffi_functions(asm: &mut Assembly, tyctx: TyCtxt) {
         FnSig::new(&[Type::USize, Type::USize], Type::Ptr(Type::U8.into())),
         "__rust_alloc_zeroed",
         vec![(Some("alloc_ptr".into()), Type::Ptr(Box::new(Type::U8)))],
-        if *crate::config::CHECK_ALLOCATIONS {
+        if crate::config::config().check_allocations {
             vec![
                 BasicBlock::new(
                     vec![CILRoot::BTrue {
@@ -413,7 +415,7 @@ pub fn insert_ffi_functions(asm: &mut Assembly, tyctx: TyCtxt) {
         ),
         "__rust_realloc",
         vec![],
-        if *crate::config::CHECK_ALLOCATIONS {
+        if crate::config::config().check_allocations {
             vec![
                 BasicBlock::new(
                     vec![CILRoot::BTrue {
@@ -484,6 +486,7 @@ pub fn insert_ffi_functions(asm: &mut Assembly, tyctx: TyCtxt) {
     pthread_attr_setstacksize(asm);
     pthread_detach(asm);
     __cxa_thread_atexit_impl(asm);
+    cilly::unwind::add_unwind_support(asm, crate::config::config().c_mode);
     let unmanaged_start = TypeDef::new(
         AccessModifer::MoudlePublic,
         "UnmanagedThreadStart".into(),
@@ -555,8 +558,10 @@ pub fn insert_ffi_functions(asm: &mut Assembly, tyctx: TyCtxt) {
                 vec![],
                 vec![BasicBlock::new(
                     vec![
+                        // `start_fn` is an `extern "C"` function, so it points to an unmanaged stub.
                         CILRoot::CallI {
-                            sig: FnSig::new(&[Type::Ptr(Box::new(Type::Void))], Type::Void),
+                            sig: FnSig::new(&[Type::Ptr(Box::new(Type::Void))], Type::Void)
+                                .with_call_conv(CallConv::C),
                             fn_ptr: ld_field!(
                                 CILNode::LDArg(0),
                                 FieldDescriptor::new(
@@ -1,6 +1,6 @@
 use crate::r#type::TyCache;
 use cilly::FnSig;
-use rustc_middle::ty::{Instance, List, ParamEnv, ParamEnvAnd, TyCtxt, TyKind};
+use rustc_middle::ty::{Instance, InstanceDef, List, ParamEnv, ParamEnvAnd, TyCtxt, TyKind};
 use rustc_target::abi::call::Conv;
 use rustc_target::spec::abi::Abi as TargetAbi;
 pub struct CallInfo {
@@ -36,11 +36,14 @@ impl CallInfo {
         // There are 2 ABI enums for some reasons(they differ in what memebers they have)
         let fn_ty = function.ty(tyctx, ParamEnv::reveal_all());
         let internal_abi = match fn_ty.kind() {
-            TyKind::FnDef(_, _) => fn_ty.fn_sig(tyctx),
-            TyKind::Closure(_, args) => args.as_closure().sig(),
+            // Thread local shims use the Rust ABI, but their type is the type of the static.
+            _ if matches!(function.def, InstanceDef::ThreadLocalShim(_)) => TargetAbi::Rust,
+            TyKind::FnDef(_, _) => fn_ty.fn_sig(tyctx).abi(),
+            TyKind::Closure(_, args) => args.as_closure().sig().abi(),
+            // The resume function of a coroutine always uses the Rust ABI.
+            TyKind::Coroutine(_, _) => TargetAbi::Rust,
             _ => todo!("Can't get signature of {fn_ty}"),
-        }
-        .abi();
+        };
         // Only those ABIs are supported
         let split_last_tuple = match internal_abi {
             TargetAbi::C { unwind: _ }
@@ -130,6 +130,9 @@ pub fn int_to_int(src: Type, target: &Type, operand: CILNode) -> CILNode {
 }
 /// Returns CIL ops required to convert type src to target
 pub fn float_to_int(src: Type, target: &Type, operand: CILNode) -> CILNode {
+    if crate::float::is_emulated(&src) {
+        return crate::float::float_to_int(&src, target, operand);
+    }
     match target {
         Type::I128 => call!(
             CallSite::new_extern(
@@ -366,7 +369,9 @@ fn to_int(target: &Type, operand: CILNode) -> CILNode {
 }
 /// Returns CIL ops required to casts from intiger type `src` to `target`
 pub fn int_to_float(src: Type, target: &Type, parrent: CILNode) -> CILNode {
-    if matches!(src, Type::I128) {
+    if crate::float::is_emulated(target) {
+        crate::float::int_to_float(&src, target, parrent)
+    } else if matches!(src, Type::I128) {
         call!(
             CallSite::boxed(
                 DotnetTypeRef::int_128().into(),
@@ -1,4 +1,7 @@
-use std::fmt::Debug;
+use std::{any::Any, fmt::Debug, path::Path, sync::Mutex};
+
+use rustc_middle::ty::TyCtxt;
+use serde::Serialize;
 
 #[derive(Debug)]
 /// Repersentation of an error which occured while converting MIR to CIL assembly.
@@ -18,31 +21,94 @@ impl CodegenError {
     pub fn from_panic_message(msg: &str) -> Self {
         Self::Error(msg.into())
     }
+    /// Returns the message describing this error.
+    pub fn message(&self) -> String {
+        match self {
+            Self::Error(msg) => msg.to_string(),
+            _ => format!("{self:?}"),
+        }
+    }
 }
-
+/// Returns the message of a panic payload, if it is a string.
+pub fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+}
+/// Describes what the codegen emitted in place of the code which failed to compile.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", content = "message")]
+pub enum Placeholder {
+    /// The statement was replaced with a `CILRoot::throw` with this message.
+    Statement(String),
+    /// The terminator was replaced with a `CILRoot::throw` with this message.
+    Terminator(String),
+    /// The whole method was skipped, so any calls to it will fail.
+    MissingMethod,
+}
+#[derive(Serialize, Clone)]
 pub struct MethodCodegenError {
+    /// The symbol of the MIR item which failed to compile.
+    item: String,
     file: String,
     line: u32,
     column: u32,
     message: String,
+    placeholder: Placeholder,
 }
+/// All the errors reported while compiling the current crate.
+static REPORTED: Mutex<Vec<MethodCodegenError>> = Mutex::new(Vec::new());
 impl MethodCodegenError {
-    pub fn new(file: &str, line: u32, column: u32, message: String) -> Self {
+    pub fn new(
+        item: &str,
+        file: &str,
+        line: u32,
+        column: u32,
+        message: String,
+        placeholder: Placeholder,
+    ) -> Self {
         Self {
+            item: item.into(),
             file: file.into(),
             line,
             column,
             message,
+            placeholder,
         }
     }
+    /// Creates an error which occured in the part of `item` described by `span`.
+    pub fn from_span(
+        tcx: TyCtxt,
+        span: rustc_span::Span,
+        item: &str,
+        message: String,
+        placeholder: Placeholder,
+    ) -> Self {
+        let (file, line, column, _, _) = tcx.sess.source_map().span_to_location_info(span);
+        let file = file
+            .map(|file| {
+                file.name
+                    .display(rustc_span::FileNameDisplayPreference::Local)
+                    .to_string()
+            })
+            .unwrap_or_default();
+        Self::new(
+            item,
+            &file,
+            u32::try_from(line).unwrap_or(u32::MAX),
+            u32::try_from(column).unwrap_or(u32::MAX),
+            message,
+            placeholder,
+        )
+    }
+    /// Prints the error, and saves it, so that it ends up in the error report of this crate.
     pub fn report(&self) {
-        eprintln!(
-            "Method Codegen Error: {file}({line},{column}): {message}",
-            file = self.file,
-            line = self.line,
-            column = self.column,
-            message = self.message
-        );
+        eprintln!("{self:?}");
+        REPORTED
+            .lock()
+            .expect("Codegen error report poisoned")
+            .push(self.clone());
     }
 }
 impl Debug for MethodCodegenError {
@@ -57,3 +123,17 @@ impl Debug for MethodCodegenError {
         )
     }
 }
+/// Returns all the errors reported so far, clearing the list.
+pub fn take_reported() -> Vec<MethodCodegenError> {
+    std::mem::take(&mut *REPORTED.lock().expect("Codegen error report poisoned"))
+}
+/// Writes `errors` to a JSON report at `path`. If there are no errors, removes the stale report from a previous build instead.
+pub fn save_report(errors: &[MethodCodegenError], path: &Path) {
+    if errors.is_empty() {
+        // The report may not exist, which is fine.
+        let _ = std::fs::remove_file(path);
+        return;
+    }
+    let file = std::fs::File::create(path).expect("Could not create the codegen error report");
+    serde_json::to_writer_pretty(file, errors).expect("Could not save the codegen error report");
+}
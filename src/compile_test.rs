@@ -6,7 +6,7 @@ pub fn test_dotnet_executable(file_path: &str, test_dir: &str) -> String {
 
     let exec_path = &format!("{file_path}.exe");
     let mut stdout = String::new();
-    if *crate::config::C_MODE {
+    if crate::config::config().c_mode {
         let out = std::process::Command::new("timeout")
             .current_dir(test_dir)
             .arg("-v")
@@ -49,7 +49,7 @@ pub fn test_dotnet_executable(file_path: &str, test_dir: &str) -> String {
         );
         stdout = String::from_utf8_lossy(&out.stdout).to_string();
     }
-    if *IS_MONO_PRESENT && *crate::config::TEST_WITH_MONO {
+    if *IS_MONO_PRESENT && crate::config::config().test_with_mono {
         // Execute the test assembly
         let out = std::process::Command::new("mono")
             .current_dir(test_dir)
@@ -679,6 +679,7 @@ test_lib! {tuple,stable}
 
 run_test! {types,dst,stable}
 run_test! {types,dyns,stable}
+run_test! {types,dyn_upcast,stable}
 run_test! {types,maybeuninit,stable}
 test_lib! {types,stable}
 
@@ -715,6 +716,9 @@ run_test! {types,slice,stable}
 run_test! {types,slice_from_end,stable}
 run_test! {types,statics,stable}
 run_test! {types,async_types,unstable}
+run_test! {types,coroutine,unstable}
+run_test! {types,f16,unstable}
+run_test! {types,simd,unstable}
 run_test! {types,self_referential_statics,stable}
 run_test! {types,int128,stable}
 
@@ -725,9 +729,12 @@ run_test! {std,futex_test,unstable}
 run_test! {std,futexrw_test,unstable}
 run_test! {std,tlocal_key_test,stable}
 run_test! {std,cstr,unstable}
+run_test! {std,native_callback,stable}
 run_test! {std,format,unstable}
+run_test! {std,float_format,unstable}
 run_test! {std,cell_test,stable}
 run_test! {std,once_lock_test,unstable}
+run_test! {std,thread_local,stable}
 run_test! {control_flow,cf_for,stable}
 run_test! {control_flow,drop,stable}
 run_test! {intrinsics,bswap,stable}
@@ -903,6 +910,7 @@ cargo_test_ignored! {build_std}
 cargo_test! {benchmarks,stable}
 cargo_test! {glam_test,stable}
 cargo_test! {fastrand_test,stable}
+cargo_test! {async_tasks,stable}
 
 use lazy_static::lazy_static;
 
@@ -943,11 +951,37 @@ lazy_static! {
         }
 
     };
+    /// Cached path to the cilly interpreter.
+    pub static ref CILLY_INTERPRETER:PathBuf = {
+        let _ = *RUSTC_BUILD_STATUS;
+        if cfg!(debug_assertions) {
+            std::process::Command::new("cargo").args(["build","--bin","interpreter"]).output().unwrap();
+            //TODO: Fix this for other platforms
+            if cfg!(target_os = "linux") || cfg!(target_os = "macos") {
+                std::fs::canonicalize("target/debug/interpreter").unwrap()
+            } else if cfg!(target_os = "windows") {
+                std::fs::canonicalize("target/debug/interpreter.exe").unwrap()
+            }
+             else {
+                panic!("Unsupported target OS");
+            }
+        } else {
+            std::process::Command::new("cargo").args(["build","--bin","interpreter","--release"]).output().unwrap();
+            //TODO: Fix this for other platforms
+            if cfg!(target_os = "linux") || cfg!(target_os = "macos") {
+                std::fs::canonicalize("target/release/interpreter").unwrap()
+            } else if cfg!(target_os = "windows") {
+                std::fs::canonicalize("target/release/interpreter.exe").unwrap()
+            } else {
+                panic!("Unsupported target OS");
+            }
+        }
+    };
 }
 /// A list of arguments needed for invoking `rustc` with this backend included.
 #[must_use]
 pub fn rustc_args() -> Box<[String]> {
-    if *crate::config::RANDOMIZE_LAYOUT {
+    if crate::config::config().randomize_layout {
         [
             "-Z".to_owned(),
             backend_path(),
@@ -979,7 +1013,7 @@ pub fn cargo_build_env() -> String {
     let backend = backend.display();
     let linker = RUSTC_CODEGEN_CLR_LINKER.display();
     let link_args = "--cargo-support";
-    let radomize_layout = if *crate::config::RANDOMIZE_LAYOUT {
+    let radomize_layout = if crate::config::config().randomize_layout {
         "-Z randomize-layout"
     } else {
         ""
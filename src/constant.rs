@@ -2,8 +2,8 @@ use crate::r#type::TyCache;
 
 use cilly::{
     call_site::CallSite, cil_node::CILNode, cil_root::CILRoot, conv_u64, conv_usize,
-    field_desc::FieldDescriptor, ldc_u64, static_field_desc::StaticFieldDescriptor, DotnetTypeRef,
-    FnSig, Type,
+    field_desc::FieldDescriptor, ldc_u64, native_callbacks::unmanaged_stub_site,
+    static_field_desc::StaticFieldDescriptor, DotnetTypeRef, FnSig, Type,
 };
 use rustc_middle::{
     mir::{
@@ -176,9 +176,10 @@ fn load_scalar_ptr(
                     return CILNode::TemporaryLocal(Box::new((
                         Type::USize,
                         [CILRoot::SetTMPLocal {
-                            value: CILNode::LDFtn(Box::new(CallSite::builtin(
-                                "statx".into(),
-                                FnSig::new(
+                            // Weak symbols are `extern "C"` functions, called trough unmanaged stubs.
+                            value: CILNode::LDFtn(Box::new(unmanaged_stub_site(
+                                "statx",
+                                &FnSig::new(
                                     &[
                                         Type::I32,
                                         Type::Ptr(Type::U8.into()),
@@ -188,7 +189,6 @@ fn load_scalar_ptr(
                                     ],
                                     Type::I32,
                                 ),
-                                true,
                             ))),
                         }]
                         .into(),
@@ -199,13 +199,12 @@ fn load_scalar_ptr(
                     return CILNode::TemporaryLocal(Box::new((
                         Type::USize,
                         [CILRoot::SetTMPLocal {
-                            value: CILNode::LDFtn(Box::new(CallSite::builtin(
-                                "getrandom".into(),
-                                FnSig::new(
+                            value: CILNode::LDFtn(Box::new(unmanaged_stub_site(
+                                "getrandom",
+                                &FnSig::new(
                                     &[Type::Ptr(Type::U8.into()), Type::USize, Type::U32],
                                     Type::USize,
                                 ),
-                                true,
                             ))),
                         }]
                         .into(),
@@ -230,9 +229,9 @@ fn load_scalar_ptr(
                     return CILNode::TemporaryLocal(Box::new((
                         Type::USize,
                         [CILRoot::SetTMPLocal {
-                            value: CILNode::LDFtn(Box::new(CallSite::builtin(
-                                "__cxa_thread_atexit_impl".into(),
-                                FnSig::new(
+                            value: CILNode::LDFtn(Box::new(unmanaged_stub_site(
+                                "__cxa_thread_atexit_impl",
+                                &FnSig::new(
                                     &[
                                         Type::DelegatePtr(Box::new(FnSig::new(
                                             [Type::Ptr(Box::new(Type::Void))],
@@ -243,7 +242,6 @@ fn load_scalar_ptr(
                                     ],
                                     Type::Void,
                                 ),
-                                true,
                             ))),
                         }]
                         .into(),
@@ -274,10 +272,7 @@ fn load_scalar_ptr(
             // If it is a function, patch its pointer up.
             let call_info =
                 crate::call_info::CallInfo::sig_from_instance_(finstance, tyctx, tycache);
-            let function_name = crate::utilis::function_name(tyctx.symbol_name(finstance));
-            return CILNode::LDFtn(
-                CallSite::new(None, function_name, call_info.sig().clone(), true).into(),
-            );
+            return crate::function_sig::fn_ptr(finstance, call_info.sig().clone(), tyctx);
         }
         _ => todo!("Unhandled global alloc {global_alloc:?}"),
     }
@@ -354,7 +349,7 @@ fn load_const_scalar<'ctx>(
 }
 fn load_const_float(value: u128, float_type: &FloatTy, _tyctx: TyCtxt) -> CILNode {
     match float_type {
-        FloatTy::F16 => todo!("Can't hanlde 16 bit floats yet!"),
+        FloatTy::F16 => crate::float::from_bits(&Type::F16, value),
         FloatTy::F32 => {
             let value = f32::from_ne_bytes((value as u32).to_ne_bytes());
             CILNode::LdcF32(value)
@@ -363,7 +358,7 @@ fn load_const_float(value: u128, float_type: &FloatTy, _tyctx: TyCtxt) -> CILNod
             let value = f64::from_ne_bytes((value as u64).to_ne_bytes());
             CILNode::LdcF64(value)
         }
-        FloatTy::F128 => todo!("Can't hanlde 128 bit floats yet!"),
+        FloatTy::F128 => crate::float::from_bits(&Type::F128, value),
     }
 }
 pub fn load_const_int(value: u128, int_type: &IntTy) -> CILNode {
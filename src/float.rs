@@ -0,0 +1,233 @@
+//! Lowering of `f16` and `f128`, which have no CIL instructions.
+//!
+//! `f16` is mapped to `System.Half`, and its operations are calls to the operators of that type. `f128` is stored as
+//! its bits, and its operations are implemented in software, by the routines `compiler_builtins` provides, or
+//! by the helpers in [`crate::builtin`].
+use cilly::{
+    call, call_site::CallSite, conv_f_un, conv_i64, conv_isize, conv_u64, conv_usize, eq, gt,
+    ldc_i32, lt, DotnetTypeRef, FnSig, Type,
+};
+use cilly::{cil_node::CILNode, cil_root::CILRoot};
+use rustc_middle::{mir::BinOp, ty::UintTy};
+/// Checks if the operations on floats of type `tpe` are lowered to calls.
+pub fn is_emulated(tpe: &Type) -> bool {
+    matches!(tpe, Type::F16 | Type::F128)
+}
+/// Calls the operator `name` of `System.Half`.
+fn half_call(
+    name: &str,
+    inputs: &[Type],
+    output: Type,
+    args: impl Into<Box<[CILNode]>>,
+) -> CILNode {
+    call!(
+        CallSite::new_extern(
+            DotnetTypeRef::half(),
+            name.into(),
+            FnSig::new(inputs, output),
+            true
+        ),
+        args
+    )
+}
+/// Calls the software float routine `name`.
+fn soft_call(
+    name: &str,
+    inputs: &[Type],
+    output: Type,
+    args: impl Into<Box<[CILNode]>>,
+) -> CILNode {
+    call!(
+        CallSite::builtin(name.into(), FnSig::new(inputs, output), true),
+        args
+    )
+}
+/// Loads the float of type `tpe` with the bit pattern `bits`.
+pub fn from_bits(tpe: &Type, bits: u128) -> CILNode {
+    CILNode::LdObj {
+        ptr: Box::new(CILNode::TransmutePtr {
+            val: Box::new(CILNode::PointerToConstValue(bits)),
+            new_ptr: Box::new(Type::Ptr(Box::new(tpe.clone()))),
+        }),
+        obj: Box::new(tpe.clone()),
+    }
+}
+/// Preforms the arithmetic or comparison `op` on two floats of the emulated type `tpe`.
+pub fn binop(op: BinOp, tpe: &Type, a: CILNode, b: CILNode) -> CILNode {
+    match tpe {
+        Type::F16 => {
+            let (name, output) = match op {
+                BinOp::Add => ("op_Addition", Type::F16),
+                BinOp::Sub => ("op_Subtraction", Type::F16),
+                BinOp::Mul => ("op_Multiply", Type::F16),
+                BinOp::Div => ("op_Division", Type::F16),
+                BinOp::Rem => ("op_Modulus", Type::F16),
+                BinOp::Eq => ("op_Equality", Type::Bool),
+                BinOp::Ne => ("op_Inequality", Type::Bool),
+                BinOp::Lt => ("op_LessThan", Type::Bool),
+                BinOp::Le => ("op_LessThanOrEqual", Type::Bool),
+                BinOp::Gt => ("op_GreaterThan", Type::Bool),
+                BinOp::Ge => ("op_GreaterThanOrEqual", Type::Bool),
+                _ => panic!("{op:?} is not a float operation"),
+            };
+            half_call(name, &[Type::F16, Type::F16], output, [a, b])
+        }
+        Type::F128 => {
+            let arith = match op {
+                BinOp::Add => Some("__addtf3"),
+                BinOp::Sub => Some("__subtf3"),
+                BinOp::Mul => Some("__multf3"),
+                BinOp::Div => Some("__divtf3"),
+                BinOp::Rem => Some(crate::builtin::f128::FMOD_F128),
+                _ => None,
+            };
+            if let Some(arith) = arith {
+                return soft_call(arith, &[Type::F128, Type::F128], Type::F128, [a, b]);
+            }
+            // The comparison routines return a value less than, equal to or greater than 0, like `memcmp`.
+            let (name, cmp): (_, fn(CILNode) -> CILNode) = match op {
+                BinOp::Eq => ("__eqtf2", |res| eq!(res, ldc_i32!(0))),
+                BinOp::Ne => ("__netf2", |res| eq!(eq!(res, ldc_i32!(0)), ldc_i32!(0))),
+                BinOp::Lt => ("__lttf2", |res| lt!(res, ldc_i32!(0))),
+                BinOp::Le => ("__letf2", |res| eq!(gt!(res, ldc_i32!(0)), ldc_i32!(0))),
+                BinOp::Gt => ("__gttf2", |res| gt!(res, ldc_i32!(0))),
+                BinOp::Ge => ("__getf2", |res| eq!(lt!(res, ldc_i32!(0)), ldc_i32!(0))),
+                _ => panic!("{op:?} is not a float operation"),
+            };
+            cmp(soft_call(
+                name,
+                &[Type::F128, Type::F128],
+                Type::I32,
+                [a, b],
+            ))
+        }
+        _ => panic!("{tpe:?} is not an emulated float type!"),
+    }
+}
+/// Negates a float of the emulated type `tpe`.
+pub fn neg(tpe: &Type, operand: CILNode) -> CILNode {
+    match tpe {
+        Type::F16 => half_call("op_UnaryNegation", &[Type::F16], Type::F16, [operand]),
+        // Flips the sign bit. The bits are accessed trough the address of the float, since it is a `__float128` in C.
+        Type::F128 => {
+            let bits = CILNode::TransmutePtr {
+                val: Box::new(CILNode::LoadAddresOfTMPLocal),
+                new_ptr: Box::new(Type::Ptr(Box::new(Type::U128))),
+            };
+            let flipped = call!(
+                CallSite::new_extern(
+                    DotnetTypeRef::uint_128(),
+                    "op_ExclusiveOr".into(),
+                    FnSig::new(&[Type::U128, Type::U128], Type::U128),
+                    true
+                ),
+                [
+                    CILNode::LdObj {
+                        ptr: Box::new(bits.clone()),
+                        obj: Box::new(Type::U128),
+                    },
+                    crate::constant::load_const_uint(1 << 127, &UintTy::U128)
+                ]
+            );
+            CILNode::TemporaryLocal(Box::new((
+                Type::F128,
+                [
+                    CILRoot::SetTMPLocal { value: operand },
+                    CILRoot::STObj {
+                        tpe: Box::new(Type::U128),
+                        addr_calc: bits,
+                        value_calc: flipped,
+                    },
+                ]
+                .into(),
+                CILNode::LoadTMPLocal,
+            )))
+        }
+        _ => panic!("{tpe:?} is not an emulated float type!"),
+    }
+}
+/// Converts between float types, at least one of which is emulated.
+pub fn float_to_float(src: &Type, target: &Type, operand: CILNode) -> CILNode {
+    match (src, target) {
+        (Type::F16, Type::F16) | (Type::F128, Type::F128) => operand,
+        (Type::F16, Type::F32 | Type::F64) => {
+            half_call("op_Explicit", &[Type::F16], target.clone(), [operand])
+        }
+        (Type::F32 | Type::F64, Type::F16) => {
+            half_call("op_Explicit", &[src.clone()], Type::F16, [operand])
+        }
+        // Any `f16` can be represented exactly as an `f64`.
+        (Type::F16, Type::F128) => float_to_float(
+            &Type::F64,
+            &Type::F128,
+            float_to_float(&Type::F16, &Type::F64, operand),
+        ),
+        (Type::F32, Type::F128) => soft_call("__extendsftf2", &[Type::F32], Type::F128, [operand]),
+        (Type::F64, Type::F128) => soft_call("__extenddftf2", &[Type::F64], Type::F128, [operand]),
+        (Type::F128, Type::F16) => soft_call("__trunctfhf2", &[Type::F128], Type::F16, [operand]),
+        (Type::F128, Type::F32) => soft_call("__trunctfsf2", &[Type::F128], Type::F32, [operand]),
+        (Type::F128, Type::F64) => soft_call("__trunctfdf2", &[Type::F128], Type::F64, [operand]),
+        _ => panic!("Can't preform a FloatToFloat cast from {src:?} to {target:?}"),
+    }
+}
+/// Converts a float of the emulated type `src` to an intiger of type `target`, saturating like `as` does.
+pub fn float_to_int(src: &Type, target: &Type, operand: CILNode) -> CILNode {
+    match src {
+        // Any `f16` can be represented exactly as an `f32`.
+        Type::F16 => crate::casts::float_to_int(
+            Type::F32,
+            target,
+            float_to_float(&Type::F16, &Type::F32, operand),
+        ),
+        Type::F128 => {
+            let fix = |name, output| soft_call(name, &[Type::F128], output, [operand]);
+            match target {
+                Type::I32 => fix("__fixtfsi", Type::I32),
+                Type::U32 => fix("__fixunstfsi", Type::U32),
+                Type::I64 => fix("__fixtfdi", Type::I64),
+                Type::U64 => fix("__fixunstfdi", Type::U64),
+                Type::I128 => fix("__fixtfti", Type::I128),
+                Type::U128 => fix("__fixunstfti", Type::U128),
+                Type::ISize => conv_isize!(fix("__fixtfdi", Type::I64)),
+                Type::USize => conv_usize!(fix("__fixunstfdi", Type::U64)),
+                // Saturated to 32 bits, the value is exact as an `f64`, and can be saturated again to the target.
+                Type::I8 | Type::I16 => crate::casts::float_to_int(
+                    Type::F64,
+                    target,
+                    CILNode::ConvF64(fix("__fixtfsi", Type::I32).into()),
+                ),
+                Type::U8 | Type::U16 => crate::casts::float_to_int(
+                    Type::F64,
+                    target,
+                    conv_f_un!(fix("__fixunstfsi", Type::U32)),
+                ),
+                _ => panic!("{target:?} is not an intiger type"),
+            }
+        }
+        _ => panic!("{src:?} is not an emulated float type!"),
+    }
+}
+/// Converts an intiger of type `src` to a float of the emulated type `target`.
+pub fn int_to_float(src: &Type, target: &Type, operand: CILNode) -> CILNode {
+    match target {
+        // Intigers too big to be exact as an `f64` are too big for an `f16` too, so the rounding is correct.
+        Type::F16 => float_to_float(
+            &Type::F64,
+            &Type::F16,
+            crate::casts::int_to_float(src.clone(), &Type::F64, operand),
+        ),
+        Type::F128 => {
+            let (name, input, operand) = match src {
+                Type::I8 | Type::I16 | Type::I32 => ("__floatsitf", Type::I32, operand),
+                Type::U8 | Type::U16 | Type::U32 => ("__floatunsitf", Type::U32, operand),
+                Type::I64 | Type::ISize => ("__floatditf", Type::I64, conv_i64!(operand)),
+                Type::U64 | Type::USize => ("__floatunditf", Type::U64, conv_u64!(operand)),
+                Type::I128 => ("__floattitf", Type::I128, operand),
+                Type::U128 => ("__floatuntitf", Type::U128, operand),
+                _ => panic!("{src:?} is not an intiger type"),
+            };
+            soft_call(name, &[input], Type::F128, [operand])
+        }
+        _ => panic!("{target:?} is not an emulated float type!"),
+    }
+}
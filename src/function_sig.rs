@@ -2,7 +2,13 @@ use crate::{
     codegen_error::CodegenError,
     r#type::{TyCache, Type},
 };
-use cilly::FnSig;
+use cilly::{
+    call_site::CallSite,
+    cil_node::CILNode,
+    exports::{ExportInfo, ExportedType},
+    FnSig,
+};
+use rustc_middle::middle::codegen_fn_attrs::CodegenFnAttrFlags;
 use rustc_middle::ty::{Instance, List, ParamEnv, ParamEnvAnd, PolyFnSig, Ty, TyCtxt, TyKind};
 use rustc_target::abi::call::Conv;
 use rustc_target::spec::abi::Abi as TargetAbi;
@@ -23,7 +29,32 @@ pub fn from_poly_sig<'tyctx>(
         .iter()
         .map(|input| tycache.type_from_cache(*input, tyctx, method_instance))
         .collect();
-    FnSig::new(inputs, output)
+    let fn_sig = FnSig::new(inputs, output);
+    if is_c_abi(sig.abi) {
+        cilly::native_callbacks::unmanaged_sig(&fn_sig)
+    } else {
+        fn_sig
+    }
+}
+/// Checks if pointers to functions using `abi` should use the C calling convention.
+fn is_c_abi(abi: TargetAbi) -> bool {
+    matches!(
+        abi,
+        TargetAbi::C { .. } | TargetAbi::Cdecl { .. } | TargetAbi::System { .. }
+    )
+}
+/// Returns a pointer to `function`, which has the signature `sig`. Pointers to `extern "C"` functions point to their
+/// `[UnmanagedCallersOnly]` stub, so that they can be passed to native code.
+pub fn fn_ptr<'tyctx>(function: Instance<'tyctx>, sig: FnSig, tyctx: TyCtxt<'tyctx>) -> CILNode {
+    let function_name = crate::utilis::function_name(tyctx.symbol_name(function));
+    let fn_ty = function.ty(tyctx, ParamEnv::reveal_all());
+    let site = match fn_ty.kind() {
+        TyKind::FnDef(_, _) if is_c_abi(fn_ty.fn_sig(tyctx).abi()) => {
+            cilly::native_callbacks::unmanaged_stub_site(&function_name, &sig)
+        }
+        _ => CallSite::new(None, function_name, sig, true),
+    };
+    CILNode::LDFtn(site.into())
 }
 /// Returns the signature of function behind `function`.
 pub fn sig_from_instance_<'tyctx>(
@@ -56,11 +87,11 @@ pub fn sig_from_instance_<'tyctx>(
     // There are 2 ABI enums for some reasons(they differ in what memebers they have)
     let fn_ty = function.ty(tyctx, ParamEnv::reveal_all());
     let internal_abi = match fn_ty.kind() {
-        TyKind::FnDef(_, _) => fn_ty.fn_sig(tyctx),
-        TyKind::Closure(_, args) => args.as_closure().sig(),
+        TyKind::FnDef(_, _) => fn_ty.fn_sig(tyctx).abi(),
+        TyKind::Closure(_, args) => args.as_closure().sig().abi(),
+        TyKind::Coroutine(_, _) => TargetAbi::Rust,
         _ => todo!("Can't get signature of {fn_ty}"),
-    }
-    .abi();
+    };
     // Only those ABIs are supported
     match internal_abi {
         TargetAbi::C { unwind: _ } => (),
@@ -81,3 +112,54 @@ pub fn sig_from_instance_<'tyctx>(
 pub fn is_fn_variadic<'tyctx>(ty: Ty<'tyctx>, tyctx: TyCtxt<'tyctx>) -> bool {
     ty.fn_sig(tyctx).skip_binder().c_variadic
 }
+/// Returns how `function` should be exposed to .NET, if it is exported from Rust(`#[no_mangle] pub extern fn`).
+pub fn export_info<'tyctx>(
+    function: Instance<'tyctx>,
+    tyctx: TyCtxt<'tyctx>,
+    tycache: &mut TyCache,
+) -> Option<ExportInfo> {
+    let def_id = function.def_id();
+    if !def_id.is_local()
+        || !tyctx.visibility(def_id).is_public()
+        || !tyctx
+            .codegen_fn_attrs(def_id)
+            .flags
+            .contains(CodegenFnAttrFlags::NO_MANGLE)
+    {
+        return None;
+    }
+    let fn_ty = function.ty(tyctx, ParamEnv::reveal_all());
+    let TyKind::FnDef(_, _) = fn_ty.kind() else {
+        return None;
+    };
+    if fn_ty.fn_sig(tyctx).abi() == TargetAbi::Rust {
+        return None;
+    }
+    let fn_abi = tyctx
+        .fn_abi_of_instance(ParamEnvAnd {
+            param_env: ParamEnv::reveal_all(),
+            value: (function, List::empty()),
+        })
+        .ok()?;
+    let mut exported = |ty: Ty<'tyctx>| match ty.kind() {
+        TyKind::Ref(_, inner, _) => match inner.kind() {
+            TyKind::Str => ExportedType::Str,
+            TyKind::Slice(element) => {
+                ExportedType::Slice(tycache.type_from_cache(*element, tyctx, function))
+            }
+            _ => ExportedType::Direct,
+        },
+        _ => ExportedType::Direct,
+    };
+    let inputs = fn_abi
+        .args
+        .iter()
+        .map(|arg| exported(arg.layout.ty))
+        .collect();
+    let output = match exported(fn_abi.ret.layout.ty) {
+        // Slices can't be returned to .NET without copying them.
+        ExportedType::Slice(_) => ExportedType::Direct,
+        output => output,
+    };
+    Some(ExportInfo::new(inputs, output))
+}
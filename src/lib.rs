@@ -123,6 +123,8 @@ mod codegen_error;
 pub mod compile_test;
 /// Code handling loading constant values in CIL.
 mod constant;
+/// Lowering of `f16` and `f128` operations.
+mod float;
 
 /// Signature of a function (inputs)->output
 pub mod function_sig;
@@ -135,12 +137,12 @@ mod verify;
 pub mod method;
 /// Handles a MIR operand.
 mod operand;
-/// Method-level CIL opitimizations
-pub mod opt;
 /// Code handling getting/setting/adressing memory locations.
 mod place;
 /// Converts righthandside of a MIR statement into CIL ops.
 mod rvalue;
+/// Mapping of `repr(simd)` types to .NET vectors.
+mod simd;
 /// Code dealing with truning an individual MIR statement into CIL ops.
 pub mod statement;
 /// Converts a terminator of a basic block into CIL ops.
@@ -152,6 +154,8 @@ pub mod r#type;
 
 /// Implementations of unary operations.
 mod unop;
+/// Propagation of Rust panics as .NET exceptions.
+mod unwind;
 /// Contains small helper functions(debug assertions, functions used to get field names, etc), which are frequently used, but are not specific to a part of the coodegen.
 mod utilis;
 
@@ -195,6 +199,12 @@ impl CodegenBackend for MyBackend {
     fn locale_resource(&self) -> &'static str {
         ""
     }
+    /// Loads the configuration, applying the options passed trough `-C llvm-args`.
+    fn init(&self, sess: &Session) {
+        if let Err(err) = cilly::config::init(&sess.opts.cg.llvm_args) {
+            panic!("{err}");
+        }
+    }
     /// Compiles a crate, and returns its in-memory representaion as a .NET assembly.
     fn codegen_crate<'a>(
         &self,
@@ -243,8 +253,14 @@ impl CodegenBackend for MyBackend {
             builtin::insert_ffi_functions(&mut asm, tcx);
             drop(ffi_compile_timer);
             let name: IString = cgus.iter().next().unwrap().name().to_string().into();
-
-            Box::new((name, asm, metadata, CrateInfo::new(tcx, "clr".to_string())))
+            let errors = codegen_error::take_reported();
+            Box::new((
+                name,
+                asm,
+                metadata,
+                CrateInfo::new(tcx, "clr".to_string()),
+                errors,
+            ))
         }
     }
     /// Saves an in-memory assemably to codegen specific IR in a .bc file.
@@ -256,9 +272,16 @@ impl CodegenBackend for MyBackend {
     ) -> (CodegenResults, FxIndexMap<WorkProductId, WorkProduct>) {
         std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
             use std::io::Write;
-            let (_asm_name, asm, metadata, crate_info) = *ongoing_codegen
-                .downcast::<(IString, Assembly, EncodedMetadata, CrateInfo)>()
+            let (_asm_name, asm, metadata, crate_info, errors) = *ongoing_codegen
+                .downcast::<(
+                    IString,
+                    Assembly,
+                    EncodedMetadata,
+                    CrateInfo,
+                    Vec<codegen_error::MethodCodegenError>,
+                )>()
                 .expect("in join_codegen: ongoing_codegen is not an Assembly");
+            codegen_error::save_report(&errors, &outputs.with_extension("codegen_errors.json"));
             let asm_name = "";
             let serialized_asm_path = outputs.temp_path(OutputType::Bitcode, Some(asm_name));
             //std::fs::create_dir_all(&serialized_asm_path).expect("Could not create the directory temporary files are supposed to be in.");
@@ -1,5 +0,0 @@
-#![allow(clippy::similar_names)]
-
-use cilly::{asm::Assembly, method::Method};
-
-pub fn opt_method(_: &mut Method, _: &Assembly) {}
@@ -135,6 +135,17 @@ pub fn place_elem_adress<'ctx>(
                     )));
                     //todo!("Handle DST fields. DST:")
                 }
+                if crate::simd::vector_type(curr_ty, tyctx, method_instance, type_cache).is_some() {
+                    return crate::simd::field_address(
+                        curr_ty,
+                        index.as_u32(),
+                        addr_calc,
+                        tyctx,
+                        method_instance,
+                        type_cache,
+                    )
+                    .1;
+                }
                 let field_desc = crate::utilis::field_descrptor(
                     curr_ty,
                     (*index).into(),
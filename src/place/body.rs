@@ -98,6 +98,29 @@ pub fn place_elem_body<'ctx>(
                     //todo!("Handle DST fields. DST:")
                 }
                 let _curr_type = crate::utilis::monomorphize(&method_instance, curr_ty, tyctx);
+                if crate::simd::vector_type(curr_ty, tyctx, method_instance, type_cache).is_some() {
+                    let (field_ty, field_addr) = crate::simd::field_address(
+                        curr_ty,
+                        (*index).into(),
+                        parrent_node,
+                        tyctx,
+                        method_instance,
+                        type_cache,
+                    );
+                    if body_ty_is_by_adress(field_ty) {
+                        return (field_ty.into(), field_addr);
+                    }
+                    return (
+                        field_ty.into(),
+                        deref_op(
+                            field_ty.into(),
+                            tyctx,
+                            &method_instance,
+                            type_cache,
+                            field_addr,
+                        ),
+                    );
+                }
                 let field_desc = crate::utilis::field_descrptor(
                     curr_ty,
                     (*index).into(),
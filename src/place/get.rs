@@ -77,6 +77,24 @@ fn place_elem_get<'a>(
             super::PlaceTy::Ty(curr_type) => {
                 let curr_type = crate::utilis::monomorphize(&method_instance, curr_type, tyctx);
                 let _field_type = crate::utilis::monomorphize(&method_instance, curr_type, tyctx);
+                if crate::simd::vector_type(curr_type, tyctx, method_instance, type_cache).is_some()
+                {
+                    let (field_ty, field_addr) = crate::simd::field_address(
+                        curr_type,
+                        (*index).into(),
+                        addr_calc,
+                        tyctx,
+                        method_instance,
+                        type_cache,
+                    );
+                    return super::deref_op(
+                        field_ty.into(),
+                        tyctx,
+                        &method_instance,
+                        type_cache,
+                        field_addr,
+                    );
+                }
 
                 let field_desc = crate::utilis::field_descrptor(
                     curr_type,
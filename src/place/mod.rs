@@ -44,6 +44,7 @@ fn body_ty_is_by_adress(last_ty: Ty) -> bool {
         //TODO: check if slices are handled propely
         TyKind::Adt(_, _)
         | TyKind::Closure(_, _)
+        | TyKind::Coroutine(_, _)
         | TyKind::Array(_, _)
         | TyKind::Slice(_)
         | TyKind::Str => true,
@@ -99,10 +100,12 @@ pub fn deref_op<'ctx>(
                    //_ => todo!("TODO: can't deref int type {int_ty:?} yet"),
             },
             TyKind::Float(float_ty) => match float_ty {
-                FloatTy::F16 => todo!("Can't handle halfs yet!"),
                 FloatTy::F32 => CILNode::LDIndF32 { ptr },
                 FloatTy::F64 => CILNode::LDIndF64 { ptr },
-                FloatTy::F128 => todo!("Can't 128 bit floats yet!"),
+                FloatTy::F16 | FloatTy::F128 => CILNode::LdObj {
+                    ptr,
+                    obj: Box::new(crate::r#type::from_float(float_ty)),
+                },
             },
             TyKind::Bool => CILNode::LDIndBool { ptr }, // Both Rust bool and a managed bool are 1 byte wide. .NET bools are 4 byte wide only in the context of Marshaling/PInvoke,
             // due to historic reasons(BOOL was an alias for int in early Windows, and it stayed this way.) - FractalFir
@@ -111,7 +114,8 @@ pub fn deref_op<'ctx>(
             | TyKind::Tuple(_)
             | TyKind::Array(_, _)
             | TyKind::FnPtr(_)
-            | TyKind::Closure(_, _) => {
+            | TyKind::Closure(_, _)
+            | TyKind::Coroutine(_, _) => {
                 let derefed_type =
                     type_cache.type_from_cache(derefed_type, tyctx, *method_instance);
 
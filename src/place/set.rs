@@ -60,6 +60,24 @@ pub fn place_elem_set<'a>(
         PlaceElem::Field(index, _field_type) => match curr_type {
             PlaceTy::Ty(curr_type) => {
                 let curr_type = crate::utilis::monomorphize(&method_instance, curr_type, ctx);
+                if crate::simd::vector_type(curr_type, ctx, method_instance, type_cache).is_some() {
+                    let (field_ty, field_addr) = crate::simd::field_address(
+                        curr_type,
+                        (*index).into(),
+                        addr_calc,
+                        ctx,
+                        method_instance,
+                        type_cache,
+                    );
+                    return ptr_set_op(
+                        field_ty.into(),
+                        ctx,
+                        &method_instance,
+                        type_cache,
+                        field_addr,
+                        value_calc,
+                    );
+                }
                 let field_desc = crate::utilis::field_descrptor(
                     curr_type,
                     (*index).into(),
@@ -279,12 +297,20 @@ pub fn ptr_set_op<'ctx>(
             TyKind::Float(float_ty) => match float_ty {
                 FloatTy::F32 => CILRoot::STIndF32(addr_calc, value_calc),
                 FloatTy::F64 => CILRoot::STIndF64(addr_calc, value_calc),
-                _ => todo!("Can't yet set {float_ty:?} behnind a pointer."),
+                FloatTy::F16 | FloatTy::F128 => CILRoot::STObj {
+                    tpe: Box::new(crate::r#type::from_float(float_ty)),
+                    addr_calc,
+                    value_calc,
+                },
             },
             TyKind::Bool => CILRoot::STIndI8(addr_calc, value_calc), // Both Rust bool and a managed bool are 1 byte wide. .NET bools are 4 byte wide only in the context of Marshaling/PInvoke,
             // due to historic reasons(BOOL was an alias for int in early Windows, and it stayed this way.) - FractalFir
             TyKind::Char => CILRoot::STIndI32(addr_calc, value_calc), // always 4 bytes wide: https://doc.rust-lang.org/std/primitive.char.html#representation
-            TyKind::Adt(_, _) | TyKind::Tuple(_) | TyKind::Array(_, _) | TyKind::Closure(_, _) => {
+            TyKind::Adt(_, _)
+            | TyKind::Tuple(_)
+            | TyKind::Array(_, _)
+            | TyKind::Closure(_, _)
+            | TyKind::Coroutine(_, _) => {
                 let pointed_type =
                     type_cache.type_from_cache(pointed_type, tyctx, *method_instance);
                 CILRoot::STObj {
@@ -4,7 +4,7 @@ use cilly::field_desc::FieldDescriptor;
 use cilly::fn_sig::FnSig;
 use cilly::{call_site::CallSite, cil_node::CILNode};
 
-use cilly::{conv_usize, ld_field, ldc_i32, ldc_u64, size_of};
+use cilly::{call, conv_usize, ld_field, ldc_i32, ldc_u64, size_of};
 
 use crate::r#type::{pointer_to_is_fat, TyCache, Type};
 use rustc_middle::{
@@ -318,7 +318,13 @@ pub fn handle_rvalue<'tcx>(
         Rvalue::Cast(CastKind::FloatToFloat, operand, target) => {
             let target = crate::utilis::monomorphize(&method_instance, *target, tyctx);
             let target = tycache.type_from_cache(target, tyctx, method_instance);
+            let src = operand.ty(&method.local_decls, tyctx);
+            let src = crate::utilis::monomorphize(&method_instance, src, tyctx);
+            let src = tycache.type_from_cache(src, tyctx, method_instance);
             let mut ops = handle_operand(operand, tyctx, method, method_instance, tycache);
+            if crate::float::is_emulated(&src) || crate::float::is_emulated(&target) {
+                return crate::float::float_to_float(&src, &target, ops);
+            }
             match target {
                 Type::F32 => ops = CILNode::ConvF32(ops.into()),
                 Type::F64 => ops = CILNode::ConvF64(ops.into()),
@@ -351,12 +357,10 @@ pub fn handle_rvalue<'tcx>(
             } else {
                 todo!("Trying to call a type which is not a function definition!");
             };
-            let function_name = crate::utilis::function_name(tyctx.symbol_name(instance));
             let function_sig = crate::function_sig::sig_from_instance_(instance, tyctx, tycache)
                 .expect("Could not get function signature when trying to get a function pointer!");
             //FIXME: propely handle `#[track_caller]`
-            let call_site = CallSite::new(None, function_name, function_sig, true);
-            CILNode::LDFtn(call_site.into())
+            crate::function_sig::fn_ptr(instance, function_sig, tyctx)
         }
         //Rvalue::Cast(kind, _operand, _) => todo!("Unhandled cast kind {kind:?}, rvalue:{rvalue:?}"),
         Rvalue::Discriminant(place) => {
@@ -475,17 +479,20 @@ pub fn handle_rvalue<'tcx>(
         }
         Rvalue::ThreadLocalRef(def_id) => {
             if !def_id.is_local() && tyctx.needs_thread_local_shim(*def_id) {
-                let _instance = Instance {
+                let instance = Instance {
                     def: InstanceDef::ThreadLocalShim(*def_id),
                     args: GenericArgs::empty(),
                 };
-                // Call instance
-                todo!("Thread locals with shims unsupported!")
+                // The shim returns a pointer to the thread local.
+                let call_info =
+                    crate::call_info::CallInfo::sig_from_instance_(instance, tyctx, tycache);
+                let function_name = crate::utilis::function_name(tyctx.symbol_name(instance));
+                call!(
+                    CallSite::new(None, function_name, call_info.sig().clone(), true),
+                    []
+                )
             } else {
-                let alloc_id = tyctx.reserve_and_set_static_alloc(*def_id);
-                CILNode::LoadGlobalAllocPtr {
-                    alloc_id: alloc_id.0.into(),
-                }
+                call!(crate::assembly::thread_local_getter(*def_id, tyctx), [])
             }
         }
         Rvalue::Cast(rustc_middle::mir::CastKind::FnPtrToPtr, operand, _) => {
@@ -0,0 +1,92 @@
+use crate::r#type::TyCache;
+use cilly::{cil_node::CILNode, conv_usize, ldc_u64, DotnetTypeRef, Type};
+use rustc_middle::ty::{Instance, ParamEnv, Ty, TyCtxt, TyKind};
+use rustc_target::abi::FieldIdx;
+/// Returns the .NET vector type the `repr(simd)` type `ty` is mapped to. Returns `None` if `ty` is not a SIMD type, or
+/// if its size or lane type have no .NET counterpart: such types are treated like ordinary structs.
+pub fn vector_type<'tyctx>(
+    ty: Ty<'tyctx>,
+    tyctx: TyCtxt<'tyctx>,
+    method: Instance<'tyctx>,
+    cache: &mut TyCache,
+) -> Option<Type> {
+    if !ty.is_simd() {
+        return None;
+    }
+    let (lanes, lane_ty) = ty.simd_size_and_type(tyctx);
+    let lane = cache.type_from_cache(lane_ty, tyctx, method);
+    if !matches!(
+        lane,
+        Type::I8
+            | Type::U8
+            | Type::I16
+            | Type::U16
+            | Type::I32
+            | Type::U32
+            | Type::I64
+            | Type::U64
+            | Type::ISize
+            | Type::USize
+            | Type::F32
+            | Type::F64
+    ) {
+        return None;
+    }
+    let size = layout_size(ty, tyctx);
+    // Vectors with a lane count which is not a power of two are padded.
+    if size != lanes * layout_size(lane_ty, tyctx) {
+        return None;
+    }
+    match size * 8 {
+        bits @ (64 | 128 | 256 | 512) => Some(DotnetTypeRef::vector(bits as u32, lane).into()),
+        _ => None,
+    }
+}
+fn layout_size<'tyctx>(ty: Ty<'tyctx>, tyctx: TyCtxt<'tyctx>) -> u64 {
+    tyctx
+        .layout_of(rustc_middle::ty::ParamEnvAnd {
+            param_env: ParamEnv::reveal_all(),
+            value: ty,
+        })
+        .expect("Could not get type layout!")
+        .size
+        .bytes()
+}
+/// Returns the type of field `field_idx` of `owner`, a type mapped to a .NET vector, and the address of that field.
+/// Vectors have no fields in .NET, so the field is located using the layout of `owner`.
+pub fn field_address<'tyctx>(
+    owner: Ty<'tyctx>,
+    field_idx: u32,
+    addr: CILNode,
+    tyctx: TyCtxt<'tyctx>,
+    method: Instance<'tyctx>,
+    cache: &mut TyCache,
+) -> (Ty<'tyctx>, CILNode) {
+    let TyKind::Adt(def, subst) = owner.kind() else {
+        panic!("{owner:?} is not a SIMD type!");
+    };
+    let field_ty = def.non_enum_variant().fields[FieldIdx::from_u32(field_idx)].ty(tyctx, subst);
+    let field_ty = crate::utilis::monomorphize(&method, field_ty, tyctx);
+    let field_type = cache.type_from_cache(field_ty, tyctx, method);
+    let offset = tyctx
+        .layout_of(rustc_middle::ty::ParamEnvAnd {
+            param_env: ParamEnv::reveal_all(),
+            value: owner,
+        })
+        .expect("Could not get type layout!")
+        .fields
+        .offset(field_idx as usize)
+        .bytes();
+    let addr = if offset == 0 {
+        addr
+    } else {
+        addr + conv_usize!(ldc_u64!(offset))
+    };
+    (
+        field_ty,
+        CILNode::TransmutePtr {
+            val: Box::new(addr),
+            new_ptr: Box::new(Type::Ptr(Box::new(field_type))),
+        },
+    )
+}
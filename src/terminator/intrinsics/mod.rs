@@ -9,6 +9,7 @@ use cilly::{
 };
 use libc::READ_IMPLIES_EXEC;
 
+use crate::codegen_error::{MethodCodegenError, Placeholder};
 use crate::r#type::tycache::TyCache;
 use crate::{operand::handle_operand, place::place_set};
 use cilly::call_site::CallSite;
@@ -21,6 +22,7 @@ use rustc_middle::{
 use rustc_span::source_map::Spanned;
 mod bswap;
 mod interop;
+mod simd;
 pub fn interlocked_add(addr: CILNode, addend: CILNode, tpe: Type) -> CILNode {
     match tpe {
         Type::U64 | Type::I64 => {
@@ -65,6 +67,39 @@ pub fn interlocked_add(addr: CILNode, addend: CILNode, tpe: Type) -> CILNode {
         _ => todo!(),
     }
 }
+/// Computes the `TypeId` of `tpe`, as a `u128`, from the hash code of its .NET type.
+pub fn type_id(tpe: Type) -> CILNode {
+    let sig = FnSig::new(
+        &[DotnetTypeRef::type_handle_type().into()],
+        DotnetTypeRef::type_type(),
+    );
+    let gethash_sig = FnSig::new(&[DotnetTypeRef::type_type().into()], Type::I32);
+    call!(
+        CallSite::boxed(
+            Some(DotnetTypeRef::uint_128()),
+            "op_Implicit".into(),
+            FnSig::new(&[Type::U32], Type::U128),
+            true,
+        ),
+        [call_virt!(
+            CallSite::boxed(
+                DotnetTypeRef::object_type().into(),
+                "GetHashCode".into(),
+                gethash_sig,
+                false,
+            ),
+            [call!(
+                CallSite::boxed(
+                    DotnetTypeRef::type_type().into(),
+                    "GetTypeFromHandle".into(),
+                    sig,
+                    true,
+                ),
+                [CILNode::LDTypeToken(tpe.into())]
+            )]
+        )]
+    )
+}
 fn compare_bytes(a: CILNode, b: CILNode, len: CILNode) -> CILNode {
     call!(
         CallSite::builtin(
@@ -725,39 +760,10 @@ pub fn handle_intrinsic<'tyctx>(
                 tyctx,
             );
             let tpe = type_cache.type_from_cache(tpe, tyctx, method_instance);
-            let sig = FnSig::new(
-                &[DotnetTypeRef::type_handle_type().into()],
-                DotnetTypeRef::type_type(),
-            );
-            let gethash_sig = FnSig::new(&[DotnetTypeRef::type_type().into()], Type::I32);
             place_set(
                 destination,
                 tyctx,
-                call!(
-                    CallSite::boxed(
-                        Some(DotnetTypeRef::uint_128()),
-                        "op_Implicit".into(),
-                        FnSig::new(&[Type::U32], Type::U128),
-                        true,
-                    ),
-                    [call_virt!(
-                        CallSite::boxed(
-                            DotnetTypeRef::object_type().into(),
-                            "GetHashCode".into(),
-                            gethash_sig,
-                            false,
-                        ),
-                        [call!(
-                            CallSite::boxed(
-                                DotnetTypeRef::type_type().into(),
-                                "GetTypeFromHandle".into(),
-                                sig,
-                                true,
-                            ),
-                            [CILNode::LDTypeToken(tpe.into())]
-                        )]
-                    )]
-                ),
+                type_id(tpe),
                 body,
                 method_instance,
                 type_cache,
@@ -1478,21 +1484,46 @@ pub fn handle_intrinsic<'tyctx>(
         "catch_unwind" => {
             debug_assert_eq!(
                 args.len(),
-                2,
-                "The intrinsic `minnumf32` MUST take in exactly 2 arguments!"
+                3,
+                "The intrinsic `catch_unwind` MUST take in exactly 3 arguments!"
             );
             let try_fn = handle_operand(&args[0].node, tyctx, body, method_instance, type_cache);
             let data_ptr = handle_operand(&args[1].node, tyctx, body, method_instance, type_cache);
             let catch_fn = handle_operand(&args[2].node, tyctx, body, method_instance, type_cache);
-            let _ = catch_fn;
-            eprintln!("WARNING: catching unwinds currently not supported! the intrinic `catch_unwind` WILL NOT CATCH UNWINDS YET!");
-            CILRoot::CallI {
-                sig: FnSig::new(&[Type::Ptr(Type::U8.into())], Type::Void),
-                fn_ptr: try_fn,
-                args: Box::new([data_ptr]),
-            }
+            place_set(
+                destination,
+                tyctx,
+                call!(
+                    cilly::unwind::catch_unwind_site(),
+                    [try_fn, data_ptr, catch_fn]
+                ),
+                body,
+                method_instance,
+                type_cache,
+            )
         }
         "abort" => CILRoot::throw("Called abort!"),
+        name if name.starts_with("simd_") => simd::simd(
+            name,
+            args,
+            destination,
+            tyctx,
+            body,
+            method_instance,
+            type_cache,
+        )
+        .unwrap_or_else(|err| {
+            let msg = err.message();
+            MethodCodegenError::from_span(
+                tyctx,
+                span,
+                &crate::utilis::function_name(tyctx.symbol_name(method_instance)),
+                msg.clone(),
+                Placeholder::Terminator(msg.clone()),
+            )
+            .report();
+            CILRoot::throw(&msg)
+        }),
         _ => intrinsic_slow(
             fn_name,
             args,
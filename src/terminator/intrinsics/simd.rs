@@ -0,0 +1,608 @@
+//! Lowering of the `simd_*` intrinsics to the operations of `System.Runtime.Intrinsics` vectors.
+//!
+//! Operations with a .NET counterpart are calls to the methods of `Vector{bits}`, the rest is done lane by lane. SIMD
+//! types with no .NET vector counterpart are ordinary structs, and all operations on them are done lane by lane.
+use crate::{
+    codegen_error::CodegenError, operand::handle_operand, place::place_set,
+    r#type::tycache::TyCache,
+};
+use cilly::{
+    and, call, call_site::CallSite, cil_node::CILNode, cil_root::CILRoot, conv_f32, conv_f64,
+    conv_i32, conv_isize, conv_usize, eq, fn_sig::FnSig, ldc_i32, ldc_i64, or, shl, size_of,
+    DotnetTypeRef, Type,
+};
+use rustc_middle::{
+    mir::{interpret::Scalar, BinOp, Body, ConstValue, Operand, Place, UnOp},
+    ty::{Instance, ParamEnv, Ty, TyCtxt},
+};
+use rustc_span::source_map::Spanned;
+/// The type of a lane, in the signatures of the generic methods of `Vector{bits}`.
+const LANE: Type = Type::CallGenericArg(0);
+/// A SIMD type. Types mapped to a .NET vector are operated on using the methods of `Vector{bits}`. The other ones are
+/// ordinary structs, operated on lane by lane.
+struct Vector<'tyctx> {
+    tpe: Type,
+    /// The width of the .NET vector this type is mapped to, if any.
+    bits: Option<u32>,
+    lane: Type,
+    lane_ty: Ty<'tyctx>,
+    lanes: u64,
+}
+impl<'tyctx> Vector<'tyctx> {
+    fn new(
+        ty: Ty<'tyctx>,
+        tyctx: TyCtxt<'tyctx>,
+        method_instance: Instance<'tyctx>,
+        type_cache: &mut TyCache,
+    ) -> Result<Self, CodegenError> {
+        if !ty.is_simd() {
+            return Err(CodegenError::Error(
+                format!("{ty:?} is not a SIMD type!").into(),
+            ));
+        }
+        let (lanes, lane_ty) = ty.simd_size_and_type(tyctx);
+        let tpe = type_cache.type_from_cache(ty, tyctx, method_instance);
+        let bits = tpe
+            .as_dotnet()
+            .and_then(|tref| tref.as_vector().map(|(bits, _)| bits));
+        Ok(Self {
+            tpe,
+            bits,
+            lane: type_cache.type_from_cache(lane_ty, tyctx, method_instance),
+            lane_ty,
+            lanes,
+        })
+    }
+    /// The type `Vector{bits}<T>`, as used in the signatures of the generic methods of `Vector{bits}`.
+    fn generic(&self) -> Type {
+        DotnetTypeRef::vector(self.dotnet_bits(), LANE).into()
+    }
+    fn dotnet_bits(&self) -> u32 {
+        self.bits
+            .expect("Only SIMD types mapped to .NET vectors have vector methods!")
+    }
+    /// Calls the generic method `name` of `Vector{bits}`, instantiated with the lane type of this vector.
+    fn call(
+        &self,
+        name: &str,
+        inputs: &[Type],
+        output: Type,
+        args: impl Into<Box<[CILNode]>>,
+    ) -> CILNode {
+        let mut site = CallSite::new_extern(
+            DotnetTypeRef::vector_helpers(self.dotnet_bits()),
+            name.into(),
+            FnSig::new(inputs, output),
+            true,
+        );
+        site.set_generics(vec![self.lane.clone()]);
+        call!(site, args)
+    }
+    /// Applies the method `name` of `Vector{bits}` to `a`, or `lane` to each of its lanes.
+    fn unary(&self, name: &str, a: CILNode, mut lane: impl FnMut(CILNode) -> CILNode) -> CILNode {
+        if self.bits.is_none() {
+            return self.from_lanes(|idx| lane(self.get(a.clone(), idx)));
+        }
+        self.call(name, &[self.generic()], self.generic(), [a])
+    }
+    /// Applies the method `name` of `Vector{bits}` to `a` and `b`, or `lane` to each pair of their lanes.
+    fn binary(
+        &self,
+        name: &str,
+        a: CILNode,
+        b: CILNode,
+        mut lane: impl FnMut(CILNode, CILNode) -> CILNode,
+    ) -> CILNode {
+        if self.bits.is_none() {
+            return self.from_lanes(|idx| lane(self.get(a.clone(), idx), self.get(b.clone(), idx)));
+        }
+        self.call(
+            name,
+            &[self.generic(), self.generic()],
+            self.generic(),
+            [a, b],
+        )
+    }
+    /// The address of the lane `idx` of the vector at `addr`.
+    fn lane_ptr(&self, addr: CILNode, idx: CILNode) -> CILNode {
+        CILNode::TransmutePtr {
+            val: Box::new(addr + conv_usize!(idx) * conv_usize!(size_of!(self.lane.clone()))),
+            new_ptr: Box::new(Type::Ptr(Box::new(self.lane.clone()))),
+        }
+    }
+    /// Gets the lane `idx` of `vector`.
+    fn get(&self, vector: CILNode, idx: u64) -> CILNode {
+        self.get_dyn(vector, ldc_i32!(idx as i32))
+    }
+    /// Gets the lane of `vector` at the `int32` index `idx`.
+    fn get_dyn(&self, vector: CILNode, idx: CILNode) -> CILNode {
+        if self.bits.is_none() {
+            return CILNode::TemporaryLocal(Box::new((
+                self.tpe.clone(),
+                [CILRoot::SetTMPLocal { value: vector }].into(),
+                CILNode::LdObj {
+                    ptr: Box::new(self.lane_ptr(CILNode::LoadAddresOfTMPLocal, idx)),
+                    obj: Box::new(self.lane.clone()),
+                },
+            )));
+        }
+        self.call(
+            "GetElement",
+            &[self.generic(), Type::I32],
+            LANE,
+            [vector, idx],
+        )
+    }
+    /// Replaces the lane of `vector` at the `int32` index `idx` with `value`.
+    fn with(&self, vector: CILNode, idx: CILNode, value: CILNode) -> CILNode {
+        if self.bits.is_none() {
+            return CILNode::TemporaryLocal(Box::new((
+                self.tpe.clone(),
+                [
+                    CILRoot::SetTMPLocal { value: vector },
+                    CILRoot::STObj {
+                        tpe: Box::new(self.lane.clone()),
+                        addr_calc: self.lane_ptr(CILNode::LoadAddresOfTMPLocal, idx),
+                        value_calc: value,
+                    },
+                ]
+                .into(),
+                CILNode::LoadTMPLocal,
+            )));
+        }
+        self.call(
+            "WithElement",
+            &[self.generic(), Type::I32, LANE],
+            self.generic(),
+            [vector, idx, value],
+        )
+    }
+    /// Creates a vector, whose lane `idx` is `lane(idx)`.
+    fn from_lanes(&self, mut lane: impl FnMut(u64) -> CILNode) -> CILNode {
+        if self.bits.is_none() {
+            let lanes: Vec<_> = (0..self.lanes)
+                .map(|idx| CILRoot::STObj {
+                    tpe: Box::new(self.lane.clone()),
+                    addr_calc: self.lane_ptr(CILNode::LoadAddresOfTMPLocal, ldc_i32!(idx as i32)),
+                    value_calc: lane(idx),
+                })
+                .collect();
+            return CILNode::TemporaryLocal(Box::new((
+                self.tpe.clone(),
+                lanes.into(),
+                CILNode::LoadTMPLocal,
+            )));
+        }
+        let mut vector = self.call("Create", &[LANE], self.generic(), [lane(0)]);
+        for idx in 1..self.lanes {
+            vector = self.with(vector, ldc_i32!(idx as i32), lane(idx));
+        }
+        vector
+    }
+    /// Combines the lanes of `vector` using `op`, starting from the first one.
+    fn fold(&self, vector: &CILNode, mut op: impl FnMut(CILNode, CILNode) -> CILNode) -> CILNode {
+        (1..self.lanes).fold(self.get(vector.clone(), 0), |acc, idx| {
+            op(acc, self.get(vector.clone(), idx))
+        })
+    }
+}
+pub fn simd<'tyctx>(
+    fn_name: &str,
+    args: &[Spanned<Operand<'tyctx>>],
+    destination: &Place<'tyctx>,
+    tyctx: TyCtxt<'tyctx>,
+    body: &'tyctx Body<'tyctx>,
+    method_instance: Instance<'tyctx>,
+    type_cache: &mut TyCache,
+) -> Result<CILRoot, CodegenError> {
+    let arg_ty = |idx: usize| {
+        crate::utilis::monomorphize(&method_instance, args[idx].node.ty(body, tyctx), tyctx)
+    };
+    let output_ty =
+        crate::utilis::monomorphize(&method_instance, destination.ty(body, tyctx).ty, tyctx);
+    let operands: Vec<_> = args
+        .iter()
+        .map(|arg| handle_operand(&arg.node, tyctx, body, method_instance, type_cache))
+        .collect();
+    let op = |idx: usize| operands[idx].clone();
+    let vector = Vector::new(arg_ty(0), tyctx, method_instance, type_cache)?;
+    let output_vector = if output_ty.is_simd() {
+        Some(Vector::new(output_ty, tyctx, method_instance, type_cache)?)
+    } else {
+        None
+    };
+    let output = || {
+        output_vector
+            .as_ref()
+            .expect("The result of this SIMD intrinsic must be a vector!")
+    };
+    // The values `simd_select` chooses from.
+    let values = if fn_name == "simd_select" {
+        Some(Vector::new(arg_ty(1), tyctx, method_instance, type_cache)?)
+    } else {
+        None
+    };
+    let output_type = type_cache.type_from_cache(output_ty, tyctx, method_instance);
+    let lane_ty = vector.lane_ty;
+    // Preforms an operation on two lanes of this vector, like it would be done on scalars of the lane type.
+    let mut lane_op = |bin_op: BinOp, a: CILNode, b: CILNode| {
+        crate::binop::binop_values(
+            bin_op,
+            lane_ty,
+            lane_ty,
+            a,
+            b,
+            tyctx,
+            method_instance,
+            type_cache,
+        )
+    };
+    let value = match fn_name {
+        "simd_add" => vector.binary("Add", op(0), op(1), |a, b| lane_op(BinOp::Add, a, b)),
+        "simd_sub" => vector.binary("Subtract", op(0), op(1), |a, b| lane_op(BinOp::Sub, a, b)),
+        "simd_mul" => vector.binary("Multiply", op(0), op(1), |a, b| lane_op(BinOp::Mul, a, b)),
+        "simd_div" => vector.binary("Divide", op(0), op(1), |a, b| lane_op(BinOp::Div, a, b)),
+        "simd_and" => vector.binary("BitwiseAnd", op(0), op(1), |a, b| {
+            lane_op(BinOp::BitAnd, a, b)
+        }),
+        "simd_or" => vector.binary("BitwiseOr", op(0), op(1), |a, b| {
+            lane_op(BinOp::BitOr, a, b)
+        }),
+        "simd_xor" => vector.binary("Xor", op(0), op(1), |a, b| lane_op(BinOp::BitXor, a, b)),
+        // `Vector{bits}.Min` and `Max` propagate NaNs, while `simd_fmin` and `simd_fmax` ignore them.
+        "simd_fmin" | "simd_fmax" => {
+            let max = fn_name == "simd_fmax";
+            vector.from_lanes(|idx| {
+                min_max(
+                    max,
+                    &vector.lane,
+                    vector.get(op(0), idx),
+                    vector.get(op(1), idx),
+                    &mut lane_op,
+                )
+            })
+        }
+        "simd_neg" => vector.unary("Negate", op(0), |a| {
+            crate::unop::unop_value(UnOp::Neg, lane_ty, a, tyctx, method_instance, type_cache)
+        }),
+        "simd_fabs" => vector.unary("Abs", op(0), |a| math_lane("Abs", &vector.lane, a)),
+        "simd_fsqrt" => vector.unary("Sqrt", op(0), |a| math_lane("Sqrt", &vector.lane, a)),
+        "simd_floor" | "simd_ceil" => {
+            // Only the `float` and `double` overloads of those exist, and they are not generic.
+            let name = if fn_name == "simd_floor" {
+                "Floor"
+            } else {
+                "Ceiling"
+            };
+            match vector.bits {
+                Some(bits) => call!(
+                    CallSite::new_extern(
+                        DotnetTypeRef::vector_helpers(bits),
+                        name.into(),
+                        FnSig::new(&[vector.tpe.clone()], vector.tpe.clone()),
+                        true,
+                    ),
+                    [op(0)]
+                ),
+                None => {
+                    vector.from_lanes(|idx| math_lane(name, &vector.lane, vector.get(op(0), idx)))
+                }
+            }
+        }
+        "simd_rem" | "simd_shl" | "simd_shr" => {
+            let bin_op = match fn_name {
+                "simd_rem" => BinOp::Rem,
+                "simd_shl" => BinOp::ShlUnchecked,
+                _ => BinOp::ShrUnchecked,
+            };
+            vector.from_lanes(|idx| lane_op(bin_op, vector.get(op(0), idx), vector.get(op(1), idx)))
+        }
+        "simd_eq" | "simd_ne" | "simd_lt" | "simd_le" | "simd_gt" | "simd_ge" => {
+            let (name, bin_op) = match fn_name {
+                "simd_eq" => ("Equals", BinOp::Eq),
+                "simd_ne" => ("Equals", BinOp::Ne),
+                "simd_lt" => ("LessThan", BinOp::Lt),
+                "simd_le" => ("LessThanOrEqual", BinOp::Le),
+                "simd_gt" => ("GreaterThan", BinOp::Gt),
+                _ => ("GreaterThanOrEqual", BinOp::Ge),
+            };
+            let mask = output();
+            match (vector.bits, mask.bits) {
+                (Some(bits), Some(mask_bits)) if bits == mask_bits => {
+                    // .NET comparisons return a vector of the compared type, with all bits of a lane set if it is true.
+                    let cmp = reinterpret(
+                        vector.tpe.clone(),
+                        mask.tpe.clone(),
+                        vector.call(
+                            name,
+                            &[vector.generic(), vector.generic()],
+                            vector.generic(),
+                            [op(0), op(1)],
+                        ),
+                    );
+                    if fn_name == "simd_ne" {
+                        mask.call("OnesComplement", &[mask.generic()], mask.generic(), [cmp])
+                    } else {
+                        cmp
+                    }
+                }
+                // The mask has lanes of a different width, so the lanes are compared one by one.
+                _ => mask.from_lanes(|idx| {
+                    mask_lane(
+                        &mask.lane,
+                        lane_op(bin_op, vector.get(op(0), idx), vector.get(op(1), idx)),
+                    )
+                }),
+            }
+        }
+        "simd_select" => {
+            let values = values.expect("`simd_select` must select between vectors!");
+            match (vector.bits, values.bits) {
+                (Some(bits), Some(values_bits)) if bits == values_bits => {
+                    let generic = values.generic();
+                    values.call(
+                        "ConditionalSelect",
+                        &[generic.clone(), generic.clone(), generic.clone()],
+                        generic,
+                        [
+                            reinterpret(vector.tpe.clone(), values.tpe.clone(), op(0)),
+                            op(1),
+                            op(2),
+                        ],
+                    )
+                }
+                _ => values.from_lanes(|idx| {
+                    select_lane(
+                        &values.lane,
+                        values.get(op(1), idx),
+                        values.get(op(2), idx),
+                        is_set(&vector.lane, vector.get(op(0), idx)),
+                    )
+                }),
+            }
+        }
+        "simd_extract" | "simd_extract_dyn" => vector.get_dyn(op(0), conv_i32!(op(1))),
+        "simd_insert" | "simd_insert_dyn" => vector.with(op(0), conv_i32!(op(1)), op(2)),
+        "simd_shuffle" => {
+            let indices = shuffle_indices(&args[2].node, tyctx, method_instance);
+            output().from_lanes(|idx| {
+                let src = u64::from(indices[idx as usize]);
+                if src < vector.lanes {
+                    vector.get(op(0), src)
+                } else {
+                    vector.get(op(1), src - vector.lanes)
+                }
+            })
+        }
+        "simd_cast" | "simd_as" => {
+            let output = output();
+            output.from_lanes(|idx| cast_lane(&vector.lane, &output.lane, vector.get(op(0), idx)))
+        }
+        "simd_reduce_add_unordered" => match vector.bits {
+            Some(_) => vector.call("Sum", &[vector.generic()], LANE, [op(0)]),
+            None => vector.fold(&op(0), |acc, lane| lane_op(BinOp::Add, acc, lane)),
+        },
+        "simd_reduce_add_ordered" => (0..vector.lanes).fold(op(1), |acc, idx| {
+            lane_op(BinOp::Add, acc, vector.get(op(0), idx))
+        }),
+        "simd_reduce_mul_ordered" => (0..vector.lanes).fold(op(1), |acc, idx| {
+            lane_op(BinOp::Mul, acc, vector.get(op(0), idx))
+        }),
+        "simd_reduce_mul_unordered" => {
+            vector.fold(&op(0), |acc, lane| lane_op(BinOp::Mul, acc, lane))
+        }
+        "simd_reduce_and" => vector.fold(&op(0), |acc, lane| lane_op(BinOp::BitAnd, acc, lane)),
+        "simd_reduce_or" => vector.fold(&op(0), |acc, lane| lane_op(BinOp::BitOr, acc, lane)),
+        "simd_reduce_xor" => vector.fold(&op(0), |acc, lane| lane_op(BinOp::BitXor, acc, lane)),
+        "simd_reduce_min" | "simd_reduce_max" => {
+            let max = fn_name == "simd_reduce_max";
+            vector.fold(&op(0), |acc, lane| {
+                min_max(max, &vector.lane, acc, lane, &mut lane_op)
+            })
+        }
+        // The lanes of masks are either all ones or all zeroes.
+        "simd_reduce_all" => eq!(
+            eq!(
+                vector.fold(&op(0), |acc, lane| and!(acc, lane)),
+                zero(&vector.lane)
+            ),
+            ldc_i32!(0)
+        ),
+        "simd_reduce_any" => is_set(
+            &vector.lane,
+            vector.fold(&op(0), |acc, lane| or!(acc, lane)),
+        ),
+        "simd_bitmask" => {
+            let bits_type = if vector.bits == Some(512) || vector.lanes > 32 {
+                Type::U64
+            } else {
+                Type::U32
+            };
+            let bits = match vector.bits {
+                Some(_) => vector.call(
+                    "ExtractMostSignificantBits",
+                    &[vector.generic()],
+                    bits_type.clone(),
+                    [op(0)],
+                ),
+                // The lanes of masks are signed, so their most significant bit is set if they are negative.
+                None => (0..vector.lanes).fold(zero(&bits_type), |acc, idx| {
+                    let negative = lane_op(BinOp::Lt, vector.get(op(0), idx), zero(&vector.lane));
+                    or!(
+                        acc,
+                        shl!(
+                            crate::casts::int_to_int(Type::Bool, &bits_type, negative),
+                            ldc_i32!(idx as i32)
+                        )
+                    )
+                }),
+            };
+            if output_ty.is_integral() {
+                crate::casts::int_to_int(bits_type, &output_type, bits)
+            } else {
+                // A byte array, with the bits in little endian order.
+                reinterpret(bits_type, output_type, bits)
+            }
+        }
+        _ => {
+            return Err(CodegenError::Error(
+                format!("Unsupported SIMD intrinsic {fn_name}").into(),
+            ))
+        }
+    };
+    Ok(place_set(
+        destination,
+        tyctx,
+        value,
+        body,
+        method_instance,
+        type_cache,
+    ))
+}
+/// Reinterprets the bits of `value`, of type `src`, as a value of type `target`.
+fn reinterpret(src: Type, target: Type, value: CILNode) -> CILNode {
+    if src == target {
+        return value;
+    }
+    CILNode::TemporaryLocal(Box::new((
+        src,
+        [CILRoot::SetTMPLocal { value }].into(),
+        CILNode::LdObj {
+            ptr: Box::new(CILNode::TransmutePtr {
+                val: Box::new(CILNode::LoadAddresOfTMPLocal),
+                new_ptr: Box::new(Type::Ptr(Box::new(target.clone()))),
+            }),
+            obj: Box::new(target),
+        },
+    )))
+}
+/// Converts the result of a lane comparison to a lane of a mask of type `mask`, with all bits set if it is true.
+fn mask_lane(mask: &Type, cond: CILNode) -> CILNode {
+    crate::casts::int_to_int(Type::I32, mask, CILNode::Neg(Box::new(cond)))
+}
+/// Checks if the mask lane `value`, of type `lane`, is set.
+fn is_set(lane: &Type, value: CILNode) -> CILNode {
+    eq!(eq!(value, zero(lane)), ldc_i32!(0))
+}
+/// Selects `a` if `cond` is true, and `b` otherwise. Floats are selected as intigers of the same size.
+fn select_lane(lane: &Type, a: CILNode, b: CILNode, cond: CILNode) -> CILNode {
+    let bits = match lane {
+        Type::F16 => Type::U16,
+        Type::F32 => Type::U32,
+        Type::F64 => Type::U64,
+        Type::F128 => Type::U128,
+        _ => return CILNode::select(lane.clone(), a, b, cond),
+    };
+    reinterpret(
+        bits.clone(),
+        lane.clone(),
+        CILNode::select(
+            bits.clone(),
+            reinterpret(lane.clone(), bits.clone(), a),
+            reinterpret(lane.clone(), bits, b),
+            cond,
+        ),
+    )
+}
+/// Returns the smaller of the lanes `a` and `b`, or the bigger one if `max` is set. Like `fmin` and `fmax`, ignores NaNs.
+fn min_max(
+    max: bool,
+    lane: &Type,
+    a: CILNode,
+    b: CILNode,
+    lane_op: &mut impl FnMut(BinOp, CILNode, CILNode) -> CILNode,
+) -> CILNode {
+    let mut pick_b = if max {
+        lane_op(BinOp::Lt, a.clone(), b.clone())
+    } else {
+        lane_op(BinOp::Lt, b.clone(), a.clone())
+    };
+    if matches!(lane, Type::F16 | Type::F32 | Type::F64 | Type::F128) {
+        pick_b = or!(pick_b, lane_op(BinOp::Ne, a.clone(), a.clone()));
+    }
+    select_lane(lane, b, a, pick_b)
+}
+/// Applies the `System.Math` function `name` to a float lane of type `lane`, computing it as a `double`. This is exact
+/// for all the functions used, and for `Sqrt` rounding twice gives the same result as rounding once.
+fn math_lane(name: &str, lane: &Type, value: CILNode) -> CILNode {
+    let value = call!(
+        CallSite::new_extern(
+            DotnetTypeRef::math(),
+            name.into(),
+            FnSig::new(&[Type::F64], Type::F64),
+            true,
+        ),
+        [cast_lane(lane, &Type::F64, value)]
+    );
+    cast_lane(&Type::F64, lane, value)
+}
+/// Loads a zero of the lane type `lane`.
+fn zero(lane: &Type) -> CILNode {
+    match lane {
+        Type::I64 | Type::U64 => ldc_i64!(0),
+        Type::ISize | Type::USize | Type::Ptr(_) => conv_isize!(ldc_i32!(0)),
+        Type::F32 => CILNode::LdcF32(0.0),
+        Type::F64 => CILNode::LdcF64(0.0),
+        _ => ldc_i32!(0),
+    }
+}
+/// Converts a lane of type `src` to the type `target`, like `as` does.
+fn cast_lane(src: &Type, target: &Type, lane: CILNode) -> CILNode {
+    let is_float = |tpe: &Type| matches!(tpe, Type::F16 | Type::F32 | Type::F64 | Type::F128);
+    match (is_float(src), is_float(target)) {
+        (false, false) => crate::casts::int_to_int(src.clone(), target, lane),
+        (false, true) => crate::casts::int_to_float(src.clone(), target, lane),
+        (true, false) => crate::casts::float_to_int(src.clone(), target, lane),
+        (true, true) if crate::float::is_emulated(src) || crate::float::is_emulated(target) => {
+            crate::float::float_to_float(src, target, lane)
+        }
+        (true, true) => match target {
+            Type::F32 => conv_f32!(lane),
+            _ => conv_f64!(lane),
+        },
+    }
+}
+/// Reads the indices of the lanes `simd_shuffle` selects, which must be a constant array of `u32`s.
+fn shuffle_indices<'tyctx>(
+    indices: &Operand<'tyctx>,
+    tyctx: TyCtxt<'tyctx>,
+    method_instance: Instance<'tyctx>,
+) -> Vec<u32> {
+    let Operand::Constant(constant) = indices else {
+        panic!("The indices of `simd_shuffle` must be a constant, not {indices:?}!");
+    };
+    let const_ = crate::utilis::monomorphize(&method_instance, constant.const_, tyctx);
+    let size = tyctx
+        .layout_of(rustc_middle::ty::ParamEnvAnd {
+            param_env: ParamEnv::reveal_all(),
+            value: const_.ty(),
+        })
+        .expect("Could not get type layout!")
+        .size
+        .bytes_usize();
+    let bytes = match const_
+        .eval(tyctx, ParamEnv::reveal_all(), constant.span)
+        .expect("Could not evaluate constant!")
+    {
+        ConstValue::Indirect { alloc_id, offset } => {
+            let start = offset.bytes_usize();
+            tyctx
+                .global_alloc(alloc_id)
+                .unwrap_memory()
+                .inner()
+                .inspect_with_uninit_and_ptr_outside_interpreter(start..start + size)
+                .to_vec()
+        }
+        ConstValue::Scalar(Scalar::Int(int)) => int
+            .try_to_uint(int.size())
+            .expect("Could not read the `simd_shuffle` indices!")
+            .to_le_bytes()[..size]
+            .to_vec(),
+        ConstValue::ZeroSized => vec![],
+        value => panic!("Unexpected `simd_shuffle` indices {value:?}!"),
+    };
+    bytes
+        .chunks_exact(4)
+        .map(|idx| u32::from_le_bytes(idx.try_into().unwrap()))
+        .collect()
+}
@@ -13,7 +13,7 @@ use rustc_middle::{
 };
 
 mod call;
-mod intrinsics;
+pub(crate) mod intrinsics;
 pub fn handle_call_terminator<'tycxt>(
     terminator: &Terminator<'tycxt>,
     body: &'tycxt Body<'tycxt>,
@@ -361,13 +361,13 @@ pub fn handle_terminator<'ctx>(
             }
             .into()]
         }
-        TerminatorKind::CoroutineDrop {} => todo!("Can't drop corutines yet!"),
-        TerminatorKind::Yield {
-            value: _,
-            resume: _,
-            resume_arg: _,
-            drop: _,
-        } => todo!("Can't yeld yet!"), //_ => todo!("Unhandled terminator kind {kind:?}", kind = terminator.kind),
+        // The coroutine transform lowers those into a state machine: suspending stores the saved
+        // locals in the coroutine variant, sets its discriminant and returns. Resuming switches
+        // on the discriminant. So, they can't appear in the MIR of a coroutine we codegen.
+        TerminatorKind::CoroutineDrop | TerminatorKind::Yield { .. } => panic!(
+            "Terminator {kind:?} was not lowered by the coroutine transform!",
+            kind = terminator.kind
+        ),
     };
     let last = res.last().unwrap().root();
     assert!(
@@ -13,9 +13,11 @@ use cilly::{
     DotnetTypeRef, Type,
 };
 use rustc_middle::ty::{
-    AdtDef, AdtKind, GenericArg, Instance, List, ParamEnv, Ty, TyCtxt, TyKind, UintTy,
+    AdtDef, AdtKind, GenericArg, GenericArgsRef, Instance, List, ParamEnv, Ty, TyCtxt, TyKind,
+    UintTy,
 };
 use rustc_span::def_id::DefId;
+use rustc_target::abi::VariantIdx;
 use std::{collections::HashMap, num::NonZeroU64};
 // CAN'T BE SERAILIZED!
 pub struct TyCache {
@@ -150,7 +152,7 @@ impl TyCache {
             .type_from_cache(adt_ty, tyctx, method)
             .as_dotnet()
             .unwrap();
-        if *crate::config::VALIDTE_VALUES {
+        if crate::config::config().validte_values {
             let tpe = self.type_from_cache(adt_ty, tyctx, method);
             let mut roots = vec![];
             for field in &adt
@@ -369,7 +371,7 @@ impl TyCache {
             None,
             Some(NonZeroU64::new(layout.layout.size().bytes()).unwrap()),
         );
-        if *crate::config::VALIDTE_VALUES {
+        if crate::config::config().validte_values {
             let tpe = self.type_from_cache(adt_ty, tyctx, method);
             let check = cilly::method::Method::new(
                 AccessModifer::MoudlePublic,
@@ -388,6 +390,72 @@ impl TyCache {
         }
         def
     }
+    /// Creates the type of a coroutine state machine. Its layout resembles an enum: the upvars and
+    /// the tag are shared, while each variant holds the locals saved across one suspension point.
+    fn coroutine_<'tyctx>(
+        &mut self,
+        name: &str,
+        def_id: DefId,
+        args: GenericArgsRef<'tyctx>,
+        coroutine_ty: Ty<'tyctx>,
+        tyctx: TyCtxt<'tyctx>,
+        method: Instance<'tyctx>,
+    ) -> TypeDef {
+        let layout = tyctx
+            .layout_of(rustc_middle::ty::ParamEnvAnd {
+                param_env: ParamEnv::reveal_all(),
+                value: coroutine_ty,
+            })
+            .expect("Could not get type layout!");
+        let coroutine = args.as_coroutine();
+        let mut fields = vec![];
+        let mut explicit_offsets: Vec<u32> = vec![];
+        // The upvars come first in the prefix of a coroutine.
+        let prefix_offsets = FieldOffsetIterator::fields((*layout.layout.0).clone());
+        for ((idx, upvar), offset) in coroutine.upvar_tys().iter().enumerate().zip(prefix_offsets) {
+            let upvar = self.type_from_cache(upvar, tyctx, method);
+            if upvar == Type::Void {
+                continue;
+            }
+            fields.push((format!("f_{idx}").into(), upvar));
+            explicit_offsets.push(offset);
+        }
+        let (tag_type, offset) = crate::utilis::adt::enum_tag_info(layout.layout, tyctx);
+        if tag_type != Type::Void {
+            fields.push(("value__".into(), tag_type));
+            explicit_offsets.push(offset);
+        }
+        for (vidx, state) in coroutine.state_tys(def_id, tyctx).enumerate() {
+            let vidx = VariantIdx::from_usize(vidx);
+            let field_offsets = FieldOffsetIterator::fields(
+                crate::utilis::adt::get_variant_at_index(vidx, (*layout.layout.0).clone()),
+            );
+            for ((field_idx, field_ty), offset) in state.enumerate().zip(field_offsets) {
+                let field_ty = self.type_from_cache(field_ty, tyctx, method);
+                if field_ty == Type::Void {
+                    continue;
+                }
+                let field_idx = u32::try_from(field_idx).unwrap();
+                fields.push((
+                    crate::r#type::coroutine_field_name(vidx, field_idx),
+                    field_ty,
+                ));
+                explicit_offsets.push(offset);
+            }
+        }
+        assert_eq!(fields.len(), explicit_offsets.len());
+        TypeDef::new(
+            AccessModifer::Public,
+            name.into(),
+            vec![],
+            fields,
+            vec![],
+            Some(explicit_offsets),
+            0,
+            None,
+            Some(NonZeroU64::new(layout.layout.size().bytes()).unwrap()),
+        )
+    }
     pub fn slice_ty<'tyctx>(
         &mut self,
         inner: Ty<'tyctx>,
@@ -478,6 +546,18 @@ impl TyCache {
                 }
                 DotnetTypeRef::new::<&str, _>(None, name).into()
             }
+            TyKind::Coroutine(def, args) => {
+                let name = crate::r#type::coroutine_name(*def, args, tyctx);
+                if !self.type_def_cache.contains_key(&name)
+                    && !self.cycle_prevention.iter().any(|c_name| *c_name == name)
+                {
+                    self.cycle_prevention.push(name.clone());
+                    let def = self.coroutine_(&name, *def, args, ty, tyctx, method);
+                    self.type_def_cache.insert(name.clone(), def);
+                    self.cycle_prevention.pop();
+                }
+                DotnetTypeRef::new::<&str, _>(None, name).into()
+            }
             TyKind::Never => Type::Void,
             TyKind::RawPtr(typ, _) => {
                 if super::pointer_to_is_fat(*typ, tyctx, method) {
@@ -504,6 +584,9 @@ impl TyCache {
                 }
             }
             TyKind::Adt(def, subst) => {
+                if let Some(vector) = crate::simd::vector_type(ty, tyctx, method, self) {
+                    return vector;
+                }
                 let name = crate::utilis::adt_name(*def, tyctx, subst);
                 if super::is_name_magic(name.as_ref()) {
                     return super::magic_type(name.as_ref(), def, subst, tyctx);
@@ -660,7 +743,7 @@ pub fn validity_check<'tyctx>(
 ) -> CILNode {
     let ty = crate::utilis::monomorphize(&method_instance, ty, tyctx);
     let tpe = type_cache.type_from_cache(ty, tyctx, method_instance);
-    if !*crate::config::VALIDTE_VALUES {
+    if !crate::config::config().validte_values {
         return val;
     }
     match ty.kind() {
@@ -15,10 +15,11 @@ use cilly::{
     Type,
 };
 use rustc_middle::ty::{
-    AdtDef, AdtKind, GenericArg, Instance, List, ParamEnv, Ty, TyCtxt, TyKind, UintTy,
+    AdtDef, AdtKind, CoroutineArgs, GenericArg, GenericArgsRef, Instance, List, ParamEnv, Ty,
+    TyCtxt, TyKind, UintTy,
 };
 use rustc_span::def_id::DefId;
-use rustc_target::abi::Layout;
+use rustc_target::abi::{Layout, VariantIdx};
 
 pub(crate) const CUSTOM_INTEROP_TYPE_DEF: &str = "RustcCLRInteropManagedCustomTypeDef";
 
@@ -98,6 +99,28 @@ pub fn closure_typedef(
         Some(NonZeroU64::new(layout.size().bytes()).unwrap()),
     )
 }
+/// Returns the name of the type holding the state machine of the coroutine `def_id`.
+pub fn coroutine_name<'tyctx>(
+    def_id: DefId,
+    args: GenericArgsRef<'tyctx>,
+    tyctx: TyCtxt<'tyctx>,
+) -> IString {
+    // The symbol of the resume function is unique for each monomorphized coroutine.
+    let instance = Instance::new(def_id, args);
+    let mangled =
+        rustc_symbol_mangling::symbol_name_for_instance_in_crate(tyctx, instance, def_id.krate);
+    let demangled = format!("{}", rustc_demangle::demangle(&mangled));
+    crate::utilis::escape_class_name(&demangled.replace("::", ".").replace(['{', '}'], "_"))
+}
+/// Returns the name of the field `field_idx` of the coroutine variant `variant_idx`.
+#[must_use]
+pub fn coroutine_field_name(variant_idx: VariantIdx, field_idx: u32) -> IString {
+    format!(
+        "{variant}_f_{field_idx}",
+        variant = CoroutineArgs::variant_name(variant_idx)
+    )
+    .into()
+}
 #[must_use]
 pub fn arr_name(element_count: usize, element: &Type) -> IString {
     cilly::arr_name(element_count, element)
@@ -6,7 +6,7 @@ use cilly::fn_sig::FnSig;
 use cilly::{ld_field, DotnetTypeRef, Type};
 
 use rustc_middle::mir::{Operand, UnOp};
-use rustc_middle::ty::{Instance, IntTy, TyCtxt, TyKind, UintTy};
+use rustc_middle::ty::{FloatTy, Instance, IntTy, Ty, TyCtxt, TyKind, UintTy};
 
 /// Implements an unary operation, such as negation.
 pub fn unop<'ctx>(
@@ -20,6 +20,17 @@ pub fn unop<'ctx>(
     let parrent_node =
         crate::operand::handle_operand(operand, tyctx, method, method_instance, tycache);
     let ty = operand.ty(&method.local_decls, tyctx);
+    unop_value(unnop, ty, parrent_node, tyctx, method_instance, tycache)
+}
+/// Implements an unary operation on the value `parrent_node`, of type `ty`.
+pub fn unop_value<'ctx>(
+    unnop: UnOp,
+    ty: Ty<'ctx>,
+    parrent_node: CILNode,
+    tyctx: TyCtxt<'ctx>,
+    method_instance: Instance<'ctx>,
+    tycache: &mut TyCache,
+) -> CILNode {
     match unnop {
         UnOp::Neg => match ty.kind() {
             TyKind::Int(IntTy::I128) => CILNode::Call {
@@ -42,6 +53,9 @@ pub fn unop<'ctx>(
                 ),
                 args: [parrent_node].into(),
             },
+            TyKind::Float(float_ty @ (FloatTy::F16 | FloatTy::F128)) => {
+                crate::float::neg(&crate::r#type::from_float(float_ty), parrent_node)
+            }
             _ => CILNode::Neg(parrent_node.into()),
         },
         UnOp::Not => match ty.kind() {
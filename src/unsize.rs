@@ -2,20 +2,22 @@ use crate::operand::handle_operand;
 
 use cilly::cil_node::CILNode;
 use cilly::cil_root::CILRoot;
-use cilly::{conv_usize, ld_field, ld_field_address, ldc_u64};
+use cilly::{conv_usize, ld_field, ld_field_address, ldc_u64, size_of};
 
 use crate::r#type::TyCache;
 use cilly::field_desc::FieldDescriptor;
 use cilly::{DotnetTypeRef, Type};
 use rustc_middle::{
     mir::Operand,
-    ty::{Instance, Ty, TyCtxt, TyKind},
+    ty::{Instance, ParamEnv, Ty, TyCtxt, TyKind},
 };
 struct UnsizeInfo<'tyctx> {
     /// Type the source pointer points to
     source_points_to: Ty<'tyctx>,
     target_ptr: CILNode,
     source_ptr: CILNode,
+    /// Fat pointer type of the source, if the source pointer is already unsized
+    source_dotnet: Option<DotnetTypeRef>,
     target_dotnet: DotnetTypeRef,
     target_type: Type,
 }
@@ -99,10 +101,12 @@ impl<'tyctx> UnsizeInfo<'tyctx> {
                 source_points_to: derefed_source,
                 target_ptr,
                 source_ptr,
+                source_dotnet: source_ptr_desc.tpe().as_dotnet(),
                 target_dotnet: non_null_ptr_desc.tpe().as_dotnet().unwrap(),
                 target_type,
             }
         } else {
+            let mut source_dotnet = source_type.as_dotnet();
             let derefed_source = match source.kind() {
                 TyKind::RawPtr(tpe, _) => *tpe,
                 TyKind::Ref(_, inner, _) => *inner,
@@ -116,6 +120,7 @@ impl<'tyctx> UnsizeInfo<'tyctx> {
                             method_instance,
                             tycache,
                         );
+                        source_dotnet = field_descriptor.tpe().as_dotnet();
                         sized_ptr = CILNode::TemporaryLocal(Box::new((
                             source_type,
                             [CILRoot::SetTMPLocal { value: sized_ptr }].into(),
@@ -132,6 +137,7 @@ impl<'tyctx> UnsizeInfo<'tyctx> {
                 source_points_to: derefed_source,
                 target_ptr: CILNode::LoadAddresOfTMPLocal,
                 source_ptr: sized_ptr,
+                source_dotnet,
                 target_dotnet,
                 target_type,
             }
@@ -149,114 +155,100 @@ pub fn unsize<'tyctx>(
     target: Ty<'tyctx>,
 ) -> CILNode {
     let info = UnsizeInfo::for_unsize(tyctx, method, method_instance, tycache, operand, target);
-    let src_points_to = info.source_points_to;
+    let target = crate::utilis::monomorphize(&method_instance, target, tyctx);
     let target_points_to = target.builtin_deref(true).unwrap();
-    match (src_points_to.kind(), target_points_to.kind()) {
-        (TyKind::Array(_, length), _) => {
+    // Unsizing `Wrapper<[T; N]>` to `Wrapper<[T]>` only changes the metadata of the struct tail.
+    let (src_tail, target_tail) = tyctx.struct_lockstep_tails_erasing_lifetimes(
+        info.source_points_to,
+        target_points_to,
+        ParamEnv::reveal_all(),
+    );
+    // Get the data pointer and the metadata of the source, if it has any.
+    let (source_data, source_metadata) = match info.source_dotnet {
+        Some(source_dotnet) => (
+            ld_field!(
+                info.source_ptr.clone(),
+                FieldDescriptor::new(
+                    source_dotnet.clone(),
+                    Type::Ptr(Type::Void.into()),
+                    "data_pointer".into(),
+                )
+            ),
+            Some(ld_field!(
+                info.source_ptr,
+                FieldDescriptor::new(source_dotnet, Type::USize, "metadata".into())
+            )),
+        ),
+        None => (
+            CILNode::TransmutePtr {
+                val: Box::new(info.source_ptr),
+                new_ptr: Box::new(Type::Ptr(Box::new(Type::Void))),
+            },
+            None,
+        ),
+    };
+    let metadata = match (src_tail.kind(), target_tail.kind()) {
+        (TyKind::Array(_, length), TyKind::Slice(_)) => {
             let length = crate::utilis::try_resolve_const_size(*length).unwrap();
-            let metadata_field =
-                FieldDescriptor::new(info.target_dotnet.clone(), Type::USize, "metadata".into());
-            let ptr_field = FieldDescriptor::new(
-                info.target_dotnet,
-                Type::Ptr(Type::Void.into()),
-                "data_pointer".into(),
-            );
-            let init_len = CILRoot::SetField {
-                addr: info.target_ptr.clone(),
-                value: conv_usize!(ldc_u64!(length as u64)),
-                desc: metadata_field,
-            };
-            let init_ptr = CILRoot::SetField {
-                addr: info.target_ptr,
-                value: CILNode::TransmutePtr {
-                    val: Box::new(info.source_ptr),
-                    new_ptr: Box::new(Type::Ptr(Box::new(Type::Void))),
-                },
-                desc: ptr_field,
-            };
-            CILNode::TemporaryLocal(Box::new((
-                info.target_type,
-                [init_len, init_ptr].into(),
-                CILNode::LoadTMPLocal,
-            )))
+            conv_usize!(ldc_u64!(length as u64))
         }
         (
-            TyKind::Dynamic(data_a, _, _src_dyn_kind),
-            TyKind::Dynamic(data_b, _, _target_dyn_kind),
-        ) => {
+            TyKind::Dynamic(data_a, _, src_dyn_kind),
+            TyKind::Dynamic(data_b, _, target_dyn_kind),
+        ) if src_dyn_kind == target_dyn_kind => {
+            let old_vtable = source_metadata.unwrap_or_else(|| {
+                panic!("Upcasting a thin pointer to {src_tail:?} into {target_tail:?}")
+            });
             if data_a.principal_def_id() == data_b.principal_def_id() {
-                return CILNode::TemporaryLocal(Box::new((
-                    info.target_type.clone(),
-                    [CILRoot::SetTMPLocal {
-                        value: CILNode::LdObj {
-                            ptr: Box::new(CILNode::TransmutePtr {
-                                val: Box::new(info.source_ptr),
-                                new_ptr: Box::new(Type::Ptr(Box::new(info.target_type.clone()))),
-                            }),
-                            obj: Box::new(info.target_type),
-                        },
-                    }]
-                    .into(),
-                    CILNode::LoadTMPLocal,
-                )));
-            }
-            let vptr_entry_idx = tyctx
-                .vtable_trait_upcasting_coercion_new_vptr_slot((operand.ty(method, tyctx), target));
-            if let Some(entry_idx) = vptr_entry_idx {
-                todo!("dyn to dyn cats not yet supported. src_points_to:{src_points_to:?} target_points_to:{target_points_to:?} entry_idx:{entry_idx:?}")
+                old_vtable
+            } else if let Some(entry_idx) =
+                tyctx.vtable_trait_upcasting_coercion_new_vptr_slot((src_tail, target_tail))
+            {
+                // The vtable of the supertrait is stored in a slot of the source vtable.
+                let entry_idx = u64::try_from(entry_idx).expect("Vtable slot out of range!");
+                let vtable_offset = conv_usize!(ldc_u64!(entry_idx)) * size_of!(Type::USize);
+                CILNode::LDIndUSize {
+                    ptr: Box::new(old_vtable + vtable_offset),
+                }
             } else {
-                CILNode::TemporaryLocal(Box::new((
-                    info.target_type.clone(),
-                    [CILRoot::SetTMPLocal {
-                        value: CILNode::LdObj {
-                            ptr: Box::new(CILNode::TransmutePtr {
-                                val: Box::new(info.source_ptr),
-                                new_ptr: Box::new(Type::Ptr(Box::new(info.target_type.clone()))),
-                            }),
-                            obj: Box::new(info.target_type),
-                        },
-                    }]
-                    .into(),
-                    CILNode::LoadTMPLocal,
-                )))
+                // The supertrait vtable is a prefix of the source vtable.
+                old_vtable
             }
         }
         (_, TyKind::Dynamic(data, _, _dyn_kind)) => {
-            let alloc_id = tyctx.vtable_allocation((info.source_points_to, data.principal()));
-            let metadata_field =
-                FieldDescriptor::new(info.target_dotnet.clone(), Type::USize, "metadata".into());
-            let ptr_field = FieldDescriptor::new(
-                info.target_dotnet,
-                Type::Ptr(Type::Void.into()),
-                "data_pointer".into(),
-            );
-            let init_vtable_ptr = CILRoot::SetField {
-                addr: info.target_ptr.clone(),
-                value: CILNode::TransmutePtr {
-                    val: Box::new(CILNode::LoadGlobalAllocPtr {
-                        alloc_id: alloc_id.0.into(),
-                    }),
-                    new_ptr: Box::new(Type::USize),
-                },
-                desc: metadata_field,
-            };
-            let init_obj_ptr = CILRoot::SetField {
-                addr: info.target_ptr,
-                value: CILNode::TransmutePtr {
-                    val: Box::new(info.source_ptr),
-                    new_ptr: Box::new(Type::Ptr(Box::new(Type::Void))),
-                },
-                desc: ptr_field,
-            };
-            CILNode::TemporaryLocal(Box::new((
-                info.target_type,
-                [init_vtable_ptr, init_obj_ptr].into(),
-                CILNode::LoadTMPLocal,
-            )))
+            let alloc_id = tyctx.vtable_allocation((src_tail, data.principal()));
+            CILNode::TransmutePtr {
+                val: Box::new(CILNode::LoadGlobalAllocPtr {
+                    alloc_id: alloc_id.0.into(),
+                }),
+                new_ptr: Box::new(Type::USize),
+            }
         }
-        (_, _) => todo!(
-            "Unhandled unsizing cast:{source:?} -> {target:?}",
+        (_, _) => panic!(
+            "Unhandled unsizing cast:{source:?} -> {target_points_to:?}. Tails: {src_tail:?} -> {target_tail:?}",
             source = info.source_points_to
         ),
-    }
+    };
+    let metadata_field =
+        FieldDescriptor::new(info.target_dotnet.clone(), Type::USize, "metadata".into());
+    let ptr_field = FieldDescriptor::new(
+        info.target_dotnet,
+        Type::Ptr(Type::Void.into()),
+        "data_pointer".into(),
+    );
+    let init_metadata = CILRoot::SetField {
+        addr: info.target_ptr.clone(),
+        value: metadata,
+        desc: metadata_field,
+    };
+    let init_ptr = CILRoot::SetField {
+        addr: info.target_ptr,
+        value: source_data,
+        desc: ptr_field,
+    };
+    CILNode::TemporaryLocal(Box::new((
+        info.target_type,
+        [init_metadata, init_ptr].into(),
+        CILNode::LoadTMPLocal,
+    )))
 }
@@ -0,0 +1,342 @@
+use crate::{call_info::CallInfo, r#type::TyCache};
+use cilly::{
+    access_modifier::AccessModifer, asm::Assembly, basic_block::BasicBlock, call_site::CallSite,
+    cil_node::CILNode, cil_root::CILRoot, conv_isize, conv_usize, field_desc::FieldDescriptor,
+    ld_field, ldc_i32, ldc_u32, ldc_u64, method::Method, method::MethodType, size_of, FnSig, Type,
+};
+use rustc_middle::ty::{Instance, InstanceDef, ParamEnv, ParamEnvAnd, Ty, TyCtxt, TyKind};
+use rustc_span::{def_id::DefId, sym, Symbol};
+/// The name of the builtin reading the message out of a `&mut dyn PanicPayload`.
+const RECORD_PANIC_MESSAGE: &str = "record_panic_message";
+/// Makes `__rust_start_panic` record the message of the panic it raises, so that a panic escaping into .NET code
+/// results in a readable exception.
+///
+/// The message is read from the payload returned by `PanicPayload::get`. For formatted panics, `get` formats their
+/// `fmt::Arguments` into a `String` using `Display`, so the message is the same one the panic hook prints.
+pub fn record_panic_message<'tyctx>(
+    method: &mut Method,
+    asm: &mut Assembly,
+    instance: Instance<'tyctx>,
+    tyctx: TyCtxt<'tyctx>,
+    cache: &mut TyCache,
+) {
+    let sig = tyctx.normalize_erasing_late_bound_regions(
+        ParamEnv::reveal_all(),
+        instance.ty(tyctx, ParamEnv::reveal_all()).fn_sig(tyctx),
+    );
+    // `__rust_start_panic` receives the payload as `&mut dyn PanicPayload`.
+    let payload_ty = sig.inputs()[0];
+    let TyKind::Ref(_, dyn_ty, _) = payload_ty.kind() else {
+        return;
+    };
+    let TyKind::Dynamic(preds, _, _) = dyn_ty.kind() else {
+        return;
+    };
+    let Some(payload_trait) = preds.principal_def_id() else {
+        return;
+    };
+    let Some(record) = record_panic_message_method(*dyn_ty, payload_trait, instance, tyctx, cache)
+    else {
+        return;
+    };
+    asm.add_method(record);
+    let payload_type = cache.type_from_cache(payload_ty, tyctx, instance);
+    let payload_type = payload_type
+        .as_dotnet()
+        .expect("`&mut dyn PanicPayload` is not a fat pointer!");
+    method.append_preamble(
+        CILRoot::Call {
+            site: record_panic_message_site(),
+            args: [
+                conv_isize!(ld_field!(
+                    CILNode::LDArgA(0),
+                    FieldDescriptor::new(
+                        payload_type.clone(),
+                        Type::Ptr(Type::Void.into()),
+                        "data_pointer".into()
+                    )
+                )),
+                ld_field!(
+                    CILNode::LDArgA(0),
+                    FieldDescriptor::new(payload_type, Type::USize, "metadata".into())
+                ),
+            ]
+            .into(),
+        }
+        .into(),
+    );
+}
+fn record_panic_message_site() -> CallSite {
+    CallSite::builtin(
+        RECORD_PANIC_MESSAGE.into(),
+        FnSig::new(&[Type::ISize, Type::USize], Type::Void),
+        true,
+    )
+}
+/// Builds the builtin recording the message of the panic payload `dyn_ty`, passed as its data pointer and vtable.
+/// Payloads which are neither a `String` nor a `&'static str` get a generic message.
+fn record_panic_message_method<'tyctx>(
+    dyn_ty: Ty<'tyctx>,
+    payload_trait: DefId,
+    instance: Instance<'tyctx>,
+    tyctx: TyCtxt<'tyctx>,
+    cache: &mut TyCache,
+) -> Option<Method> {
+    let (get, get_idx) = virtual_method(tyctx, payload_trait, "get", dyn_ty)?;
+    // `get` returns the payload as `&(dyn Any + Send)`.
+    let get_sig = tyctx.normalize_erasing_late_bound_regions(
+        ParamEnv::reveal_all(),
+        get.ty(tyctx, ParamEnv::reveal_all()).fn_sig(tyctx),
+    );
+    let any_ref_ty = get_sig.output();
+    let TyKind::Ref(_, any_ty, _) = any_ref_ty.kind() else {
+        return None;
+    };
+    let (type_id_fn, type_id_idx) = virtual_method(
+        tyctx,
+        tyctx.get_diagnostic_item(sym::Any)?,
+        "type_id",
+        *any_ty,
+    )?;
+    let string_ty = tyctx
+        .type_of(tyctx.get_diagnostic_item(sym::String)?)
+        .instantiate_identity();
+    let string_ptr = find_field(string_ty, tyctx, &|_, ty| {
+        matches!(ty.kind(), TyKind::RawPtr(..))
+    })?;
+    let string_len = find_field(string_ty, tyctx, &|name, ty| {
+        name.as_str() == "len" && ty == tyctx.types.usize
+    })?;
+    let any_ref_type = cache.type_from_cache(any_ref_ty, tyctx, instance);
+    let any_ref_dotnet = any_ref_type.as_dotnet()?;
+    let str_type = cache.type_from_cache(Ty::new_static_str(tyctx), tyctx, instance);
+    let str_dotnet = str_type.as_dotnet()?;
+    let string_type = cache.type_from_cache(string_ty, tyctx, instance);
+    let get_call = call_virtual(
+        get,
+        get_idx,
+        CILNode::LDArg(0),
+        CILNode::LDArg(1),
+        tyctx,
+        cache,
+    );
+    let type_id_call = call_virtual(
+        type_id_fn,
+        type_id_idx,
+        conv_isize!(ld_field!(
+            CILNode::LDLocA(0),
+            FieldDescriptor::new(
+                any_ref_dotnet.clone(),
+                Type::Ptr(Type::Void.into()),
+                "data_pointer".into()
+            )
+        )),
+        ld_field!(
+            CILNode::LDLocA(0),
+            FieldDescriptor::new(any_ref_dotnet.clone(), Type::USize, "metadata".into())
+        ),
+        tyctx,
+        cache,
+    );
+    let type_id_type = CallInfo::sig_from_instance_(type_id_fn, tyctx, cache)
+        .sig()
+        .output()
+        .clone();
+    // `TypeId` wraps the `u128` computed by the `type_id` intrinsic.
+    let type_id = CILNode::LdObj {
+        ptr: Box::new(CILNode::TransmutePtr {
+            val: Box::new(CILNode::LDLocA(1)),
+            new_ptr: Box::new(Type::Ptr(Type::U128.into())),
+        }),
+        obj: Box::new(Type::U128),
+    };
+    let payload = ld_field!(
+        CILNode::LDLocA(0),
+        FieldDescriptor::new(
+            any_ref_dotnet,
+            Type::Ptr(Type::Void.into()),
+            "data_pointer".into()
+        )
+    );
+    let payload_str = CILNode::TransmutePtr {
+        val: Box::new(payload.clone()),
+        new_ptr: Box::new(Type::Ptr(str_type.clone().into())),
+    };
+    let blocks = vec![
+        BasicBlock::new(
+            vec![
+                CILRoot::STLoc {
+                    local: 0,
+                    tree: get_call,
+                }
+                .into(),
+                CILRoot::STLoc {
+                    local: 1,
+                    tree: type_id_call,
+                }
+                .into(),
+                CILRoot::BTrue {
+                    target: 1,
+                    sub_target: 0,
+                    cond: crate::binop::cmp::eq_unchecked(
+                        tyctx.types.u128,
+                        type_id.clone(),
+                        crate::terminator::intrinsics::type_id(string_type),
+                    ),
+                }
+                .into(),
+                CILRoot::BTrue {
+                    target: 2,
+                    sub_target: 0,
+                    cond: crate::binop::cmp::eq_unchecked(
+                        tyctx.types.u128,
+                        type_id,
+                        crate::terminator::intrinsics::type_id(str_type),
+                    ),
+                }
+                .into(),
+                cilly::unwind::set_panic_message(
+                    conv_isize!(ldc_i32!(0)),
+                    conv_usize!(ldc_i32!(0)),
+                )
+                .into(),
+                CILRoot::VoidRet.into(),
+            ],
+            0,
+            None,
+        ),
+        BasicBlock::new(
+            vec![
+                cilly::unwind::set_panic_message(
+                    CILNode::LDIndISize {
+                        ptr: Box::new(payload.clone() + conv_usize!(ldc_u64!(string_ptr))),
+                    },
+                    CILNode::LDIndUSize {
+                        ptr: Box::new(payload + conv_usize!(ldc_u64!(string_len))),
+                    },
+                )
+                .into(),
+                CILRoot::VoidRet.into(),
+            ],
+            1,
+            None,
+        ),
+        BasicBlock::new(
+            vec![
+                cilly::unwind::set_panic_message(
+                    ld_field!(
+                        payload_str.clone(),
+                        FieldDescriptor::new(
+                            str_dotnet.clone(),
+                            Type::Ptr(Type::Void.into()),
+                            "data_pointer".into()
+                        )
+                    ),
+                    ld_field!(
+                        payload_str,
+                        FieldDescriptor::new(str_dotnet, Type::USize, "metadata".into())
+                    ),
+                )
+                .into(),
+                CILRoot::VoidRet.into(),
+            ],
+            2,
+            None,
+        ),
+    ];
+    let site = record_panic_message_site();
+    Some(Method::new(
+        AccessModifer::Private,
+        MethodType::Static,
+        site.signature().clone(),
+        site.name(),
+        vec![
+            (Some("payload".into()), any_ref_type),
+            (Some("type_id".into()), type_id_type),
+        ],
+        blocks,
+        vec![Some("data".into()), Some("vtable".into())],
+    ))
+}
+/// Resolves the method `name` of `trait_def`, called on the trait object `dyn_ty`. Returns the method along with its
+/// index in the vtable.
+fn virtual_method<'tyctx>(
+    tyctx: TyCtxt<'tyctx>,
+    trait_def: DefId,
+    name: &str,
+    dyn_ty: Ty<'tyctx>,
+) -> Option<(Instance<'tyctx>, usize)> {
+    let item = tyctx
+        .associated_items(trait_def)
+        .filter_by_name_unhygienic(Symbol::intern(name))
+        .next()?;
+    let instance = Instance::resolve(
+        tyctx,
+        ParamEnv::reveal_all(),
+        item.def_id,
+        tyctx.mk_args(&[dyn_ty.into()]),
+    )
+    .ok()??;
+    match instance.def {
+        InstanceDef::Virtual(_, fn_idx) => Some((instance, fn_idx)),
+        _ => None,
+    }
+}
+/// Calls the virtual method `method`, at `fn_idx` in `vtable`, on the object at `this`.
+fn call_virtual<'tyctx>(
+    method: Instance<'tyctx>,
+    fn_idx: usize,
+    this: CILNode,
+    vtable: CILNode,
+    tyctx: TyCtxt<'tyctx>,
+    cache: &mut TyCache,
+) -> CILNode {
+    let mut sig = CallInfo::sig_from_instance_(method, tyctx, cache)
+        .sig()
+        .clone();
+    // The receiver is a thin pointer to the object.
+    sig.inputs_mut()[0] = Type::ISize;
+    let vtable_offset = conv_usize!(
+        ldc_u32!(u32::try_from(fn_idx).expect("More tahn 2^32 functions in a vtable!"))
+            * size_of!(Type::USize)
+    );
+    CILNode::CallI(Box::new((
+        sig,
+        CILNode::LDIndISize {
+            ptr: Box::new(vtable + vtable_offset),
+        },
+        [this].into(),
+    )))
+}
+/// Returns the offset of the first field matching `pred`, searching the fields of `ty` and of the structs nested in it.
+fn find_field<'tyctx>(
+    ty: Ty<'tyctx>,
+    tyctx: TyCtxt<'tyctx>,
+    pred: &impl Fn(Symbol, Ty<'tyctx>) -> bool,
+) -> Option<u64> {
+    let TyKind::Adt(def, args) = ty.kind() else {
+        return None;
+    };
+    if !def.is_struct() {
+        return None;
+    }
+    let layout = tyctx
+        .layout_of(ParamEnvAnd {
+            param_env: ParamEnv::reveal_all(),
+            value: ty,
+        })
+        .ok()?;
+    def.non_enum_variant()
+        .fields
+        .iter()
+        .enumerate()
+        .find_map(|(idx, field)| {
+            let field_ty = field.ty(tyctx, args);
+            let offset = layout.fields.offset(idx).bytes();
+            if pred(field.name, field_ty) {
+                Some(offset)
+            } else {
+                find_field(field_ty, tyctx, pred).map(|inner| offset + inner)
+            }
+        })
+}
@@ -4,6 +4,7 @@ use rustc_middle::ty::{
     AdtDef, Const, ConstKind, EarlyBinder, GenericArg, Instance, List, ParamEnv, SymbolName, Ty,
     TyCtxt, TyKind, TypeFoldable,
 };
+use rustc_target::abi::VariantIdx;
 pub const CTOR_FN_NAME: &str = "rustc_clr_interop_managed_ctor";
 pub const MANAGED_CALL_FN_NAME: &str = "rustc_clr_interop_managed_call";
 pub const MANAGED_CALL_VIRT_FN_NAME: &str = "rustc_clr_interop_managed_call_virt";
@@ -79,7 +80,7 @@ pub fn variant_name(ty: Ty, idx: u32) -> crate::IString {
 pub fn function_name(name: SymbolName) -> crate::IString {
     let mut name: String = name.to_string();
     // Name TOO long
-    if *crate::config::ESCAPE_NAMES {
+    if crate::config::config().escape_names {
         name = name.replace('.', "_dot_").replace('$', "_ds_");
     }
     if name.len() > 1000 {
@@ -116,6 +117,25 @@ pub fn enum_field_descriptor<'ctx>(
     method_instance: Instance<'ctx>,
     type_cache: &mut TyCache,
 ) -> FieldDescriptor {
+    if let TyKind::Coroutine(def_id, args) = owner_ty.kind() {
+        // Fields of a coroutine variant are the locals saved across its suspension point.
+        let variant_idx = VariantIdx::from_u32(variant_idx);
+        let field_ty = args
+            .as_coroutine()
+            .state_tys(*def_id, ctx)
+            .nth(variant_idx.as_usize())
+            .expect("No coroutine variant with such index!")
+            .nth(field_idx as usize)
+            .expect("No coroutine field with provided index!");
+        let field_ty = crate::utilis::monomorphize(&method_instance, field_ty, ctx);
+        let field_ty = type_cache.type_from_cache(field_ty, ctx, method_instance);
+        let owner_ty = type_cache
+            .type_from_cache(owner_ty, ctx, method_instance)
+            .as_dotnet()
+            .expect("Coroutine type invalid!");
+        let field_name = crate::r#type::coroutine_field_name(variant_idx, field_idx);
+        return FieldDescriptor::new(owner_ty, field_ty, field_name);
+    }
     let (adt, subst) = as_adt(owner_ty).expect("Tried to get a field of a non ADT or tuple type!");
     let variant = adt
         .variants()
@@ -184,6 +204,23 @@ pub fn field_descrptor<'tyctx>(
             field_type,
             field_name,
         );
+    } else if let TyKind::Coroutine(_, args) = owner_ty.kind() {
+        // Outside of a variant, only the upvars of a coroutine can be accessed.
+        let field_type = args
+            .as_coroutine()
+            .upvar_tys()
+            .iter()
+            .nth(field_idx as usize)
+            .expect("Could not find coroutine upvars!");
+        let field_type = crate::utilis::monomorphize(&method_instance, field_type, tyctx);
+        let field_type = type_cache.type_from_cache(field_type, tyctx, method_instance);
+        let owner_ty = crate::utilis::monomorphize(&method_instance, owner_ty, tyctx);
+        let owner_type = type_cache.type_from_cache(owner_ty, tyctx, method_instance);
+        return FieldDescriptor::new(
+            owner_type.as_dotnet().expect("Coroutine type invalid!"),
+            field_type,
+            format!("f_{field_idx}").into(),
+        );
     }
     let (adt, subst) = as_adt(owner_ty).expect("Tried to get a field of a non ADT or tuple type!");
     let field = adt
@@ -0,0 +1,44 @@
+#![feature(lang_items,adt_const_params,associated_type_defaults,core_intrinsics,start,f16,f128)]
+#![allow(internal_features,incomplete_features,unused_variables,dead_code)]
+#![no_std]
+include!("../common.rs");
+use core::fmt::Write;
+/// A fixed size buffer the formatted values are written into.
+struct Buffer {
+    bytes: [u8; 64],
+    len: usize,
+}
+impl Write for Buffer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        if end > self.bytes.len() {
+            return Err(core::fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+macro_rules! test_format{
+    ($expected:literal,$($arg:tt)*)=>{
+        let mut buffer = Buffer { bytes: [0; 64], len: 0 };
+        test!(write!(buffer, $($arg)*).is_ok());
+        test!(&buffer.bytes[..buffer.len] == $expected.as_bytes());
+    }
+}
+fn main(){
+    let a = black_box(1.5_f16);
+    test_format!("1.5", "{}", a);
+    test_format!("-0.1", "{:?}", -black_box(0.1_f16));
+    test_format!("65500", "{}", black_box(f16::MAX));
+    test_format!("inf", "{}", black_box(f16::INFINITY));
+    test_format!("NaN", "{:?}", black_box(f16::NAN));
+    test_format!("2.250", "{:.3}", a + black_box(0.75_f16));
+    // `core` only implements `Debug` for `f128`, which prints its bits.
+    let b = black_box(-2.25_f128);
+    test_format!("0xc0002000000000000000000000000000", "{:?}", b);
+    test_format!("0x3fff0000000000000000000000000000", "{:?}", b + black_box(3.25_f128));
+    test_format!("0x3ffb999999999999999999999999999a", "{:?}", black_box(0.1_f128));
+    test_format!("0xffff0000000000000000000000000000", "{:?}", black_box(f128::NEG_INFINITY));
+    test_format!("0x7fff8000000000000000000000000000", "{:?}", black_box(f128::NAN));
+}
@@ -0,0 +1,46 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code,unused_imports,unused_mut,private_interfaces,non_upper_case_globals)]
+#![no_std]
+use core::ffi::{c_int, c_void};
+
+include!("../common.rs");
+extern "C" {
+    fn qsort(
+        base: *mut c_void,
+        num: usize,
+        size: usize,
+        compar: Option<unsafe extern "C" fn(*const c_void, *const c_void) -> c_int>,
+    );
+}
+unsafe extern "C" fn compare_i32(a: *const c_void, b: *const c_void) -> c_int {
+    let a = *a.cast::<i32>();
+    let b = *b.cast::<i32>();
+    a.cmp(&b) as c_int
+}
+extern "C" fn is_even(val: i32) -> bool {
+    val % 2 == 0
+}
+fn main() {
+    let mut vals = [5_i32, -3, 8, 0, 17, 2];
+    // Native code calls back into Rust.
+    unsafe {
+        qsort(
+            vals.as_mut_ptr().cast(),
+            vals.len(),
+            core::mem::size_of::<i32>(),
+            Some(compare_i32),
+        )
+    };
+    test_eq!(vals, [-3, 0, 2, 5, 8, 17]);
+    // Pointers to `extern "C"` functions can still be called from Rust.
+    let is_even: extern "C" fn(i32) -> bool = is_even;
+    let is_even = black_box(is_even);
+    test!(is_even(8));
+    test!(!is_even(17));
+}
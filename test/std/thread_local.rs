@@ -0,0 +1,59 @@
+#![feature(
+    lang_items,
+    adt_const_params,
+    associated_type_defaults,
+    core_intrinsics,
+    start,
+    thread_local
+)]
+#![allow(internal_features, incomplete_features, unused_variables, dead_code, improper_ctypes)]
+#![no_std]
+include!("../common.rs");
+extern "C" {
+    fn pthread_create(
+        __newthread: *mut pthread_t,
+        __attr: *const core::ffi::c_void,
+        __start_routine: Option<
+            unsafe extern "C" fn(*mut core::ffi::c_void) -> *mut core::ffi::c_void,
+        >,
+        __arg: *mut core::ffi::c_void,
+    ) -> core::ffi::c_int;
+    fn pthread_join(__th: pthread_t, __retval: *mut *mut core::ffi::c_void) -> core::ffi::c_int;
+}
+pub type pthread_t = core::ffi::c_ulong;
+#[thread_local]
+static mut COUNTER: u32 = 7;
+#[thread_local]
+static mut VALUES: [u64; 3] = [1, 2, 3];
+// What the launched thread saw, before and after changing its copies.
+static mut SEEN: [u32; 2] = [0; 2];
+static mut SEEN_VALUES: u64 = 0;
+unsafe extern "C" fn thread(arg: *mut core::ffi::c_void) -> *mut core::ffi::c_void {
+    // The main thread changed its copies, but this thread gets its own, freshly initialized ones.
+    SEEN[0] = COUNTER;
+    SEEN_VALUES = VALUES[0] + VALUES[1] + VALUES[2];
+    COUNTER += 1;
+    VALUES[2] = 0;
+    SEEN[1] = COUNTER;
+    core::ptr::null_mut()
+}
+fn main() {
+    unsafe {
+        test_eq!(COUNTER, 7);
+        COUNTER = 100;
+        VALUES[0] = 40;
+        let mut thid: pthread_t = 0;
+        test_eq!(
+            pthread_create(&mut thid, core::ptr::null(), Some(thread), core::ptr::null_mut()),
+            0
+        );
+        test_eq!(pthread_join(thid, core::ptr::null_mut()), 0);
+        test_eq!(SEEN[0], 7);
+        test_eq!(SEEN[1], 8);
+        test_eq!(SEEN_VALUES, 6);
+        // The changes made by the launched thread are not visible here.
+        test_eq!(COUNTER, 100);
+        test_eq!(VALUES[0], 40);
+        test_eq!(VALUES[2], 3);
+    }
+}
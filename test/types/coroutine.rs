@@ -0,0 +1,76 @@
+#![feature(lang_items,adt_const_params,associated_type_defaults,core_intrinsics,start,coroutines,coroutine_trait,stmt_expr_attributes)]
+#![allow(internal_features,incomplete_features,unused_variables,dead_code)]
+#![no_std]
+include!("../common.rs");
+use core::future::Future;
+use core::ops::{Coroutine, CoroutineState};
+use core::pin::{pin, Pin};
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+fn clone(ptr: *const ()) -> RawWaker {
+    RawWaker::new(ptr, &VTABLE)
+}
+fn noop(_: *const ()) {}
+/// A minimal executor: polls `fut` until it completes.
+fn block_on<F: Future>(fut: F) -> F::Output {
+    let waker = unsafe { Waker::from_raw(RawWaker::new(core::ptr::null(), &VTABLE)) };
+    let mut cx = Context::from_waker(&waker);
+    let mut fut = pin!(fut);
+    loop {
+        if let Poll::Ready(val) = fut.as_mut().poll(&mut cx) {
+            return val;
+        }
+    }
+}
+/// Returns `Pending` once before completing, forcing its caller to suspend.
+struct YieldOnce(bool);
+impl Future for YieldOnce {
+    type Output = ();
+    fn poll(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            Poll::Ready(())
+        } else {
+            self.0 = true;
+            Poll::Pending
+        }
+    }
+}
+async fn add(a: u32, b: u32) -> u32 {
+    YieldOnce(false).await;
+    a + b
+}
+async fn sum(values: &[u32]) -> u32 {
+    let mut acc = 0;
+    for value in values {
+        acc = add(acc, *value).await;
+    }
+    acc
+}
+fn main() {
+    test_eq!(block_on(add(black_box(2), 3)), 5);
+    let values = [1, 2, 3, 4];
+    test_eq!(block_on(sum(black_box(&values))), 10);
+    let captured = black_box(7_u32);
+    test_eq!(block_on(async move { add(captured, 1).await * 2 }), 16);
+    let mut counter = #[coroutine]
+    || {
+        let mut i = black_box(0_u32);
+        while i < 3 {
+            yield i;
+            i += 1;
+        }
+        0xDEAD_BEEF_u32
+    };
+    let mut counter = pin!(counter);
+    for expected in 0..3 {
+        match counter.as_mut().resume(()) {
+            CoroutineState::Yielded(val) => test_eq!(val, expected),
+            CoroutineState::Complete(_) => test_eq!(0, 1),
+        }
+    }
+    match counter.as_mut().resume(()) {
+        CoroutineState::Yielded(_) => test_eq!(0, 1),
+        CoroutineState::Complete(val) => test_eq!(val, 0xDEAD_BEEF),
+    }
+}
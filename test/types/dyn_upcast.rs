@@ -0,0 +1,81 @@
+#![feature(lang_items,adt_const_params,associated_type_defaults,core_intrinsics,start,trait_upcasting)]
+#![allow(internal_features,incomplete_features,unused_variables,dead_code,stable_features)]
+#![no_std]
+include!("../common.rs");
+pub trait Named{
+    fn name(&self)->u32;
+}
+pub trait Sized2{
+    fn size(&self)->u32;
+}
+pub trait Plugin:Named{
+    fn run(&self,arg:u32)->u32;
+}
+pub trait Shape:Named+Sized2{
+    fn area(&self)->u32;
+}
+struct Square(pub u32);
+impl Named for Square{
+    fn name(&self)->u32{
+        0xDEAD_BEEF
+    }
+}
+impl Sized2 for Square{
+    fn size(&self)->u32{
+        self.0
+    }
+}
+impl Plugin for Square{
+    fn run(&self,arg:u32)->u32{
+        self.0 + arg
+    }
+}
+impl Shape for Square{
+    fn area(&self)->u32{
+        self.0 * self.0
+    }
+}
+struct Wrapper<T:?Sized>{
+    tag:u32,
+    inner:T,
+}
+fn as_named(plugin:&dyn Plugin)->&dyn Named{
+    plugin
+}
+fn as_sized(shape:&dyn Shape)->&dyn Sized2{
+    shape
+}
+fn as_sized_ptr(shape:*const dyn Shape)->*const dyn Sized2{
+    shape
+}
+fn main(){
+    let square = Square(8);
+    // Upcasting to the first supertrait reuses the vtable prefix.
+    let plugin:&dyn Plugin = black_box(&square);
+    test_eq!(plugin.run(2),10);
+    let named = black_box(as_named(plugin));
+    test_eq!(named.name(),0xDEAD_BEEF);
+    // Upcasting to a later supertrait loads the vtable from a slot of the source vtable.
+    let shape:&dyn Shape = black_box(&square);
+    test_eq!(shape.area(),64);
+    let sized = black_box(as_sized(shape));
+    test_eq!(sized.size(),8);
+    let named:&dyn Named = black_box(shape);
+    test_eq!(named.name(),0xDEAD_BEEF);
+    let sized = black_box(as_sized_ptr(shape as *const dyn Shape));
+    test_eq!(unsafe{(*sized).size()},8);
+    // Dyn to dyn, only dropping auto traits.
+    let send_named:&(dyn Named + Send) = black_box(&square);
+    let named:&dyn Named = send_named;
+    test_eq!(named.name(),0xDEAD_BEEF);
+    // Unsizing the tail of a struct.
+    let wrapper = Wrapper{tag:7,inner:[1_u32,2,3,4]};
+    let unsized_wrapper:&Wrapper<[u32]> = black_box(&wrapper);
+    test_eq!(unsized_wrapper.tag,7);
+    test_eq!(unsized_wrapper.inner.len(),4);
+    test_eq!(unsized_wrapper.inner[3],4);
+    let wrapper = Wrapper{tag:3,inner:Square(5)};
+    let dyn_wrapper:&Wrapper<dyn Shape> = black_box(&wrapper);
+    test_eq!(dyn_wrapper.tag,3);
+    test_eq!(dyn_wrapper.inner.area(),25);
+}
@@ -0,0 +1,33 @@
+#![feature(lang_items,adt_const_params,associated_type_defaults,core_intrinsics,start,f16,f128)]
+#![allow(internal_features,incomplete_features,unused_variables,dead_code)]
+#![no_std]
+include!("../common.rs");
+fn main(){
+    // f16 is backed by System.Half
+    let a = black_box(1.5_f16);
+    let b = black_box(2.25_f16);
+    test_eq!(a + b, 3.75_f16);
+    test_eq!(b - a, 0.75_f16);
+    test_eq!(a * b, 3.375_f16);
+    test_eq!(-a, -1.5_f16);
+    test!(a < b);
+    test!(!(a >= b));
+    test_eq!(b as f32, 2.25_f32);
+    test_eq!(b as i32, 2_i32);
+    test_eq!(black_box(70000.0_f32) as f16, f16::INFINITY);
+    test_eq!(black_box(-3_i8) as f16, -3.0_f16);
+    test_eq!(a.to_bits(), 0x3e00);
+    // f128 is implemented in software
+    let c = black_box(1.5_f128);
+    let d = black_box(2.25_f128);
+    test_eq!(c + d, 3.75_f128);
+    test_eq!(d / c, 1.5_f128);
+    test_eq!(-c, -1.5_f128);
+    test!(c <= d);
+    test!(!(c == d));
+    test_eq!(d as f64, 2.25_f64);
+    test_eq!(d as u8, 2_u8);
+    test_eq!(black_box(-1e30_f128) as i16, i16::MIN);
+    test_eq!(black_box(u64::MAX) as f128, 18446744073709551615.0_f128);
+    test_eq!(a as f128, c);
+}
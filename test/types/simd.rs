@@ -0,0 +1,85 @@
+#![feature(lang_items,adt_const_params,associated_type_defaults,core_intrinsics,start,repr_simd)]
+#![allow(internal_features,incomplete_features,unused_variables,dead_code)]
+#![no_std]
+include!("../common.rs");
+use core::intrinsics::simd::*;
+#[repr(simd)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct I32x4(i32, i32, i32, i32);
+#[repr(simd)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct F32x4(f32, f32, f32, f32);
+// Types with no .NET vector counterpart.
+#[repr(simd)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct U8x4(u8, u8, u8, u8);
+#[repr(simd)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct I8x4(i8, i8, i8, i8);
+#[repr(simd)]
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct F32x3(f32, f32, f32);
+#[repr(simd)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct I64x2(i64, i64);
+#[repr(simd)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct I32x2(i32, i32);
+fn main(){
+    let a = black_box(I32x4(1, 2, 3, 4));
+    let b = black_box(I32x4(10, 20, 30, 40));
+    unsafe {
+        test_eq!(simd_add(a, b), I32x4(11, 22, 33, 44));
+        test_eq!(simd_sub(b, a), I32x4(9, 18, 27, 36));
+        test_eq!(simd_mul(a, b), I32x4(10, 40, 90, 160));
+        test_eq!(simd_shl(a, I32x4(1, 1, 2, 2)), I32x4(2, 4, 12, 16));
+        test_eq!(simd_rem(b, I32x4(3, 3, 7, 7)), I32x4(1, 2, 2, 5));
+        test_eq!(simd_eq(a, I32x4(1, 0, 3, 0)), I32x4(-1, 0, -1, 0));
+        test_eq!(simd_select(I32x4(-1, 0, -1, 0), a, b), I32x4(1, 20, 3, 40));
+        test_eq!(simd_extract::<_, i32>(b, 2), 30);
+        test_eq!(simd_insert(a, 0, 7_i32), I32x4(7, 2, 3, 4));
+        test_eq!(simd_shuffle::<_, _, I32x4>(a, b, [7_u32, 0, 5, 2]), I32x4(40, 1, 20, 3));
+        test_eq!(simd_reduce_add_unordered::<_, i32>(a), 10);
+        test_eq!(simd_reduce_max::<_, i32>(b), 40);
+        test!(simd_reduce_any::<_>(simd_gt(a, I32x4(0, 0, 0, 3))));
+        test!(!simd_reduce_all::<_>(simd_gt(a, I32x4(0, 0, 0, 4))));
+        test_eq!(simd_bitmask::<_, u8>(I32x4(-1, 0, -1, -1)), 0b1101);
+    }
+    let c = black_box(F32x4(1.5, -2.0, 4.0, 9.0));
+    unsafe {
+        test_eq!(simd_fabs(c), F32x4(1.5, 2.0, 4.0, 9.0));
+        test_eq!(simd_fsqrt(F32x4(1.0, 4.0, 9.0, 16.0)), F32x4(1.0, 2.0, 3.0, 4.0));
+        test_eq!(simd_floor(c), F32x4(1.0, -2.0, 4.0, 9.0));
+        test_eq!(simd_cast::<_, I32x4>(c), I32x4(1, -2, 4, 9));
+        // NaN lanes are ignored, in either operand.
+        let nan = black_box(F32x4(f32::NAN, 0.0, f32::NAN, 10.0));
+        test_eq!(simd_fmin(c, nan), F32x4(1.5, -2.0, 4.0, 9.0));
+        test_eq!(simd_fmax(nan, c), F32x4(1.5, 0.0, 4.0, 10.0));
+    }
+    let d = black_box(U8x4(1, 2, 200, 255));
+    unsafe {
+        test_eq!(simd_add(d, U8x4(1, 1, 100, 1)), U8x4(2, 3, 44, 0));
+        test_eq!(simd_div(d, U8x4(1, 2, 3, 5)), U8x4(1, 1, 66, 51));
+        test_eq!(simd_eq(d, U8x4(1, 0, 200, 0)), I8x4(-1, 0, -1, 0));
+        test_eq!(simd_select(I8x4(-1, 0, 0, -1), d, U8x4(0, 0, 0, 0)), U8x4(1, 0, 0, 255));
+        test_eq!(simd_extract::<_, u8>(d, 3), 255);
+        test_eq!(simd_insert(d, 1, 9_u8), U8x4(1, 9, 200, 255));
+        test_eq!(simd_reduce_max::<_, u8>(d), 255);
+        test_eq!(simd_bitmask::<_, u8>(I8x4(-1, 0, 0, -1)), 0b1001);
+    }
+    let e = black_box(F32x3(1.5, -2.0, 9.0));
+    unsafe {
+        test_eq!(simd_mul(e, F32x3(2.0, 2.0, 0.5)), F32x3(3.0, -4.0, 4.5));
+        test_eq!(simd_neg(e), F32x3(-1.5, 2.0, -9.0));
+        test_eq!(simd_fabs(e), F32x3(1.5, 2.0, 9.0));
+        test_eq!(simd_fsqrt(F32x3(1.0, 4.0, 9.0)), F32x3(1.0, 2.0, 3.0));
+        test_eq!(simd_fmin(e, F32x3(f32::NAN, 0.0, 10.0)), F32x3(1.5, -2.0, 9.0));
+        test_eq!(simd_reduce_add_ordered::<_, f32>(e, 0.5), 9.0);
+    }
+    // Masks with lanes narrower than the compared ones.
+    let f = black_box(I64x2(5, -7));
+    unsafe {
+        test_eq!(simd_lt(f, I64x2(6, -8)), I32x2(-1, 0));
+        test_eq!(simd_select(I32x2(0, -1), f, I64x2(1, 2)), I64x2(1, -7));
+    }
+}